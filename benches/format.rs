@@ -0,0 +1,71 @@
+//! Benchmarks of capturing, resolving and formatting backtraces.
+//!
+//! Run with `cargo bench`, see `BENCH.md` for the allocation and demangling budgets checked by
+//! `cargo test --test perf`.
+
+use {
+    backtrace::Backtrace,
+    backtrace_string::{
+        fingerprint,
+        frame::{ResolvedFrame, ResolvedSymbol},
+        BacktraceBuffer, FormatOptions,
+    },
+    criterion::{criterion_group, criterion_main, Criterion},
+    std::{hint::black_box, path::PathBuf},
+};
+
+/// Frames like the ones of a panic in a dependency, called from the application.
+fn synthetic_frames(count: usize) -> Vec<ResolvedFrame> {
+    (0..count)
+        .map(|i| ResolvedFrame {
+            ip: 0x5555_0000 + i * 0x40,
+            symbols: vec![ResolvedSymbol {
+                name: Some(format!("_ZN6my_app6module8function{}17h0123456789abcdefE", i)),
+                filename: Some(PathBuf::from(format!(
+                    "/home/alice/.cargo/registry/src/index.crates.io-6f17d22bba15001f/dep-1.0.0/src/lib{}.rs",
+                    i
+                ))),
+                lineno: Some(10 + i as u32),
+                addr: Some(0x5555_0000 + i * 0x40),
+            }],
+        })
+        .collect()
+}
+
+fn format(c: &mut Criterion) {
+    let frames = synthetic_frames(32);
+    let options = FormatOptions::new();
+    c.bench_function("format_frames/32", |b| {
+        b.iter(|| backtrace_string::format_frames(black_box(&frames), &options))
+    });
+    let production = FormatOptions::production();
+    c.bench_function("format_frames/32/production", |b| {
+        b.iter(|| backtrace_string::format_frames(black_box(&frames), &production))
+    });
+
+    let mut bt = Backtrace::new();
+    c.bench_function("format_backtrace/resolved", |b| {
+        b.iter(|| backtrace_string::format_backtrace_with(black_box(&mut bt), &options))
+    });
+    c.bench_function("fingerprint/resolved", |b| {
+        b.iter(|| fingerprint(black_box(&mut bt), &options))
+    });
+}
+
+fn capture(c: &mut Criterion) {
+    c.bench_function("capture/unresolved", |b| b.iter(Backtrace::new_unresolved));
+    c.bench_function("capture/resolved", |b| b.iter(Backtrace::new));
+
+    let mut buffer = BacktraceBuffer::new(64, 16 * 1024);
+    c.bench_function("buffer/capture_and_format", |b| {
+        b.iter(|| buffer.capture_and_format().len())
+    });
+    let mut unresolved =
+        BacktraceBuffer::with_options(64, 16 * 1024, FormatOptions::new().resolve_symbols(false));
+    c.bench_function("buffer/capture_and_format/unresolved", |b| {
+        b.iter(|| unresolved.capture_and_format().len())
+    });
+}
+
+criterion_group!(benches, format, capture);
+criterion_main!(benches);
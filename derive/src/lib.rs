@@ -0,0 +1,181 @@
+//! The `Backtraced` derive macro, re-exported by `backtrace-string` with its `derive` feature.
+//!
+//! See the documentation of `backtrace_string::Backtraced`.
+
+extern crate proc_macro;
+
+use {
+    proc_macro::TokenStream,
+    proc_macro2::{Span, TokenStream as TokenStream2},
+    quote::quote,
+    syn::{parse_macro_input, Data, DeriveInput, Error, Fields, Index, Member, Result, Type},
+};
+
+/// Derives the `backtrace()` accessor and the `From` impls capturing the backtrace.
+#[proc_macro_derive(Backtraced, attributes(backtraced))]
+pub fn derive_backtraced(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// The fields of a struct or variant this macro cares about.
+struct Roles<'a> {
+    /// The `LazyBacktrace` field.
+    trace: Option<Member>,
+    /// The field converted from by a `From` impl, and its type.
+    from: Option<(Member, &'a Type)>,
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let lazy = quote!(::backtrace_string::lazy::LazyBacktrace);
+    let mut from_impls = Vec::new();
+    let accessor = match &input.data {
+        Data::Struct(data) => {
+            let roles = roles(&data.fields)?;
+            let trace = match &roles.trace {
+                Some(trace) => trace,
+                None => {
+                    return Err(Error::new(
+                        Span::call_site(),
+                        "`Backtraced` needs a `LazyBacktrace` field",
+                    ))
+                }
+            };
+            if let Some((source, ty)) = roles.from {
+                from_impls.push((quote!(#name), source, ty, trace.clone()));
+            }
+            quote! {
+                /// Returns the backtrace captured when the error was created.
+                pub fn backtrace(&self) -> &#lazy {
+                    &self.#trace
+                }
+            }
+        }
+        Data::Enum(data) => {
+            let mut arms = Vec::new();
+            for variant in &data.variants {
+                let ident = &variant.ident;
+                let roles = roles(&variant.fields)?;
+                match &roles.trace {
+                    Some(trace) => {
+                        arms.push(
+                            quote!(#name::#ident { #trace: backtrace, .. } => Some(backtrace)),
+                        );
+                        if let Some((source, ty)) = roles.from {
+                            from_impls.push((quote!(#name::#ident), source, ty, trace.clone()));
+                        }
+                    }
+                    None => arms.push(quote!(#name::#ident { .. } => None)),
+                }
+            }
+            quote! {
+                /// Returns the backtrace captured when the error was created, if the variant
+                /// has one.
+                pub fn backtrace(&self) -> Option<&#lazy> {
+                    match self {
+                        #(#arms,)*
+                    }
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(Error::new(
+                Span::call_site(),
+                "`Backtraced` can't be derived for unions",
+            ))
+        }
+    };
+    let from_impls = from_impls.into_iter().map(|(path, source, ty, trace)| {
+        quote! {
+            impl #impl_generics ::core::convert::From<#ty> for #name #ty_generics #where_clause {
+                fn from(source: #ty) -> Self {
+                    #path {
+                        #source: source,
+                        #trace: #lazy::capture(),
+                    }
+                }
+            }
+        }
+    });
+    Ok(quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            #accessor
+        }
+        #(#from_impls)*
+    })
+}
+
+/// Finds the backtrace field, either marked `#[backtraced(trace)]` or of type `LazyBacktrace`,
+/// and the field marked `#[backtraced(from)]`.
+fn roles(fields: &Fields) -> Result<Roles<'_>> {
+    let mut roles = Roles {
+        trace: None,
+        from: None,
+    };
+    for (i, field) in fields.iter().enumerate() {
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(i)),
+        };
+        let mut is_trace = is_lazy_backtrace(&field.ty);
+        let mut is_from = false;
+        for attr in &field.attrs {
+            if !attr.path().is_ident("backtraced") {
+                continue;
+            }
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("trace") {
+                    is_trace = true;
+                    Ok(())
+                } else if meta.path.is_ident("from") {
+                    is_from = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `trace` or `from`"))
+                }
+            })?;
+        }
+        let duplicate = |role| Error::new_spanned(field, format!("more than one {} field", role));
+        if is_trace {
+            if roles.trace.is_some() {
+                return Err(duplicate("backtrace"));
+            }
+            roles.trace = Some(member);
+        } else if is_from {
+            if roles.from.is_some() {
+                return Err(duplicate("`#[backtraced(from)]`"));
+            }
+            roles.from = Some((member, &field.ty));
+        }
+    }
+    if roles.from.is_some() {
+        if roles.trace.is_none() {
+            return Err(Error::new_spanned(
+                fields,
+                "a `#[backtraced(from)]` field needs a `LazyBacktrace` field next to it",
+            ));
+        }
+        if fields.len() > 2 {
+            return Err(Error::new_spanned(
+                fields,
+                "a `#[backtraced(from)]` field allows no fields besides the backtrace",
+            ));
+        }
+    }
+    Ok(roles)
+}
+
+fn is_lazy_backtrace(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "LazyBacktrace"),
+        _ => false,
+    }
+}
@@ -22,8 +22,6 @@ fn main() {
 fn do_that_thing(x: u32)  {
     if x > 4 {
         panic!("and run away...");
-    } else if x > 4 {
-        return;
     }
     do_that_thing(x+1);
 }
@@ -0,0 +1,66 @@
+//! Feeds arbitrary frames through the formatter, run with `cargo fuzz run format_frames`.
+
+#![no_main]
+
+use {
+    arbitrary::Arbitrary,
+    backtrace_string::{
+        frame::{ResolvedFrame, ResolvedSymbol},
+        ColorChoice, FormatOptions, Style, UnknownSymbols,
+    },
+    libfuzzer_sys::fuzz_target,
+    std::path::PathBuf,
+};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    frames: Vec<(usize, Vec<Symbol>)>,
+    compact: bool,
+    unknown: u8,
+    deterministic: bool,
+    max_frames: Option<u8>,
+}
+
+#[derive(Debug, Arbitrary)]
+struct Symbol {
+    name: Option<String>,
+    filename: Option<String>,
+    lineno: Option<u32>,
+    addr: Option<usize>,
+}
+
+fuzz_target!(|input: Input| {
+    let frames = input
+        .frames
+        .into_iter()
+        .map(|(ip, symbols)| ResolvedFrame {
+            ip,
+            symbols: symbols
+                .into_iter()
+                .map(|symbol| ResolvedSymbol {
+                    name: symbol.name,
+                    filename: symbol.filename.map(PathBuf::from),
+                    lineno: symbol.lineno,
+                    addr: symbol.addr,
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    let options = FormatOptions::new()
+        .color(ColorChoice::Never)
+        .style(if input.compact {
+            Style::Compact
+        } else {
+            Style::Full
+        })
+        .unknown_symbols(match input.unknown % 3 {
+            0 => UnknownSymbols::Show,
+            1 => UnknownSymbols::Hide,
+            _ => UnknownSymbols::ModuleOffset,
+        })
+        .deterministic(input.deterministic)
+        .max_frames(input.max_frames.map(usize::from))
+        .detect_stack_overflow(true);
+    let _ = backtrace_string::format_frames(&frames, &options);
+    let _ = backtrace_string::format_frame_source(&frames, &options);
+});
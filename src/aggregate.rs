@@ -0,0 +1,265 @@
+//! Counting backtraces by their fingerprint before shipping them.
+//!
+//! Services turning errors into backtraces can produce the same few backtraces thousands of times.
+//! A [`TraceAggregator`] keeps each distinct backtrace once, with how often and when it was seen,
+//! and reports the most frequent ones:
+//!
+//! ```
+//! use {
+//!     backtrace::Backtrace,
+//!     backtrace_string::{aggregate::TraceAggregator, FormatOptions},
+//! };
+//!
+//! let aggregator = TraceAggregator::new(FormatOptions::new(), 100);
+//! for _ in 0..3 {
+//!     aggregator.add(&mut Backtrace::new_unresolved());
+//! }
+//! assert_eq!(aggregator.total(), 3);
+//! eprint!("{}", aggregator.report(10));
+//! ```
+//!
+//! The number of distinct backtraces kept is bounded; when it is reached, the least frequent one
+//! is evicted to make room for a new one.
+//!
+//![`TraceAggregator`]: struct.TraceAggregator.html
+
+use {
+    crate::{
+        filter_frames, format_frames,
+        frame::{Frame, FrameSource, ResolvedFrame, ResolvedSymbol},
+        resolve, Fingerprint, FormatOptions,
+    },
+    backtrace::Backtrace,
+    std::{
+        collections::HashMap,
+        fmt::Write,
+        sync::{Mutex, MutexGuard},
+        time::{Duration, SystemTime},
+    },
+};
+
+/// Backtraces counted by their fingerprint, safe to share between threads.
+#[derive(Debug)]
+pub struct TraceAggregator {
+    options: FormatOptions,
+    capacity: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    traces: HashMap<Fingerprint, AggregatedTrace>,
+    total: u64,
+    evicted: u64,
+}
+
+/// A distinct backtrace and how often and when it was seen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AggregatedTrace {
+    /// The fingerprint of the frames.
+    pub fingerprint: Fingerprint,
+    /// The trimmed and visible frames, the most recent call first.
+    pub frames: Vec<ResolvedFrame>,
+    /// How often the backtrace was added.
+    pub count: u64,
+    /// When the backtrace was first added.
+    pub first_seen: SystemTime,
+    /// When the backtrace was last added.
+    pub last_seen: SystemTime,
+}
+
+impl TraceAggregator {
+    /// Creates an aggregator keeping up to `capacity` distinct backtraces, trimming, hiding and
+    /// formatting the frames according to the `options`.
+    pub fn new(options: FormatOptions, capacity: usize) -> Self {
+        TraceAggregator {
+            options,
+            capacity,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Resolves `bt` and counts it, returning its fingerprint.
+    pub fn add(&self, bt: &mut Backtrace) -> Fingerprint {
+        resolve(bt, &self.options);
+        self.add_frames(&*bt)
+    }
+
+    /// Counts the frames of `source`, returning their fingerprint.
+    pub fn add_frames<S: FrameSource + ?Sized>(&self, source: &S) -> Fingerprint {
+        self.add_at(source, SystemTime::now())
+    }
+
+    fn add_at<S: FrameSource + ?Sized>(&self, source: &S, now: SystemTime) -> Fingerprint {
+        let options = &self.options;
+        let frames = filter_frames(source.frames(), options)
+            .filter(|frame| options.is_visible(*frame))
+            .collect::<Vec<_>>();
+        let fingerprint = Fingerprint::from_frames(frames.iter().copied());
+        let mut state = self.lock();
+        state.total += 1;
+        if let Some(trace) = state.traces.get_mut(&fingerprint) {
+            trace.count += 1;
+            trace.last_seen = now;
+            return fingerprint;
+        }
+        if state.traces.len() >= self.capacity {
+            // Frequent backtraces are the interesting ones, of equally frequent ones the stale one
+            // goes.
+            let evicted = state
+                .traces
+                .values()
+                .min_by_key(|trace| (trace.count, trace.last_seen))
+                .map(|trace| (trace.fingerprint, trace.count));
+            match evicted {
+                Some((evicted, count)) => {
+                    state.traces.remove(&evicted);
+                    state.evicted += count;
+                }
+                // A capacity of 0 keeps nothing.
+                None => {
+                    state.evicted += 1;
+                    return fingerprint;
+                }
+            }
+        }
+        let frames = frames
+            .into_iter()
+            .map(|frame| ResolvedFrame {
+                ip: frame.ip(),
+                symbols: frame.symbols().map(ResolvedSymbol::from).collect(),
+            })
+            .collect();
+        state.traces.insert(
+            fingerprint,
+            AggregatedTrace {
+                fingerprint,
+                frames,
+                count: 1,
+                first_seen: now,
+                last_seen: now,
+            },
+        );
+        fingerprint
+    }
+
+    /// Returns how many backtraces were added, including the evicted ones.
+    pub fn total(&self) -> u64 {
+        self.lock().total
+    }
+
+    /// Returns how many of the added backtraces were evicted.
+    pub fn evicted(&self) -> u64 {
+        self.lock().evicted
+    }
+
+    /// Returns the distinct backtraces kept, the most frequent first.
+    pub fn traces(&self) -> Vec<AggregatedTrace> {
+        let mut traces = self.lock().traces.values().cloned().collect::<Vec<_>>();
+        traces.sort_by(|a, b| {
+            (b.count, b.last_seen, a.fingerprint).cmp(&(a.count, a.last_seen, b.fingerprint))
+        });
+        traces
+    }
+
+    /// Formats the `n` most frequent backtraces with their counts and when they were seen.
+    pub fn report(&self, n: usize) -> String {
+        self.report_at(n, SystemTime::now())
+    }
+
+    fn report_at(&self, n: usize, now: SystemTime) -> String {
+        let ago = |time: SystemTime| {
+            now.duration_since(time)
+                .unwrap_or(Duration::from_secs(0))
+                .as_secs()
+        };
+        let mut out = String::new();
+        for trace in self.traces().into_iter().take(n) {
+            write!(
+                out,
+                "{}: {}, seen {} times, first {}s ago, last {}s ago:",
+                self.options.vocabulary.fingerprint,
+                trace.fingerprint,
+                trace.count,
+                ago(trace.first_seen),
+                ago(trace.last_seen)
+            )
+            .unwrap();
+            out.push_str(&format_frames(&trace.frames, &self.options));
+        }
+        let evicted = self.evicted();
+        if evicted > 0 {
+            writeln!(out, "{} backtraces evicted", evicted).unwrap();
+        }
+        out
+    }
+
+    /// Removes all backtraces and resets the counts.
+    pub fn clear(&self) {
+        *self.lock() = State::default();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::TraceAggregator,
+        crate::{
+            frame::{ResolvedFrame, ResolvedSymbol},
+            FormatOptions, Locations,
+        },
+        std::time::{Duration, SystemTime},
+    };
+
+    fn stack(names: &[&str]) -> Vec<ResolvedFrame> {
+        names
+            .iter()
+            .map(|name| ResolvedFrame {
+                ip: 0,
+                symbols: vec![ResolvedSymbol {
+                    name: Some(name.to_string()),
+                    ..ResolvedSymbol::default()
+                }],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn counts_and_evicts() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let at = |secs| start + Duration::from_secs(secs);
+        let aggregator = TraceAggregator::new(FormatOptions::new().locations(Locations::Hidden), 2);
+        let parse = stack(&["my_app::parse", "my_app::main"]);
+        let load = stack(&["my_app::load", "my_app::main"]);
+        let print = stack(&["my_app::print", "my_app::main"]);
+        aggregator.add_at(&parse, at(0));
+        aggregator.add_at(&load, at(1));
+        aggregator.add_at(&parse, at(2));
+        // Evicts `load`, which was seen less often.
+        let printed = aggregator.add_at(&print, at(3));
+        assert_eq!((aggregator.total(), aggregator.evicted()), (4, 1));
+
+        let traces = aggregator.traces();
+        assert_eq!(traces.len(), 2);
+        assert_eq!((traces[0].count, traces[0].first_seen), (2, at(0)));
+        assert_eq!(traces[0].last_seen, at(2));
+        assert_eq!(traces[1].fingerprint, printed);
+        assert_eq!(
+            aggregator.report_at(1, at(10)),
+            format!(
+                "Fingerprint: {}, seen 2 times, first 10s ago, last 8s ago:\n   \
+                 0: my_app::parse\n   1: my_app::main\n1 backtraces evicted\n",
+                traces[0].fingerprint
+            )
+        );
+
+        aggregator.clear();
+        assert_eq!(aggregator.total(), 0);
+        assert!(aggregator.traces().is_empty());
+    }
+}
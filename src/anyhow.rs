@@ -0,0 +1,52 @@
+//! Integration with the [`anyhow` crate](https://crates.io/crates/anyhow).
+//!
+//! `anyhow` captures its backtraces with `std::backtrace::Backtrace`, which doesn't give access
+//! to its frames. [`format_error()`] therefore captures a new backtrace at the point the error is
+//! reported, which is usually where it is handled (e.g. in `main`).
+//!
+//!```no_run
+//! fn run() -> anyhow::Result<()> {
+//!     anyhow::bail!("something went wrong")
+//! }
+//!
+//! if let Err(error) = run() {
+//!     eprintln!("{}", backtrace_string::anyhow::format_error(&error));
+//! }
+//! ```
+//!
+//![`format_error()`]: fn.format_error.html
+
+use {
+    crate::{format_backtrace_after_capture, write_error_chain, FormatOptions},
+    backtrace::Backtrace,
+    std::fmt::Write,
+};
+
+/// Formats `error` and its causes followed by the backtrace of the caller.
+pub fn format_error(error: &::anyhow::Error) -> String {
+    let options = FormatOptions::current();
+    let mut out = String::new();
+    write_error_chain(&mut out, error.as_ref(), &options.vocabulary).unwrap();
+
+    let mut bt = Backtrace::new_unresolved();
+    write!(out, "\n\n{}:", options.vocabulary.backtrace).unwrap();
+    out.push_str(&format_backtrace_after_capture(&mut bt, &options, |sym| {
+        sym.starts_with("backtrace::") || sym.starts_with("backtrace_string::anyhow::format_error")
+    }));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_error;
+
+    #[test]
+    fn formats_chain_and_backtrace() {
+        let error = ::anyhow::anyhow!("inner failure").context("outer failure");
+        let out = format_error(&error);
+
+        assert!(out.starts_with("outer failure\n\nCaused by:\n   0: inner failure"));
+        assert!(out.contains("formats_chain_and_backtrace"));
+        assert!(!out.contains("backtrace_string::anyhow::format_error"));
+    }
+}
@@ -0,0 +1,111 @@
+//! Telling the application's own code apart from its dependencies.
+//!
+//! By default a symbol belongs to the application unless its source file is in the Rust
+//! toolchain or below `.cargo`, which misjudges symbols without debug info and vendored
+//! dependencies. Registering the crates of the application with [`register_app_crates!`] at
+//! startup makes the crate of the symbol decide instead:
+//!
+//! ```
+//! // Registers the calling crate and the other crates of its workspace.
+//! backtrace_string::register_app_crates!("my_app_core", "my_app_proto");
+//!
+//! assert!(backtrace_string::app::is_app_symbol("my_app_core::parse::h0123456789abcdef"));
+//! assert!(!backtrace_string::app::is_app_symbol("tokio::runtime::park::block_on"));
+//! ```
+//!
+//! The classification decides which symbols are highlighted as the application's.
+//!
+//![`register_app_crates!`]: ../macro.register_app_crates.html
+
+use {
+    crate::{color::is_dependency_path, crate_name},
+    std::{borrow::Cow, path::Path, sync::RwLock},
+};
+
+static APP_CRATES: RwLock<Vec<Cow<'static, str>>> = RwLock::new(Vec::new());
+
+/// Registers the calling crate as the application's code, followed by further crates given as
+/// string literals, usually the other members of the workspace.
+///
+/// Crate names may be given as package names, `-` is read as `_`. See the [`app`] module.
+///
+/// ```
+/// backtrace_string::register_app_crates!();
+/// ```
+///
+///[`app`]: app/index.html
+#[macro_export]
+macro_rules! register_app_crates {
+    ($($name:expr),* $(,)?) => {
+        $crate::app::register_crates(&[env!("CARGO_CRATE_NAME") $(, $name)*])
+    };
+}
+
+/// Registers crates as the application's code, see [`register_app_crates!`].
+///
+///[`register_app_crates!`]: ../macro.register_app_crates.html
+pub fn register_crates(names: &[&'static str]) {
+    let mut crates = APP_CRATES.write().unwrap_or_else(|err| err.into_inner());
+    for name in names {
+        let name = if name.contains('-') {
+            Cow::Owned(name.replace('-', "_"))
+        } else {
+            Cow::Borrowed(*name)
+        };
+        if !crates.contains(&name) {
+            crates.push(name);
+        }
+    }
+}
+
+/// Returns the registered crates of the application.
+pub fn app_crates() -> Vec<String> {
+    let crates = APP_CRATES.read().unwrap_or_else(|err| err.into_inner());
+    crates.iter().map(|name| name.to_string()).collect()
+}
+
+/// Returns whether the symbol belongs to one of the registered crates.
+///
+/// Trait implementations like `<my_app::Config as core::fmt::Debug>::fmt` belong to the crate
+/// of the type.
+pub fn is_app_symbol(name: &str) -> bool {
+    let crates = APP_CRATES.read().unwrap_or_else(|err| err.into_inner());
+    in_crates(&crates, name)
+}
+
+/// Returns whether a symbol with the source file `path` belongs to a dependency, by its crate if
+/// the application registered its crates and by its path otherwise.
+pub(crate) fn is_dependency(name: &str, path: Option<&Path>) -> bool {
+    let crates = APP_CRATES.read().unwrap_or_else(|err| err.into_inner());
+    if crates.is_empty() {
+        is_dependency_path(path)
+    } else {
+        !in_crates(&crates, name)
+    }
+}
+
+fn in_crates(crates: &[Cow<'static, str>], name: &str) -> bool {
+    match crate_name(name.trim_start_matches('<')) {
+        Some(krate) => crates.iter().any(|app| *app == krate),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::in_crates, std::borrow::Cow};
+
+    #[test]
+    fn symbols_are_classified_by_crate() {
+        let crates = [Cow::Borrowed("my_app"), Cow::Borrowed("my_app_core")];
+        assert!(in_crates(&crates, "my_app::main"));
+        assert!(in_crates(&crates, "my_app_core::parse::h0123456789abcdef"));
+        assert!(in_crates(
+            &crates,
+            "<my_app::Config as core::fmt::Debug>::fmt"
+        ));
+        assert!(!in_crates(&crates, "my_application::main"));
+        assert!(!in_crates(&crates, "tokio::runtime::park::block_on"));
+        assert!(!in_crates(&crates, "main"));
+    }
+}
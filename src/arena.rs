@@ -0,0 +1,228 @@
+//! A reserved arena for the allocations of panic hooks, built with the `hook-arena` feature.
+//!
+//! A panic hook formatting a report allocates its strings through the global allocator, so a
+//! corrupted or exhausted heap can keep the report from being written. [`HookArena`] wraps the
+//! global allocator (the system allocator, `mimalloc` or any other one) and serves the
+//! allocations made on a thread inside of [`enter()`] from a static arena reserved at build time
+//! instead:
+//!
+//! ```
+//! use {
+//!     backtrace_string::arena::{self, HookArena},
+//!     std::{alloc::System, panic},
+//! };
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: HookArena<System, { 256 * 1024 }> = HookArena::new(System);
+//!
+//! panic::set_hook(Box::new(|info| {
+//!     let _arena = arena::enter();
+//!     eprintln!("{}{}", info, backtrace_string::create_backtrace());
+//! }));
+//! ```
+//!
+//! The arena is a bump allocator whose memory isn't reused, it is meant for the final report of
+//! a crashing process. Once it is used up, allocations fall back to the wrapped allocator. Memory
+//! allocated before entering is still freed through the wrapped allocator. Without `HookArena`
+//! installed as global allocator, [`enter()`] has no effect.
+//!
+//! The panic hook installed by this crate to capture panics for [`spawn_reported()`] enters the
+//! arena by itself.
+//!
+//![`HookArena`]: struct.HookArena.html
+//![`enter()`]: fn.enter.html
+//![`spawn_reported()`]: ../thread/fn.spawn_reported.html
+
+use std::{
+    alloc::{GlobalAlloc, Layout},
+    cell::{Cell, UnsafeCell},
+    marker::PhantomData,
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+thread_local! {
+    /// The number of active `enter()` scopes of the thread.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Makes the allocations of the current thread use the arena until the returned guard is
+/// dropped. Scopes can be nested.
+pub fn enter() -> ArenaScope {
+    DEPTH.with(|depth| depth.set(depth.get() + 1));
+    ArenaScope {
+        _not_send: PhantomData,
+    }
+}
+
+/// Leaves the arena scope entered with [`enter()`] when dropped.
+///
+///[`enter()`]: fn.enter.html
+#[derive(Debug)]
+#[must_use = "the arena is only used while the scope is alive"]
+pub struct ArenaScope {
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for ArenaScope {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// Whether the current thread is inside of an arena scope.
+fn in_scope() -> bool {
+    // The thread local is gone while the thread is torn down, then nothing is in scope.
+    DEPTH.try_with(|depth| depth.get() > 0).unwrap_or(false)
+}
+
+/// A global allocator serving the allocations inside of [`enter()`] from an arena of `N` bytes,
+/// see the [module docs].
+///
+///[`enter()`]: fn.enter.html
+///[module docs]: index.html
+pub struct HookArena<A, const N: usize> {
+    inner: A,
+    memory: UnsafeCell<Memory<N>>,
+    /// The offset of the unused part of the memory.
+    next: AtomicUsize,
+}
+
+/// The memory of the arena, aligned for the usual allocations.
+#[repr(align(16))]
+struct Memory<const N: usize>([u8; N]);
+
+// SAFETY: The memory is only handed out in disjoint parts, claimed atomically through `next`.
+unsafe impl<A: Sync, const N: usize> Sync for HookArena<A, N> {}
+
+impl<A, const N: usize> HookArena<A, N> {
+    /// Wraps the allocator `inner`, reserving an arena of `N` bytes.
+    pub const fn new(inner: A) -> Self {
+        HookArena {
+            inner,
+            memory: UnsafeCell::new(Memory([0; N])),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of bytes of the arena which were handed out so far.
+    pub fn used(&self) -> usize {
+        self.next.load(Ordering::Relaxed)
+    }
+
+    fn contains(&self, ptr: *mut u8) -> bool {
+        let start = self.memory.get() as usize;
+        (start..start + N).contains(&(ptr as usize))
+    }
+
+    /// Claims memory for `layout` from the arena, null if it doesn't fit.
+    fn bump(&self, layout: Layout) -> *mut u8 {
+        let start = self.memory.get() as usize;
+        let mut next = self.next.load(Ordering::Relaxed);
+        loop {
+            let aligned = match (start + next).checked_next_multiple_of(layout.align()) {
+                Some(aligned) => aligned - start,
+                None => return ptr::null_mut(),
+            };
+            let end = match aligned.checked_add(layout.size()) {
+                Some(end) if end <= N => end,
+                _ => return ptr::null_mut(),
+            };
+            match self
+                .next
+                .compare_exchange_weak(next, end, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                // SAFETY: `aligned..end` lies within the memory and was claimed by this call.
+                Ok(_) => return unsafe { (self.memory.get() as *mut u8).add(aligned) },
+                Err(current) => next = current,
+            }
+        }
+    }
+}
+
+// SAFETY: Arena memory is handed out once and never to the wrapped allocator, all other calls
+// are forwarded to the wrapped allocator.
+unsafe impl<A: GlobalAlloc, const N: usize> GlobalAlloc for HookArena<A, N> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if in_scope() {
+            let ptr = self.bump(layout);
+            if !ptr.is_null() {
+                return ptr;
+            }
+        }
+        self.inner.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if in_scope() {
+            let ptr = self.bump(layout);
+            if !ptr.is_null() {
+                // The memory may have been handed out zeroed, but it is cheap to make sure.
+                ptr::write_bytes(ptr, 0, layout.size());
+                return ptr;
+            }
+        }
+        self.inner.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if !self.contains(ptr) {
+            self.inner.dealloc(ptr, layout)
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if !self.contains(ptr) && !in_scope() {
+            return self.inner.realloc(ptr, layout, new_size);
+        }
+        // SAFETY: The caller guarantees that the new size is a valid size for the alignment.
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{enter, HookArena},
+        std::alloc::{GlobalAlloc, Layout, System},
+    };
+
+    #[test]
+    fn allocates_from_arena_inside_scope() {
+        static ARENA: HookArena<System, 64> = HookArena::new(System);
+        let layout = Layout::from_size_align(24, 8).unwrap();
+        unsafe {
+            let outside = ARENA.alloc(layout);
+            assert!(!ARENA.contains(outside));
+            assert_eq!(ARENA.used(), 0);
+
+            let scope = enter();
+            let first = ARENA.alloc(layout);
+            let second = ARENA.alloc_zeroed(layout);
+            assert!(ARENA.contains(first) && ARENA.contains(second));
+            assert_eq!(first as usize % 8, 0);
+            assert_eq!(second as usize - first as usize, 24);
+            // Used up, so the wrapped allocator takes over.
+            let third = ARENA.alloc(layout);
+            assert!(!ARENA.contains(third));
+
+            first.write(7);
+            let grown = ARENA.realloc(first, layout, 48);
+            assert!(!ARENA.contains(grown));
+            assert_eq!(grown.read(), 7);
+            drop(scope);
+
+            for (ptr, layout) in [(outside, layout), (second, layout), (third, layout)] {
+                ARENA.dealloc(ptr, layout);
+            }
+            ARENA.dealloc(grown, Layout::from_size_align(48, 8).unwrap());
+            assert_eq!(ARENA.used(), 48);
+        }
+    }
+}
@@ -0,0 +1,423 @@
+//! Compact binary crash artifacts for post-mortem tooling.
+//!
+//! A [`CrashArtifact`] holds what the formatted report is made of before the symbols are
+//! resolved: the panic message and location, the modules mapped into the process and the stacks
+//! of the threads as raw addresses. It is written next to the human readable report and can be
+//! read back and rendered later, e.g. by a tool resolving the addresses with the debug info:
+//!
+//! ```
+//! use {
+//!     backtrace_string::{artifact::CrashArtifact, FormatOptions},
+//!     std::panic,
+//! };
+//!
+//! panic::set_hook(Box::new(|info| {
+//!     let mut file = Vec::new();
+//!     CrashArtifact::from_panic(info).write_to(&mut file).unwrap();
+//!     // The file is written somewhere it is found after the crash.
+//!     let artifact = CrashArtifact::read_from(&mut &file[..]).unwrap();
+//!     eprintln!("{}", artifact.render(&FormatOptions::new()));
+//! }));
+//! ```
+//!
+//! # Format
+//!
+//! All integers are little endian, strings are a `u32` length followed by as many bytes of UTF-8
+//! and optional values are a `u8` of `0` (absent) or `1` followed by the value.
+//!
+//! | Field    | Encoding                                                                 |
+//! |----------|--------------------------------------------------------------------------|
+//! | magic    | the 4 bytes `BTSA`                                                       |
+//! | version  | `u32`, currently `1`                                                     |
+//! | message  | string                                                                   |
+//! | location | optional string (file), `u32` (line), `u32` (column)                     |
+//! | modules  | `u32` count, each `u64` start, `u64` end, `u64` file offset, string path |
+//! | threads  | `u32` count, each optional string (name), `u32` count, `u64` addresses   |
+//!
+//! The addresses of a thread start with the most recent call. Except for the first one they are
+//! return addresses, i.e. point after the call instruction.
+//!
+//![`CrashArtifact`]: struct.CrashArtifact.html
+
+use {
+    crate::{
+        capture_below, format_frames,
+        frame::{ResolvedFrame, ResolvedSymbol},
+        module, payload, FormatOptions,
+    },
+    std::{
+        convert::TryFrom,
+        fmt::Write as _,
+        io::{self, Read, Write},
+        panic::PanicHookInfo,
+        path::PathBuf,
+        thread,
+    },
+};
+
+const MAGIC: &[u8; 4] = b"BTSA";
+const VERSION: u32 = 1;
+
+/// The panic, modules and thread stacks of a crash, see the [module documentation](index.html).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct CrashArtifact {
+    /// The panic message.
+    pub message: String,
+    /// The file, line and column where the panic occurred.
+    pub location: Option<(String, u32, u32)>,
+    /// The modules mapped into the process, currently only known on Linux.
+    pub modules: Vec<ArtifactModule>,
+    /// The stacks of the threads, the crashing thread first.
+    pub threads: Vec<ThreadStack>,
+}
+
+/// A module mapped into the crashed process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtifactModule {
+    /// The first address of the mapping.
+    pub start: u64,
+    /// The address after the mapping.
+    pub end: u64,
+    /// The offset of the mapping in the file.
+    pub file_offset: u64,
+    /// The path of the file.
+    pub path: PathBuf,
+}
+
+/// The stack of a thread as raw addresses.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ThreadStack {
+    /// The name of the thread.
+    pub name: Option<String>,
+    /// The addresses of the frames, the most recent call first.
+    pub addresses: Vec<u64>,
+}
+
+impl CrashArtifact {
+    /// Captures the stack of the calling thread and the modules, with the given message.
+    #[inline(never)]
+    pub fn capture(message: &str) -> Self {
+        let stack = capture_current(Self::capture as *const () as usize);
+        CrashArtifact {
+            message: message.to_owned(),
+            location: None,
+            modules: capture_modules(),
+            threads: vec![stack],
+        }
+    }
+
+    /// Captures the artifact of a panic from inside the panic hook.
+    #[inline(never)]
+    pub fn from_panic(info: &PanicHookInfo) -> Self {
+        let stack = capture_current(Self::from_panic as *const () as usize);
+        CrashArtifact {
+            message: payload::describe(info.payload()),
+            location: info
+                .location()
+                .map(|loc| (loc.file().to_owned(), loc.line(), loc.column())),
+            modules: capture_modules(),
+            threads: vec![stack],
+        }
+    }
+
+    /// Adds the stacks of the other threads of the process, see [`watchdog`].
+    ///
+    /// Threads which don't handle the signal within `timeout` are left out.
+    ///
+    ///[`watchdog`]: ../watchdog/index.html
+    #[cfg(target_os = "linux")]
+    pub fn capture_other_threads(&mut self, timeout: std::time::Duration) -> io::Result<()> {
+        use crate::watchdog;
+
+        let current = watchdog::current_thread_id();
+        for id in watchdog::thread_ids()? {
+            if id == current {
+                continue;
+            }
+            if let Ok(ips) = watchdog::capture_thread(id, timeout) {
+                let name = std::fs::read_to_string(format!("/proc/self/task/{}/comm", id)).ok();
+                self.threads.push(ThreadStack {
+                    name: name.map(|name| name.trim_end().to_owned()),
+                    addresses: ips.into_iter().map(|ip| ip as u64).collect(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the artifact in the binary format.
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        write_u32(out, VERSION)?;
+        write_str(out, &self.message)?;
+        match &self.location {
+            Some((file, line, column)) => {
+                out.write_all(&[1])?;
+                write_str(out, file)?;
+                write_u32(out, *line)?;
+                write_u32(out, *column)?;
+            }
+            None => out.write_all(&[0])?,
+        }
+        write_len(out, self.modules.len())?;
+        for module in &self.modules {
+            write_u64(out, module.start)?;
+            write_u64(out, module.end)?;
+            write_u64(out, module.file_offset)?;
+            write_str(out, &module.path.to_string_lossy())?;
+        }
+        write_len(out, self.threads.len())?;
+        for thread in &self.threads {
+            match &thread.name {
+                Some(name) => {
+                    out.write_all(&[1])?;
+                    write_str(out, name)?;
+                }
+                None => out.write_all(&[0])?,
+            }
+            write_len(out, thread.addresses.len())?;
+            for &address in &thread.addresses {
+                write_u64(out, address)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads an artifact written by [`write_to()`].
+    ///
+    /// Fails with `ErrorKind::InvalidData` if the data isn't an artifact of a known version.
+    ///
+    ///[`write_to()`]: #method.write_to
+    pub fn read_from(input: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a crash artifact"));
+        }
+        if read_u32(input)? != VERSION {
+            return Err(invalid_data("unknown version of the crash artifact"));
+        }
+        let message = read_string(input)?;
+        let location = if read_flag(input)? {
+            Some((read_string(input)?, read_u32(input)?, read_u32(input)?))
+        } else {
+            None
+        };
+        let mut modules = Vec::new();
+        for _ in 0..read_u32(input)? {
+            modules.push(ArtifactModule {
+                start: read_u64(input)?,
+                end: read_u64(input)?,
+                file_offset: read_u64(input)?,
+                path: PathBuf::from(read_string(input)?),
+            });
+        }
+        let mut threads = Vec::new();
+        for _ in 0..read_u32(input)? {
+            let name = if read_flag(input)? {
+                Some(read_string(input)?)
+            } else {
+                None
+            };
+            let mut addresses = Vec::new();
+            for _ in 0..read_u32(input)? {
+                addresses.push(read_u64(input)?);
+            }
+            threads.push(ThreadStack { name, addresses });
+        }
+        Ok(CrashArtifact {
+            message,
+            location,
+            modules,
+            threads,
+        })
+    }
+
+    /// Renders the artifact as a report, the frames showing their module and offset like
+    /// [`UnknownSymbols::ModuleOffset`].
+    ///
+    /// The addresses aren't resolved, as the process they belong to is gone.
+    ///
+    ///[`UnknownSymbols::ModuleOffset`]: ../enum.UnknownSymbols.html#variant.ModuleOffset
+    pub fn render(&self, options: &FormatOptions) -> String {
+        let vocabulary = &options.vocabulary;
+        let mut out = match &self.location {
+            Some((file, line, column)) => format!(
+                "{} {} {}:{}:{}: {}\n",
+                vocabulary.panicked, vocabulary.at, file, line, column, self.message
+            ),
+            None => format!("{}: {}\n", vocabulary.panicked, self.message),
+        };
+        for (index, thread) in self.threads.iter().enumerate() {
+            let frames = thread
+                .addresses
+                .iter()
+                .map(|&address| ResolvedFrame {
+                    ip: address as usize,
+                    symbols: self
+                        .module_offset(address)
+                        .map(|name| ResolvedSymbol {
+                            name: Some(name),
+                            ..ResolvedSymbol::default()
+                        })
+                        .into_iter()
+                        .collect(),
+                })
+                .collect::<Vec<_>>();
+            let name = thread.name.as_deref().unwrap_or("<unnamed>");
+            write!(out, "thread {} '{}':", index, name).unwrap();
+            out.push_str(&format_frames(&frames, options));
+        }
+        out
+    }
+
+    /// Returns `module+0xoffset` for an address within the modules.
+    fn module_offset(&self, address: u64) -> Option<String> {
+        let module = self
+            .modules
+            .iter()
+            .find(|module| module.start <= address && address < module.end)?;
+        let file = module.path.file_name().unwrap_or(module.path.as_os_str());
+        let offset = address - module.start + module.file_offset;
+        Some(format!("{}+{:#x}", file.to_string_lossy(), offset))
+    }
+}
+
+/// Captures the addresses of the calling thread below the function at `ip`.
+fn capture_current(ip: usize) -> ThreadStack {
+    ThreadStack {
+        name: thread::current().name().map(str::to_owned),
+        addresses: capture_below(ip)
+            .frames()
+            .iter()
+            .map(|frame| frame.ip() as u64)
+            .collect(),
+    }
+}
+
+fn capture_modules() -> Vec<ArtifactModule> {
+    module::load()
+        .into_iter()
+        .map(|module| ArtifactModule {
+            start: module.start as u64,
+            end: module.end as u64,
+            file_offset: module.file_offset as u64,
+            path: module.path,
+        })
+        .collect()
+}
+
+fn write_u32(out: &mut impl Write, value: u32) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_u64(out: &mut impl Write, value: u64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_len(out: &mut impl Write, len: usize) -> io::Result<()> {
+    let len = u32::try_from(len).map_err(|_| invalid_data("too many entries"))?;
+    write_u32(out, len)
+}
+
+fn write_str(out: &mut impl Write, s: &str) -> io::Result<()> {
+    write_len(out, s.len())?;
+    out.write_all(s.as_bytes())
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_flag(input: &mut impl Read) -> io::Result<bool> {
+    let mut byte = [0];
+    input.read_exact(&mut byte)?;
+    match byte[0] {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(invalid_data("invalid optional value")),
+    }
+}
+
+fn read_string(input: &mut impl Read) -> io::Result<String> {
+    let len = read_u32(input)?;
+    let mut bytes = Vec::new();
+    // Reads no more than there is, whatever the length claims.
+    input.take(u64::from(len)).read_to_end(&mut bytes)?;
+    if bytes.len() != len as usize {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(bytes).map_err(|_| invalid_data("string isn't valid UTF-8"))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{ArtifactModule, CrashArtifact, ThreadStack},
+        crate::FormatOptions,
+        std::io,
+    };
+
+    fn artifact() -> CrashArtifact {
+        CrashArtifact {
+            message: String::from("index out of bounds"),
+            location: Some((String::from("src/main.rs"), 3, 5)),
+            modules: vec![ArtifactModule {
+                start: 0x5555_5555_0000,
+                end: 0x5555_5556_0000,
+                file_offset: 0x1000,
+                path: "/usr/bin/my_app".into(),
+            }],
+            threads: vec![ThreadStack {
+                name: Some(String::from("main")),
+                addresses: vec![0x5555_5555_a0b0, 0x7f00_0000_0000],
+            }],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut data = Vec::new();
+        artifact().write_to(&mut data).unwrap();
+        assert_eq!(&data[..8], b"BTSA\x01\0\0\0");
+        assert_eq!(
+            CrashArtifact::read_from(&mut &data[..]).unwrap(),
+            artifact()
+        );
+
+        let err = CrashArtifact::read_from(&mut &data[..data.len() - 1]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        let err = CrashArtifact::read_from(&mut &b"MDMP\x01\0\0\0"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let captured = CrashArtifact::capture("hung");
+        let mut data = Vec::new();
+        captured.write_to(&mut data).unwrap();
+        assert_eq!(CrashArtifact::read_from(&mut &data[..]).unwrap(), captured);
+        assert!(!captured.threads[0].addresses.is_empty());
+    }
+
+    #[test]
+    fn renders_module_offsets() {
+        assert_eq!(
+            artifact().render(&FormatOptions::new()),
+            "panicked at src/main.rs:3:5: index out of bounds\n\
+             thread 0 'main':\n   \
+             0: my_app+0xb0b0\n          at <unknown>\n   \
+             1: <unknown>\n          at address 0x7f0000000000\n"
+        );
+    }
+}
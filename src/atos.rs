@@ -0,0 +1,124 @@
+//! Resolving frames with `atos`, for macOS builds without dSYM bundle.
+//!
+//! The symbolizer of the `backtrace` crate needs the debug info of a dSYM bundle or of the object
+//! files, which are usually gone where a binary runs. `atos` still finds the symbols of the
+//! symbol table and, while the object files exist, the locations of the debug map.
+
+use crate::frame::{ResolvedFrame, ResolvedSymbol};
+
+/// Resolves the frames without symbols with `atos`, leaving them as they are if that fails.
+#[cfg(target_os = "macos")]
+pub(crate) fn resolve_unresolved(frames: &mut [ResolvedFrame]) {
+    use std::{
+        collections::HashMap,
+        ffi::CStr,
+        os::raw::{c_char, c_int, c_void},
+        process::Command,
+        ptr,
+    };
+
+    #[repr(C)]
+    struct DlInfo {
+        dli_fname: *const c_char,
+        dli_fbase: *mut c_void,
+        dli_sname: *const c_char,
+        dli_saddr: *mut c_void,
+    }
+
+    extern "C" {
+        fn dladdr(addr: *const c_void, info: *mut DlInfo) -> c_int;
+    }
+
+    // `atos` resolves the addresses of one image at a time.
+    let mut images = HashMap::<(String, usize), Vec<usize>>::new();
+    for (i, frame) in frames.iter().enumerate() {
+        if frame.symbols.iter().any(|symbol| symbol.name.is_some()) {
+            continue;
+        }
+        let mut info = DlInfo {
+            dli_fname: ptr::null(),
+            dli_fbase: ptr::null_mut(),
+            dli_sname: ptr::null(),
+            dli_saddr: ptr::null_mut(),
+        };
+        // SAFETY: `dladdr` only looks up the address and fills in `info`.
+        if unsafe { dladdr(frame.ip as *const c_void, &mut info) } == 0 || info.dli_fname.is_null()
+        {
+            continue;
+        }
+        // SAFETY: `dladdr` returned the path of the image as nul terminated string.
+        let path = unsafe { CStr::from_ptr(info.dli_fname) };
+        let image = (path.to_string_lossy().into_owned(), info.dli_fbase as usize);
+        images.entry(image).or_default().push(i);
+    }
+
+    for ((path, base), indices) in images {
+        let output = Command::new("atos")
+            .arg("-o")
+            .arg(&path)
+            .arg("-l")
+            .arg(format!("{:#x}", base))
+            .args(indices.iter().map(|&i| format!("{:#x}", frames[i].ip)))
+            .output();
+        let output = match output {
+            Ok(output) if output.status.success() => output,
+            _ => continue,
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        for (&i, line) in indices.iter().zip(stdout.lines()) {
+            if let Some(symbol) = parse_line(line) {
+                frames[i].symbols = vec![symbol];
+            }
+        }
+    }
+}
+
+/// Does nothing, `atos` only exists on macOS.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn resolve_unresolved(_frames: &mut [ResolvedFrame]) {}
+
+/// Parses a line of `atos` output like `my_app::main (in my_app) (main.rs:3)`.
+///
+/// Addresses `atos` couldn't resolve are printed as they are, which gives `None`.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn parse_line(line: &str) -> Option<ResolvedSymbol> {
+    let (name, rest) = line.split_once(" (in ")?;
+    let (_image, rest) = rest.split_once(')')?;
+    let location = rest
+        .trim()
+        .strip_prefix('(')
+        .and_then(|location| location.strip_suffix(')'))
+        .and_then(|location| location.rsplit_once(':'));
+    Some(ResolvedSymbol {
+        name: Some(name.to_owned()),
+        filename: location.map(|(file, _)| file.into()),
+        lineno: location.and_then(|(_, line)| line.parse().ok()),
+        addr: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::parse_line, crate::frame::ResolvedSymbol};
+
+    #[test]
+    fn parse_atos_output() {
+        assert_eq!(
+            parse_line("my_app::main::h0123456789abcdef (in my_app) (main.rs:3)"),
+            Some(ResolvedSymbol {
+                name: Some(String::from("my_app::main::h0123456789abcdef")),
+                filename: Some("main.rs".into()),
+                lineno: Some(3),
+                addr: None,
+            })
+        );
+        assert_eq!(
+            parse_line("_main (in my_app) + 52"),
+            Some(ResolvedSymbol {
+                name: Some(String::from("_main")),
+                ..ResolvedSymbol::default()
+            })
+        );
+        assert_eq!(parse_line("0x100003f50"), None);
+    }
+}
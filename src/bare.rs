@@ -0,0 +1,249 @@
+//! Formatting the panics of `#![no_std]` firmware, built with the `bare-panic` feature.
+//!
+//! Without `std` there is no panic hook and no unwinder to capture a backtrace with, but firmware
+//! usually has a way to walk its stack, e.g. along frame pointers or with an unwinder for its
+//! target. Built without the default `std` feature, the crate only needs `core` and `alloc`.
+//!
+//! Firmware with a `#[panic_handler]` of its own, e.g. from `panic-probe` or `panic-halt`,
+//! formats its panics with [`write_panic()`], which writes the report of a panic with the
+//! instruction pointers such an unwinder reports to any `fmt::Write`. The opt-in
+//! `bare-panic-handler` feature brings a ready-made `#[panic_handler]` instead, which writes the
+//! report to the output registered with [`set_panic_handler()`]:
+//!
+//! ```ignore
+//! use backtrace_string::bare::{self, PanicHandler};
+//!
+//! static PANIC_HANDLER: PanicHandler = PanicHandler {
+//!     write: |text| uart::write(text.as_bytes()),
+//!     unwind: |frame| frame_pointers::walk(|ip| frame(ip)),
+//!     max_frames: 32,
+//!     halt: || cortex_m::peripheral::SCB::sys_reset(),
+//! };
+//!
+//! // At the start of `main`:
+//! bare::set_panic_handler(&PANIC_HANDLER);
+//! ```
+//!
+//! The frames are laid out like the unresolved frames of the formatter of the crate, with their
+//! addresses, which `backtrace-string symbolicate` resolves with the unstripped firmware image:
+//!
+//! ```text
+//! panicked at src/main.rs:42:5: index out of bounds
+//! Backtrace:
+//!    0: <unknown>
+//!           at address 0x8001234
+//!    1: <unknown>
+//!           at address 0x8000abc
+//! ```
+//!
+//! The most recent frames are those of the panic handler and of `core::panicking`, as nothing is
+//! resolved on the device to trim them. Neither the panic handler nor [`write_panic()`]
+//! allocates.
+//!
+//! With `std` the standard library brings the panic handler, so `bare-panic-handler` has no
+//! effect and only [`write_panic()`] and [`write_report()`] are available, e.g. to format the
+//! addresses a fault handler found.
+//!
+//![`set_panic_handler()`]: fn.set_panic_handler.html
+//![`write_panic()`]: fn.write_panic.html
+//![`write_report()`]: fn.write_report.html
+
+#[cfg(all(feature = "bare-panic-handler", not(any(feature = "std", test))))]
+use core::sync::atomic::AtomicBool;
+#[cfg(all(feature = "bare-panic-handler", not(feature = "std")))]
+use core::{
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+use {
+    crate::{
+        layout::{Layout, Style, NUMBER_WIDTH},
+        vocabulary::Vocabulary,
+    },
+    core::{
+        fmt::{self, Write},
+        panic::{Location, PanicInfo},
+    },
+};
+
+/// The output and the unwinder of the `#[panic_handler]`, see the [module docs].
+///
+///[module docs]: index.html
+#[cfg(all(feature = "bare-panic-handler", not(feature = "std")))]
+#[derive(Debug, Clone, Copy)]
+pub struct PanicHandler {
+    /// Writes a part of the report, e.g. to a serial port or a reserved region of memory.
+    pub write: fn(&str),
+    /// Walks the stack from the most recent frame, calling the given function with the
+    /// instruction pointer of each frame until it returns `false`, like `backtrace::trace()`.
+    pub unwind: fn(&mut dyn FnMut(usize) -> bool),
+    /// The maximum number of frames written, the ones after it are only counted.
+    pub max_frames: usize,
+    /// Called after the report is written, e.g. to reset the device or to wait for a debugger.
+    pub halt: fn() -> !,
+}
+
+#[cfg(all(feature = "bare-panic-handler", not(feature = "std")))]
+static HANDLER: AtomicPtr<PanicHandler> = AtomicPtr::new(ptr::null_mut());
+
+/// Set while reporting, as the report may panic itself.
+#[cfg(all(feature = "bare-panic-handler", not(any(feature = "std", test))))]
+static REPORTING: AtomicBool = AtomicBool::new(false);
+
+/// Sets the output and the unwinder of the `#[panic_handler]`, replacing the previous ones.
+///
+/// Without them a panic only halts in an endless loop.
+#[cfg(all(feature = "bare-panic-handler", not(feature = "std")))]
+pub fn set_panic_handler(handler: &'static PanicHandler) {
+    HANDLER.store(handler as *const PanicHandler as *mut _, Ordering::Release);
+}
+
+#[cfg(all(feature = "bare-panic-handler", not(any(feature = "std", test))))]
+#[panic_handler]
+fn panic(info: &PanicInfo<'_>) -> ! {
+    // SAFETY: The pointer is null or comes from the `&'static` passed to `set_panic_handler()`.
+    let handler = match unsafe { HANDLER.load(Ordering::Acquire).as_ref() } {
+        Some(handler) => handler,
+        None => loop {
+            core::hint::spin_loop();
+        },
+    };
+    // Only loads and stores, as targets like `thumbv6m` can't swap atomically.
+    if !REPORTING.load(Ordering::Relaxed) {
+        REPORTING.store(true, Ordering::Relaxed);
+        let mut out = Output(handler.write);
+        let _ = write_panic(&mut out, info, handler.max_frames, handler.unwind);
+    }
+    (handler.halt)()
+}
+
+/// Adapts the `write` function of a [`PanicHandler`] to `fmt::Write`.
+#[cfg(all(feature = "bare-panic-handler", not(any(feature = "std", test))))]
+struct Output(fn(&str));
+
+#[cfg(all(feature = "bare-panic-handler", not(any(feature = "std", test))))]
+impl Write for Output {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        (self.0)(s);
+        Ok(())
+    }
+}
+
+/// Writes the report of the panic `info` to `out`, with up to `max_frames` frames reported by
+/// `unwind`.
+///
+/// `unwind` walks the stack from the most recent frame, calling the given function with the
+/// instruction pointer of each frame until it returns `false`, like `backtrace::trace()`.
+pub fn write_panic(
+    out: &mut impl Write,
+    info: &PanicInfo<'_>,
+    max_frames: usize,
+    unwind: impl FnOnce(&mut dyn FnMut(usize) -> bool),
+) -> fmt::Result {
+    write_report(out, info.message(), info.location(), max_frames, unwind)
+}
+
+/// Like [`write_panic()`] but with the message and location of the panic given separately, e.g.
+/// from a fault handler.
+///
+///[`write_panic()`]: fn.write_panic.html
+pub fn write_report(
+    out: &mut impl Write,
+    message: impl fmt::Display,
+    location: Option<&Location<'_>>,
+    max_frames: usize,
+    unwind: impl FnOnce(&mut dyn FnMut(usize) -> bool),
+) -> fmt::Result {
+    let vocabulary = Vocabulary::default();
+    let layout = Layout {
+        style: Style::Full,
+        width: NUMBER_WIDTH,
+        vocabulary: &vocabulary,
+    };
+    write!(out, "{}", vocabulary.panicked)?;
+    if let Some(location) = location {
+        write!(out, " {} {}", vocabulary.at, location)?;
+    }
+    writeln!(out, ": {}", message)?;
+    writeln!(out, "{}:", vocabulary.backtrace)?;
+    let mut frames = 0;
+    let mut result = Ok(());
+    unwind(&mut |ip| {
+        if frames < max_frames {
+            result = write!(out, "{}:", layout.number(frames))
+                .and_then(|()| layout.write_unresolved(out, ip))
+                .and_then(|()| writeln!(out));
+        }
+        frames += 1;
+        result.is_ok()
+    });
+    result?;
+    if frames > max_frames {
+        layout.write_omitted(out, frames - max_frames)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::write_report, alloc::string::String, core::panic::Location};
+
+    fn unwind(frame: &mut dyn FnMut(usize) -> bool) {
+        for ip in [0x0800_1234, 0x0800_0abc, 0x0800_0100] {
+            if !frame(ip) {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn writes_the_reported_frames() {
+        let mut out = String::new();
+        write_report(&mut out, "overflow", Some(Location::caller()), 2, unwind).unwrap();
+        assert!(out.starts_with("panicked at src/bare.rs:"), "{}", out);
+        assert!(
+            out.ends_with(
+                ": overflow\nBacktrace:\n   \
+                 0: <unknown>\n          at address 0x8001234\n   \
+                 1: <unknown>\n          at address 0x8000abc\n      \
+                 ... 1 more frames omitted\n"
+            ),
+            "{}",
+            out
+        );
+
+        let mut out = String::new();
+        write_report(&mut out, "overflow", None, 8, unwind).unwrap();
+        assert!(
+            out.starts_with("panicked: overflow\nBacktrace:\n"),
+            "{}",
+            out
+        );
+        assert!(
+            out.ends_with("   2: <unknown>\n          at address 0x8000100\n"),
+            "{}",
+            out
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn frames_are_laid_out_like_unresolved_frames() {
+        use crate::{format_frames, frame::ResolvedFrame, ColorChoice, FormatOptions};
+
+        let mut out = String::new();
+        write_report(&mut out, "overflow", None, 2, unwind).unwrap();
+        let frames = [0x0800_1234, 0x0800_0abc, 0x0800_0100]
+            .iter()
+            .map(|&ip| ResolvedFrame {
+                ip,
+                symbols: Vec::new(),
+            })
+            .collect::<Vec<_>>();
+        let options = FormatOptions::new()
+            .color(ColorChoice::Never)
+            .max_frames(Some(2));
+        let expected = format_frames(&frames, &options);
+        assert_eq!(out, format!("panicked: overflow\nBacktrace:{}", expected));
+    }
+}
@@ -0,0 +1,520 @@
+//! The `backtrace-string` command line tool, built with the `cli` feature.
+//!
+//! `backtrace-string fmt` reads backtraces printed by the standard library or this crate, or dumps
+//! of raw addresses, and prints them in the formats of this crate. `backtrace-string symbolicate`
+//! resolves the addresses of backtraces captured without symbols with a local binary.
+//! `backtrace-string cluster` groups many backtraces into clusters of similar crashes.
+
+mod symbolicate;
+
+use {
+    backtrace_string::{
+        cluster::Clusterer,
+        format_frame_source,
+        frame::ResolvedFrame,
+        report::{symbol_name, CrashReport, ThreadTrace},
+        std_trace,
+        stream::StreamRewriter,
+        FormatOptions, Style,
+    },
+    std::{
+        env, fmt,
+        fs::File,
+        io::{self, Read, Write},
+        path::Path,
+        process,
+    },
+};
+
+const USAGE: &str = "\
+Usage: backtrace-string fmt [OPTIONS] [FILE]
+       backtrace-string symbolicate --binary <BINARY> [--base <ADDRESS>] [OPTIONS] [FILE]
+       backtrace-string cluster [--skip-top <N>] [--line-numbers] [OPTIONS] [FILE]...
+
+`fmt` reads backtraces from FILE, or stdin if it is missing or `-`, and prints them formatted.
+
+`symbolicate` reads a backtrace with module offsets like `my_app+0x1a2b3`, as formatted with
+`profile=minimal`, a backtrace with addresses like `at address 0x8001234`, as written by firmware
+with the `bare-panic` feature, or a dump of addresses, one per line, and resolves them with the
+debug info of BINARY, which must be the unstripped build of the binary which captured them.
+
+`cluster` reads the panics or backtraces of all FILEs and prints groups of backtraces with the same
+shown frames, the largest first, with the number of backtraces, a representative backtrace and
+where they were read.
+
+Options:
+      --binary <BINARY>   the binary to resolve addresses with
+      --base <ADDRESS>    the address BINARY was loaded at, if the addresses are runtime ones
+      --skip-top <N>      leaves the N most recent shown frames out of the comparison
+      --line-numbers      also compares the file names and line numbers of the frames
+  -f, --format <FORMAT>   pretty (default), compact, json or folded
+  -o, --options <LIST>    format options as `key=value` list, like BACKTRACE_STRING_FORMAT
+      --filters <LIST>    filter presets to apply, separated by `+`
+      --hide <PREFIX>     hides the symbols starting with PREFIX, can be repeated
+      --max-frames <N>    shows at most N frames
+      --only-my-code      collapses the frames of dependencies
+      --redact-paths      redacts the paths outside of the crate directories
+  -h, --help              prints this help
+";
+
+/// The output formats of `fmt`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Pretty,
+    Compact,
+    Json,
+    Folded,
+}
+
+/// The subcommands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Fmt,
+    Symbolicate,
+    Cluster,
+}
+
+#[derive(Debug)]
+struct Args {
+    format: Format,
+    options: FormatOptions,
+    files: Vec<String>,
+    binary: Option<String>,
+    base: Option<u64>,
+    skip_top: usize,
+    line_numbers: bool,
+}
+
+/// An error of the command line, shown with the usage.
+#[derive(Debug)]
+struct UsageError(String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let result = match args.first().map(String::as_str) {
+        Some("fmt") => parse_args(Command::Fmt, &args[1..]).map(run_fmt),
+        Some("symbolicate") => parse_args(Command::Symbolicate, &args[1..]).map(run_symbolicate),
+        Some("cluster") => parse_args(Command::Cluster, &args[1..]).map(run_cluster),
+        Some("-h") | Some("--help") => {
+            print!("{}", USAGE);
+            return;
+        }
+        Some(command) => Err(UsageError(format!("unknown command `{}`", command))),
+        None => Err(UsageError(String::from("missing command"))),
+    };
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+        Err(err) => {
+            eprint!("error: {}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+/// A change of the options by a flag.
+type Tweak = Box<dyn FnOnce(FormatOptions) -> Result<FormatOptions, UsageError>>;
+
+fn parse_args(command: Command, args: &[String]) -> Result<Args, UsageError> {
+    let mut format = Format::Pretty;
+    let mut options = None;
+    let mut tweaks: Vec<Tweak> = Vec::new();
+    let mut files = Vec::new();
+    let mut binary = None;
+    let mut base = None;
+    let mut skip_top = 0;
+    let mut line_numbers = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| UsageError(format!("missing value of `{}`", arg)))
+        };
+        match arg.as_str() {
+            "-f" | "--format" => {
+                format = match value()?.as_str() {
+                    "pretty" => Format::Pretty,
+                    "compact" => Format::Compact,
+                    "json" => Format::Json,
+                    "folded" => Format::Folded,
+                    other => return Err(UsageError(format!("unknown format `{}`", other))),
+                }
+            }
+            "-o" | "--options" => {
+                let list = value()?;
+                options = Some(
+                    FormatOptions::parse(&list)
+                        .map_err(|err| UsageError(format!("invalid options: {}", err)))?,
+                );
+            }
+            "--binary" if command == Command::Symbolicate => binary = Some(value()?),
+            "--base" if command == Command::Symbolicate => {
+                let address = value()?;
+                let hex = address.strip_prefix("0x").unwrap_or(&address);
+                base = Some(
+                    u64::from_str_radix(hex, 16)
+                        .map_err(|_| UsageError(String::from("invalid value of `--base`")))?,
+                );
+            }
+            "--skip-top" if command == Command::Cluster => {
+                skip_top = value()?
+                    .parse()
+                    .map_err(|_| UsageError(String::from("invalid value of `--skip-top`")))?;
+            }
+            "--line-numbers" if command == Command::Cluster => line_numbers = true,
+            "--filters" => {
+                let list = value()?;
+                tweaks.push(Box::new(move |options: FormatOptions| {
+                    options
+                        .filter_presets(&list)
+                        .map_err(|err| UsageError(err.to_string()))
+                }));
+            }
+            "--hide" => {
+                let prefix = value()?;
+                tweaks.push(Box::new(move |options: FormatOptions| {
+                    Ok(options.hide_symbols_starting_with(prefix))
+                }));
+            }
+            "--max-frames" => {
+                let max = value()?
+                    .parse()
+                    .map_err(|_| UsageError(String::from("invalid value of `--max-frames`")))?;
+                tweaks.push(Box::new(move |options: FormatOptions| {
+                    Ok(options.max_frames(Some(max)))
+                }));
+            }
+            "--only-my-code" => tweaks.push(Box::new(|options: FormatOptions| {
+                Ok(options.only_my_code(true))
+            })),
+            "--redact-paths" => tweaks.push(Box::new(|options: FormatOptions| {
+                Ok(options.redact_paths(true))
+            })),
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            path if !path.starts_with('-') || path == "-" => {
+                if !files.is_empty() && command != Command::Cluster {
+                    return Err(UsageError(String::from("more than one input file")));
+                }
+                files.push(path.to_owned());
+            }
+            other => return Err(UsageError(format!("unknown option `{}`", other))),
+        }
+    }
+    // The flags refine the options, wherever they are given.
+    let mut options = options.unwrap_or_default();
+    for tweak in tweaks {
+        options = tweak(options)?;
+    }
+    if format == Format::Compact {
+        options = options.style(Style::Compact);
+    }
+    if command == Command::Symbolicate && binary.is_none() {
+        return Err(UsageError(String::from("missing `--binary`")));
+    }
+    Ok(Args {
+        format,
+        options,
+        files,
+        binary,
+        base,
+        skip_top,
+        line_numbers,
+    })
+}
+
+fn read_input(args: &Args) -> io::Result<String> {
+    read_file(args.files.first().map(String::as_str))
+}
+
+fn read_file(path: Option<&str>) -> io::Result<String> {
+    let mut text = String::new();
+    match path {
+        None | Some("-") => io::stdin().read_to_string(&mut text)?,
+        Some(path) => File::open(path)?.read_to_string(&mut text)?,
+    };
+    Ok(text)
+}
+
+fn run_fmt(args: Args) -> io::Result<()> {
+    let text = read_input(&args)?;
+    let out = format(&text, args.format, &args.options);
+    io::stdout().write_all(out.as_bytes())
+}
+
+fn run_symbolicate(args: Args) -> io::Result<()> {
+    let text = read_input(&args)?;
+    let binary = symbolicate::Binary::open(Path::new(args.binary.as_deref().unwrap()))?;
+    let frames = symbolicate::symbolicate(&binary, &text, args.base);
+    let out = match args.format {
+        Format::Pretty | Format::Compact => format_frame_source(&frames, &args.options),
+        format => format_reports(&[report_of(frames)], format, &args.options),
+    };
+    io::stdout().write_all(out.as_bytes())
+}
+
+fn run_cluster(args: Args) -> io::Result<()> {
+    let mut traces = Vec::new();
+    let mut sources = Vec::new();
+    let paths = if args.files.is_empty() {
+        vec![String::from("-")]
+    } else {
+        args.files.clone()
+    };
+    for path in &paths {
+        let found = split_traces(&read_file(Some(path))?);
+        let count = found.len();
+        for (i, frames) in found.into_iter().enumerate() {
+            traces.push(frames);
+            sources.push(match count {
+                1 => path.clone(),
+                _ => format!("{}#{}", path, i + 1),
+            });
+        }
+    }
+    let clusterer = Clusterer::new(args.options.clone())
+        .skip_top_frames(args.skip_top)
+        .line_numbers(args.line_numbers);
+    let clusters = clusterer.cluster(&traces);
+    let mut out = String::new();
+    for cluster in &clusters {
+        match args.format {
+            Format::Pretty | Format::Compact => {
+                out.push_str(&clusterer.report(std::slice::from_ref(cluster)));
+                let members = cluster.members.iter().map(|&i| sources[i].as_str());
+                out.push_str(&format!(
+                    "  in {}\n",
+                    members.collect::<Vec<_>>().join(", ")
+                ));
+            }
+            Format::Json => out.push_str(&format!(
+                "{{\"fingerprint\":\"{}\",\"size\":{},\"report\":{}}}\n",
+                cluster.fingerprint,
+                cluster.size(),
+                report_of(cluster.representative.clone()).to_json(&args.options)
+            )),
+            Format::Folded => {
+                let report = report_of(cluster.representative.clone());
+                out.push_str(&folded(&report.threads[0], &args.options));
+                out.push_str(&format!(" {}\n", cluster.size()));
+            }
+        }
+    }
+    io::stdout().write_all(out.as_bytes())
+}
+
+/// Returns the backtraces of the panics in `text`, or of the backtraces starting with frame 0.
+fn split_traces(text: &str) -> Vec<Vec<ResolvedFrame>> {
+    let panics = std_trace::parse(text);
+    if !panics.is_empty() {
+        return panics.into_iter().map(|panic| panic.frames).collect();
+    }
+    let mut traces = Vec::new();
+    let mut current = String::new();
+    for line in text.lines() {
+        if line.trim_start().starts_with("0: ") && !current.is_empty() {
+            traces.push(std_trace::parse_backtrace(&current));
+            current.clear();
+        }
+        current.push_str(line);
+        current.push('\n');
+    }
+    traces.push(std_trace::parse_backtrace(&current));
+    traces.retain(|frames| !frames.is_empty());
+    traces
+}
+
+/// Formats the backtraces in `text`.
+fn format(text: &str, format: Format, options: &FormatOptions) -> String {
+    let addresses = parse_addresses(text);
+    match format {
+        Format::Pretty | Format::Compact => match addresses {
+            Some(frames) => format_frame_source(&frames, options),
+            None => {
+                let mut out = String::new();
+                let mut rewriter = StreamRewriter::new(options.clone());
+                for line in text.lines() {
+                    rewriter.push_line(line, &mut out);
+                }
+                rewriter.finish(&mut out);
+                out
+            }
+        },
+        format => format_reports(&reports(text, addresses), format, options),
+    }
+}
+
+/// Formats reports as JSON, one per line, or their threads as folded stacks.
+fn format_reports(reports: &[CrashReport], format: Format, options: &FormatOptions) -> String {
+    let mut out = String::new();
+    for report in reports {
+        match format {
+            Format::Json => {
+                out.push_str(&report.to_json(options));
+                out.push('\n');
+            }
+            Format::Folded => {
+                for thread in &report.threads {
+                    out.push_str(&folded(thread, options));
+                    out.push_str(" 1\n");
+                }
+            }
+            Format::Pretty | Format::Compact => out.push_str(&report.to_text(options)),
+        }
+    }
+    out
+}
+
+/// Returns the panics in `text` as reports, or a report of its only backtrace.
+fn reports(text: &str, addresses: Option<Vec<ResolvedFrame>>) -> Vec<CrashReport> {
+    let panics = std_trace::parse(text);
+    if !panics.is_empty() {
+        return panics.iter().map(|panic| panic.to_report()).collect();
+    }
+    let frames = addresses.unwrap_or_else(|| std_trace::parse_backtrace(text));
+    vec![report_of(frames)]
+}
+
+/// Returns a report of a single backtrace.
+fn report_of(frames: Vec<ResolvedFrame>) -> CrashReport {
+    let mut thread = ThreadTrace::default();
+    thread.frames = frames;
+    let mut report = CrashReport::default();
+    report.threads.push(thread);
+    report
+}
+
+/// Parses a dump of raw addresses, one per line, e.g. `0x55d1c0a1b2c3`.
+fn parse_addresses(text: &str) -> Option<Vec<ResolvedFrame>> {
+    let mut frames = Vec::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let hex = line.strip_prefix("0x").unwrap_or(line);
+        let ip = usize::from_str_radix(hex, 16).ok()?;
+        frames.push(ResolvedFrame {
+            ip,
+            symbols: Vec::new(),
+        });
+    }
+    if frames.is_empty() {
+        None
+    } else {
+        Some(frames)
+    }
+}
+
+/// Formats the shown frames of `thread` as a stack of flame graph tools, the outermost function
+/// first and separated by `;`.
+fn folded(thread: &ThreadTrace, options: &FormatOptions) -> String {
+    let frames = thread.shown_frames(options).collect::<Vec<_>>();
+    let mut names = Vec::new();
+    for frame in frames.into_iter().rev() {
+        let symbols = frame
+            .symbols
+            .iter()
+            .filter_map(|symbol| symbol.name.as_ref());
+        let count = names.len();
+        names.extend(symbols.rev().map(|name| symbol_name(name, options)));
+        if names.len() == count {
+            // Unresolved frames are told apart by their address.
+            names.push(format!("{:#x}", frame.ip));
+        }
+    }
+    names.join(";")
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{format, parse_args, split_traces, Command, Format},
+        backtrace_string::{Demangling, FormatOptions, Locations},
+    };
+
+    const PANIC: &str = "\
+thread 'main' panicked at src/main.rs:2:5:
+boom
+stack backtrace:
+   0: my_app::parse
+             at ./src/main.rs:2:5
+   1: my_app::main
+             at ./src/main.rs:7:5
+";
+
+    fn options() -> FormatOptions {
+        FormatOptions::new()
+            .demangling(Demangling::WithoutHashes)
+            .locations(Locations::Hidden)
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(
+            format(PANIC, Format::Pretty, &options()),
+            "thread 'main' panicked at src/main.rs:2:5:\nboom\nstack backtrace:\n   \
+             0: my_app::parse\n   1: my_app::main\n"
+        );
+        assert_eq!(
+            format(PANIC, Format::Folded, &options()),
+            "my_app::main;my_app::parse 1\n"
+        );
+        let json = format(PANIC, Format::Json, &options());
+        assert!(json.contains("\"message\":\"boom\""), "{}", json);
+        assert_eq!(
+            format("0x1000\n0x2000\n", Format::Folded, &options()),
+            "0x2000;0x1000 1\n"
+        );
+    }
+
+    #[test]
+    fn parses_arguments() {
+        let args = ["-f", "compact", "--max-frames", "3", "trace.txt"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>();
+        let args = parse_args(Command::Fmt, &args).unwrap();
+        assert_eq!(args.format, Format::Compact);
+        assert_eq!(args.files, ["trace.txt"]);
+        assert!(parse_args(Command::Fmt, &[String::from("--format")]).is_err());
+        assert!(parse_args(Command::Fmt, &[String::from("--unknown")]).is_err());
+
+        let args = ["--binary", "my_app", "--base", "0x55d1c0a00000"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>();
+        assert!(parse_args(Command::Fmt, &args).is_err());
+        let args = parse_args(Command::Symbolicate, &args).unwrap();
+        assert_eq!(args.binary.as_deref(), Some("my_app"));
+        assert_eq!(args.base, Some(0x55d1_c0a0_0000));
+        assert!(parse_args(Command::Symbolicate, &[]).is_err());
+
+        let args = ["--skip-top", "2", "--line-numbers", "a.txt", "b.txt"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>();
+        assert!(parse_args(Command::Fmt, &args).is_err());
+        let args = parse_args(Command::Cluster, &args).unwrap();
+        assert_eq!((args.skip_top, args.line_numbers), (2, true));
+        assert_eq!(args.files, ["a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn splits_traces() {
+        let traces = split_traces(&format!("{}{}", PANIC, PANIC));
+        assert_eq!(traces.len(), 2);
+        assert_eq!(traces[0].len(), 2);
+        let traces = split_traces("   0: my_app::parse\n   1: my_app::main\n   0: my_app::main\n");
+        assert_eq!(traces.len(), 2);
+        assert_eq!(traces[1].len(), 1);
+    }
+}
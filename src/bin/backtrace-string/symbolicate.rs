@@ -0,0 +1,214 @@
+//! Resolving the addresses of offline captures with the debug info of a local binary.
+
+use {
+    crate::parse_addresses,
+    addr2line::Loader,
+    backtrace_string::{
+        frame::{ResolvedFrame, ResolvedSymbol},
+        std_trace,
+    },
+    object::{Object, ObjectSegment},
+    std::{fs, io, path::Path},
+};
+
+/// The debug info and segments of a binary.
+pub(crate) struct Binary {
+    loader: Loader,
+    /// The file name, which module offsets like `my_app+0x1a2b3` are matched against.
+    name: String,
+    /// The file range and the address of each segment.
+    segments: Vec<(u64, u64, u64)>,
+}
+
+impl Binary {
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        let other = |err: &dyn std::fmt::Display| io::Error::other(err.to_string());
+        let data = fs::read(path)?;
+        let file = object::File::parse(&*data).map_err(|err| other(&err))?;
+        let segments = file
+            .segments()
+            .filter_map(|segment| {
+                let (offset, size) = segment.file_range();
+                Some((offset, size, segment.address())).filter(|_| size > 0)
+            })
+            .collect();
+        Ok(Binary {
+            loader: Loader::new(path).map_err(|err| other(&err))?,
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            segments,
+        })
+    }
+
+    /// Returns the address of the file offset `offset`.
+    fn address_of_offset(&self, offset: u64) -> Option<u64> {
+        self.segments
+            .iter()
+            .find(|(start, size, _)| *start <= offset && offset - start < *size)
+            .map(|(start, _, address)| address + (offset - start))
+    }
+
+    /// Returns the symbols at `address`, the innermost inlined function first.
+    fn symbols(&self, address: u64) -> Vec<ResolvedSymbol> {
+        let mut symbols = Vec::new();
+        if let Ok(mut frames) = self.loader.find_frames(address) {
+            while let Ok(Some(frame)) = frames.next() {
+                let name = frame
+                    .function
+                    .as_ref()
+                    .and_then(|function| function.raw_name().ok())
+                    .map(|name| name.into_owned());
+                let location = frame.location.as_ref();
+                symbols.push(ResolvedSymbol {
+                    name,
+                    filename: location.and_then(|location| location.file).map(Into::into),
+                    lineno: location.and_then(|location| location.line),
+                    addr: None,
+                });
+            }
+        }
+        if symbols.iter().all(|symbol| symbol.name.is_none()) {
+            // Without debug info the symbol table still names the function.
+            if let Some(name) = self.loader.find_symbol(address) {
+                symbols = vec![ResolvedSymbol {
+                    name: Some(name.to_owned()),
+                    ..ResolvedSymbol::default()
+                }];
+            }
+        }
+        symbols
+    }
+}
+
+/// Resolves the frames in `text`, either a backtrace with module offsets like `my_app+0x1a2b3`,
+/// as formatted with `FormatOptions::minimal()`, a backtrace with the addresses of unresolved
+/// frames like `at address 0x8001234`, as written by the `bare` module, or a dump of addresses,
+/// one per line.
+///
+/// The addresses are taken as addresses of the binary, or, with `base`, the address the binary
+/// was loaded at, as runtime addresses. Frames of other modules are left as they are.
+pub(crate) fn symbolicate(binary: &Binary, text: &str, base: Option<u64>) -> Vec<ResolvedFrame> {
+    let address = |ip: usize| match base {
+        Some(base) => (ip as u64)
+            .wrapping_sub(base)
+            .wrapping_add(binary.loader.relative_address_base()),
+        None => ip as u64,
+    };
+    let mut frames = match parse_addresses(text) {
+        Some(frames) => frames
+            .into_iter()
+            .map(|frame| {
+                let address = address(frame.ip);
+                (frame, Some(address))
+            })
+            .collect::<Vec<_>>(),
+        None => std_trace::parse_backtrace(text)
+            .into_iter()
+            .map(|frame| {
+                let unresolved = frame.symbols.iter().all(|symbol| symbol.name.is_none());
+                let address = match module_offset(&frame, &binary.name) {
+                    Some(offset) => binary.address_of_offset(offset),
+                    None if unresolved && frame.ip != 0 => Some(address(frame.ip)),
+                    None => None,
+                };
+                (frame, address)
+            })
+            .collect(),
+    };
+    for (i, (frame, address)) in frames.iter_mut().enumerate() {
+        let address = match *address {
+            Some(address) => address,
+            None => continue,
+        };
+        // Except for the first frame, the addresses are return addresses after the call.
+        let probe = match i {
+            0 => address,
+            _ => address.saturating_sub(1),
+        };
+        let symbols = binary.symbols(probe);
+        if !symbols.is_empty() {
+            frame.symbols = symbols;
+        }
+    }
+    frames.into_iter().map(|(frame, _)| frame).collect()
+}
+
+/// Returns the offset of a frame named like `my_app+0x1a2b3` if `my_app` is `module`.
+fn module_offset(frame: &ResolvedFrame, module: &str) -> Option<u64> {
+    let name = frame.symbols.first()?.name.as_deref()?;
+    let (name, offset) = name.rsplit_once("+0x")?;
+    if name == module {
+        u64::from_str_radix(offset, 16).ok()
+    } else {
+        None
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use {
+        super::{module_offset, symbolicate, Binary},
+        backtrace::Backtrace,
+        backtrace_string::{
+            format_backtrace_with, frame::ResolvedFrame, report::symbol_name, std_trace,
+            Demangling, FormatOptions,
+        },
+        std::{env, fmt::Write},
+    };
+
+    #[inline(never)]
+    fn capture_offline() -> String {
+        format_backtrace_with(&mut Backtrace::new_unresolved(), &FormatOptions::minimal())
+    }
+
+    fn names(frames: &[ResolvedFrame]) -> Vec<String> {
+        let options = FormatOptions::new().demangling(Demangling::WithoutHashes);
+        frames
+            .iter()
+            .flat_map(|frame| &frame.symbols)
+            .filter_map(|symbol| symbol.name.as_deref())
+            .map(|name| symbol_name(name, &options))
+            .collect()
+    }
+
+    #[test]
+    fn resolves_module_offsets() {
+        let text = capture_offline();
+        let binary = Binary::open(&env::current_exe().unwrap()).unwrap();
+        assert!(text.contains(&format!("{}+0x", binary.name)), "{}", text);
+        let names = names(&symbolicate(&binary, &text, None));
+        assert!(
+            names
+                .iter()
+                .any(|name| name.ends_with("tests::capture_offline")),
+            "{:?}",
+            names
+        );
+    }
+
+    #[test]
+    fn resolves_addresses_of_unresolved_frames() {
+        let binary = Binary::open(&env::current_exe().unwrap()).unwrap();
+        let addresses = std_trace::parse_backtrace(&capture_offline())
+            .iter()
+            .filter_map(|frame| module_offset(frame, &binary.name))
+            .filter_map(|offset| binary.address_of_offset(offset))
+            .collect::<Vec<_>>();
+        // Laid out like the reports of the `bare` module.
+        let mut text = String::from("panicked: boom\nBacktrace:\n");
+        for (i, address) in addresses.iter().enumerate() {
+            writeln!(text, "{:4}: <unknown>", i).unwrap();
+            writeln!(text, "          at address {:#x}", address).unwrap();
+        }
+        let names = names(&symbolicate(&binary, &text, None));
+        assert!(
+            names
+                .iter()
+                .any(|name| name.ends_with("tests::capture_offline")),
+            "{:?}",
+            names
+        );
+    }
+}
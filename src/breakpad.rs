@@ -0,0 +1,461 @@
+//! Resolving frames with Breakpad symbol files, built with the `breakpad` feature.
+//!
+//! Symbol files produced by `dump_syms` hold the functions, lines and inlined calls of a module
+//! as text. A [`SymbolStore`] reads them from a symbol directory laid out like the stores of
+//! Breakpad and Crashpad, `<dir>/<module>/<debug id>/<module>.sym`, and resolves the frames of
+//! backtraces captured without symbols, which show their module and offset like
+//! `my_app+0x1a2b3` (see [`FormatOptions::minimal()`]):
+//!
+//! ```no_run
+//! use backtrace_string::{breakpad::SymbolStore, format_frame_source, std_trace, FormatOptions};
+//!
+//! let captured = std::fs::read_to_string("crash.txt")?;
+//! let mut frames = std_trace::parse_backtrace(&captured);
+//! SymbolStore::new("symbols").symbolicate(&mut frames);
+//! println!("{}", format_frame_source(&frames, &FormatOptions::new()));
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! The offsets are taken as addresses relative to the module's base, as used by Breakpad. They
+//! are the same for the usual binaries, whose code is mapped from the file at the addresses of
+//! the file offsets.
+//!
+//![`SymbolStore`]: struct.SymbolStore.html
+//![`FormatOptions::minimal()`]: ../struct.FormatOptions.html#method.minimal
+
+use {
+    crate::frame::{ResolvedFrame, ResolvedSymbol},
+    std::{
+        collections::HashMap,
+        fs, io,
+        path::{Path, PathBuf},
+    },
+};
+
+/// The parsed contents of a Breakpad symbol file.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolFile {
+    debug_id: String,
+    name: String,
+    files: HashMap<u32, String>,
+    inline_origins: HashMap<u32, String>,
+    /// Sorted by address.
+    functions: Vec<Function>,
+    /// The address and name of the public symbols, sorted by address.
+    publics: Vec<(u64, String)>,
+}
+
+#[derive(Debug, Clone)]
+struct Function {
+    address: u64,
+    size: u64,
+    name: String,
+    lines: Vec<Line>,
+    inlines: Vec<Inline>,
+}
+
+#[derive(Debug, Clone)]
+struct Line {
+    address: u64,
+    size: u64,
+    line: u32,
+    file: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Inline {
+    depth: u32,
+    call_line: u32,
+    call_file: u32,
+    origin: u32,
+    /// The address and size of each range.
+    ranges: Vec<(u64, u64)>,
+}
+
+impl SymbolFile {
+    /// Reads and parses the symbol file at `path`.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses the text of a symbol file.
+    ///
+    /// Fails with `ErrorKind::InvalidData` if a record is malformed. Stack unwinding records and
+    /// records unknown to this parser are skipped.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut file = SymbolFile::default();
+        for (i, line) in text.lines().enumerate() {
+            file.parse_record(line.trim_end()).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("malformed symbol file record in line {}: {}", i + 1, line),
+                )
+            })?;
+        }
+        file.functions.sort_by_key(|function| function.address);
+        file.publics.sort_by_key(|(address, _)| *address);
+        Ok(file)
+    }
+
+    fn parse_record(&mut self, line: &str) -> Option<()> {
+        let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+        match kind {
+            "MODULE" => {
+                // MODULE <os> <arch> <debug id> <name>
+                let mut fields = rest.splitn(4, ' ');
+                let (_os, _arch) = (fields.next()?, fields.next()?);
+                self.debug_id = fields.next()?.to_owned();
+                self.name = fields.next()?.to_owned();
+            }
+            "FILE" => {
+                let (number, name) = rest.split_once(' ')?;
+                self.files.insert(number.parse().ok()?, name.to_owned());
+            }
+            "INLINE_ORIGIN" => {
+                let (number, name) = rest.split_once(' ')?;
+                self.inline_origins
+                    .insert(number.parse().ok()?, name.to_owned());
+            }
+            "FUNC" => {
+                // FUNC [m] <address> <size> <parameter size> <name>
+                let rest = rest.strip_prefix("m ").unwrap_or(rest);
+                let mut fields = rest.splitn(4, ' ');
+                let address = hex(fields.next()?)?;
+                let size = hex(fields.next()?)?;
+                let _parameter_size = hex(fields.next()?)?;
+                self.functions.push(Function {
+                    address,
+                    size,
+                    name: fields.next()?.to_owned(),
+                    lines: Vec::new(),
+                    inlines: Vec::new(),
+                });
+            }
+            "PUBLIC" => {
+                // PUBLIC [m] <address> <parameter size> <name>
+                let rest = rest.strip_prefix("m ").unwrap_or(rest);
+                let mut fields = rest.splitn(3, ' ');
+                let address = hex(fields.next()?)?;
+                let _parameter_size = hex(fields.next()?)?;
+                self.publics.push((address, fields.next()?.to_owned()));
+            }
+            "INLINE" => {
+                // INLINE <depth> <call line> <call file> <origin> (<address> <size>)+
+                let mut fields = rest.split(' ');
+                let depth = fields.next()?.parse().ok()?;
+                let call_line = fields.next()?.parse().ok()?;
+                let call_file = fields.next()?.parse().ok()?;
+                let origin = fields.next()?.parse().ok()?;
+                let mut ranges = Vec::new();
+                while let Some(address) = fields.next() {
+                    ranges.push((hex(address)?, hex(fields.next()?)?));
+                }
+                self.functions.last_mut()?.inlines.push(Inline {
+                    depth,
+                    call_line,
+                    call_file,
+                    origin,
+                    ranges,
+                });
+            }
+            "STACK" | "INFO" | "" => {}
+            _ if kind.chars().all(|c| c.is_ascii_hexdigit()) => {
+                // <address> <size> <line> <file>, a line record of the last function.
+                let mut fields = line.split(' ');
+                let address = hex(fields.next()?)?;
+                let size = hex(fields.next()?)?;
+                let line = fields.next()?.parse().ok()?;
+                let file = fields.next()?.parse().ok()?;
+                self.functions.last_mut()?.lines.push(Line {
+                    address,
+                    size,
+                    line,
+                    file,
+                });
+            }
+            _ => {}
+        }
+        Some(())
+    }
+
+    /// Returns the debug id of the module, the directory name of the file in a symbol store.
+    pub fn debug_id(&self) -> &str {
+        &self.debug_id
+    }
+
+    /// Returns the file name of the module.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the symbols at `address`, relative to the module's base, the innermost inlined
+    /// function first. Addresses outside of all functions are named after the preceding public
+    /// symbol, if any.
+    pub fn symbols(&self, address: u64) -> Vec<ResolvedSymbol> {
+        let index = self
+            .functions
+            .partition_point(|function| function.address <= address);
+        let function = index
+            .checked_sub(1)
+            .map(|i| &self.functions[i])
+            .filter(|function| address - function.address < function.size.max(1));
+        let function = match function {
+            Some(function) => function,
+            None => {
+                let index = self.publics.partition_point(|(start, _)| *start <= address);
+                return match index.checked_sub(1) {
+                    Some(i) => vec![ResolvedSymbol {
+                        name: Some(self.publics[i].1.clone()),
+                        ..ResolvedSymbol::default()
+                    }],
+                    None => Vec::new(),
+                };
+            }
+        };
+
+        let line = function
+            .lines
+            .iter()
+            .find(|line| line.address <= address && address - line.address < line.size);
+        let mut inlines: Vec<_> = function
+            .inlines
+            .iter()
+            .filter(|inline| {
+                inline
+                    .ranges
+                    .iter()
+                    .any(|(start, size)| *start <= address && address - start < *size)
+            })
+            .collect();
+        inlines.sort_by_key(|inline| inline.depth);
+
+        // Each inlined call names the location in its caller, the line record the innermost one.
+        let mut symbols = Vec::with_capacity(inlines.len() + 1);
+        let mut location = (line.map(|line| line.file), line.map(|line| line.line));
+        for inline in inlines.iter().rev() {
+            symbols.push(self.symbol(self.inline_origins.get(&inline.origin), location));
+            location = (Some(inline.call_file), Some(inline.call_line));
+        }
+        symbols.push(self.symbol(Some(&function.name), location));
+        symbols
+    }
+
+    fn symbol(
+        &self,
+        name: Option<&String>,
+        (file, line): (Option<u32>, Option<u32>),
+    ) -> ResolvedSymbol {
+        ResolvedSymbol {
+            name: name.cloned(),
+            filename: file
+                .and_then(|file| self.files.get(&file))
+                .map(PathBuf::from),
+            lineno: line.filter(|line| *line > 0),
+            addr: None,
+        }
+    }
+}
+
+/// A directory of Breakpad symbol files, see the [module docs].
+///
+///[module docs]: index.html
+#[derive(Debug, Clone)]
+pub struct SymbolStore {
+    dir: PathBuf,
+    debug_ids: HashMap<String, String>,
+}
+
+impl SymbolStore {
+    /// Creates a store reading the symbol files from `dir`.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        SymbolStore {
+            dir: dir.into(),
+            debug_ids: HashMap::new(),
+        }
+    }
+
+    /// Sets the debug id of the symbol file used for `module`. Without one, the store must hold a
+    /// single symbol file of the module.
+    pub fn debug_id(mut self, module: impl Into<String>, id: impl Into<String>) -> Self {
+        self.debug_ids.insert(module.into(), id.into());
+        self
+    }
+
+    /// Reads the symbol file of `module` with the debug id `id`.
+    pub fn load(&self, module: &str, id: &str) -> io::Result<SymbolFile> {
+        SymbolFile::open(
+            self.dir
+                .join(module)
+                .join(id)
+                .join(format!("{}.sym", module.trim_end_matches(".pdb"))),
+        )
+    }
+
+    /// Reads the symbol file used for `module`, see [`debug_id()`].
+    ///
+    /// Fails with `ErrorKind::NotFound` if there is none, and with `ErrorKind::InvalidInput` if
+    /// there are several and no debug id was set.
+    ///
+    ///[`debug_id()`]: #method.debug_id
+    pub fn load_module(&self, module: &str) -> io::Result<SymbolFile> {
+        if let Some(id) = self.debug_ids.get(module) {
+            return self.load(module, id);
+        }
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(self.dir.join(module))? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                ids.push(entry.file_name());
+            }
+        }
+        match &*ids {
+            [id] => self.load(module, &id.to_string_lossy()),
+            [] => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no symbol file of {}", module),
+            )),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "several symbol files of {}, the debug id must be set",
+                    module
+                ),
+            )),
+        }
+    }
+
+    /// Resolves the frames named by their module and offset like `my_app+0x1a2b3`, the most
+    /// recent call first. Frames of modules without a symbol file, or whose offset isn't covered
+    /// by it, are left as they are.
+    pub fn symbolicate(&self, frames: &mut [ResolvedFrame]) {
+        let mut files: HashMap<String, Option<SymbolFile>> = HashMap::new();
+        for (i, frame) in frames.iter_mut().enumerate() {
+            let (module, offset) = match module_offset(frame) {
+                Some(module_offset) => module_offset,
+                None => continue,
+            };
+            let file = files
+                .entry(module.clone())
+                .or_insert_with(|| self.load_module(&module).ok());
+            let file = match file {
+                Some(file) => file,
+                None => continue,
+            };
+            // Except for the first frame, the offsets are of return addresses after the call.
+            let probe = match i {
+                0 => offset,
+                _ => offset.saturating_sub(1),
+            };
+            let symbols = file.symbols(probe);
+            if !symbols.is_empty() {
+                frame.symbols = symbols;
+            }
+        }
+    }
+}
+
+/// Returns the module and offset of a frame named like `my_app+0x1a2b3`.
+fn module_offset(frame: &ResolvedFrame) -> Option<(String, u64)> {
+    let name = frame.symbols.first()?.name.as_deref()?;
+    let (module, offset) = name.rsplit_once("+0x")?;
+    Some((module.to_owned(), hex(offset)?))
+}
+
+fn hex(s: &str) -> Option<u64> {
+    u64::from_str_radix(s, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{SymbolFile, SymbolStore},
+        crate::frame::{ResolvedFrame, ResolvedSymbol},
+        std::{fs, path::PathBuf, process},
+    };
+
+    const SYMBOLS: &str = "\
+MODULE Linux x86_64 0123456789ABCDEF0123456789ABCDEF0 my_app
+INFO CODE_ID 89ABCDEF
+FILE 0 src/main.rs
+FILE 1 src/parse.rs
+INLINE_ORIGIN 0 my_app::parse::digit
+FUNC 1000 40 0 my_app::parse
+INLINE 0 12 1 0 1010 10
+1000 10 10 1
+1010 10 3 1
+1020 20 14 1
+FUNC m 2000 30 0 my_app::main
+2000 30 5 0
+PUBLIC 3000 0 my_app_exported
+STACK CFI INIT 1000 40 .cfa: $rsp 8 +
+";
+
+    fn symbol(name: &str, file: &str, line: u32) -> ResolvedSymbol {
+        ResolvedSymbol {
+            name: Some(name.to_owned()),
+            filename: Some(PathBuf::from(file)),
+            lineno: Some(line),
+            addr: None,
+        }
+    }
+
+    #[test]
+    fn resolves_inlined_calls() {
+        let file = SymbolFile::parse(SYMBOLS).unwrap();
+        assert_eq!(file.name(), "my_app");
+        assert_eq!(file.debug_id(), "0123456789ABCDEF0123456789ABCDEF0");
+        assert_eq!(
+            file.symbols(0x1004),
+            [symbol("my_app::parse", "src/parse.rs", 10)]
+        );
+        assert_eq!(
+            file.symbols(0x1018),
+            [
+                symbol("my_app::parse::digit", "src/parse.rs", 3),
+                symbol("my_app::parse", "src/parse.rs", 12),
+            ]
+        );
+        assert_eq!(
+            file.symbols(0x3010),
+            [ResolvedSymbol {
+                name: Some("my_app_exported".to_owned()),
+                ..ResolvedSymbol::default()
+            }]
+        );
+        assert_eq!(file.symbols(0x10), []);
+        assert!(SymbolFile::parse("FUNC zz 1 0 f").is_err());
+    }
+
+    #[test]
+    fn symbolicates_from_store() {
+        let dir = std::env::temp_dir().join(format!("backtrace-string-breakpad-{}", process::id()));
+        let module_dir = dir.join("my_app").join("0123456789ABCDEF0123456789ABCDEF0");
+        fs::create_dir_all(&module_dir).unwrap();
+        fs::write(module_dir.join("my_app.sym"), SYMBOLS).unwrap();
+
+        let frame = |name: &str| ResolvedFrame {
+            ip: 0,
+            symbols: vec![ResolvedSymbol {
+                name: Some(name.to_owned()),
+                ..ResolvedSymbol::default()
+            }],
+        };
+        let mut frames = vec![
+            frame("my_app+0x1004"),
+            frame("my_app+0x2010"),
+            frame("libc.so.6+0x29d90"),
+        ];
+        SymbolStore::new(&dir).symbolicate(&mut frames);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            frames[0].symbols,
+            [symbol("my_app::parse", "src/parse.rs", 10)]
+        );
+        assert_eq!(
+            frames[1].symbols,
+            [symbol("my_app::main", "src/main.rs", 5)]
+        );
+        assert_eq!(frames[2], frame("libc.so.6+0x29d90"));
+    }
+}
@@ -0,0 +1,337 @@
+//! Capturing and formatting backtraces into storage allocated up front.
+//!
+//! A panic hook which allocates is likely to abort the process if the panic is caused by memory
+//! exhaustion. A [`BacktraceBuffer`] is created at startup and reused by the hook, capturing into
+//! its pre-allocated frame storage and formatting into its pre-allocated output:
+//!
+//! ```
+//! use {backtrace_string::BacktraceBuffer, std::sync::Mutex};
+//!
+//! let buffer = Mutex::new(BacktraceBuffer::new(64, 16 * 1024));
+//! std::panic::set_hook(Box::new(move |_| {
+//!     if let Ok(mut buffer) = buffer.try_lock() {
+//!         eprintln!("Backtrace:\n{}", buffer.capture_and_format());
+//!     }
+//! }));
+//! ```
+//!
+//! Capturing and formatting unresolved frames never allocates. Resolving symbols uses the
+//! symbolizer of the `backtrace` crate, which caches the debug info it parsed; [`new()`] warms
+//! these caches up, but the first lookups in so far unused parts of the debug info may still
+//! allocate. Disable the resolution with [`resolve_symbols(false)`] to rule that out.
+//!
+//! Frames which don't fit into the storage are left out, and output which doesn't fit is cut off.
+//! Only the style, the numbering, the index base and width, the demangling, the resolution and the
+//! vocabulary of the options are honored.
+//!
+//![`BacktraceBuffer`]: struct.BacktraceBuffer.html
+//![`new()`]: struct.BacktraceBuffer.html#method.new
+//![`resolve_symbols(false)`]: ../struct.FormatOptions.html#method.resolve_symbols
+
+use {
+    crate::{
+        demangle::{demangle, SymbolName},
+        FormatOptions, Numbering,
+    },
+    backtrace::Frame,
+    std::fmt::{self, Write},
+};
+
+/// The number of frames at the top searched for the panic machinery.
+const PANIC_FRAMES: usize = 10;
+
+/// Pre-allocated storage for capturing and formatting a backtrace.
+#[derive(Debug)]
+pub struct BacktraceBuffer {
+    frames: Vec<Frame>,
+    output: String,
+    options: FormatOptions,
+}
+
+impl BacktraceBuffer {
+    /// Creates a buffer for up to `max_frames` frames and `output_capacity` bytes of output,
+    /// formatted with the [current options].
+    ///
+    ///[current options]: ../struct.FormatOptions.html#method.current
+    pub fn new(max_frames: usize, output_capacity: usize) -> Self {
+        Self::with_options(max_frames, output_capacity, FormatOptions::current())
+    }
+
+    /// Like [`new()`] but formats according to the given `options`.
+    ///
+    ///[`new()`]: #method.new
+    pub fn with_options(max_frames: usize, output_capacity: usize, options: FormatOptions) -> Self {
+        let mut buffer = BacktraceBuffer {
+            frames: Vec::with_capacity(max_frames),
+            output: String::with_capacity(output_capacity),
+            options,
+        };
+        // Loads the debug info of the executable, so the first panic doesn't have to.
+        buffer.capture_and_format();
+        buffer.frames.clear();
+        buffer.output.clear();
+        buffer
+    }
+
+    /// Captures the stack below the caller, replacing the previously captured frames.
+    #[inline(never)]
+    pub fn capture(&mut self) {
+        self.capture_below(Self::capture as *const () as usize);
+    }
+
+    /// Captures the stack below the caller and formats it, see [`format()`].
+    ///
+    ///[`format()`]: #method.format
+    #[inline(never)]
+    pub fn capture_and_format(&mut self) -> &str {
+        self.capture_below(Self::capture_and_format as *const () as usize);
+        self.format()
+    }
+
+    /// Formats the captured frames, leaving out the panic machinery and the runtime startup.
+    pub fn format(&mut self) -> &str {
+        let BacktraceBuffer {
+            frames,
+            output,
+            options,
+        } = self;
+        output.clear();
+        let resolve = cfg!(feature = "resolve") && !options.no_resolve;
+        let start = if resolve {
+            frames[..frames.len().min(PANIC_FRAMES)]
+                .iter()
+                .rposition(|frame| contains_symbol(frame, is_panic_machinery))
+                .map_or(0, |index| index + 1)
+        } else {
+            0
+        };
+
+        let mut out = Bounded(output);
+        let layout = options.layout();
+        let mut number = options.index_base;
+        for frame in &frames[start..] {
+            if resolve
+                && contains_symbol(frame, |name| name.contains("__rust_begin_short_backtrace"))
+            {
+                break;
+            }
+            let _ = write!(out, "{}:", layout.number(number));
+            let mut symbols = 0;
+            if resolve {
+                resolve_frame(frame, |symbol| {
+                    if symbols > 0 {
+                        let _ = match options.numbering {
+                            Numbering::Frames => layout.write_indent(&mut out, 1),
+                            Numbering::Symbols => {
+                                write!(out, "\n{}:", layout.number(number + symbols))
+                            }
+                        };
+                    }
+                    symbols += 1;
+                    let _ = write_symbol(&mut out, options, symbol);
+                });
+            }
+            number += match options.numbering {
+                Numbering::Frames => 1,
+                Numbering::Symbols => symbols.max(1),
+            };
+            if symbols == 0 {
+                let _ = layout.write_unresolved(&mut out, frame.ip() as usize);
+            }
+            let _ = writeln!(out);
+        }
+        &self.output
+    }
+
+    /// Returns the output of the last call to [`format()`].
+    ///
+    ///[`format()`]: #method.format
+    pub fn as_str(&self) -> &str {
+        &self.output
+    }
+
+    fn capture_below(&mut self, ip: usize) {
+        let frames = &mut self.frames;
+        frames.clear();
+        let mut found = false;
+        backtrace::trace(|frame| {
+            if !found {
+                // The frames of the unwinder are dropped up to the capturing function.
+                found = frame.symbol_address() as usize == ip;
+                return true;
+            }
+            frames.push(frame.clone());
+            frames.len() < frames.capacity()
+        });
+    }
+}
+
+#[cfg(feature = "resolve")]
+fn resolve_frame(frame: &Frame, callback: impl FnMut(&backtrace::Symbol)) {
+    backtrace::resolve_frame(frame, callback);
+}
+
+#[cfg(not(feature = "resolve"))]
+fn resolve_frame(_frame: &Frame, _callback: impl FnMut(&backtrace::Symbol)) {}
+
+/// Writes the name and location of `symbol`.
+fn write_symbol(
+    out: &mut impl Write,
+    options: &FormatOptions,
+    symbol: &backtrace::Symbol,
+) -> fmt::Result {
+    match symbol.name() {
+        Some(name) => match name.as_str() {
+            Some(name) => write!(
+                out,
+                " {}",
+                SymbolName {
+                    name,
+                    demangling: options.demangling,
+                }
+            )?,
+            // Shows the name with invalid UTF-8 replaced.
+            None => write!(out, " {}", name)?,
+        },
+        None => write!(out, " {}", options.vocabulary.unknown)?,
+    }
+    let layout = options.layout();
+    match (symbol.filename(), symbol.lineno()) {
+        (Some(path), Some(line)) => {
+            layout.write_location(out, format_args!("{}:{}", path.display(), line))
+        }
+        (Some(path), None) => layout.write_location(out, path.display()),
+        (None, _) => layout.write_location(out, &options.vocabulary.unknown),
+    }
+}
+
+/// Returns whether `frame` has a symbol whose demangled name `pred` returns `true` for.
+fn contains_symbol(frame: &Frame, mut pred: impl FnMut(&str) -> bool) -> bool {
+    let mut found = false;
+    resolve_frame(frame, |symbol| {
+        if let Some(name) = symbol.name().and_then(|name| name.as_str()) {
+            let mut buf = NameBuf::default();
+            let _ = write!(buf, "{:#}", demangle(name));
+            found |= pred(buf.as_str());
+        }
+    });
+    found
+}
+
+/// Returns whether `name` belongs to the panic machinery, like `filter_frames()` does.
+fn is_panic_machinery(name: &str) -> bool {
+    // Newer compilers include crate disambiguators (`std[1a2b3c]::panicking`).
+    let in_std = name.starts_with("std::") || name.starts_with("std[");
+    let panicking = name.find("::panicking::").map(|index| &name[index..]);
+    name == "panic_fmt"
+        || panicking.is_some_and(|rest| {
+            in_std
+                && !rest.starts_with("::panicking::try")
+                && !rest.starts_with("::panicking::catch_unwind")
+        })
+        || name.contains("__rust_end_short_backtrace")
+        || name.ends_with("::rust_begin_unwind")
+}
+
+/// Writes into a `String` without growing it, cutting off what doesn't fit.
+struct Bounded<'a>(&'a mut String);
+
+impl Write for Bounded<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let free = self.0.capacity() - self.0.len();
+        let mut end = s.len().min(free);
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.0.push_str(&s[..end]);
+        Ok(())
+    }
+}
+
+/// A symbol name on the stack, cut off after 256 bytes.
+struct NameBuf {
+    buf: [u8; 256],
+    len: usize,
+}
+
+impl Default for NameBuf {
+    fn default() -> Self {
+        NameBuf {
+            buf: [0; 256],
+            len: 0,
+        }
+    }
+}
+
+impl NameBuf {
+    fn as_str(&self) -> &str {
+        // Only whole characters are copied in.
+        std::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+}
+
+impl Write for NameBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut end = s.len().min(self.buf.len() - self.len);
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.buf[self.len..self.len + end].copy_from_slice(&s.as_bytes()[..end]);
+        self.len += end;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::BacktraceBuffer,
+        crate::FormatOptions,
+        std::{
+            alloc::{GlobalAlloc, Layout, System},
+            cell::Cell,
+        },
+    };
+
+    struct CountingAlloc;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOC: CountingAlloc = CountingAlloc;
+
+    #[test]
+    #[cfg(all(feature = "resolve", feature = "demangle"))]
+    fn formats_into_buffer() {
+        let mut buffer = BacktraceBuffer::with_options(64, 16 * 1024, FormatOptions::new());
+        let out = buffer.capture_and_format();
+        let first = out.lines().next().unwrap();
+        assert!(first.starts_with("   0: backtrace_string::buffer::tests::formats_into_buffer"));
+    }
+
+    #[test]
+    fn unresolved_capture_does_not_allocate() {
+        let options = FormatOptions::new().resolve_symbols(false);
+        let mut buffer = BacktraceBuffer::with_options(64, 16 * 1024, options);
+        let before = ALLOCATIONS.with(Cell::get);
+        let lines = buffer.capture_and_format().lines().count();
+        assert_eq!(ALLOCATIONS.with(Cell::get), before);
+        assert!(lines > 0);
+
+        let mut small = BacktraceBuffer::with_options(2, 16, FormatOptions::new());
+        assert_eq!(small.capture_and_format().len(), 16);
+    }
+}
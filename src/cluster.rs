@@ -0,0 +1,201 @@
+//! Grouping many crashes into clusters of similar backtraces, for triaging them.
+//!
+//! A [`Clusterer`] trims and hides the frames of each backtrace like the formatted backtraces and
+//! groups the backtraces by the [fingerprint] of the remaining frames. How fuzzy the grouping is
+//! can be tuned: the top frames, e.g. the functions of a panic macro or an allocator which differ
+//! between otherwise identical crashes, can be left out, and line numbers, which are ignored by
+//! default, can be compared as well:
+//!
+//! ```
+//! use backtrace_string::{cluster::Clusterer, std_trace, FormatOptions};
+//!
+//! let traces = [
+//!     "   0: my_app::parse\n   1: my_app::main\n",
+//!     "   0: my_app::parse\n   1: my_app::main\n",
+//!     "   0: my_app::render\n   1: my_app::main\n",
+//! ]
+//! .iter()
+//! .map(|text| std_trace::parse_backtrace(text))
+//! .collect::<Vec<_>>();
+//! let clusters = Clusterer::new(FormatOptions::new()).cluster(&traces);
+//! assert_eq!(clusters.len(), 2);
+//! assert_eq!(clusters[0].members, [0, 1]);
+//! ```
+//!
+//![`Clusterer`]: struct.Clusterer.html
+//![fingerprint]: ../struct.Fingerprint.html
+
+use {
+    crate::{
+        filter_frames, format_frames,
+        frame::{Frame, FrameSource, ResolvedFrame, ResolvedSymbol},
+        Fingerprint, FormatOptions,
+    },
+    std::{cmp::Reverse, collections::HashMap, fmt::Write},
+};
+
+/// Groups backtraces by their fingerprint, see the [module docs].
+///
+///[module docs]: index.html
+#[derive(Debug, Clone)]
+pub struct Clusterer {
+    options: FormatOptions,
+    skip_top_frames: usize,
+    line_numbers: bool,
+}
+
+/// Backtraces with the same fingerprint.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Cluster {
+    /// The fingerprint of the compared frames.
+    pub fingerprint: Fingerprint,
+    /// The indices of the backtraces in this cluster, in the order they were given.
+    pub members: Vec<usize>,
+    /// The shown frames of the first backtrace of this cluster, including the skipped top frames.
+    pub representative: Vec<ResolvedFrame>,
+}
+
+impl Cluster {
+    /// Returns the number of backtraces in this cluster.
+    pub fn size(&self) -> usize {
+        self.members.len()
+    }
+}
+
+impl Clusterer {
+    /// Creates a clusterer trimming and hiding the frames with `options`.
+    pub fn new(options: FormatOptions) -> Self {
+        Clusterer {
+            options,
+            skip_top_frames: 0,
+            line_numbers: false,
+        }
+    }
+
+    /// Sets how many of the most recent shown frames are left out of the comparison, 0 by default.
+    pub fn skip_top_frames(mut self, n: usize) -> Self {
+        self.skip_top_frames = n;
+        self
+    }
+
+    /// Sets whether the file names and line numbers of the frames are compared, so the same call
+    /// stack crashing at another line forms its own cluster. Disabled by default.
+    pub fn line_numbers(mut self, compare: bool) -> Self {
+        self.line_numbers = compare;
+        self
+    }
+
+    /// Returns the fingerprint `trace` is grouped by.
+    pub fn fingerprint<S: FrameSource + ?Sized>(&self, trace: &S) -> Fingerprint {
+        let options = &self.options;
+        let frames = filter_frames(trace.frames(), options)
+            .filter(|frame| options.is_visible(*frame))
+            .skip(self.skip_top_frames);
+        Fingerprint::from_frames_with_lines(frames, self.line_numbers)
+    }
+
+    /// Groups `traces`, returning the clusters with the most backtraces first.
+    pub fn cluster<'a, S: FrameSource + ?Sized + 'a>(
+        &self,
+        traces: impl IntoIterator<Item = &'a S>,
+    ) -> Vec<Cluster> {
+        let mut clusters = Vec::<Cluster>::new();
+        let mut by_fingerprint = HashMap::new();
+        for (index, trace) in traces.into_iter().enumerate() {
+            let fingerprint = self.fingerprint(trace);
+            let cluster = *by_fingerprint.entry(fingerprint).or_insert_with(|| {
+                let options = &self.options;
+                clusters.push(Cluster {
+                    fingerprint,
+                    members: Vec::new(),
+                    representative: filter_frames(trace.frames(), options)
+                        .filter(|frame| options.is_visible(*frame))
+                        .map(|frame| ResolvedFrame {
+                            ip: frame.ip(),
+                            symbols: frame.symbols().map(ResolvedSymbol::from).collect(),
+                        })
+                        .collect(),
+                });
+                clusters.len() - 1
+            });
+            clusters[cluster].members.push(index);
+        }
+        // Stable, so clusters of the same size stay in the order they were first seen.
+        clusters.sort_by_key(|cluster| Reverse(cluster.size()));
+        clusters
+    }
+
+    /// Formats `clusters` with their size and the backtrace of their representative.
+    pub fn report(&self, clusters: &[Cluster]) -> String {
+        let mut out = String::new();
+        for cluster in clusters {
+            write!(
+                out,
+                "{}: {}, {} {}:",
+                self.options.vocabulary.fingerprint,
+                cluster.fingerprint,
+                cluster.size(),
+                match cluster.size() {
+                    1 => "backtrace",
+                    _ => "backtraces",
+                }
+            )
+            .unwrap();
+            out.push_str(&format_frames(&cluster.representative, &self.options));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::Clusterer,
+        crate::{std_trace::parse_backtrace, FormatOptions, Locations},
+    };
+
+    const PARSE_AT_2: &str = "   0: my_app::error::fail\n   1: my_app::parse\n             \
+                              at ./src/main.rs:2:5\n   2: my_app::main\n";
+    const PARSE_AT_3: &str = "   0: my_app::error::bail\n   1: my_app::parse\n             \
+                              at ./src/main.rs:3:5\n   2: my_app::main\n";
+    const RENDER: &str = "   0: my_app::error::fail\n   1: my_app::render\n   2: my_app::main\n";
+
+    #[test]
+    fn fuzziness() {
+        let traces = [PARSE_AT_2, RENDER, PARSE_AT_3, PARSE_AT_2]
+            .iter()
+            .map(|text| parse_backtrace(text))
+            .collect::<Vec<_>>();
+        let options = FormatOptions::new().locations(Locations::Hidden);
+        let members = |clusterer: Clusterer| {
+            clusterer
+                .cluster(&traces)
+                .into_iter()
+                .map(|cluster| cluster.members)
+                .collect::<Vec<_>>()
+        };
+        let clusterer = Clusterer::new(options.clone());
+        assert_eq!(members(clusterer.clone()), [vec![0, 3], vec![1], vec![2]]);
+        assert_eq!(
+            members(clusterer.clone().skip_top_frames(1)),
+            [vec![0, 2, 3], vec![1]]
+        );
+        assert_eq!(
+            members(clusterer.skip_top_frames(1).line_numbers(true)),
+            [vec![0, 3], vec![1], vec![2]]
+        );
+
+        let clusters = Clusterer::new(options.clone()).cluster(&traces);
+        assert_eq!(clusters[0].representative.len(), 3);
+        let report = Clusterer::new(options).report(&clusters[..1]);
+        assert!(
+            report.ends_with(
+                ", 2 backtraces:\n   0: my_app::error::fail\n   \
+                 1: my_app::parse\n   2: my_app::main\n"
+            ),
+            "{}",
+            report
+        );
+    }
+}
@@ -0,0 +1,490 @@
+//! Coloring of the formatted output with ANSI escape codes.
+//!
+//! Whether to color is decided by the [`ColorChoice`], how to color by the [`Theme`]. Themes can
+//! use colors of the 256 color palette or true colors, which are downgraded to what the terminal
+//! supports (see [`ColorDepth::detect()`]).
+//!
+//! ```
+//! use backtrace_string::{
+//!     color::{Color, Paint, Theme},
+//!     ColorChoice, FormatOptions,
+//! };
+//!
+//! let theme = Theme {
+//!     user_symbol: Paint::new().fg(Color::Rgb(0xff, 0x87, 0x00)).bold(),
+//!     ..Theme::dark()
+//! };
+//! let options = FormatOptions::new().color(ColorChoice::Auto).theme(theme);
+//! ```
+//!
+//![`ColorChoice`]: enum.ColorChoice.html
+//![`Theme`]: struct.Theme.html
+//![`ColorDepth::detect()`]: enum.ColorDepth.html#method.detect
+
+use std::{
+    env,
+    fmt::{self, Display},
+    io::{self, IsTerminal},
+    path::{Component, Path},
+    str::FromStr,
+};
+
+/// Whether the output is colored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    /// Color if stderr is a terminal, `TERM` isn't `dumb` and the `NO_COLOR` environment variable
+    /// isn't set.
+    Auto,
+    /// Always color.
+    Always,
+    /// Never color, this is the default.
+    #[default]
+    Never,
+}
+
+impl ColorChoice {
+    /// Returns whether to color, detecting the terminal for `Auto`.
+    pub(crate) fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                env::var_os("NO_COLOR").is_none()
+                    && env::var_os("TERM").map_or(true, |term| term != "dumb")
+                    && io::stderr().is_terminal()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The colors a terminal supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorDepth {
+    /// The 16 basic colors.
+    Ansi16,
+    /// The 256 color palette.
+    Ansi256,
+    /// 24 bit colors.
+    TrueColor,
+}
+
+impl ColorDepth {
+    /// Detects the depth from the `COLORTERM` and `TERM` environment variables.
+    ///
+    /// `COLORTERM=truecolor` (or `24bit`) means true colors, a `TERM` containing `256color` means
+    /// 256 colors and anything else 16 colors.
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+}
+
+/// A foreground color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// One of the 16 basic colors, 0 to 7 are the normal and 8 to 15 the bright ones.
+    Ansi(u8),
+    /// A color of the 256 color palette.
+    Fixed(u8),
+    /// A 24 bit color.
+    Rgb(u8, u8, u8),
+}
+
+/// The RGB values of the 16 basic colors as xterm shows them by default.
+const ANSI16_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The channel values of the 6x6x6 color cube in the 256 color palette.
+const CUBE_STEPS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl Color {
+    /// Returns the closest color available at `depth`.
+    fn downgrade(self, depth: ColorDepth) -> Color {
+        match (self, depth) {
+            (Color::Ansi(n), _) => Color::Ansi(n & 15),
+            (Color::Fixed(n), ColorDepth::Ansi16) if n < 16 => Color::Ansi(n),
+            (Color::Fixed(n), ColorDepth::Ansi16) => nearest_ansi16(fixed_to_rgb(n)),
+            (Color::Fixed(n), _) => Color::Fixed(n),
+            (Color::Rgb(r, g, b), ColorDepth::Ansi16) => nearest_ansi16((r, g, b)),
+            (Color::Rgb(r, g, b), ColorDepth::Ansi256) => Color::Fixed(rgb_to_fixed(r, g, b)),
+            (Color::Rgb(r, g, b), ColorDepth::TrueColor) => Color::Rgb(r, g, b),
+        }
+    }
+
+    /// Writes the SGR parameters selecting this color as foreground.
+    fn write_params(self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Color::Ansi(n) if n < 8 => write!(f, "{}", 30 + n),
+            Color::Ansi(n) => write!(f, "{}", 90 + (n & 15) - 8),
+            Color::Fixed(n) => write!(f, "38;5;{}", n),
+            Color::Rgb(r, g, b) => write!(f, "38;2;{};{};{}", r, g, b),
+        }
+    }
+}
+
+fn fixed_to_rgb(n: u8) -> (u8, u8, u8) {
+    match n {
+        0..=15 => ANSI16_RGB[n as usize],
+        16..=231 => {
+            let n = n - 16;
+            (
+                CUBE_STEPS[(n / 36) as usize],
+                CUBE_STEPS[(n / 6 % 6) as usize],
+                CUBE_STEPS[(n % 6) as usize],
+            )
+        }
+        _ => {
+            let gray = 8 + (n - 232) * 10;
+            (gray, gray, gray)
+        }
+    }
+}
+
+fn rgb_to_fixed(r: u8, g: u8, b: u8) -> u8 {
+    let nearest_step = |c: u8| {
+        (0..CUBE_STEPS.len())
+            .min_by_key(|&i| (CUBE_STEPS[i] as i32 - c as i32).abs())
+            .unwrap() as u8
+    };
+    let cube = 16 + 36 * nearest_step(r) + 6 * nearest_step(g) + nearest_step(b);
+
+    // Grays are often better matched by the gray ramp than by the cube.
+    let average = ((r as u32 + g as u32 + b as u32) / 3) as u8;
+    let gray = if average < 8 {
+        232
+    } else {
+        232 + ((average - 8) / 10).min(23)
+    };
+
+    let target = (r, g, b);
+    if distance(fixed_to_rgb(gray), target) < distance(fixed_to_rgb(cube), target) {
+        gray
+    } else {
+        cube
+    }
+}
+
+fn nearest_ansi16(target: (u8, u8, u8)) -> Color {
+    let index = (0..ANSI16_RGB.len())
+        .min_by_key(|&i| distance(ANSI16_RGB[i], target))
+        .unwrap();
+    Color::Ansi(index as u8)
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+/// How a part of the output is painted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Paint {
+    /// The foreground color, `None` keeps the terminal's color.
+    pub fg: Option<Color>,
+    /// Bold text.
+    pub bold: bool,
+    /// Dimmed text.
+    pub dimmed: bool,
+}
+
+impl Paint {
+    /// Creates a paint which leaves the text as it is.
+    pub const fn new() -> Self {
+        Paint {
+            fg: None,
+            bold: false,
+            dimmed: false,
+        }
+    }
+
+    /// Sets the foreground color.
+    pub const fn fg(mut self, color: Color) -> Self {
+        self.fg = Some(color);
+        self
+    }
+
+    /// Makes the text bold.
+    pub const fn bold(mut self) -> Self {
+        self.bold = true;
+        self
+    }
+
+    /// Dims the text.
+    pub const fn dimmed(mut self) -> Self {
+        self.dimmed = true;
+        self
+    }
+
+    fn is_plain(&self) -> bool {
+        self.fg.is_none() && !self.bold && !self.dimmed
+    }
+}
+
+/// The paints for the different parts of the output.
+///
+/// The built-in themes are [`dark()`](#method.dark) (the default), [`light()`](#method.light)
+/// and [`dimmed()`](#method.dimmed), custom themes are built by setting the fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    /// The frame indices.
+    pub index: Paint,
+    /// Symbols of the application's own code.
+    pub user_symbol: Paint,
+    /// Symbols of the standard library and dependencies.
+    pub dependency_symbol: Paint,
+    /// Source paths, addresses and `<unknown>` locations.
+    pub path: Paint,
+    /// Line numbers.
+    pub line_number: Paint,
+    /// The header line of panic reports.
+    pub header: Paint,
+}
+
+impl Theme {
+    /// A theme for terminals with a dark background.
+    pub const fn dark() -> Self {
+        Theme {
+            index: Paint::new().fg(Color::Fixed(244)),
+            user_symbol: Paint::new().fg(Color::Rgb(0x5f, 0xd7, 0x87)).bold(),
+            dependency_symbol: Paint::new().fg(Color::Rgb(0xd7, 0x87, 0x5f)),
+            path: Paint::new().fg(Color::Rgb(0x87, 0xaf, 0xd7)),
+            line_number: Paint::new().fg(Color::Rgb(0xd7, 0xd7, 0x5f)),
+            header: Paint::new().fg(Color::Rgb(0xff, 0x5f, 0x5f)).bold(),
+        }
+    }
+
+    /// A theme for terminals with a light background.
+    pub const fn light() -> Self {
+        Theme {
+            index: Paint::new().fg(Color::Fixed(242)),
+            user_symbol: Paint::new().fg(Color::Rgb(0x00, 0x87, 0x00)).bold(),
+            dependency_symbol: Paint::new().fg(Color::Rgb(0x87, 0x5f, 0x00)),
+            path: Paint::new().fg(Color::Rgb(0x00, 0x5f, 0x87)),
+            line_number: Paint::new().fg(Color::Rgb(0x87, 0x00, 0x87)),
+            header: Paint::new().fg(Color::Rgb(0xaf, 0x00, 0x00)).bold(),
+        }
+    }
+
+    /// A theme without colors, only highlighting the application's symbols.
+    pub const fn dimmed() -> Self {
+        Theme {
+            index: Paint::new().dimmed(),
+            user_symbol: Paint::new().bold(),
+            dependency_symbol: Paint::new(),
+            path: Paint::new().dimmed(),
+            line_number: Paint::new().dimmed(),
+            header: Paint::new().bold(),
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::dark()
+    }
+}
+
+impl FromStr for Theme {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "dark" => Ok(Theme::dark()),
+            "light" => Ok(Theme::light()),
+            "dimmed" => Ok(Theme::dimmed()),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Returns whether the source file belongs to the standard library or a dependency.
+///
+/// These are the files of the Rust source (`/rustc/<hash>/...`, `library/...` or the older
+/// `src/libstd/...`) and of crates downloaded by cargo (below `.cargo`). Symbols without file
+/// are counted as dependencies, as the application's code usually comes with debug info.
+pub(crate) fn is_dependency_path(path: Option<&Path>) -> bool {
+    let path = match path {
+        Some(path) => path,
+        None => return true,
+    };
+    if path.starts_with("/rustc") || path.starts_with("library") {
+        return true;
+    }
+    let mut components = path.components().filter_map(|component| match component {
+        Component::Normal(name) => name.to_str(),
+        _ => None,
+    });
+    if components.next() == Some("src") {
+        if let Some(dir) = components.next() {
+            if ["libstd", "libcore", "liballoc", "libpanic_unwind"].contains(&dir) {
+                return true;
+            }
+        }
+    }
+    // Toolchains installed by rustup or Homebrew ship the sources below `lib/rustlib`.
+    path.components()
+        .any(|component| component.as_os_str() == ".cargo" || component.as_os_str() == "rustlib")
+}
+
+/// Wraps text in escape codes if coloring is enabled.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Painter<'a> {
+    theme: Option<&'a Theme>,
+    depth: ColorDepth,
+}
+
+impl<'a> Painter<'a> {
+    pub(crate) fn new(choice: ColorChoice, theme: &'a Theme) -> Self {
+        Painter {
+            theme: if choice.enabled() { Some(theme) } else { None },
+            depth: ColorDepth::detect(),
+        }
+    }
+
+    /// Paints a frame index.
+    pub(crate) fn index<T: Display>(self, text: T) -> Painted<T> {
+        self.paint(|theme| theme.index, text)
+    }
+
+    /// Paints a symbol name of user or dependency code.
+    pub(crate) fn symbol<T: Display>(self, text: T, dependency: bool) -> Painted<T> {
+        if dependency {
+            self.paint(|theme| theme.dependency_symbol, text)
+        } else {
+            self.paint(|theme| theme.user_symbol, text)
+        }
+    }
+
+    /// Paints a source path, address or unknown location.
+    pub(crate) fn path<T: Display>(self, text: T) -> Painted<T> {
+        self.paint(|theme| theme.path, text)
+    }
+
+    /// Paints a line number.
+    pub(crate) fn line_number<T: Display>(self, text: T) -> Painted<T> {
+        self.paint(|theme| theme.line_number, text)
+    }
+
+    /// Paints the header of a report.
+    pub(crate) fn header<T: Display>(self, text: T) -> Painted<T> {
+        self.paint(|theme| theme.header, text)
+    }
+
+    fn paint<T: Display>(self, select: impl Fn(&Theme) -> Paint, text: T) -> Painted<T> {
+        Painted {
+            paint: self
+                .theme
+                .map(select)
+                .filter(|paint| !paint.is_plain())
+                .map(|paint| Paint {
+                    fg: paint.fg.map(|color| color.downgrade(self.depth)),
+                    ..paint
+                }),
+            text,
+        }
+    }
+}
+
+/// Text which is displayed surrounded by an escape code and a reset.
+pub(crate) struct Painted<T> {
+    paint: Option<Paint>,
+    text: T,
+}
+
+impl<T: Display> Display for Painted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let paint = match self.paint {
+            Some(paint) => paint,
+            None => return self.text.fmt(f),
+        };
+        f.write_str("\x1b[")?;
+        let mut separator = "";
+        for (enabled, param) in [(paint.bold, "1"), (paint.dimmed, "2")] {
+            if enabled {
+                write!(f, "{}{}", separator, param)?;
+                separator = ";";
+            }
+        }
+        if let Some(color) = paint.fg {
+            f.write_str(separator)?;
+            color.write_params(f)?;
+        }
+        write!(f, "m{}\x1b[0m", self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_are_downgraded_to_the_terminal() {
+        let orange = Color::Rgb(0xff, 0x87, 0x00);
+        assert_eq!(orange.downgrade(ColorDepth::TrueColor), orange);
+        assert_eq!(orange.downgrade(ColorDepth::Ansi256), Color::Fixed(208));
+        assert_eq!(orange.downgrade(ColorDepth::Ansi16), Color::Ansi(3));
+        assert_eq!(
+            Color::Rgb(0x80, 0x80, 0x80).downgrade(ColorDepth::Ansi256),
+            Color::Fixed(244)
+        );
+        assert_eq!(
+            Color::Fixed(196).downgrade(ColorDepth::Ansi16),
+            Color::Ansi(9)
+        );
+
+        let painted = Painted {
+            paint: Some(Paint::new().fg(Color::Ansi(9)).bold()),
+            text: "main",
+        };
+        assert_eq!(painted.to_string(), "\x1b[1;91mmain\x1b[0m");
+    }
+
+    #[test]
+    fn dependency_paths() {
+        let is_dep = |path: &str| is_dependency_path(Some(Path::new(path)));
+        assert!(is_dep(
+            "/rustc/90b35a6239c3d8bdabc530a6a0816f7ff89a0aaf/library/std/src/rt.rs"
+        ));
+        assert!(is_dep("/home/alice/.cargo/registry/src/index.crates.io-6f17d22bba15001f/tokio-1.28.0/src/lib.rs"));
+        assert!(is_dep("src/libstd/panicking.rs"));
+        assert!(is_dependency_path(None));
+        assert!(!is_dep("/home/alice/my_app/src/main.rs"));
+        assert!(!is_dep("src/lib.rs"));
+    }
+}
@@ -0,0 +1,154 @@
+//! Finding out whether and where a crash leaves a core dump, for hints in fatal reports.
+//!
+//! Operators looking at a crash report usually want the core dump next, and often only find out
+//! then that core dumps were disabled. [`probe()`] checks the core file size limit of the process
+//! and the `core_pattern` of the kernel; its result formats as a hint:
+//!
+//! ```
+//! if let Some(core_dumps) = backtrace_string::coredump::probe() {
+//!     eprintln!("note: {}", core_dumps);
+//! }
+//! ```
+//!
+//! Reports of [`FormatOptions::core_dump_hint()`] include the hint.
+//!
+//![`probe()`]: fn.probe.html
+//![`FormatOptions::core_dump_hint()`]: ../struct.FormatOptions.html#method.core_dump_hint
+
+use std::{fmt, path::PathBuf};
+
+/// What happens to a core dump of this process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CoreDumps {
+    /// No core dump is written, as the core file size limit (`ulimit -c`) is 0.
+    Disabled,
+    /// The core dump is piped to the given program, e.g. `systemd-coredump`.
+    Piped(String),
+    /// The core dump is written to the given file.
+    File(PathBuf),
+}
+
+impl fmt::Display for CoreDumps {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoreDumps::Disabled => write!(
+                f,
+                "no core dump was written as the core file size limit is 0, raise it with \
+                 `ulimit -c unlimited`"
+            ),
+            CoreDumps::Piped(program) => {
+                write!(f, "a core dump may have been passed to `{}`", program)?;
+                if program.ends_with("systemd-coredump") {
+                    write!(f, ", see `coredumpctl list`")?;
+                }
+                Ok(())
+            }
+            CoreDumps::File(path) => {
+                write!(f, "a core dump may have been written to {}", path.display())
+            }
+        }
+    }
+}
+
+/// Finds out what happens to a core dump of this process, `None` where that isn't known.
+///
+/// Currently only Linux is probed.
+#[cfg(target_os = "linux")]
+pub fn probe() -> Option<CoreDumps> {
+    // SAFETY: `getrlimit` only fills in `limit`.
+    let limit = unsafe {
+        let mut limit = std::mem::zeroed::<libc::rlimit>();
+        if libc::getrlimit(libc::RLIMIT_CORE, &mut limit) != 0 {
+            return None;
+        }
+        limit
+    };
+    // Programs receiving the core dump through a pipe get the limit too and honor it.
+    if limit.rlim_cur == 0 {
+        return Some(CoreDumps::Disabled);
+    }
+    let pattern = std::fs::read_to_string("/proc/sys/kernel/core_pattern").ok()?;
+    let exe = std::env::current_exe().ok();
+    let exe = exe
+        .as_ref()
+        .and_then(|exe| exe.file_name())
+        .map(|name| name.to_string_lossy().into_owned());
+    Some(parse_pattern(
+        pattern.trim_end(),
+        std::process::id(),
+        exe.as_deref().unwrap_or(""),
+    ))
+}
+
+/// Finds out what happens to a core dump of this process, `None` where that isn't known.
+///
+/// Currently only Linux is probed.
+#[cfg(not(target_os = "linux"))]
+pub fn probe() -> Option<CoreDumps> {
+    None
+}
+
+/// Interprets a `core_pattern`, expanding the process id (`%p`) and executable name (`%e`).
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_pattern(pattern: &str, pid: u32, exe: &str) -> CoreDumps {
+    if let Some(command) = pattern.strip_prefix('|') {
+        let program = command.split_whitespace().next().unwrap_or(command);
+        return CoreDumps::Piped(program.to_owned());
+    }
+    let mut file = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('%', Some(spec)) => {
+                chars.next();
+                match spec {
+                    'p' => file.push_str(&pid.to_string()),
+                    'e' => file.push_str(exe),
+                    '%' => file.push('%'),
+                    // The time, host name etc. aren't known yet, they are shown as they are.
+                    _ => {
+                        file.push('%');
+                        file.push(spec);
+                    }
+                }
+            }
+            _ => file.push(c),
+        }
+    }
+    let file = PathBuf::from(file);
+    // Relative patterns are relative to the working directory at the time of the crash.
+    CoreDumps::File(match std::env::current_dir() {
+        Ok(dir) if file.is_relative() => dir.join(file),
+        _ => file,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{parse_pattern, CoreDumps},
+        std::path::PathBuf,
+    };
+
+    #[test]
+    fn parse_core_patterns() {
+        assert_eq!(
+            parse_pattern("|/usr/lib/systemd/systemd-coredump %P %u %g", 42, "my_app"),
+            CoreDumps::Piped(String::from("/usr/lib/systemd/systemd-coredump"))
+        );
+        assert_eq!(
+            parse_pattern("/var/crash/core.%e.%p.%t%%", 42, "my_app"),
+            CoreDumps::File(PathBuf::from("/var/crash/core.my_app.42.%t%"))
+        );
+        assert_eq!(
+            parse_pattern("core", 42, "my_app"),
+            CoreDumps::File(std::env::current_dir().unwrap().join("core"))
+        );
+        assert_eq!(
+            CoreDumps::Piped(String::from("/usr/lib/systemd/systemd-coredump")).to_string(),
+            "a core dump may have been passed to `/usr/lib/systemd/systemd-coredump`, see \
+             `coredumpctl list`"
+        );
+    }
+}
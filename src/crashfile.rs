@@ -0,0 +1,352 @@
+//! Crash reports kept as one file per crash in a directory.
+//!
+//! A [`CrashDir`] writes every report to a file of its own named
+//! `crash-YYYYmmdd-HHMMSS-<pid>-<fingerprint>.txt` (the time in UTC), deleting the oldest files
+//! beyond a maximum number. On the next start the application lists the reports left behind,
+//! e.g. to offer sending them:
+//!
+//! ```no_run
+//! use backtrace_string::{crashfile::CrashDir, report::CrashReport, FormatOptions};
+//!
+//! let crashes = CrashDir::new("crashes").max_files(5);
+//! for previous in crashes.reports().unwrap() {
+//!     println!("crashed at {:?}: {}", previous.time, previous.path.display());
+//! }
+//!
+//! std::panic::set_hook(Box::new(move |info| {
+//!     let report = CrashReport::from_panic(info);
+//!     let _ = crashes.write_crash(&report, &FormatOptions::current());
+//! }));
+//! ```
+//!
+//![`CrashDir`]: struct.CrashDir.html
+
+use {
+    crate::{report::CrashReport, Fingerprint, FormatOptions},
+    std::{
+        fs,
+        io::{self, Write},
+        path::{Path, PathBuf},
+        process,
+        time::{Duration, SystemTime},
+    },
+};
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// A directory of crash report files, see the [module docs].
+///
+///[module docs]: index.html
+#[derive(Debug, Clone)]
+pub struct CrashDir {
+    dir: PathBuf,
+    max_files: usize,
+}
+
+/// A crash report file found in a [`CrashDir`].
+///
+///[`CrashDir`]: struct.CrashDir.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CrashFile {
+    /// The path of the file.
+    pub path: PathBuf,
+    /// When the report was written, to the second.
+    pub time: SystemTime,
+    /// The id of the crashed process.
+    pub pid: u32,
+    /// The fingerprint of the crash.
+    pub fingerprint: Fingerprint,
+}
+
+impl CrashDir {
+    /// Creates a crash directory at `dir`, which is created when the first report is written. By
+    /// default 10 reports are retained.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        CrashDir {
+            dir: dir.into(),
+            max_files: 10,
+        }
+    }
+
+    /// Sets how many report files are retained, the oldest ones are deleted after a report is
+    /// written. With 0 all reports are retained.
+    pub fn max_files(mut self, n: usize) -> Self {
+        self.max_files = n;
+        self
+    }
+
+    /// Returns the path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Writes `report` rendered as text with `options` to a new file, named after the fingerprint
+    /// of the crashed thread's shown frames. Returns the path of the file.
+    pub fn write_crash(
+        &self,
+        report: &CrashReport,
+        options: &FormatOptions,
+    ) -> io::Result<PathBuf> {
+        let crashed = report.threads.iter().find(|thread| thread.crashed);
+        let fingerprint = Fingerprint::from_frames(
+            crashed
+                .into_iter()
+                .flat_map(|thread| thread.shown_frames(options)),
+        );
+        self.write(&report.to_text(options), fingerprint)
+    }
+
+    /// Writes `report` to a new file named after the current time, the id of this process and
+    /// `fingerprint`, then deletes the oldest files beyond the maximum. Returns the path of the
+    /// file.
+    ///
+    /// Several reports of the same process and crash within a second get a counter appended to
+    /// their names, e.g. `-1` before the extension.
+    pub fn write(&self, report: &str, fingerprint: Fingerprint) -> io::Result<PathBuf> {
+        self.write_at(report, fingerprint, SystemTime::now())
+    }
+
+    fn write_at(
+        &self,
+        report: &str,
+        fingerprint: Fingerprint,
+        time: SystemTime,
+    ) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+        let stem = format!(
+            "crash-{}-{}-{}",
+            format_timestamp(time),
+            process::id(),
+            fingerprint
+        );
+        let mut counter = 0;
+        let (path, mut file) = loop {
+            let name = match counter {
+                0 => format!("{}.txt", stem),
+                _ => format!("{}-{}.txt", stem, counter),
+            };
+            let path = self.dir.join(name);
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(file) => break (path, file),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => counter += 1,
+                Err(err) => return Err(err),
+            }
+        };
+        file.write_all(report.as_bytes())?;
+        file.write_all(b"\n")?;
+        file.flush()?;
+        drop(file);
+
+        self.prune()?;
+        Ok(path)
+    }
+
+    /// Lists the report files in the directory, the oldest first. Other files are ignored, a
+    /// missing directory has no reports.
+    pub fn reports(&self) -> io::Result<Vec<CrashFile>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut reports = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            if let Some((time, pid, fingerprint, counter)) = name.to_str().and_then(parse_name) {
+                let report = CrashFile {
+                    path: entry.path(),
+                    time,
+                    pid,
+                    fingerprint,
+                };
+                reports.push((counter, report));
+            }
+        }
+        reports.sort_by(|(a_counter, a), (b_counter, b)| {
+            (a.time, a_counter, &a.path).cmp(&(b.time, b_counter, &b.path))
+        });
+        Ok(reports.into_iter().map(|(_, report)| report).collect())
+    }
+
+    /// Deletes the oldest reports beyond the maximum number of files.
+    fn prune(&self) -> io::Result<()> {
+        if self.max_files == 0 {
+            return Ok(());
+        }
+        let reports = self.reports()?;
+        let excess = reports.len().saturating_sub(self.max_files);
+        for report in &reports[..excess] {
+            match fs::remove_file(&report.path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses the time, process id, fingerprint and counter from a report file name.
+fn parse_name(name: &str) -> Option<(SystemTime, u32, Fingerprint, u32)> {
+    let rest = name.strip_prefix("crash-")?.strip_suffix(".txt")?;
+    let mut parts = rest.split('-');
+    let (date, time) = (parts.next()?, parts.next()?);
+    let pid = parts.next()?.parse().ok()?;
+    let fingerprint = parts.next()?;
+    if fingerprint.len() != 16 {
+        return None;
+    }
+    let fingerprint = Fingerprint(u64::from_str_radix(fingerprint, 16).ok()?);
+    let counter = match (parts.next(), parts.next()) {
+        (None, _) => 0,
+        (Some(counter), None) => counter.parse().ok()?,
+        _ => return None,
+    };
+    Some((parse_timestamp(date, time)?, pid, fingerprint, counter))
+}
+
+/// Formats `time` as `YYYYmmdd-HHMMSS` in UTC, times before 1970 as the epoch.
+pub(crate) fn format_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (year, month, day) = civil_from_days(secs / SECS_PER_DAY);
+    let secs = secs % SECS_PER_DAY;
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Parses the `YYYYmmdd` and `HHMMSS` parts formatted by `format_timestamp()`.
+fn parse_timestamp(date: &str, time: &str) -> Option<SystemTime> {
+    if date.len() != 8
+        || time.len() != 6
+        || !(date.chars().chain(time.chars())).all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    let number = |s: &str, range: std::ops::Range<usize>| s[range].parse::<u64>().ok();
+    let (year, month, day) = (
+        number(date, 0..4)?,
+        number(date, 4..6)?,
+        number(date, 6..8)?,
+    );
+    let (hours, minutes, secs) = (
+        number(time, 0..2)?,
+        number(time, 2..4)?,
+        number(time, 4..6)?,
+    );
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    if hours > 23 || minutes > 59 || secs > 59 {
+        return None;
+    }
+    let secs =
+        days_from_civil(year, month, day) * SECS_PER_DAY + hours * 3600 + minutes * 60 + secs;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Converts days since 1970-01-01 to the year, month and day of the Gregorian calendar.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // Howard Hinnant's algorithm, with eras of 400 years starting on March 1st.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Converts a date of the Gregorian calendar from 1970 on to days since 1970-01-01.
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{format_timestamp, parse_name, CrashDir},
+        crate::Fingerprint,
+        std::{
+            fs, process,
+            time::{Duration, SystemTime},
+        },
+    };
+
+    #[test]
+    fn timestamps_roundtrip() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        assert_eq!(format_timestamp(time), "20240229-123456");
+        let name = format!("crash-20240229-123456-42-{}.txt", Fingerprint(0xabc));
+        assert_eq!(parse_name(&name), Some((time, 42, Fingerprint(0xabc), 0)));
+        assert_eq!(
+            parse_name("crash-19700101-000000-7-00000000000000ff-2.txt"),
+            Some((SystemTime::UNIX_EPOCH, 7, Fingerprint(0xff), 2))
+        );
+        assert_eq!(
+            parse_name("crash-20241301-000000-7-00000000000000ff.txt"),
+            None
+        );
+        assert_eq!(parse_name("notes.txt"), None);
+    }
+
+    #[test]
+    fn retains_newest_reports() {
+        let dir =
+            std::env::temp_dir().join(format!("backtrace-string-crashfile-{}", process::id()));
+        let crashes = CrashDir::new(&dir).max_files(2);
+        assert_eq!(crashes.reports().unwrap(), []);
+
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut paths = Vec::new();
+        for (secs, fingerprint) in [(0, 1), (1, 2), (1, 2), (5, 3)] {
+            let time = start + Duration::from_secs(secs);
+            let path = crashes
+                .write_at("report", Fingerprint(fingerprint), time)
+                .unwrap();
+            paths.push(path);
+        }
+        fs::write(dir.join("notes.txt"), "kept").unwrap();
+
+        let reports = crashes.reports().unwrap();
+        let contents = fs::read_to_string(&reports[1].path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            paths[2].file_name().unwrap().to_str().unwrap(),
+            format!(
+                "crash-20231114-221321-{}-{}-1.txt",
+                process::id(),
+                Fingerprint(2)
+            )
+        );
+        let found: Vec<_> = reports.iter().map(|report| report.path.clone()).collect();
+        assert_eq!(found, paths[2..]);
+        assert_eq!(reports[1].time, start + Duration::from_secs(5));
+        assert_eq!(reports[1].pid, process::id());
+        assert_eq!(reports[1].fingerprint, Fingerprint(3));
+        assert_eq!(contents, "report\n");
+    }
+}
@@ -0,0 +1,111 @@
+//! Detection of deep recursion, the usual cause of stack overflows.
+
+use crate::frame::Frame;
+
+/// The longest cycle of frames which is searched for.
+const MAX_PERIOD: usize = 16;
+
+/// How often a cycle has to repeat to count as a likely stack overflow.
+const MIN_REPEATS: usize = 32;
+
+/// A sequence of frames repeated back to back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Cycle {
+    /// The index of the first frame of the first repetition.
+    pub(crate) start: usize,
+    /// The number of frames in one repetition.
+    pub(crate) period: usize,
+    /// The number of repetitions.
+    pub(crate) repeats: usize,
+}
+
+impl Cycle {
+    /// Returns the first index after the first repetition.
+    pub(crate) fn first_end(&self) -> usize {
+        self.start + self.period
+    }
+
+    /// Returns the first index after the last repetition.
+    pub(crate) fn end(&self) -> usize {
+        self.start + self.period * self.repeats
+    }
+}
+
+/// Finds the cycle covering the most frames, if it repeats often enough to suggest a stack
+/// overflow.
+pub(crate) fn find_cycle<F: Frame>(frames: &[F]) -> Option<Cycle> {
+    let keys = frames.iter().map(frame_key).collect::<Vec<_>>();
+
+    let mut best: Option<Cycle> = None;
+    for period in 1..=MAX_PERIOD.min(keys.len() / 2) {
+        // `run` counts the consecutive frames equal to the frame one period further.
+        let mut run = 0;
+        for i in 0..=keys.len() - period {
+            if i + period < keys.len() && keys[i] == keys[i + period] {
+                run += 1;
+                continue;
+            }
+            let repeats = (run + period) / period;
+            let covered = repeats * period;
+            if repeats >= MIN_REPEATS && best.map_or(true, |best| covered > best.end() - best.start)
+            {
+                best = Some(Cycle {
+                    start: i - run,
+                    period,
+                    repeats,
+                });
+            }
+            run = 0;
+        }
+    }
+    best
+}
+
+/// Identifies a frame by its symbol names, or by its address if it has none.
+fn frame_key(frame: &impl Frame) -> Result<Vec<&str>, usize> {
+    if frame.is_resolved() {
+        Ok(frame.symbols().filter_map(|symbol| symbol.name).collect())
+    } else {
+        Err(frame.ip())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{find_cycle, Cycle},
+        crate::frame::{ResolvedFrame, ResolvedSymbol},
+    };
+
+    fn frames(names: &[&str]) -> Vec<ResolvedFrame> {
+        names
+            .iter()
+            .map(|name| ResolvedFrame {
+                ip: 0,
+                symbols: vec![ResolvedSymbol {
+                    name: Some(name.to_string()),
+                    ..ResolvedSymbol::default()
+                }],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_longest_cycle() {
+        let mut names = vec!["overflow"];
+        for _ in 0..40 {
+            names.extend(&["a", "b", "c"]);
+        }
+        names.extend(&["a", "main"]);
+        assert_eq!(
+            find_cycle(&frames(&names)),
+            Some(Cycle {
+                start: 1,
+                period: 3,
+                repeats: 40,
+            })
+        );
+
+        assert_eq!(find_cycle(&frames(&["a"; 31])), None);
+    }
+}
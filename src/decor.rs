@@ -0,0 +1,84 @@
+//! Decorations around formatted reports.
+
+use std::fmt::Write;
+
+/// Draws a box with `title` in its top border around `content`.
+///
+/// ```text
+/// ┌─ panic in my_app v1.2.3 ─────┐
+/// │    0: my_app::main           │
+/// │           at src/main.rs:3   │
+/// └──────────────────────────────┘
+/// ```
+///
+/// Leading and trailing empty lines of `content` are dropped.
+pub fn draw_box(title: &str, content: &str) -> String {
+    let lines = content
+        .trim_matches('\n')
+        .lines()
+        .map(|line| line.replace('\t', "    "))
+        .collect::<Vec<_>>();
+    let title_width = visible_width(title) + 4;
+    let width = lines
+        .iter()
+        .map(|line| visible_width(line) + 2)
+        .max()
+        .unwrap_or(0)
+        .max(title_width + 1);
+
+    let mut out = String::with_capacity(content.len() + (lines.len() + 2) * (width + 2) * 3);
+    write!(out, "┌─ {} ", title).unwrap();
+    push_repeated(&mut out, '─', width - title_width + 1);
+    out.push_str("┐\n");
+    for line in &lines {
+        write!(out, "│ {}", line).unwrap();
+        push_repeated(&mut out, ' ', width - visible_width(line) - 1);
+        out.push_str("│\n");
+    }
+    out.push('└');
+    push_repeated(&mut out, '─', width);
+    out.push_str("┘\n");
+    out
+}
+
+fn push_repeated(out: &mut String, c: char, count: usize) {
+    out.extend(std::iter::repeat(c).take(count));
+}
+
+/// Returns the number of characters in `text`, not counting ANSI escape sequences.
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip to the end of the escape sequence, e.g. `\x1b[1m`.
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::draw_box;
+
+    #[test]
+    fn box_fits_content_and_title() {
+        assert_eq!(
+            draw_box(
+                "panic in app",
+                "\n   0: app::main\n\x1b[2m      at main.rs:3\x1b[0m\n"
+            ),
+            "┌─ panic in app ─────┐\n\
+             │    0: app::main    │\n\
+             │ \x1b[2m      at main.rs:3\x1b[0m │\n\
+             └────────────────────┘\n"
+        );
+    }
+}
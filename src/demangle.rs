@@ -0,0 +1,118 @@
+//! Demangling of symbol names, if enabled with the `demangle` feature.
+
+use {
+    crate::{options::Demangling, stats},
+    std::{
+        cell::RefCell,
+        fmt::{self, Write},
+    },
+};
+
+thread_local! {
+    /// The buffer symbol names are demangled into by [`SymbolName::with_str()`].
+    static NAME: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Demangles `name`, the alternate format (`{:#}`) leaves out the hashes rustc appends.
+#[cfg(feature = "demangle")]
+pub(crate) fn demangle(name: &str) -> rustc_demangle::Demangle<'_> {
+    stats::count_demangle_call();
+    rustc_demangle::demangle(name)
+}
+
+/// Returns `name` as it is, demangling is disabled.
+#[cfg(not(feature = "demangle"))]
+pub(crate) fn demangle(name: &str) -> Mangled<'_> {
+    stats::count_demangle_call();
+    Mangled(name)
+}
+
+/// A symbol name displayed as found in the binary.
+#[cfg(not(feature = "demangle"))]
+pub(crate) struct Mangled<'a>(&'a str);
+
+#[cfg(not(feature = "demangle"))]
+impl fmt::Display for Mangled<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// A symbol name displayed according to the [`Demangling`] option.
+///
+///[`Demangling`]: ../enum.Demangling.html
+pub(crate) struct SymbolName<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) demangling: Demangling,
+}
+
+impl SymbolName<'_> {
+    /// Calls `func` with the displayed name, which is written into a buffer reused by the
+    /// thread instead of a new `String`.
+    ///
+    /// Frame filters check every symbol of deep stacks, allocating a string for each check adds
+    /// up to thousands of allocations per panic.
+    pub(crate) fn with_str<R>(&self, func: impl FnOnce(&str) -> R) -> R {
+        if self.demangling == Demangling::Off {
+            return func(self.name);
+        }
+        let mut func = Some(func);
+        let reused = NAME.try_with(|buf| {
+            // `func` may check symbols itself, then it gets a buffer of its own.
+            let mut buf = buf.try_borrow_mut().ok()?;
+            buf.clear();
+            write!(buf, "{}", self).unwrap();
+            Some(func.take().unwrap()(&buf))
+        });
+        match reused {
+            Ok(Some(result)) => result,
+            // The thread is torn down or the buffer is in use.
+            _ => func.take().unwrap()(&self.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for SymbolName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.demangling {
+            Demangling::WithHashes => write!(f, "{}", demangle(self.name)),
+            Demangling::WithoutHashes => write!(f, "{:#}", demangle(self.name)),
+            Demangling::Off => f.write_str(self.name),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "demangle"))]
+mod tests {
+    use {super::SymbolName, crate::options::Demangling};
+
+    #[test]
+    fn demangling_styles() {
+        let name = |name, demangling| SymbolName { name, demangling }.to_string();
+        let legacy = "_ZN6my_app4main17h0123456789abcdefE";
+        assert_eq!(
+            name(legacy, Demangling::WithHashes),
+            "my_app::main::h0123456789abcdef"
+        );
+        assert_eq!(name(legacy, Demangling::WithoutHashes), "my_app::main");
+        assert_eq!(name(legacy, Demangling::Off), legacy);
+
+        let v0 = "_RNvCs1234_6my_app4main";
+        assert!(name(v0, Demangling::WithHashes).starts_with("my_app["));
+        assert_eq!(name(v0, Demangling::WithoutHashes), "my_app::main");
+    }
+
+    #[test]
+    fn nested_names_get_their_own_buffer() {
+        let name = |name| SymbolName {
+            name,
+            demangling: Demangling::WithoutHashes,
+        };
+        let outer = name("_ZN6my_app4main17h0123456789abcdefE");
+        let inner = name("_ZN6my_app3run17h0123456789abcdefE");
+        outer.with_str(|outer| {
+            inner.with_str(|inner| assert_eq!(inner, "my_app::run"));
+            assert_eq!(outer, "my_app::main");
+        });
+    }
+}
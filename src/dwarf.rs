@@ -0,0 +1,138 @@
+//! Resolving frames with the `addr2line` crate, built with the `addr2line-backend` feature.
+//!
+//! The `addr2line` crate reads the DWARF debug info of the binaries mapped into the process
+//! itself, instead of going through the platform symbolizer the `backtrace` crate picks. This
+//! follows the inlined calls of release builds more closely. The debug info of each binary is
+//! loaded once and kept for the lifetime of the process.
+
+use {
+    crate::{
+        frame::{resolve_symbols, Frame, ResolvedFrame, ResolvedSymbol},
+        module::Modules,
+    },
+    addr2line::Loader,
+    object::{Object, ObjectSegment},
+    std::{
+        fs,
+        path::{Path, PathBuf},
+        sync::Mutex,
+    },
+};
+
+/// The loaded binaries, `None` for those without readable debug info.
+static BINARIES: Mutex<Vec<(PathBuf, Option<Binary>)>> = Mutex::new(Vec::new());
+
+/// The debug info and segments of a binary.
+struct Binary {
+    loader: Loader,
+    /// The file range and the address of each segment.
+    segments: Vec<(u64, u64, u64)>,
+}
+
+impl Binary {
+    fn open(path: &Path) -> Option<Self> {
+        let data = fs::read(path).ok()?;
+        let file = object::File::parse(&*data).ok()?;
+        let segments = file
+            .segments()
+            .filter_map(|segment| {
+                let (offset, size) = segment.file_range();
+                Some((offset, size, segment.address())).filter(|_| size > 0)
+            })
+            .collect();
+        Some(Binary {
+            loader: Loader::new(path).ok()?,
+            segments,
+        })
+    }
+
+    /// Returns the binary at `path` from the loaded `binaries`, loading it on first use.
+    fn get<'a>(binaries: &'a mut Vec<(PathBuf, Option<Binary>)>, path: &Path) -> Option<&'a Self> {
+        let index = match binaries.iter().position(|(known, _)| known == path) {
+            Some(index) => index,
+            None => {
+                binaries.push((path.to_owned(), Binary::open(path)));
+                binaries.len() - 1
+            }
+        };
+        binaries[index].1.as_ref()
+    }
+
+    /// Returns the address of the file offset `offset`.
+    fn address_of_offset(&self, offset: u64) -> Option<u64> {
+        self.segments
+            .iter()
+            .find(|(start, size, _)| *start <= offset && offset - start < *size)
+            .map(|(start, _, address)| address + (offset - start))
+    }
+
+    /// Returns the symbols at `address`, the innermost inlined function first.
+    fn symbols(&self, address: u64) -> Vec<ResolvedSymbol> {
+        let mut symbols = Vec::new();
+        if let Ok(mut frames) = self.loader.find_frames(address) {
+            while let Ok(Some(frame)) = frames.next() {
+                let location = frame.location.as_ref();
+                symbols.push(ResolvedSymbol {
+                    name: frame
+                        .function
+                        .as_ref()
+                        .and_then(|function| function.raw_name().ok())
+                        .map(|name| name.into_owned()),
+                    filename: location.and_then(|location| location.file).map(Into::into),
+                    lineno: location.and_then(|location| location.line),
+                    addr: None,
+                });
+            }
+        }
+        if symbols.iter().any(|symbol| symbol.name.is_some()) {
+            symbols
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+/// Resolves `frames` with the debug info of their binaries, and with the `backtrace` crate for
+/// the frames this finds no symbols for.
+pub(crate) fn resolve_frames<F: Frame>(frames: &[F]) -> Vec<ResolvedFrame> {
+    let modules = Modules::default();
+    let mut binaries = BINARIES.lock().unwrap_or_else(|err| err.into_inner());
+    frames
+        .iter()
+        .map(|frame| {
+            let ip = frame.ip();
+            // The addresses are return addresses after the call, which may belong to the next
+            // line or even to another function.
+            let symbols = modules
+                .find(ip.saturating_sub(1))
+                .and_then(|(path, offset)| {
+                    let binary = Binary::get(&mut binaries, path)?;
+                    let address = binary.address_of_offset(offset as u64)?;
+                    Some(binary.symbols(address))
+                })
+                .filter(|symbols| !symbols.is_empty())
+                .unwrap_or_else(|| resolve_symbols(ip));
+            ResolvedFrame { ip, symbols }
+        })
+        .collect()
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use {
+        crate::{format_backtrace_with, FormatOptions},
+        backtrace::Backtrace,
+    };
+
+    #[test]
+    fn resolves_with_the_debug_info() {
+        let options = FormatOptions::new().addr2line_backend(true);
+        let out = format_backtrace_with(&mut Backtrace::new_unresolved(), &options);
+        assert!(
+            out.contains("dwarf::tests::resolves_with_the_debug_info"),
+            "{}",
+            out
+        );
+        assert!(out.contains("src/dwarf.rs:"), "{}", out);
+    }
+}
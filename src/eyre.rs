@@ -0,0 +1,122 @@
+//! Integration with the [`eyre` crate](https://crates.io/crates/eyre).
+//!
+//! Installing the hook makes every `eyre::Report` capture a backtrace when it is created and
+//! render it with this crate's formatting when the report is printed with `{:?}`, so that error
+//! reports look the same as backtraces printed from a panic hook.
+//!
+//! ```no_run
+//! fn main() -> eyre::Result<()> {
+//!     backtrace_string::eyre::install().unwrap();
+//!
+//!     Err(eyre::eyre!("something went wrong"))
+//! }
+//! ```
+
+use {
+    crate::{format_backtrace_after_capture, write_error_chain, FormatOptions},
+    ::eyre::{EyreHandler, InstallError},
+    backtrace::Backtrace,
+    std::{error::Error, fmt, panic::Location},
+};
+
+/// Installs [`Handler`] as the hook used by `eyre` to create report handlers.
+///
+/// This fails if another hook was installed before.
+///
+///[`Handler`]: struct.Handler.html
+pub fn install() -> Result<(), InstallError> {
+    ::eyre::set_hook(Box::new(|_error| Box::new(Handler::new())))
+}
+
+/// An `eyre` report handler printing the backtrace using this crate's formatting.
+///
+/// The backtrace is captured unresolved when the report is created and only resolved when the
+/// report gets printed.
+pub struct Handler {
+    backtrace: Backtrace,
+    location: Option<&'static Location<'static>>,
+}
+
+impl Handler {
+    /// Creates a new handler, capturing the backtrace of the current thread.
+    pub fn new() -> Self {
+        Handler {
+            backtrace: Backtrace::new_unresolved(),
+            location: None,
+        }
+    }
+}
+
+impl Default for Handler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EyreHandler for Handler {
+    fn debug(&self, error: &(dyn Error + 'static), f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            return fmt::Debug::fmt(error, f);
+        }
+
+        let options = FormatOptions::current();
+        let vocabulary = &options.vocabulary;
+        write_error_chain(f, error, vocabulary)?;
+
+        if let Some(location) = self.location {
+            write!(
+                f,
+                "\n\n{}: {}:{}",
+                vocabulary.location,
+                location.file(),
+                location.line()
+            )?;
+        }
+
+        let mut bt = self.backtrace.clone();
+        write!(
+            f,
+            "\n\n{}:{}",
+            vocabulary.backtrace,
+            format_backtrace_after_capture(&mut bt, &options, is_capture_symbol)
+        )
+    }
+
+    fn track_caller(&mut self, location: &'static Location<'static>) {
+        self.location = Some(location);
+    }
+}
+
+/// Returns whether `sym` belongs to the code running between creating the `eyre::Report` and
+/// capturing the backtrace.
+fn is_capture_symbol(sym: &str) -> bool {
+    let sym = sym.trim_start_matches('<');
+    [
+        "backtrace::",
+        "backtrace_string::eyre::Handler",
+        "backtrace_string::eyre::install",
+        "eyre::",
+        "core::ops::function::",
+        "alloc::boxed::Box<F",
+    ]
+    .iter()
+    .any(|prefix| sym.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::install;
+
+    #[test]
+    fn report_contains_chain_and_backtrace() {
+        install().unwrap();
+
+        let report = ::eyre::eyre!("inner failure").wrap_err("outer failure");
+        let out = format!("{:?}", report);
+
+        assert!(out.starts_with("outer failure\n\nCaused by:\n   0: inner failure"));
+        assert!(out.contains("\n\nBacktrace:\n"));
+        assert!(out.contains("report_contains_chain_and_backtrace"));
+        assert!(!out.contains("eyre::capture_handler"));
+    }
+}
@@ -0,0 +1,106 @@
+//! Stable identification of backtraces by the symbols of their frames.
+
+use {
+    crate::{
+        demangle::demangle, filter_frames, frame::Frame, options::normalize_symbol, resolve,
+        FormatOptions,
+    },
+    backtrace::Backtrace,
+    std::fmt,
+};
+
+/// A hash of the symbol names of a backtrace's frames.
+///
+/// Addresses, file paths, line numbers and the hashes rustc appends to symbols are not included,
+/// so the fingerprint stays the same across builds and machines as long as the call stack
+/// consists of the same functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Fingerprint(pub u64);
+
+impl Fingerprint {
+    /// Computes the fingerprint of the given frames.
+    pub(crate) fn from_frames<'a, F: Frame + 'a>(frames: impl IntoIterator<Item = &'a F>) -> Self {
+        Fingerprint::from_frames_with_lines(frames, false)
+    }
+
+    /// Computes the fingerprint of the given frames, with `lines` also of the file names (without
+    /// their directories) and line numbers of their symbols.
+    pub(crate) fn from_frames_with_lines<'a, F: Frame + 'a>(
+        frames: impl IntoIterator<Item = &'a F>,
+        lines: bool,
+    ) -> Self {
+        let mut hash = Fnv1a::new();
+        for frame in frames {
+            for symbol in frame.symbols() {
+                match symbol.name_lossy() {
+                    Some(name) => {
+                        let name = format!("{:#}", demangle(&name));
+                        hash.write(normalize_symbol(&name).as_bytes());
+                    }
+                    None => hash.write(b"<unknown>"),
+                }
+                if lines {
+                    if let Some(file) = symbol.filename.and_then(|path| path.file_name()) {
+                        hash.write(b" ");
+                        hash.write(file.to_string_lossy().as_bytes());
+                    }
+                    if let Some(line) = symbol.lineno {
+                        hash.write(format!(":{}", line).as_bytes());
+                    }
+                }
+                hash.write(b"\n");
+            }
+            // Separates the frames, so moving an inlined symbol to its own frame changes it.
+            hash.write(b"\n");
+        }
+        Fingerprint(hash.finish())
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Computes the fingerprint of the frames [`format_backtrace_with()`] would format.
+///
+///[`format_backtrace_with()`]: fn.format_backtrace_with.html
+pub fn fingerprint(bt: &mut Backtrace, options: &FormatOptions) -> Fingerprint {
+    resolve(bt, options);
+    Fingerprint::from_frames(
+        filter_frames(bt.frames(), options).filter(|frame| options.is_visible(frame)),
+    )
+}
+
+/// The 64 bit FNV-1a hash, which unlike `DefaultHasher` is guaranteed to be stable.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Fnv1a;
+
+    #[test]
+    fn fnv1a_matches_reference() {
+        let mut hash = Fnv1a::new();
+        hash.write(b"foobar");
+        assert_eq!(hash.finish(), 0x85944171f73967e8);
+    }
+}
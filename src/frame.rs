@@ -0,0 +1,449 @@
+//! The frames the formatter works on, independent of where they were captured.
+//!
+//! The formatter only needs the symbol names, files, lines and addresses of each frame, which
+//! [`Frame`] provides. [`FrameSource`] is implemented for `backtrace::Backtrace`, slices and
+//! vectors of frames, including [`ResolvedFrame`]s built by the caller, e.g. from a trace captured
+//! by another tool:
+//!
+//! ```
+//! use backtrace_string::{
+//!     format_frame_source,
+//!     frame::{ResolvedFrame, ResolvedSymbol},
+//!     FormatOptions,
+//! };
+//!
+//! let frames = vec![ResolvedFrame {
+//!     ip: 0x5555_5555_a0b0,
+//!     symbols: vec![ResolvedSymbol {
+//!         name: Some(String::from("my_app::main")),
+//!         filename: Some("src/main.rs".into()),
+//!         lineno: Some(3),
+//!         addr: Some(0x5555_5555_a000),
+//!     }],
+//! }];
+//! let out = format_frame_source(&frames, &FormatOptions::new());
+//! assert_eq!(out, "\n   0: my_app::main\n          at src/main.rs:3\n");
+//! ```
+//!
+//! Stacks captured as plain return addresses, e.g. by custom samplers or eBPF, are resolved with
+//! the symbols of the current process by [`ResolvedFrame::from_return_addresses()`].
+//!
+//![`Frame`]: trait.Frame.html
+//![`FrameSource`]: trait.FrameSource.html
+//![`ResolvedFrame`]: struct.ResolvedFrame.html
+//![`ResolvedFrame::from_return_addresses()`]: struct.ResolvedFrame.html#method.from_return_addresses
+
+use {
+    crate::demangle::demangle,
+    backtrace::{Backtrace, BacktraceFrame},
+    std::{
+        borrow::Cow,
+        ffi::c_void,
+        fmt,
+        path::{Path, PathBuf},
+    },
+};
+
+/// A symbol of a frame, borrowed from the frame.
+///
+/// Frames have more than one symbol if functions were inlined into each other, the innermost
+/// function comes first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Symbol<'a> {
+    /// The name, either mangled or demangled, `None` if it isn't valid UTF-8.
+    pub name: Option<&'a str>,
+    /// The bytes of the name, also if it isn't valid UTF-8.
+    pub raw_name: Option<&'a [u8]>,
+    /// The source file.
+    pub filename: Option<&'a Path>,
+    /// The line in the source file.
+    pub lineno: Option<u32>,
+    /// The start address of the symbol.
+    pub addr: Option<usize>,
+}
+
+impl<'a> Symbol<'a> {
+    /// Returns the name, with invalid UTF-8 replaced by `U+FFFD REPLACEMENT CHARACTER`.
+    pub fn name_lossy(&self) -> Option<Cow<'a, str>> {
+        match (self.name, self.raw_name) {
+            (Some(name), _) => Some(Cow::Borrowed(name)),
+            (None, Some(bytes)) => Some(String::from_utf8_lossy(bytes)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// A stack frame which can be formatted.
+pub trait Frame {
+    /// The instruction pointer of the frame.
+    fn ip(&self) -> usize;
+
+    /// The symbols of the frame, empty if it isn't resolved.
+    fn symbols(&self) -> impl Iterator<Item = Symbol<'_>> + '_;
+
+    /// Returns whether any of the symbols has a name.
+    fn is_resolved(&self) -> bool {
+        self.symbols()
+            .any(|symbol| symbol.name.is_some() || symbol.raw_name.is_some())
+    }
+}
+
+impl<F: Frame + ?Sized> Frame for &F {
+    fn ip(&self) -> usize {
+        (**self).ip()
+    }
+
+    fn symbols(&self) -> impl Iterator<Item = Symbol<'_>> + '_ {
+        (**self).symbols()
+    }
+}
+
+impl Frame for BacktraceFrame {
+    fn ip(&self) -> usize {
+        BacktraceFrame::ip(self) as usize
+    }
+
+    fn symbols(&self) -> impl Iterator<Item = Symbol<'_>> + '_ {
+        BacktraceFrame::symbols(self).iter().map(|symbol| Symbol {
+            name: symbol.name().and_then(|name| name.as_str()),
+            raw_name: symbol.name().map(|name| name.as_bytes()),
+            filename: symbol.filename(),
+            lineno: symbol.lineno(),
+            addr: symbol.addr().map(|addr| addr as usize),
+        })
+    }
+}
+
+/// A frame with owned symbol information, for frames not captured by this crate.
+///
+/// It displays as a one-line summary, e.g. `my_app::main at src/main.rs:3`, with the names
+/// demangled; the alternate format (`{:#}`) leaves out the hashes rustc appends. The debug format
+/// shows all fields, with addresses in hexadecimal.
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct ResolvedFrame {
+    /// The instruction pointer of the frame.
+    pub ip: usize,
+    /// The symbols of the frame, the innermost inlined function first.
+    pub symbols: Vec<ResolvedSymbol>,
+}
+
+/// An owned symbol of a [`ResolvedFrame`].
+///
+/// Like the frame it displays as a one-line summary.
+///
+///[`ResolvedFrame`]: struct.ResolvedFrame.html
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct ResolvedSymbol {
+    /// The name, either mangled or demangled.
+    pub name: Option<String>,
+    /// The source file.
+    pub filename: Option<PathBuf>,
+    /// The line in the source file.
+    pub lineno: Option<u32>,
+    /// The start address of the symbol.
+    pub addr: Option<usize>,
+}
+
+impl Frame for ResolvedFrame {
+    fn ip(&self) -> usize {
+        self.ip
+    }
+
+    fn symbols(&self) -> impl Iterator<Item = Symbol<'_>> + '_ {
+        self.symbols.iter().map(|symbol| Symbol {
+            name: symbol.name.as_deref(),
+            raw_name: symbol.name.as_deref().map(str::as_bytes),
+            filename: symbol.filename.as_deref(),
+            lineno: symbol.lineno,
+            addr: symbol.addr,
+        })
+    }
+}
+
+impl fmt::Display for ResolvedFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut symbols = self.symbols.iter();
+        match symbols.next() {
+            Some(symbol) => fmt::Display::fmt(symbol, f)?,
+            None => write!(f, "<unknown> at {:#x}", self.ip)?,
+        }
+        for symbol in symbols {
+            f.write_str(", inlined into ")?;
+            fmt::Display::fmt(symbol, f)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ResolvedFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResolvedFrame")
+            .field("ip", &Hex(self.ip))
+            .field("symbols", &self.symbols)
+            .finish()
+    }
+}
+
+impl fmt::Display for ResolvedSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) if f.alternate() => write!(f, "{:#}", demangle(name))?,
+            Some(name) => write!(f, "{}", demangle(name))?,
+            None => f.write_str("<unknown>")?,
+        }
+        match (&self.filename, self.lineno) {
+            (Some(file), Some(line)) => write!(f, " at {}:{}", file.display(), line),
+            (Some(file), None) => write!(f, " at {}", file.display()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for ResolvedSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut f = f.debug_struct("ResolvedSymbol");
+        f.field("name", &self.name);
+        // Mangled names are hard to read in assertion failures.
+        if let Some(name) = &self.name {
+            let demangled = demangle(name).to_string();
+            if demangled != *name {
+                f.field("demangled", &demangled);
+            }
+        }
+        f.field("filename", &self.filename)
+            .field("lineno", &self.lineno)
+            .field("addr", &self.addr.map(Hex))
+            .finish()
+    }
+}
+
+/// Debug formats an address in hexadecimal.
+struct Hex(usize);
+
+impl fmt::Debug for Hex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}", self.0)
+    }
+}
+
+impl<'a> From<Symbol<'a>> for ResolvedSymbol {
+    fn from(symbol: Symbol<'a>) -> Self {
+        ResolvedSymbol {
+            name: symbol.name_lossy().map(Cow::into_owned),
+            filename: symbol.filename.map(PathBuf::from),
+            lineno: symbol.lineno,
+            addr: symbol.addr,
+        }
+    }
+}
+
+impl ResolvedFrame {
+    /// Resolves a stack captured as return addresses, e.g. by a custom sampler or over FFI,
+    /// with the symbols of the current process.
+    ///
+    /// The addresses start with the most recent call. Like for the frames of a `Backtrace`, the
+    /// symbols are looked up at the call instruction before each address. Without the `resolve`
+    /// feature the frames stay unresolved.
+    ///
+    /// ```
+    /// use backtrace_string::{format_frames, frame::ResolvedFrame, FormatOptions};
+    ///
+    /// let mut addresses = Vec::new();
+    /// backtrace::trace(|frame| {
+    ///     addresses.push(frame.ip());
+    ///     true
+    /// });
+    /// let frames = ResolvedFrame::from_return_addresses(&addresses);
+    /// println!("{}", format_frames(&frames, &FormatOptions::new()));
+    /// ```
+    pub fn from_return_addresses<A: ReturnAddress>(addresses: &[A]) -> Vec<ResolvedFrame> {
+        addresses
+            .iter()
+            .map(|address| {
+                let ip = address.to_usize();
+                ResolvedFrame {
+                    ip,
+                    symbols: if cfg!(feature = "resolve") {
+                        resolve_symbols(ip.saturating_sub(1))
+                    } else {
+                        Vec::new()
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+/// An address in a captured stack, implemented for integers and raw pointers.
+pub trait ReturnAddress: Copy {
+    /// Returns the address as integer.
+    fn to_usize(self) -> usize;
+}
+
+impl ReturnAddress for usize {
+    fn to_usize(self) -> usize {
+        self
+    }
+}
+
+impl ReturnAddress for u64 {
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+}
+
+impl ReturnAddress for *mut c_void {
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+}
+
+impl ReturnAddress for *const c_void {
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+}
+
+/// Resolves the symbols at `ip`, the innermost inlined function first.
+pub(crate) fn resolve_symbols(ip: usize) -> Vec<ResolvedSymbol> {
+    let mut symbols = Vec::new();
+    backtrace::resolve(ip as *mut c_void, |symbol| {
+        symbols.push(ResolvedSymbol {
+            name: symbol
+                .name()
+                .map(|name| String::from_utf8_lossy(name.as_bytes()).into_owned()),
+            filename: symbol.filename().map(Into::into),
+            lineno: symbol.lineno(),
+            addr: symbol.addr().map(|addr| addr as usize),
+        });
+    });
+    symbols
+}
+
+impl From<&BacktraceFrame> for ResolvedFrame {
+    fn from(frame: &BacktraceFrame) -> Self {
+        ResolvedFrame {
+            ip: Frame::ip(frame),
+            symbols: Frame::symbols(frame).map(ResolvedSymbol::from).collect(),
+        }
+    }
+}
+
+/// Something holding the frames of a backtrace, the most recent call first.
+///
+/// Backtraces need to be resolved before formatting them, otherwise only the addresses of the
+/// frames are known.
+pub trait FrameSource {
+    /// The type of the frames.
+    type Frame: Frame;
+
+    /// Returns the frames.
+    fn frames(&self) -> &[Self::Frame];
+}
+
+impl FrameSource for Backtrace {
+    type Frame = BacktraceFrame;
+
+    fn frames(&self) -> &[BacktraceFrame] {
+        Backtrace::frames(self)
+    }
+}
+
+impl<F: Frame> FrameSource for [F] {
+    type Frame = F;
+
+    fn frames(&self) -> &[F] {
+        self
+    }
+}
+
+impl<F: Frame> FrameSource for Vec<F> {
+    type Frame = F;
+
+    fn frames(&self) -> &[F] {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::ResolvedFrame,
+        crate::{format_backtrace_with, format_frame_source, FormatOptions},
+        backtrace::Backtrace,
+    };
+
+    #[test]
+    fn return_addresses_resolve_like_the_backtrace() {
+        let options = FormatOptions::new();
+        let mut bt = Backtrace::new_unresolved();
+        let addresses = bt
+            .frames()
+            .iter()
+            .map(|frame| frame.ip())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            format_frame_source(&ResolvedFrame::from_return_addresses(&addresses), &options),
+            format_backtrace_with(&mut bt, &options)
+        );
+    }
+
+    #[test]
+    fn resolved_frames_format_like_the_backtrace() {
+        let options = FormatOptions::new();
+        let mut bt = Backtrace::new();
+        let frames = bt
+            .frames()
+            .iter()
+            .map(ResolvedFrame::from)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            format_frame_source(&frames, &options),
+            format_backtrace_with(&mut bt, &options)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "demangle")]
+    fn resolved_frames_display_and_debug() {
+        use super::ResolvedSymbol;
+
+        let symbol = |name: &str, line| ResolvedSymbol {
+            name: Some(name.to_owned()),
+            filename: Some("src/main.rs".into()),
+            lineno: Some(line),
+            addr: Some(0x1000),
+        };
+        let frame = ResolvedFrame {
+            ip: 0x1010,
+            symbols: vec![
+                symbol("_ZN6my_app6helper17h0123456789abcdefE", 3),
+                symbol("my_app::main", 9),
+            ],
+        };
+        assert_eq!(
+            frame.to_string(),
+            "my_app::helper::h0123456789abcdef at src/main.rs:3, \
+             inlined into my_app::main at src/main.rs:9"
+        );
+        assert_eq!(
+            format!("{:#}", frame),
+            "my_app::helper at src/main.rs:3, inlined into my_app::main at src/main.rs:9"
+        );
+        let unresolved = ResolvedFrame {
+            ip: 0x1010,
+            symbols: Vec::new(),
+        };
+        assert_eq!(unresolved.to_string(), "<unknown> at 0x1010");
+        assert_eq!(
+            format!("{:?}", frame.symbols[0]),
+            "ResolvedSymbol { name: Some(\"_ZN6my_app6helper17h0123456789abcdefE\"), \
+             demangled: \"my_app::helper::h0123456789abcdef\", filename: Some(\"src/main.rs\"), \
+             lineno: Some(3), addr: Some(0x1000) }"
+        );
+        assert_eq!(
+            format!("{:?}", unresolved),
+            "ResolvedFrame { ip: 0x1010, symbols: [] }"
+        );
+    }
+}
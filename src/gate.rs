@@ -0,0 +1,216 @@
+//! Suppression of backtrace captures on hot paths.
+//!
+//! Capturing and especially resolving a backtrace is expensive. When backtraces are created for
+//! frequent errors, the [`CaptureGate`] configured with [`set_capture_gate()`] decides whether
+//! [`create_backtrace()`] captures at all, or returns a short placeholder instead.
+//!
+//! ```
+//! use backtrace_string::gate::{set_capture_gate, CaptureGate};
+//!
+//! // Capture one in 100 backtraces.
+//! set_capture_gate(CaptureGate::Sample { one_in: 100 });
+//! # set_capture_gate(CaptureGate::Always);
+//! ```
+//!
+//![`CaptureGate`]: enum.CaptureGate.html
+//![`set_capture_gate()`]: fn.set_capture_gate.html
+//![`create_backtrace()`]: ../fn.create_backtrace.html
+
+use {
+    backtrace::Backtrace,
+    std::{
+        collections::{hash_map::DefaultHasher, HashMap},
+        fmt,
+        hash::{Hash, Hasher},
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex, RwLock,
+        },
+    },
+};
+
+/// Decides which backtraces get captured.
+#[derive(Clone, Default)]
+#[non_exhaustive]
+pub enum CaptureGate {
+    /// Capture every backtrace, this is the default.
+    #[default]
+    Always,
+    /// Capture the first and then every `one_in`th backtrace.
+    Sample {
+        /// The sampling ratio, `0` and `1` capture every backtrace.
+        one_in: u64,
+    },
+    /// Capture each distinct call stack at most `per_stack` times.
+    ///
+    /// Call stacks are distinguished by their return addresses, so the backtrace still needs to
+    /// be captured, but the expensive symbol resolution and formatting are skipped. The counts
+    /// can be reset with [`reset_budgets()`](fn.reset_budgets.html).
+    Budget {
+        /// How often the same call stack is captured.
+        per_stack: u32,
+    },
+    /// Capture if the closure returns `true`.
+    Custom(Arc<dyn Fn() -> bool + Send + Sync>),
+}
+
+impl fmt::Debug for CaptureGate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureGate::Always => f.write_str("Always"),
+            CaptureGate::Sample { one_in } => {
+                f.debug_struct("Sample").field("one_in", one_in).finish()
+            }
+            CaptureGate::Budget { per_stack } => f
+                .debug_struct("Budget")
+                .field("per_stack", per_stack)
+                .finish(),
+            CaptureGate::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+static GATE: RwLock<CaptureGate> = RwLock::new(CaptureGate::Always);
+static STATE: GateState = GateState::new();
+
+/// Sets the gate consulted by [`create_backtrace()`](../fn.create_backtrace.html).
+pub fn set_capture_gate(gate: CaptureGate) {
+    *GATE.write().unwrap_or_else(|err| err.into_inner()) = gate;
+}
+
+/// Forgets how often each call stack was captured by a [`CaptureGate::Budget`].
+///
+///[`CaptureGate::Budget`]: enum.CaptureGate.html#variant.Budget
+pub fn reset_budgets() {
+    *STATE.budgets.lock().unwrap_or_else(|err| err.into_inner()) = None;
+}
+
+/// Returns a placeholder if the backtrace shouldn't be captured.
+pub(crate) fn skip_before_capture() -> Option<String> {
+    current_gate().skip_before_capture(&STATE)
+}
+
+/// Returns a placeholder if the captured but not yet resolved backtrace shouldn't be formatted.
+pub(crate) fn skip_after_capture(bt: &Backtrace) -> Option<String> {
+    current_gate().skip_after_capture(&STATE, bt)
+}
+
+/// Returns the configured gate, cloned so the lock isn't held while a [`CaptureGate::Custom`]
+/// closure runs, which may set the gate itself.
+///
+///[`CaptureGate::Custom`]: enum.CaptureGate.html#variant.Custom
+fn current_gate() -> CaptureGate {
+    GATE.read().unwrap_or_else(|err| err.into_inner()).clone()
+}
+
+/// The counters of the sampling and budget gates.
+struct GateState {
+    sampled: AtomicU64,
+    budgets: Mutex<Option<HashMap<u64, u32>>>,
+}
+
+impl GateState {
+    const fn new() -> Self {
+        GateState {
+            sampled: AtomicU64::new(0),
+            budgets: Mutex::new(None),
+        }
+    }
+}
+
+impl CaptureGate {
+    fn skip_before_capture(&self, state: &GateState) -> Option<String> {
+        match self {
+            CaptureGate::Always | CaptureGate::Budget { .. } => None,
+            CaptureGate::Sample { one_in } => {
+                let count = state.sampled.fetch_add(1, Ordering::Relaxed);
+                if *one_in <= 1 || count % *one_in == 0 {
+                    None
+                } else {
+                    Some(format!(
+                        "\n      <backtrace not captured, sampling 1 in {}>\n",
+                        one_in
+                    ))
+                }
+            }
+            CaptureGate::Custom(gate) => {
+                if gate() {
+                    None
+                } else {
+                    Some(String::from("\n      <backtrace not captured>\n"))
+                }
+            }
+        }
+    }
+
+    fn skip_after_capture(&self, state: &GateState, bt: &Backtrace) -> Option<String> {
+        let per_stack = match self {
+            CaptureGate::Budget { per_stack } => *per_stack,
+            _ => return None,
+        };
+
+        let mut hasher = DefaultHasher::new();
+        for frame in bt.frames() {
+            (frame.ip() as usize).hash(&mut hasher);
+        }
+        let key = hasher.finish();
+
+        let mut budgets = state.budgets.lock().unwrap_or_else(|err| err.into_inner());
+        let count = budgets
+            .get_or_insert_with(HashMap::new)
+            .entry(key)
+            .or_insert(0);
+        if *count < per_stack {
+            *count += 1;
+            None
+        } else {
+            Some(format!(
+                "\n      <backtrace not captured, same call stack already captured {} times>\n",
+                per_stack
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{set_capture_gate, skip_before_capture, CaptureGate, GateState, GATE},
+        backtrace::Backtrace,
+        std::sync::Arc,
+    };
+
+    #[test]
+    fn sample_captures_every_nth() {
+        let state = GateState::new();
+        let gate = CaptureGate::Sample { one_in: 3 };
+        let captured = (0..6)
+            .filter(|_| gate.skip_before_capture(&state).is_none())
+            .count();
+        assert_eq!(captured, 2);
+    }
+
+    #[test]
+    fn budget_limits_captures_per_stack() {
+        let state = GateState::new();
+        let gate = CaptureGate::Budget { per_stack: 2 };
+        let skipped = (0..3)
+            .map(|_| gate.skip_after_capture(&state, &Backtrace::new_unresolved()))
+            .collect::<Vec<_>>();
+        assert_eq!(skipped[..2], [None, None]);
+        assert!(skipped[2]
+            .as_ref()
+            .unwrap()
+            .contains("already captured 2 times"));
+    }
+
+    #[test]
+    fn custom_gate_can_replace_itself() {
+        set_capture_gate(CaptureGate::Custom(Arc::new(|| {
+            set_capture_gate(CaptureGate::Always);
+            true
+        })));
+        assert_eq!(skip_before_capture(), None);
+        assert!(matches!(*GATE.read().unwrap(), CaptureGate::Always));
+    }
+}
@@ -0,0 +1,205 @@
+//! Running code in a child process to test panic hooks and crash reporting.
+//!
+//! The panic hook is global to the process, so tests installing one race with every other test of
+//! the binary, and reports written straight to stderr can't be captured in-process.
+//! [`run_in_subprocess()`] reruns the test binary with only the calling test and runs the closure
+//! in that child process, returning what it wrote and how it exited:
+//!
+//! ```no_run
+//! use backtrace_string::harness::run_in_subprocess;
+//!
+//! #[test]
+//! fn panics_are_reported() {
+//!     let output = run_in_subprocess(|| {
+//!         std::panic::set_hook(Box::new(|_| eprint!("{}", backtrace_string::create_backtrace())));
+//!         panic!("boom");
+//!     });
+//!     assert!(output.panicked());
+//!     assert!(output.stderr.contains("panics_are_reported"));
+//! }
+//! ```
+//!
+//! The child process is found by the name of the test, which is the name of the thread the test
+//! runs on. Tests running the closure on another thread set it with [`Subprocess::test_name()`].
+//!
+//![`run_in_subprocess()`]: fn.run_in_subprocess.html
+//![`Subprocess::test_name()`]: struct.Subprocess.html#method.test_name
+
+use std::{
+    env,
+    ffi::OsString,
+    io::{self, Write},
+    panic::{self, AssertUnwindSafe},
+    process::{self, Command, ExitStatus},
+    thread,
+};
+
+/// Set in the child process to the name of the test it runs.
+const HARNESS_ENV_VAR: &str = "BACKTRACE_STRING_HARNESS";
+
+/// Written by the child process before running the closure, the output of the test harness
+/// before it is left out.
+const START_MARKER: &str = "\u{1}backtrace-string harness start\u{1}\n";
+
+/// The exit code of a child process whose closure panicked, the same as of a Rust program
+/// exiting due to a panic.
+const PANIC_EXIT_CODE: i32 = 101;
+
+/// A configurable run of a closure in a child process, see the [module docs].
+///
+///[module docs]: index.html
+#[derive(Debug, Clone, Default)]
+pub struct Subprocess {
+    test_name: Option<String>,
+    envs: Vec<(OsString, OsString)>,
+}
+
+/// How the child process exited and what it wrote.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct SubprocessOutput {
+    /// The exit status of the child process.
+    pub status: ExitStatus,
+    /// What the closure wrote to stdout.
+    pub stdout: String,
+    /// What the closure and the panic hook wrote to stderr.
+    pub stderr: String,
+}
+
+impl SubprocessOutput {
+    /// Returns whether the closure panicked, without the process being aborted.
+    pub fn panicked(&self) -> bool {
+        self.status.code() == Some(PANIC_EXIT_CODE)
+    }
+}
+
+/// Runs `func` in a child process, see the [module docs].
+///
+/// # Panics
+///
+/// If the test name can't be found or the child process can't be run.
+///
+///[module docs]: index.html
+pub fn run_in_subprocess(func: impl FnOnce()) -> SubprocessOutput {
+    Subprocess::new()
+        .run(func)
+        .expect("running the test in a child process failed")
+}
+
+impl Subprocess {
+    /// Creates a run in a child process with the environment of the parent.
+    pub fn new() -> Self {
+        Subprocess::default()
+    }
+
+    /// Sets the name of the test which runs the closure, as shown by the test harness (e.g.
+    /// `tests::panics_are_reported`). By default it is the name of the current thread.
+    pub fn test_name(mut self, name: impl Into<String>) -> Self {
+        self.test_name = Some(name.into());
+        self
+    }
+
+    /// Sets an environment variable of the child process, e.g. `RUST_BACKTRACE`.
+    pub fn env(mut self, key: impl Into<OsString>, value: impl Into<OsString>) -> Self {
+        self.envs.push((key.into(), value.into()));
+        self
+    }
+
+    /// Runs `func` in the child process and waits for it to exit.
+    ///
+    /// In the child process this doesn't return, it exits once `func` returned or panicked.
+    pub fn run(self, func: impl FnOnce()) -> io::Result<SubprocessOutput> {
+        let test_name = match self.test_name {
+            Some(name) => name,
+            None => match thread::current().name() {
+                Some(name) if name != "main" => name.to_owned(),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "the test name is unknown, set it with `Subprocess::test_name()`",
+                    ))
+                }
+            },
+        };
+        if env::var_os(HARNESS_ENV_VAR).is_some_and(|name| name == *test_name) {
+            run_child(func);
+        }
+
+        let output = Command::new(env::current_exe()?)
+            .args([&test_name, "--exact", "--nocapture", "--test-threads=1"])
+            .env(HARNESS_ENV_VAR, &test_name)
+            .envs(self.envs)
+            .output()?;
+        let after_start = |out: Vec<u8>| {
+            let out = String::from_utf8_lossy(&out).into_owned();
+            match out.find(START_MARKER) {
+                Some(start) => out[start + START_MARKER.len()..].to_owned(),
+                None => out,
+            }
+        };
+        Ok(SubprocessOutput {
+            status: output.status,
+            stdout: after_start(output.stdout),
+            stderr: after_start(output.stderr),
+        })
+    }
+}
+
+fn run_child(func: impl FnOnce()) -> ! {
+    print!("{}", START_MARKER);
+    eprint!("{}", START_MARKER);
+    let _ = io::stdout().flush();
+    let result = panic::catch_unwind(AssertUnwindSafe(func));
+    let _ = io::stdout().flush();
+    // Exiting here keeps the test harness from adding its own output.
+    process::exit(match result {
+        Ok(()) => 0,
+        Err(_) => PANIC_EXIT_CODE,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Subprocess;
+
+    #[test]
+    #[cfg(all(feature = "resolve", feature = "demangle"))]
+    fn panics_are_reported_in_the_child() {
+        use {
+            super::run_in_subprocess,
+            crate::{create_backtrace, payload::describe},
+            std::panic,
+        };
+
+        let output = run_in_subprocess(|| {
+            panic::set_hook(Box::new(|info| {
+                eprint!("hook: {}{}", describe(info.payload()), create_backtrace());
+            }));
+            println!("before the panic");
+            panic!("boom");
+        });
+        assert!(output.panicked(), "{:?}", output);
+        assert_eq!(output.stdout, "before the panic\n");
+        assert!(
+            output.stderr.starts_with("hook: boom\n"),
+            "{}",
+            output.stderr
+        );
+        assert!(
+            output.stderr.contains("panics_are_reported_in_the_child"),
+            "{}",
+            output.stderr
+        );
+    }
+
+    #[test]
+    fn environment_and_exit() {
+        let output = Subprocess::new()
+            .env("BACKTRACE_STRING_TEST", "set")
+            .run(|| print!("{}", std::env::var("BACKTRACE_STRING_TEST").unwrap()))
+            .unwrap();
+        assert!(output.status.success() && !output.panicked());
+        assert_eq!(output.stdout, "set");
+        assert_eq!(output.stderr, "");
+    }
+}
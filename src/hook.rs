@@ -0,0 +1,315 @@
+//! Capturing of backtraces from inside the panic hook on behalf of code further up the stack.
+//!
+//! Once a panic unwound to a `catch_unwind` the stack of the panic site is gone, so code which
+//! wants to report panics with a backtrace needs the panic hook to capture it. [`catch_captured`]
+//! enables the capture for the current thread and installs a hook doing so (chained with the
+//! previously installed one) the first time it is used.
+//!
+//! [`install_panic_hook()`] installs a hook reporting every panic to the [sink], chained with
+//! the previous hook like `std::panic::update_hook` (which isn't stable yet) would, and
+//! [`uninstall_panic_hook()`] puts the previous hook back.
+//!
+//![sink]: ../sink/index.html
+//![`install_panic_hook()`]: fn.install_panic_hook.html
+//![`uninstall_panic_hook()`]: fn.uninstall_panic_hook.html
+
+use {
+    crate::{
+        capture_limited, coredump, decor::draw_box, fingerprint, format_backtrace_with, panics,
+        payload, sink, stack, FormatOptions,
+    },
+    backtrace::Backtrace,
+    std::{
+        any::Any,
+        borrow::Cow,
+        cell::{Cell, RefCell},
+        fmt::Write,
+        panic::{self, AssertUnwindSafe, PanicHookInfo},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex, Once,
+        },
+        thread,
+    },
+};
+
+thread_local! {
+    static CAPTURE_DEPTH: Cell<usize> = const { Cell::new(0) };
+    static CAPTURED: RefCell<Option<CapturedPanic>> = const { RefCell::new(None) };
+    /// Whether the panic being handled on this thread was reported, see `mark_panic_reported()`.
+    static PANIC_REPORTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// A panic captured by the panic hook.
+pub(crate) struct CapturedPanic {
+    message: String,
+    location: Option<(String, u32, u32)>,
+    backtrace: Backtrace,
+    #[cfg(feature = "logical-frames")]
+    logical_frames: Option<String>,
+    stack_usage: Option<stack::StackUsage>,
+    #[cfg(feature = "tracing-error")]
+    span_trace: tracing_error::SpanTrace,
+}
+
+impl CapturedPanic {
+    fn from_hook(info: &PanicHookInfo) -> Self {
+        let options = FormatOptions::current();
+        CapturedPanic {
+            message: payload::describe(info.payload()),
+            location: info
+                .location()
+                .map(|loc| (loc.file().to_owned(), loc.line(), loc.column())),
+            backtrace: match options.capture_depth {
+                Some(max_depth) => capture_limited(max_depth),
+                None => Backtrace::new_unresolved(),
+            },
+            #[cfg(feature = "logical-frames")]
+            logical_frames: crate::logical::format_current_frames(),
+            stack_usage: if options.stack_usage {
+                stack::usage()
+            } else {
+                None
+            },
+            #[cfg(feature = "tracing-error")]
+            span_trace: tracing_error::SpanTrace::capture(),
+        }
+    }
+
+    /// Formats the panic as a report, `subject` names what panicked (e.g. `thread 'worker'`).
+    pub(crate) fn into_report(mut self, subject: &str, options: &FormatOptions) -> String {
+        // The box goes around the whole report, not just the backtrace.
+        let mut options = Cow::Borrowed(options);
+        let box_title = options.to_mut().box_title.take();
+        if let Some((file, line, _)) = &self.location {
+            options = Cow::Owned(options.into_owned().panic_location(file, *line));
+        }
+        #[cfg(feature = "tracing-error")]
+        let spans = crate::tracing_error::collect_spans(&self.span_trace);
+        #[cfg(feature = "tracing-error")]
+        if !spans.is_empty() {
+            options = Cow::Owned(crate::tracing_error::annotate_spans(
+                options.into_owned(),
+                &spans,
+            ));
+        }
+
+        let vocabulary = &options.vocabulary;
+        let mut header = format!("{} {}", subject, vocabulary.panicked);
+        if let Some((file, line, column)) = &self.location {
+            write!(header, " {} {}:{}:{}", vocabulary.at, file, line, column).unwrap();
+        }
+        header.push_str(": ");
+        header.push_str(&self.message);
+        let painter = options.painter();
+        let mut out = painter.header(header).to_string();
+        if let Some(usage) = &self.stack_usage {
+            write!(out, "\n{}", vocabulary.stack_usage(usage.used, usage.size)).unwrap();
+        }
+        write!(out, "\n{}:", vocabulary.backtrace).unwrap();
+        out.push_str(&format_backtrace_with(&mut self.backtrace, &options));
+        #[cfg(feature = "logical-frames")]
+        if let Some(logical_frames) = &self.logical_frames {
+            write!(out, "{}:", vocabulary.logical_frames).unwrap();
+            out.push_str(logical_frames);
+        }
+        #[cfg(feature = "tracing-error")]
+        crate::tracing_error::write_spans(&mut out, &spans, &options);
+        if options.core_dump_hint {
+            if let Some(core_dumps) = coredump::probe() {
+                writeln!(out, "{}: {}", vocabulary.note, core_dumps).unwrap();
+            }
+        }
+        let out = match box_title {
+            Some(title) => draw_box(&title, &out),
+            None => out,
+        };
+        if panics::is_tracking() {
+            panics::record(fingerprint(&mut self.backtrace, &options), &out);
+        }
+        out
+    }
+}
+
+/// The payload of a caught panic and the panic captured by the hook, boxed to keep results small.
+pub(crate) type CaughtPanic = (Box<dyn Any + Send>, Option<Box<CapturedPanic>>);
+
+/// Runs `func`, catching a panic together with the backtrace captured by the panic hook.
+///
+/// The captured panic is `None` if the hook was replaced by someone else.
+pub(crate) fn catch_captured<T>(func: impl FnOnce() -> T) -> Result<T, CaughtPanic> {
+    install_capture_hook();
+
+    CAPTURE_DEPTH.with(|depth| depth.set(depth.get() + 1));
+    let result = panic::catch_unwind(AssertUnwindSafe(func));
+    CAPTURE_DEPTH.with(|depth| depth.set(depth.get() - 1));
+
+    let captured = CAPTURED.with(|captured| captured.borrow_mut().take().map(Box::new));
+    result.map_err(|payload| (payload, captured))
+}
+
+fn install_capture_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let old_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            #[cfg(feature = "hook-arena")]
+            let _arena = crate::arena::enter();
+            if CAPTURE_DEPTH.with(|depth| depth.get()) > 0 {
+                let panic = CapturedPanic::from_hook(info);
+                CAPTURED.with(|captured| *captured.borrow_mut() = Some(panic));
+            }
+            old_hook(info);
+        }));
+    });
+}
+
+type PanicHook = Box<dyn Fn(&PanicHookInfo<'_>) + Sync + Send + 'static>;
+
+/// The hook installed by `install_panic_hook()`, if it wasn't uninstalled.
+static REPORT_HOOK: Mutex<Option<ReportHook>> = Mutex::new(None);
+
+struct ReportHook {
+    /// The address of the installed hook, to recognize it when it is taken back.
+    address: usize,
+    chained: Arc<Chained>,
+}
+
+/// The state shared with the installed hook.
+struct Chained {
+    previous: PanicHook,
+    /// Cleared when the hook is uninstalled while another hook was chained on top of it.
+    reporting: AtomicBool,
+}
+
+/// Installs a panic hook writing a report of each panic to the [sink], like the reports of
+/// [`spawn_reported()`], formatted with the [current options].
+///
+/// The hook runs the previously installed hook first, so libraries can add reporting without
+/// replacing the hook of the application (or the default one printing the panic message).
+/// Panics caught by [`spawn_reported()`] and the likes are left to them. Returns `false` if
+/// the hook is already installed.
+///
+/// ```
+/// backtrace_string::install_panic_hook();
+/// let _ = std::panic::catch_unwind(|| panic!("reported"));
+/// backtrace_string::uninstall_panic_hook();
+/// ```
+///
+///[sink]: sink/index.html
+///[`spawn_reported()`]: thread/fn.spawn_reported.html
+///[current options]: struct.FormatOptions.html#method.current
+pub fn install_panic_hook() -> bool {
+    let mut installed = REPORT_HOOK.lock().unwrap_or_else(|err| err.into_inner());
+    if installed.is_some() {
+        return false;
+    }
+    let chained = Arc::new(Chained {
+        previous: panic::take_hook(),
+        reporting: AtomicBool::new(true),
+    });
+    let shared = chained.clone();
+    let hook: PanicHook = Box::new(move |info| {
+        // Once the hooks chained below are done, the mark of the panic has served its purpose.
+        let _clear = ClearReported;
+        (shared.previous)(info);
+        if !shared.reporting.load(Ordering::Relaxed) || CAPTURE_DEPTH.with(|depth| depth.get()) > 0
+        {
+            return;
+        }
+        let options = FormatOptions::current();
+        if options.report_once && panic_reported() {
+            return;
+        }
+        #[cfg(feature = "hook-arena")]
+        let _arena = crate::arena::enter();
+        let current = thread::current();
+        let subject = format!("thread '{}'", current.name().unwrap_or("<unnamed>"));
+        sink::write_report(&CapturedPanic::from_hook(info).into_report(&subject, &options));
+    });
+    *installed = Some(ReportHook {
+        address: hook_address(&hook),
+        chained,
+    });
+    panic::set_hook(hook);
+    true
+}
+
+/// Uninstalls the hook of [`install_panic_hook()`], putting back the hook it replaced.
+///
+/// If another hook was installed on top of it since, that one stays installed and keeps
+/// running the previous hooks, only the reporting stops. Returns whether the previous hook was
+/// put back, `false` if it wasn't or the hook wasn't installed.
+///
+///[`install_panic_hook()`]: fn.install_panic_hook.html
+pub fn uninstall_panic_hook() -> bool {
+    let mut installed = REPORT_HOOK.lock().unwrap_or_else(|err| err.into_inner());
+    let ReportHook { address, chained } = match installed.take() {
+        Some(installed) => installed,
+        None => return false,
+    };
+    let current = panic::take_hook();
+    if hook_address(&current) != address {
+        chained.reporting.store(false, Ordering::Relaxed);
+        panic::set_hook(current);
+        return false;
+    }
+    // Taking the hook waited for running hooks, so this is the last reference.
+    drop(current);
+    match Arc::try_unwrap(chained) {
+        Ok(chained) => {
+            panic::set_hook(chained.previous);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Marks the panic the current thread is handling as reported, keeping the hook of
+/// [`install_panic_hook()`] from reporting it again if configured with
+/// [`FormatOptions::report_once()`].
+///
+/// Other reporters call this from their panic hook to cooperate, either from a hook chained
+/// below the one of [`install_panic_hook()`], i.e. installed before it, or from one installed on
+/// top of it before calling it:
+///
+/// ```
+/// std::panic::set_hook(Box::new(|info| {
+///     eprintln!("{}", info);
+///     backtrace_string::mark_panic_reported();
+/// }));
+/// backtrace_string::install_panic_hook();
+/// ```
+///
+/// The mark only applies to the current panic, it is cleared once the hook of
+/// [`install_panic_hook()`] is done with it. Outside of a panic this does nothing.
+///
+///[`install_panic_hook()`]: fn.install_panic_hook.html
+///[`FormatOptions::report_once()`]: struct.FormatOptions.html#method.report_once
+pub fn mark_panic_reported() {
+    if thread::panicking() {
+        PANIC_REPORTED.with(|reported| reported.set(true));
+    }
+}
+
+/// Returns whether the panic the current thread is handling was reported, see
+/// [`mark_panic_reported()`].
+///
+///[`mark_panic_reported()`]: fn.mark_panic_reported.html
+pub fn panic_reported() -> bool {
+    PANIC_REPORTED.with(Cell::get)
+}
+
+/// Clears the mark of `mark_panic_reported()` when dropped.
+struct ClearReported;
+
+impl Drop for ClearReported {
+    fn drop(&mut self) {
+        PANIC_REPORTED.with(|reported| reported.set(false));
+    }
+}
+
+/// Returns the address of `hook`, which stays the same while it is installed.
+fn hook_address(hook: &PanicHook) -> usize {
+    &**hook as *const (dyn Fn(&PanicHookInfo<'_>) + Sync + Send) as *const () as usize
+}
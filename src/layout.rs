@@ -0,0 +1,99 @@
+//! The layout of the formatted frames, shared by the formatter, the [`BacktraceBuffer`] and the
+//! panic reports of `#![no_std]` firmware.
+//!
+//! Everything here writes into a `fmt::Write` without allocating and is available without `std`.
+//!
+//![`BacktraceBuffer`]: ../struct.BacktraceBuffer.html
+
+use {
+    crate::vocabulary::{write_filled, Vocabulary},
+    core::fmt::{self, Display, Write},
+};
+
+/// The width the frame numbers are aligned to by default.
+pub(crate) const NUMBER_WIDTH: usize = 4;
+
+/// The layout of the formatted frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Style {
+    /// Each symbol's location goes on its own line below the symbol, this is the default.
+    #[default]
+    Full,
+    /// Each symbol goes on a single line, followed by its location in parentheses.
+    Compact,
+}
+
+/// The parts of the options laying out the frames.
+#[derive(Clone, Copy)]
+pub(crate) struct Layout<'a> {
+    pub(crate) style: Style,
+    /// The width the frame numbers are aligned to.
+    pub(crate) width: usize,
+    pub(crate) vocabulary: &'a Vocabulary,
+}
+
+impl<'a> Layout<'a> {
+    /// Returns the number of a frame, or of a symbol numbered like one, aligned to the width.
+    pub(crate) fn number(&self, number: usize) -> impl Display {
+        Aligned(number, self.width)
+    }
+
+    /// Returns the location standing in for the one of an unresolved frame at `ip`, e.g.
+    /// `address 0x55d1c0a1b2c3`.
+    pub(crate) fn address(&self, ip: usize) -> impl Display + 'a {
+        Address(&self.vocabulary.address, ip)
+    }
+
+    /// Starts a line continuing a frame, indented by `extra` columns beyond the frame numbers.
+    pub(crate) fn write_indent(&self, out: &mut impl Write, extra: usize) -> fmt::Result {
+        write!(out, "\n{:1$}", "", self.width + extra)
+    }
+
+    /// Writes the location of a symbol in the layout of the style.
+    pub(crate) fn write_location(
+        &self,
+        out: &mut impl Write,
+        location: impl Display,
+    ) -> fmt::Result {
+        match self.style {
+            Style::Full => {
+                self.write_indent(out, 6)?;
+                write!(out, "{} {}", self.vocabulary.at, location)
+            }
+            Style::Compact => write!(out, " ({})", location),
+        }
+    }
+
+    /// Writes the symbol and location of an unresolved frame at `ip`, after its number.
+    pub(crate) fn write_unresolved(&self, out: &mut impl Write, ip: usize) -> fmt::Result {
+        write!(out, " {}", self.vocabulary.unknown)?;
+        self.write_location(out, self.address(ip))
+    }
+
+    /// Writes the line for `count` frames left out because of the frame limit.
+    pub(crate) fn write_omitted(&self, out: &mut impl Write, count: usize) -> fmt::Result {
+        out.write_str("      ")?;
+        write_filled(out, &self.vocabulary.frames_omitted, &[&count])?;
+        writeln!(out)
+    }
+}
+
+/// A number right-aligned to a width.
+struct Aligned(usize, usize);
+
+impl Display for Aligned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:1$}", self.0, self.1)
+    }
+}
+
+/// An address introduced by the [`Vocabulary::address`] word.
+///
+///[`Vocabulary::address`]: ../struct.Vocabulary.html#structfield.address
+struct Address<'a>(&'a str, usize);
+
+impl Display for Address<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:#x}", self.0, self.1)
+    }
+}
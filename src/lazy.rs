@@ -0,0 +1,274 @@
+//! Backtraces captured when an error is created and formatted when it is shown.
+//!
+//! Capturing the stack is cheap compared to resolving and formatting it, which most errors never
+//! need as they are handled without being shown. A [`LazyBacktrace`] stored in an error captures
+//! the stack where the error is created and formats it with the [current options] on first use:
+//!
+//! ```
+//! use backtrace_string::lazy::LazyBacktrace;
+//!
+//! #[derive(Debug)]
+//! struct ConfigError {
+//!     key: String,
+//!     backtrace: LazyBacktrace,
+//! }
+//!
+//! let error = ConfigError {
+//!     key: String::from("port"),
+//!     backtrace: LazyBacktrace::capture(),
+//! };
+//! eprintln!("invalid {}, backtrace:{}", error.key, error.backtrace);
+//! ```
+//!
+//! With the `derive` feature, `#[derive(Backtraced)]` adds the accessor and the `From` impls
+//! capturing the backtrace to error types, see [`Backtraced`].
+//!
+//! For errors of other crates, [`ResultExt::with_trace()`] wraps the error of a `Result` in an
+//! [`ErrorTrace`] capturing the stack where it was propagated:
+//!
+//! ```
+//! use backtrace_string::lazy::{ErrorTrace, ResultExt};
+//!
+//! fn read_config() -> Result<String, ErrorTrace<std::io::Error>> {
+//!     let config = std::fs::read_to_string("/nonexistent/config.toml").with_trace()?;
+//!     Ok(config)
+//! }
+//!
+//! if let Err(error) = read_config() {
+//!     eprintln!("{}", error);
+//! }
+//! ```
+//!
+//![`LazyBacktrace`]: struct.LazyBacktrace.html
+//![`ResultExt::with_trace()`]: trait.ResultExt.html#tymethod.with_trace
+//![`ErrorTrace`]: struct.ErrorTrace.html
+//![current options]: ../struct.FormatOptions.html#method.current
+//![`Backtraced`]: ../derive.Backtraced.html
+
+use {
+    crate::{capture_below, format_backtrace_with, format_chain_with, gate, FormatOptions},
+    backtrace::Backtrace,
+    std::{
+        error::Error,
+        fmt,
+        sync::{Mutex, OnceLock},
+    },
+};
+
+/// A backtrace captured eagerly and formatted lazily.
+///
+/// It displays as the formatted backtrace, the debug format leaves it out so errors deriving
+/// `Debug` stay readable.
+pub struct LazyBacktrace {
+    captured: Mutex<Option<Backtrace>>,
+    formatted: OnceLock<String>,
+}
+
+impl LazyBacktrace {
+    /// Captures the stack below the caller, unless the [capture gate] suppresses it.
+    ///
+    ///[capture gate]: ../gate/index.html
+    #[inline(never)]
+    pub fn capture() -> Self {
+        Self::capture_below(Self::capture as *const () as usize)
+    }
+
+    /// Captures the stack below the function starting at `ip`, see `capture_below()`.
+    fn capture_below(ip: usize) -> Self {
+        let backtrace = LazyBacktrace {
+            captured: Mutex::new(None),
+            formatted: OnceLock::new(),
+        };
+        match gate::skip_before_capture() {
+            Some(placeholder) => {
+                let _ = backtrace.formatted.set(placeholder);
+            }
+            None => {
+                let bt = capture_below(ip);
+                *backtrace.captured.lock().unwrap() = Some(bt);
+            }
+        }
+        backtrace
+    }
+
+    /// Returns the backtrace formatted with the [current options] of the first call.
+    ///
+    ///[current options]: ../struct.FormatOptions.html#method.current
+    pub fn as_str(&self) -> &str {
+        self.formatted.get_or_init(|| {
+            self.with_captured(|bt| match gate::skip_after_capture(bt) {
+                Some(placeholder) => placeholder,
+                None => format_backtrace_with(bt, &FormatOptions::current()),
+            })
+        })
+    }
+
+    /// Runs `func` with the captured backtrace, which is empty if the capture was suppressed.
+    fn with_captured<R>(&self, func: impl FnOnce(&mut Backtrace) -> R) -> R {
+        let mut captured = self.captured.lock().unwrap_or_else(|err| err.into_inner());
+        func(captured.get_or_insert_with(|| Backtrace::from(Vec::new())))
+    }
+}
+
+impl fmt::Display for LazyBacktrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for LazyBacktrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LazyBacktrace").finish_non_exhaustive()
+    }
+}
+
+/// An error with the backtrace of where it was propagated, see [`ResultExt::with_trace()`].
+///
+/// It displays as the error followed by the backtrace, its source is the one of the error.
+///
+///[`ResultExt::with_trace()`]: trait.ResultExt.html#tymethod.with_trace
+#[derive(Debug)]
+pub struct ErrorTrace<E> {
+    error: E,
+    backtrace: LazyBacktrace,
+}
+
+impl<E> ErrorTrace<E> {
+    /// Returns the wrapped error.
+    pub fn error(&self) -> &E {
+        &self.error
+    }
+
+    /// Returns the backtrace of where the error was propagated.
+    pub fn backtrace(&self) -> &LazyBacktrace {
+        &self.backtrace
+    }
+
+    /// Returns the wrapped error, dropping the backtrace.
+    pub fn into_error(self) -> E {
+        self.error
+    }
+}
+
+impl<E: fmt::Display> ErrorTrace<E> {
+    /// Formats a single report of a panic caused by the error, from the backtrace `panic`
+    /// captured in the panic hook and the one of the error.
+    ///
+    /// Like with [`format_chain_with()`] the frames the error's backtrace shares with the one of
+    /// the panic are shown only once, so what is left of it is where the two went apart:
+    ///
+    /// ```text
+    /// panic:
+    ///    0: my_app::start
+    ///    1: my_app::main
+    ///
+    /// Caused by: invalid digit found in string:
+    ///    0: my_app::config::parse_port
+    ///       (+ same 2 bottom frames as 'panic')
+    /// ```
+    ///
+    ///[`format_chain_with()`]: ../fn.format_chain_with.html
+    pub fn format_with_panic(&self, panic: &mut Backtrace, options: &FormatOptions) -> String {
+        let name = self.error.to_string();
+        self.backtrace.with_captured(|error| {
+            format_chain_with(&mut [("panic", panic), (&name, error)], options)
+        })
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for ErrorTrace<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let vocabulary = &FormatOptions::current().vocabulary;
+        write!(
+            f,
+            "{}\n{}:{}",
+            self.error, vocabulary.backtrace, self.backtrace
+        )
+    }
+}
+
+impl<E: Error> Error for ErrorTrace<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.error.source()
+    }
+}
+
+/// Extension of `Result` attaching backtraces to errors.
+pub trait ResultExt<T, E> {
+    /// Wraps the error in an [`ErrorTrace`] with a [`LazyBacktrace`] of the caller, so
+    /// `result.with_trace()?` records where the error was propagated.
+    ///
+    /// The stack is only captured for errors, and formatted only when the error is shown.
+    ///
+    ///[`ErrorTrace`]: struct.ErrorTrace.html
+    ///[`LazyBacktrace`]: struct.LazyBacktrace.html
+    fn with_trace(self) -> Result<T, ErrorTrace<E>>;
+}
+
+impl<T, E> ResultExt<T, E> for Result<T, E> {
+    #[inline(never)]
+    fn with_trace(self) -> Result<T, ErrorTrace<E>> {
+        self.map_err(|error| ErrorTrace {
+            error,
+            backtrace: LazyBacktrace::capture_below(
+                <Self as ResultExt<T, E>>::with_trace as *const () as usize,
+            ),
+        })
+    }
+}
+
+#[cfg(all(test, feature = "resolve", feature = "demangle"))]
+mod tests {
+    use super::{LazyBacktrace, ResultExt};
+
+    #[inline(never)]
+    fn create_error() -> LazyBacktrace {
+        LazyBacktrace::capture()
+    }
+
+    #[test]
+    fn formats_on_first_use() {
+        let backtrace = create_error();
+        let text = backtrace.to_string();
+        assert!(
+            text.starts_with("\n   0: backtrace_string::lazy::tests::create_error"),
+            "{}",
+            text
+        );
+        assert_eq!(backtrace.as_str(), text);
+        assert_eq!(format!("{:?}", backtrace), "LazyBacktrace { .. }");
+    }
+
+    #[inline(never)]
+    fn parse_port(text: &str) -> Result<u16, super::ErrorTrace<std::num::ParseIntError>> {
+        let port = text.parse::<u16>().with_trace()?;
+        Ok(port)
+    }
+
+    #[test]
+    fn with_trace_captures_the_caller() {
+        assert_eq!(parse_port("80").unwrap(), 80);
+        let error = parse_port("port").unwrap_err();
+        let text = error.to_string();
+        assert!(
+            text.starts_with("invalid digit found in string\nBacktrace:\n   0: "),
+            "{}",
+            text
+        );
+        let top = text.lines().nth(2).unwrap();
+        assert!(top.contains("lazy::tests::parse_port"), "{}", text);
+    }
+
+    #[test]
+    fn error_and_panic_share_their_bottom_frames() {
+        let error = parse_port("port").unwrap_err();
+        let mut panic = backtrace::Backtrace::new_unresolved();
+        let out = error.format_with_panic(&mut panic, &crate::FormatOptions::new());
+        assert!(out.starts_with("panic:\n   0: "), "{}", out);
+        let (_, cause) = out
+            .split_once("\nCaused by: invalid digit found in string:\n")
+            .unwrap();
+        assert!(cause.contains("lazy::tests::parse_port"), "{}", out);
+        assert!(cause.contains("bottom frames as 'panic')"), "{}", out);
+    }
+}
@@ -5,24 +5,426 @@
 //! by demangleing names, doing some formating etc.
 //!
 //! Note that for this is meant to be used in panic hooks only.
+//!
+//! # Features
+//!
+//! - `std` (default): everything but the [`bare`] module, which all other features except
+//!   `bare-panic` and `bare-panic-handler` enable. Without it the crate is `#![no_std]` and only needs `alloc`.
+//! - `demangle` (default): demangles symbol names, without it the names are shown as found in the
+//!   binary.
+//! - `resolve` (default): resolves the addresses of frames to symbol names, files and lines.
+//!   Without it frames are never resolved and formatted like with
+//!   [`FormatOptions::resolve_symbols(false)`], leaving only addresses (and the module offsets of
+//!   [`UnknownSymbols::ModuleOffset`]). The `backtrace` crate always compiles its symbolizer,
+//!   but as it is never called the linker can leave it out of the binary.
+//! - `anyhow`, `eyre` and `tokio`: integrations with these crates.
+//! - `tracing-error`: the [`tracing_error`] module, reporting the spans of `tracing` together
+//!   with the frames of backtraces and panics.
+//! - `logical-frames`: the [`logical`] module and [`trace_frame!`], recording logical frames
+//!   which are appended to the panic reports of this crate.
+//! - `sampler`: the [`sampler`] module, a sampling profiler aggregating stacks by fingerprint.
+//! - `crash-artifact`: the [`artifact`] module, compact binary crash artifacts for post-mortem
+//!   tooling.
+//! - `pprof`: the [`pprof`] module, exporting captured stacks as `pprof` profiles.
+//! - `derive`: [`Backtraced`], a derive macro for error types capturing a [`LazyBacktrace`]
+//!   when they are created.
+//! - `addr2line-backend`: [`FormatOptions::addr2line_backend()`], resolving frames with the
+//!   `addr2line` crate, which follows the inlined functions of release builds more closely.
+//! - `breakpad`: the [`breakpad`] module, resolving frames with the symbol files of Breakpad
+//!   symbol stores.
+//! - `symbol-table`: [`FormatOptions::symbol_table_fallback()`], naming the frames of builds
+//!   without debug info with the symbol table of their binary.
+//! - `hook-arena`: the [`arena`] module, a global allocator wrapper serving the allocations of
+//!   panic hooks from a reserved arena.
+//! - `bare-panic`: the [`bare`] module, formatting the panics of `#![no_std]` firmware with the
+//!   addresses reported by its own unwinder.
+//! - `bare-panic-handler`: the `#[panic_handler]` of the [`bare`] module, for firmware built
+//!   without `std` which doesn't bring its own, e.g. with `panic-probe` or `panic-halt`.
+//! - `local-hints`: the [`locals`] module, reading the names of the locals in scope at the top
+//!   user frame from the DWARF debug info of the binary.
+//! - `test-harness`: the [`harness`] module, running closures in a child process to test panic
+//!   hooks and crash reporting.
+//! - `cli`: the `backtrace-string` binary, whose `fmt` command reads backtraces printed by the
+//!   standard library or this crate, or dumps of addresses, and prints them pretty, compact, as
+//!   JSON or folded, whose `symbolicate` command resolves the addresses of backtraces captured
+//!   without symbols with the debug info of a local binary, and whose `cluster` command groups
+//!   many backtraces into clusters of similar crashes with the [`cluster`] module.
+//!
+//![`FormatOptions::resolve_symbols(false)`]: struct.FormatOptions.html#method.resolve_symbols
+//![`UnknownSymbols::ModuleOffset`]: enum.UnknownSymbols.html#variant.ModuleOffset
+//![`tracing_error`]: tracing_error/index.html
+//![`logical`]: logical/index.html
+//![`trace_frame!`]: macro.trace_frame.html
+//![`sampler`]: sampler/index.html
+//![`artifact`]: artifact/index.html
+//![`pprof`]: pprof/index.html
+//![`Backtraced`]: derive.Backtraced.html
+//![`LazyBacktrace`]: lazy/struct.LazyBacktrace.html
+//![`FormatOptions::addr2line_backend()`]: struct.FormatOptions.html#method.addr2line_backend
+//![`breakpad`]: breakpad/index.html
+//![`FormatOptions::symbol_table_fallback()`]: struct.FormatOptions.html#method.symbol_table_fallback
+//![`arena`]: arena/index.html
+//![`bare`]: bare/index.html
+//![`locals`]: locals/index.html
+//![`harness`]: harness/index.html
+//![`cluster`]: cluster/index.html
+
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+#[cfg(feature = "std")]
 use {
     backtrace::{Backtrace, BacktraceFrame},
-    rustc_demangle::demangle,
     std::{
         borrow::Cow,
-        fmt::Write,
-        path::{Path, PathBuf},
+        error::Error,
+        fmt::{self, Write},
+        fs,
+        path::{Component, Path, PathBuf},
     },
 };
 
+#[cfg(feature = "std")]
+pub mod aggregate;
+#[cfg(feature = "anyhow")]
+pub mod anyhow;
+#[cfg(feature = "std")]
+pub mod app;
+#[cfg(feature = "hook-arena")]
+pub mod arena;
+#[cfg(feature = "crash-artifact")]
+pub mod artifact;
+#[cfg(feature = "std")]
+mod atos;
+#[cfg(feature = "bare-panic")]
+pub mod bare;
+#[cfg(feature = "breakpad")]
+pub mod breakpad;
+#[cfg(feature = "std")]
+pub mod buffer;
+#[cfg(feature = "std")]
+pub mod cluster;
+#[cfg(feature = "std")]
+pub mod color;
+#[cfg(feature = "std")]
+pub mod coredump;
+#[cfg(feature = "std")]
+pub mod crashfile;
+#[cfg(feature = "std")]
+mod cycle;
+#[cfg(feature = "std")]
+pub mod decor;
+#[cfg(feature = "std")]
+mod demangle;
+#[cfg(feature = "addr2line-backend")]
+mod dwarf;
+#[cfg(feature = "eyre")]
+pub mod eyre;
+#[cfg(feature = "std")]
+mod fingerprint;
+#[cfg(feature = "std")]
+pub mod frame;
+#[cfg(feature = "std")]
+pub mod gate;
+#[cfg(feature = "test-harness")]
+pub mod harness;
+#[cfg(feature = "std")]
+mod hook;
+#[cfg(any(feature = "std", feature = "bare-panic"))]
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+mod layout;
+#[cfg(feature = "std")]
+pub mod lazy;
+#[cfg(feature = "local-hints")]
+pub mod locals;
+#[cfg(feature = "logical-frames")]
+pub mod logical;
+#[cfg(feature = "std")]
+mod module;
+#[cfg(feature = "std")]
+pub mod oom;
+#[cfg(feature = "std")]
+mod options;
+#[cfg(feature = "std")]
+mod outcome;
+#[cfg(feature = "std")]
+pub mod panics;
+#[cfg(feature = "std")]
+pub mod payload;
+#[cfg(feature = "std")]
+pub mod perf;
+#[cfg(feature = "std")]
+mod platform;
+#[cfg(feature = "pprof")]
+pub mod pprof;
+#[cfg(feature = "std")]
+pub mod preset;
+#[cfg(feature = "std")]
+pub mod queue;
+#[cfg(feature = "std")]
+pub mod report;
+#[cfg(feature = "std")]
+pub mod resolution;
+#[cfg(feature = "sampler")]
+pub mod sampler;
+#[cfg(feature = "std")]
+pub mod scope;
+#[cfg(feature = "std")]
+pub mod sink;
+#[cfg(feature = "std")]
+pub mod stack;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod std_trace;
+#[cfg(feature = "std")]
+pub mod stream;
+#[cfg(feature = "std")]
+mod summary;
+#[cfg(feature = "symbol-table")]
+mod symtab;
+#[cfg(feature = "std")]
+pub mod test_report;
+#[cfg(feature = "std")]
+pub mod thread;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+#[cfg(feature = "tracing-error")]
+pub mod tracing_error;
+#[cfg(feature = "std")]
+pub mod upload;
+#[cfg(any(feature = "std", feature = "bare-panic"))]
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+mod vocabulary;
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub mod watchdog;
+
+#[cfg(feature = "std")]
+pub use crate::{
+    buffer::BacktraceBuffer,
+    color::ColorChoice,
+    fingerprint::{fingerprint, Fingerprint},
+    hook::{install_panic_hook, mark_panic_reported, panic_reported, uninstall_panic_hook},
+    layout::Style,
+    options::{
+        Demangling, FormatOptions, Locations, Numbering, ParseOptionsError, Symbolication,
+        UnknownSymbols, FORMAT_ENV_VAR,
+    },
+    outcome::CaptureOutcome,
+    preset::Preset,
+    summary::{summary, TraceSummary},
+    vocabulary::Vocabulary,
+};
+
+/// Derives an accessor for the [`LazyBacktrace`] field of an error type and `From` impls
+/// capturing it, for errors built with `thiserror` or by hand.
+///
+/// The backtrace is the field of type `LazyBacktrace` or the one marked `#[backtraced(trace)]`.
+/// For a field marked `#[backtraced(from)]`, `From` is implemented for its type, capturing the
+/// backtrace, so `?` records where the error was converted. Besides the backtrace it must be the
+/// only field of its struct or variant.
+///
+/// Structs get a `backtrace()` method returning the backtrace, enums one returning it as
+/// `Option`, as not every variant has to have one:
+///
+/// ```
+/// use backtrace_string::{lazy::LazyBacktrace, Backtraced};
+///
+/// #[derive(Debug, Backtraced)]
+/// enum AppError {
+///     Io(#[backtraced(from)] std::io::Error, LazyBacktrace),
+///     Parse {
+///         #[backtraced(from)]
+///         source: std::num::ParseIntError,
+///         backtrace: LazyBacktrace,
+///     },
+///     Unsupported,
+/// }
+///
+/// fn parse(text: &str) -> Result<u16, AppError> {
+///     Ok(text.parse()?)
+/// }
+///
+/// let error = parse("port").unwrap_err();
+/// eprintln!("{:?}, backtrace:{}", error, error.backtrace().unwrap());
+/// assert!(AppError::Unsupported.backtrace().is_none());
+/// ```
+///
+///[`LazyBacktrace`]: lazy/struct.LazyBacktrace.html
+#[cfg(feature = "derive")]
+pub use backtrace_string_derive::Backtraced;
+
+#[cfg(feature = "std")]
+use crate::{
+    color::Painter,
+    demangle::{demangle, SymbolName},
+    frame::{Frame, FrameSource, ResolvedFrame, Symbol},
+    module::Modules,
+    options::normalize_symbol,
+    stats::{CaptureStats, Timer},
+};
 
 /// Creates a backtrace and calls [`format_backtrace()`] on it.
 ///
+/// The backtrace is formatted with the [current options], which can be overridden per thread.
+/// If the configured [capture gate] suppresses the backtrace, a placeholder is returned.
+///
 ///[`format_backtrace()`]: fn.format_backtrace.html
+///[current options]: struct.FormatOptions.html#method.current
+///[capture gate]: gate/index.html
+#[cfg(feature = "std")]
 pub fn create_backtrace() -> String {
-    let mut bt = Backtrace::new();
-    format_backtrace(&mut bt)
+    if let Some(placeholder) = gate::skip_before_capture() {
+        return placeholder;
+    }
+    let options = FormatOptions::current();
+    let mut timer = Timer::start();
+    let mut bt = match options.capture_depth {
+        Some(max_depth) => capture_limited(max_depth),
+        None => Backtrace::new_unresolved(),
+    };
+    let capture_time = timer.lap();
+    if let Some(placeholder) = gate::skip_after_capture(&bt) {
+        return placeholder;
+    }
+    format_timed(&mut bt, &options, timer, Some(capture_time))
+}
+
+/// Resolves the symbols of `bt`, unless disabled by the options or the `resolve` feature.
+#[cfg(feature = "std")]
+pub(crate) fn resolve(bt: &mut Backtrace, options: &FormatOptions) {
+    #[cfg(feature = "resolve")]
+    {
+        if !options.no_resolve {
+            bt.resolve();
+        }
+    }
+    #[cfg(not(feature = "resolve"))]
+    let _ = (bt, options);
+}
+
+/// Resolves `bt` for formatting, returning the resolved frames instead if the options select
+/// another resolver than the one of the `backtrace` crate.
+#[cfg(feature = "std")]
+fn resolve_for_format(bt: &mut Backtrace, options: &FormatOptions) -> Option<Vec<ResolvedFrame>> {
+    #[cfg(feature = "addr2line-backend")]
+    {
+        if options.addr2line_backend && !options.no_resolve {
+            return Some(dwarf::resolve_frames(bt.frames()));
+        }
+    }
+    #[cfg(feature = "resolve")]
+    {
+        if !options.module_symbolication.is_empty() && !options.no_resolve {
+            return Some(resolve_by_module(bt.frames(), options));
+        }
+    }
+    resolve(bt, options);
+    None
+}
+
+/// Resolves `frames` as far as the [`Symbolication`] of their module says.
+#[cfg(feature = "resolve")]
+fn resolve_by_module(frames: &[BacktraceFrame], options: &FormatOptions) -> Vec<ResolvedFrame> {
+    use crate::frame::{resolve_symbols, ResolvedSymbol};
+
+    let modules = Modules::default();
+    frames
+        .iter()
+        .map(|frame| {
+            let ip = Frame::ip(frame);
+            let symbolication = modules
+                .find(ip)
+                .map_or(Symbolication::Full, |(path, _)| options.symbolication(path));
+            let symbols = match symbolication {
+                Symbolication::Full => resolve_symbols(ip),
+                Symbolication::Names => resolve_symbols(ip)
+                    .into_iter()
+                    .map(|symbol| ResolvedSymbol {
+                        filename: None,
+                        lineno: None,
+                        ..symbol
+                    })
+                    .collect(),
+                Symbolication::Addresses => Vec::new(),
+            };
+            ResolvedFrame { ip, symbols }
+        })
+        .collect()
+}
+
+/// Whether the options select a resolver for the frames the `backtrace` crate couldn't resolve.
+#[cfg(feature = "std")]
+fn has_fallback(options: &FormatOptions) -> bool {
+    #[cfg(feature = "symbol-table")]
+    {
+        if options.symbol_table_fallback && !options.no_resolve {
+            return true;
+        }
+    }
+    options.atos_fallback && !options.no_resolve
+}
+
+/// Resolves the frames without symbols with the fallbacks selected by the options.
+#[cfg(feature = "std")]
+fn resolve_unresolved(frames: &mut [ResolvedFrame], options: &FormatOptions) {
+    if options.atos_fallback {
+        atos::resolve_unresolved(frames);
+    }
+    #[cfg(feature = "symbol-table")]
+    {
+        if options.symbol_table_fallback {
+            symtab::resolve_unresolved(frames);
+        }
+    }
+}
+
+/// Captures the stack below the caller, stopping the unwinder after `max_depth` frames.
+#[cfg(feature = "std")]
+#[inline(never)]
+pub(crate) fn capture_limited(max_depth: usize) -> Backtrace {
+    // Like `Backtrace::new_unresolved()` the frames up to this function are dropped. If its
+    // frame isn't recognized, the few frames of the unwinder itself are kept in addition.
+    const UNWINDER_FRAMES: usize = 8;
+    let ip = capture_limited as *const () as usize;
+    let mut found = false;
+    let mut traced = 0;
+    let mut frames = Vec::new();
+    backtrace::trace(|frame| {
+        traced += 1;
+        if !found && frame.symbol_address() as usize == ip {
+            found = true;
+            frames.clear();
+        } else {
+            frames.push(BacktraceFrame::from(frame.clone()));
+        }
+        if found {
+            frames.len() < max_depth
+        } else {
+            traced < max_depth + UNWINDER_FRAMES
+        }
+    });
+    Backtrace::from(frames)
+}
+
+/// Captures the stack below the function starting at `ip`, unresolved.
+///
+/// Callers are `#[inline(never)]` and pass their own address, so their frame and the frames of
+/// the unwinder are dropped.
+#[cfg(feature = "std")]
+pub(crate) fn capture_below(ip: usize) -> Backtrace {
+    let mut found = false;
+    let mut frames = Vec::new();
+    backtrace::trace(|frame| {
+        if found {
+            frames.push(BacktraceFrame::from(frame.clone()));
+        } else {
+            found = frame.symbol_address() as usize == ip;
+        }
+        true
+    });
+    Backtrace::from(frames)
 }
 
 /// Outputs the backtrace as a human readable string.
@@ -34,58 +436,734 @@ pub fn create_backtrace() -> String {
 /// Note that this does some rust specific backtrace shortening, mainly
 /// some frames from the panic handling functionality are skipped over
 /// and some rust paths to crates get shortened.
+///
+/// The backtrace is formatted with the [current options](struct.FormatOptions.html#method.current).
+/// A backtrace without any frames is formatted as the [`Vocabulary::no_frames`] notice instead,
+/// see [`CaptureOutcome`].
+///
+///[`Vocabulary::no_frames`]: struct.Vocabulary.html#structfield.no_frames
+///[`CaptureOutcome`]: enum.CaptureOutcome.html
+#[cfg(feature = "std")]
 pub fn format_backtrace(bt: &mut Backtrace) -> String {
-    bt.resolve();
+    format_backtrace_with(bt, &FormatOptions::current())
+}
+
+/// Like [`format_backtrace()`] but formats according to the given `options`.
+///
+///[`format_backtrace()`]: fn.format_backtrace.html
+#[cfg(feature = "std")]
+pub fn format_backtrace_with(bt: &mut Backtrace, options: &FormatOptions) -> String {
+    format_timed(bt, options, Timer::start(), None)
+}
+
+/// Resolves and formats `bt`, reporting the [stats](stats/index.html) if enabled.
+#[cfg(feature = "std")]
+fn format_timed(
+    bt: &mut Backtrace,
+    options: &FormatOptions,
+    mut timer: Timer,
+    capture_time: Option<std::time::Duration>,
+) -> String {
+    if bt.frames().is_empty() {
+        return format!("\n{}\n", options.vocabulary.no_frames);
+    }
+    let resolved = resolve_for_format(bt, options);
+    let resolve_time = timer.lap();
+    let demangle_calls = stats::demangle_calls();
+    let out = match resolved {
+        Some(frames) => format_boxed(filter_frames(&frames, options), options),
+        None if has_fallback(options) && bt.frames().iter().any(|frame| !frame.is_resolved()) => {
+            let mut frames = bt.frames().iter().map(ResolvedFrame::from).collect::<Vec<_>>();
+            resolve_unresolved(&mut frames, options);
+            format_boxed(filter_frames(&frames, options), options)
+        }
+        None => format_boxed(filter_frames(bt.frames(), options), options),
+    };
+    let format_time = timer.lap();
+
+    if timer.is_running() {
+        stats::report(&CaptureStats {
+            frames: bt.frames().len(),
+            capture_time,
+            resolve_time,
+            format_time,
+            bytes: out.len(),
+            demangle_calls: stats::demangle_calls() - demangle_calls,
+        });
+    }
+    out
+}
+
+/// Formats the frames of any [`FrameSource`] like [`format_backtrace_with()`].
+///
+/// Backtraces must already be resolved.
+///
+///[`FrameSource`]: frame/trait.FrameSource.html
+///[`format_backtrace_with()`]: fn.format_backtrace_with.html
+#[cfg(feature = "std")]
+pub fn format_frame_source<S: FrameSource + ?Sized>(
+    source: &S,
+    options: &FormatOptions,
+) -> String {
+    format_boxed(filter_frames(source.frames(), options), options)
+}
+
+/// Formats several backtraces as a causal chain, with the [current options].
+///
+/// E.g. the backtrace of a panic followed by the one captured where the error causing it was
+/// created. Each backtrace is introduced by its name, the ones after the first by `Caused by:`.
+/// The trailing frames a backtrace shares with one before it, usually `main` and the runtime
+/// startup, are shown only once:
+///
+/// ```text
+/// panic:
+///    0: my_app::handle
+///    1: my_app::main
+///
+/// Caused by: error created:
+///    0: my_app::config::load
+///       (+ same 1 bottom frames as 'panic')
+/// ```
+///
+///[current options]: struct.FormatOptions.html#method.current
+#[cfg(feature = "std")]
+pub fn format_chain(traces: &mut [(&str, &mut Backtrace)]) -> String {
+    format_chain_with(traces, &FormatOptions::current())
+}
+
+/// Like [`format_chain()`] but formats according to the given `options`.
+///
+///[`format_chain()`]: fn.format_chain.html
+#[cfg(feature = "std")]
+pub fn format_chain_with(traces: &mut [(&str, &mut Backtrace)], options: &FormatOptions) -> String {
+    for (_, bt) in traces.iter_mut() {
+        resolve(bt, options);
+    }
+    let traces = traces
+        .iter()
+        .map(|(name, bt)| (*name, bt.frames()))
+        .collect::<Vec<_>>();
+    format_chain_frames(&traces, options)
+}
+
+#[cfg(feature = "std")]
+fn format_chain_frames<F: Frame>(traces: &[(&str, &[F])], options: &FormatOptions) -> String {
+    let traces = traces
+        .iter()
+        .map(|(name, frames)| (*name, filter_frames(frames, options).collect::<Vec<_>>()))
+        .collect::<Vec<_>>();
+    let visible = traces
+        .iter()
+        .map(|(_, frames)| {
+            let visible = frames.iter().filter(|frame| options.is_visible(frame));
+            visible.copied().collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let shared = shared_suffixes(visible.iter().map(|frames| &frames[..]));
+    let mut out = String::new();
+    for (i, (name, frames)) in traces.iter().enumerate() {
+        if i > 0 {
+            write!(out, "\n{}: ", options.vocabulary.caused_by).unwrap();
+        }
+        write!(out, "{}:", name).unwrap();
+        let shared = shared[i].map(|(count, other)| (count, format!("'{}'", traces[other].0)));
+        let shared = shared.as_ref().map(|(count, other)| (*count, &other[..]));
+        out.push_str(&format_elided(frames, shared, options));
+    }
+    out
+}
+
+/// Returns for each backtrace the number of trailing frames it shares with an earlier one and
+/// the index of that one, the earliest of those sharing the most frames.
+///
+/// The frames must already be trimmed and without the hidden ones.
+#[cfg(feature = "std")]
+pub(crate) fn shared_suffixes<'a, F: Frame + 'a>(
+    traces: impl Iterator<Item = &'a [F]>,
+) -> Vec<Option<(usize, usize)>> {
+    let traces = traces.collect::<Vec<_>>();
+    (0..traces.len())
+        .map(|i| {
+            (0..i)
+                .rev()
+                .map(|other| (common_suffix(traces[i], traces[other]), other))
+                .max_by_key(|(count, _)| *count)
+                .filter(|(count, _)| *count > 0)
+        })
+        .collect()
+}
+
+/// Returns the number of trailing frames `a` and `b` have in common.
+#[cfg(feature = "std")]
+fn common_suffix<A: Frame, B: Frame>(a: &[A], b: &[B]) -> usize {
+    a.iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(a, b)| a.ip() == b.ip() && a.symbols().eq(b.symbols()))
+        .count()
+}
+
+/// Formats `frames`, which are already trimmed, replacing the `shared` number of trailing visible
+/// frames by a line naming the backtrace they are shown in.
+#[cfg(feature = "std")]
+pub(crate) fn format_elided<F: Frame>(
+    frames: &[F],
+    shared: Option<(usize, &str)>,
+    options: &FormatOptions,
+) -> String {
+    let mut end = frames.len();
+    let mut elided = shared.map_or(0, |(count, _)| count);
+    while elided > 0 {
+        end -= 1;
+        if options.is_visible(&frames[end]) {
+            elided -= 1;
+        }
+    }
+    let mut out = render_frames(frames[..end].iter(), options);
+    if let Some((count, other)) = shared {
+        writeln!(out, "      {}", options.vocabulary.same_frames(count, other)).unwrap();
+    }
+    match &options.box_title {
+        Some(title) => decor::draw_box(title, &out),
+        None => out,
+    }
+}
+
+/// Formats exactly the given frames, for callers which selected the frames themselves.
+///
+/// Unlike [`format_backtrace()`] this doesn't trim the frames of the panic machinery and the
+/// runtime startup. The `options` still apply, including the hidden frames and the frame limit.
+///
+/// ```
+/// use backtrace::Backtrace;
+/// use backtrace_string::{format_frames, FormatOptions};
+///
+/// let bt = Backtrace::new();
+/// let own_frames = bt
+///     .frames()
+///     .iter()
+///     .filter(|frame| {
+///         frame.symbols().iter().any(|symbol| {
+///             symbol
+///                 .name()
+///                 .map_or(false, |name| name.to_string().starts_with("rust_out::"))
+///         })
+///     })
+///     .cloned()
+///     .collect::<Vec<_>>();
+/// println!("{}", format_frames(&own_frames, &FormatOptions::new()));
+/// ```
+///
+///[`format_backtrace()`]: fn.format_backtrace.html
+#[cfg(feature = "std")]
+pub fn format_frames<F: Frame>(frames: &[F], options: &FormatOptions) -> String {
+    format_boxed(frames.iter(), options)
+}
+
+/// Resolves `bt` and formats each frame on its own, with the [current options].
+///
+/// The strings have no trailing newline. The frames are trimmed and hidden like by
+/// [`format_backtrace()`], but the frame limit, the fingerprint and the box don't apply, which is
+/// left to the consumer, e.g. when emitting one log record per frame:
+///
+/// ```
+/// let mut bt = backtrace::Backtrace::new();
+/// for frame in backtrace_string::formatted_frames(&mut bt) {
+///     println!("frame: {}", frame.trim_start());
+/// }
+/// ```
+///
+///[current options]: struct.FormatOptions.html#method.current
+///[`format_backtrace()`]: fn.format_backtrace.html
+#[cfg(feature = "std")]
+pub fn formatted_frames(bt: &mut Backtrace) -> impl Iterator<Item = String> + '_ {
+    let options = FormatOptions::current();
+    resolve(bt, &options);
+    let frames = filter_frames(bt.frames(), &options)
+        .filter(|frame| options.is_visible(frame))
+        .collect::<Vec<_>>();
+    let modules = Modules::default();
+    frames
+        .into_iter()
+        .scan(0, move |number, frame| {
+            let index = *number;
+            *number += numbered_entries(&options, frame);
+            Some(format_single_frame(index, frame, &options, &modules))
+        })
+}
+
+/// Like [`formatted_frames()`] but formats according to the given `options`.
+///
+///[`formatted_frames()`]: fn.formatted_frames.html
+#[cfg(feature = "std")]
+pub fn formatted_frames_with<'a>(
+    bt: &'a mut Backtrace,
+    options: &'a FormatOptions,
+) -> impl Iterator<Item = String> + 'a {
+    resolve(bt, options);
+    let modules = Modules::default();
+    filter_frames(bt.frames(), options)
+        .filter(move |frame| options.is_visible(frame))
+        .scan(0, move |number, frame| {
+            let index = *number;
+            *number += numbered_entries(options, frame);
+            Some(format_single_frame(index, frame, options, &modules))
+        })
+}
+
+#[cfg(feature = "std")]
+fn format_single_frame(
+    index: usize,
+    frame: &impl Frame,
+    options: &FormatOptions,
+    modules: &Modules,
+) -> String {
+    let mut out = String::new();
+    format_frame_into(&mut out, index, frame, options, options.painter(), modules);
+    out.pop();
+    out
+}
+
+/// Like [`format_backtrace()`] but additionally skips the leading frames which only contain
+/// symbols for which `is_capture_symbol` returns `true`.
+///
+/// This is used by the error report integrations, where the backtrace is captured deep inside of
+/// the error library instead of a panic hook, so the panic based trimming doesn't apply.
+///
+///[`format_backtrace()`]: fn.format_backtrace.html
+#[cfg(feature = "std")]
+#[cfg_attr(not(any(feature = "anyhow", feature = "eyre")), allow(dead_code))]
+pub(crate) fn format_backtrace_after_capture(
+    bt: &mut Backtrace,
+    options: &FormatOptions,
+    is_capture_symbol: impl Fn(&str) -> bool,
+) -> String {
+    resolve(bt, options);
+    let frames = bt.frames();
+    let skip = frames
+        .iter()
+        .take_while(|frame| frame_only_contains_symbols(frame, &is_capture_symbol))
+        .count();
+    render_frames(filter_frames(&frames[skip..], options), options)
+}
+
+/// Formats the frames and draws the box around them, if any.
+#[cfg(feature = "std")]
+fn format_boxed<'a, F: Frame + 'a>(
+    frames: impl Iterator<Item = &'a F>,
+    options: &FormatOptions,
+) -> String {
+    let out = render_frames(frames, options);
+    match &options.box_title {
+        Some(title) => decor::draw_box(title, &out),
+        None => out,
+    }
+}
 
+#[cfg(feature = "std")]
+fn render_frames<'a, F: Frame + 'a>(
+    frames: impl Iterator<Item = &'a F>,
+    options: &FormatOptions,
+) -> String {
     let mut out = String::from("\n");
-    for (i, frame) in filter_frames(bt.frames()).enumerate() {
-        format_frame_into(&mut out, i, frame);
+    let painter = options.painter();
+    let modules = Modules::default();
+    let mut hidden_unresolved = 0;
+    let frames = frames
+        .filter(|frame| {
+            let visible = options.is_visible(frame);
+            // Frames without symbol names are only hidden by `UnknownSymbols::Hide`.
+            if !visible && !frame.is_resolved() {
+                hidden_unresolved += 1;
+            }
+            visible
+        })
+        .collect::<Vec<_>>();
+
+    // The repetitions of a recursion cycle after the first one are left out.
+    let cycle = if options.detect_stack_overflow {
+        cycle::find_cycle(&frames)
+    } else {
+        None
+    };
+    let collapsed = cycle.map_or(0..0, |cycle| cycle.first_end()..cycle.end());
+    let slide = if options.aslr_slide && !options.deterministic {
+        module::load_bias()
+    } else {
+        None
+    };
+    if let Some(slide) = slide {
+        let header = options.vocabulary.aslr_slide(slide);
+        writeln!(out, "{}", painter.header(header)).unwrap();
+    }
+    if let Some(cycle) = cycle {
+        let header = options
+            .vocabulary
+            .stack_overflow(cycle.period, cycle.repeats);
+        writeln!(out, "{}", painter.header(header)).unwrap();
+    }
+
+    let rows = (0..frames.len())
+        .filter(|i| !collapsed.contains(i))
+        .collect::<Vec<_>>();
+    let shown = options.max_frames.unwrap_or(rows.len()).min(rows.len());
+    // Collapsed frames keep their numbers, so the numbers tell the depth.
+    let numbers = frames
+        .iter()
+        .scan(0, |number, frame| {
+            let index = *number;
+            *number += numbered_entries(options, frame);
+            Some(index)
+        })
+        .collect::<Vec<_>>();
+    let external = if options.only_my_code {
+        external_frames(&frames)
+    } else {
+        Vec::new()
+    };
+    // The machinery of outer panics separates their frames from those of the inner ones.
+    let machinery = if options.nested_panics {
+        frames
+            .iter()
+            .map(|frame| frame_contains_symbol(*frame, is_panic_marker))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    // The first and last row of the current run of dependency frames and their number.
+    let mut run: Option<(usize, usize, usize)> = None;
+    for &i in &rows[..shown] {
+        if machinery.get(i) == Some(&true) {
+            if let Some(run) = run.take() {
+                write_external_frames(&mut out, run, &frames, cycle, options);
+            }
+            if i == 0 || !machinery[i - 1] {
+                writeln!(out, "      {}", options.vocabulary.nested_panic).unwrap();
+            }
+            continue;
+        }
+        if external.get(i) == Some(&true) {
+            run = Some(match run {
+                Some((first, _, count)) => (first, i, count + 1),
+                None => (i, i, 1),
+            });
+            continue;
+        }
+        if let Some(run) = run.take() {
+            write_external_frames(&mut out, run, &frames, cycle, options);
+        }
+        format_frame_into(&mut out, numbers[i], frames[i], options, painter, &modules);
+        if let Some(cycle) = cycle.filter(|cycle| cycle.first_end() == i + 1) {
+            let notice = options
+                .vocabulary
+                .cycle_repeated(cycle.period, cycle.repeats - 1);
+            writeln!(out, "      {}", notice).unwrap();
+        }
+    }
+    if let Some(run) = run {
+        write_external_frames(&mut out, run, &frames, cycle, options);
+    }
+
+    let omitted = rows.len() - shown;
+    if omitted > 0 {
+        options.layout().write_omitted(&mut out, omitted).unwrap();
+    }
+    if hidden_unresolved > 0 {
+        let notice = options.vocabulary.unresolved_hidden(hidden_unresolved);
+        writeln!(out, "      {}", notice).unwrap();
+    }
+    if options.fingerprint {
+        let fingerprint = Fingerprint::from_frames(frames);
+        writeln!(out, "{}: {}", options.vocabulary.fingerprint, fingerprint).unwrap();
     }
     out
 }
 
+/// Returns for each frame whether it is a dependency's, or nothing if all are.
+#[cfg(feature = "std")]
+fn external_frames<F: Frame>(frames: &[&F]) -> Vec<bool> {
+    let external = frames
+        .iter()
+        .map(|frame| {
+            frame.symbols().all(|symbol| {
+                let name = symbol.name_lossy().unwrap_or_default();
+                let name = SymbolName {
+                    name: &name,
+                    demangling: Demangling::WithoutHashes,
+                };
+                name.with_str(|name| app::is_dependency(name, symbol.filename))
+            })
+        })
+        .collect::<Vec<_>>();
+    if external.contains(&false) {
+        external
+    } else {
+        Vec::new()
+    }
+}
 
-fn format_frame_into(out: &mut String, index: usize, frame: &BacktraceFrame) {
-    write!(out, "{:4}:", index).unwrap();
+/// Writes the line standing in for the dependency frames `first..=last` of `frames`.
+#[cfg(feature = "std")]
+fn write_external_frames<F: Frame>(
+    out: &mut String,
+    (first, last, count): (usize, usize, usize),
+    frames: &[&F],
+    cycle: Option<cycle::Cycle>,
+    options: &FormatOptions,
+) {
+    // The run was entered through the outermost symbol of its outermost frame.
+    let name = frames[last]
+        .symbols()
+        .last()
+        .and_then(|symbol| symbol.name_lossy())
+        .map(|name| format!("{:#}", demangle(&name)));
+    let module = match &name {
+        Some(name) => module_path(normalize_symbol(name).as_ref()).to_owned(),
+        None => options.vocabulary.unknown.to_string(),
+    };
+    writeln!(
+        out,
+        "      {}",
+        options.vocabulary.external_frames(&module, count)
+    )
+    .unwrap();
+    if let Some(cycle) = cycle.filter(|cycle| (first..=last).contains(&(cycle.first_end() - 1))) {
+        let notice = options
+            .vocabulary
+            .cycle_repeated(cycle.period, cycle.repeats - 1);
+        writeln!(out, "      {}", notice).unwrap();
+    }
+}
 
-    let mut last_symbol = None;
-    for symbol in frame.symbols() {
-        let name = demangle(
-            symbol
-                .name()
-                .and_then(|name| name.as_str())
-                .unwrap_or("<unknown>"),
-        )
-        .to_string();
+/// Returns the crate and the first module of a symbol's path, e.g. `tokio::runtime` for
+/// `tokio::runtime::task::harness::Harness<T,S>::poll`.
+#[cfg(feature = "std")]
+fn module_path(symbol: &str) -> &str {
+    let path = &symbol[..symbol.find(['<', ' ']).unwrap_or(symbol.len())];
+    match path.match_indices("::").nth(1) {
+        Some((end, _)) => &path[..end],
+        _ => match path.find("::") {
+            Some(end) => &path[..end],
+            None => path,
+        },
+    }
+}
+
+/// Returns the number of numbered entries `frame` is shown as.
+#[cfg(feature = "std")]
+fn numbered_entries(options: &FormatOptions, frame: &impl Frame) -> usize {
+    match options.numbering {
+        Numbering::Frames => 1,
+        Numbering::Symbols => frame.symbols().count().max(1),
+    }
+}
+
+/// Writes `error` followed by an enumerated list of its sources.
+///
+/// The layout mirrors the one of the backtrace so that both look alike when printed together.
+#[cfg(feature = "std")]
+#[cfg_attr(not(any(feature = "anyhow", feature = "eyre")), allow(dead_code))]
+pub(crate) fn write_error_chain(
+    out: &mut impl Write,
+    error: &(dyn Error + 'static),
+    vocabulary: &Vocabulary,
+) -> fmt::Result {
+    write!(out, "{}", error)?;
+
+    let mut source = error.source();
+    if source.is_some() {
+        write!(out, "\n\n{}:", vocabulary.caused_by)?;
+    }
+    let mut index = 0;
+    while let Some(cause) = source {
+        write!(out, "\n{:4}: {}", index, cause)?;
+        source = cause.source();
+        index += 1;
+    }
+    Ok(())
+}
+
+
+#[cfg(feature = "std")]
+fn format_frame_into(
+    out: &mut String,
+    index: usize,
+    frame: &impl Frame,
+    options: &FormatOptions,
+    painter: Painter<'_>,
+    modules: &Modules,
+) {
+    let layout = options.layout();
+    let number = index + options.index_base;
+    write!(out, "{}:", painter.index(layout.number(number))).unwrap();
+
+    let unresolved_name = || {
+        let module_offset = match options.unknown_symbols {
+            UnknownSymbols::ModuleOffset if !options.deterministic => modules.find(frame.ip()),
+            _ => None,
+        };
+        match module_offset {
+            Some((path, offset)) => {
+                let module = path.file_name().unwrap_or(path.as_os_str());
+                format!("{}+{:#x}", module.to_string_lossy(), offset)
+            }
+            None => options.vocabulary.unknown.to_string(),
+        }
+    };
+    if frame.symbols().next().is_none() {
+        write!(out, " {}", painter.symbol(unresolved_name(), true)).unwrap();
+        match options.locations {
+            Locations::Hidden => {}
+            Locations::Crate => {
+                write_location(out, painter.path(&options.vocabulary.unknown), options)
+            }
+            Locations::Shown if options.deterministic => {
+                write_location(out, painter.path(&options.vocabulary.unknown), options)
+            }
+            Locations::Shown => {
+                write_location(out, painter.path(layout.address(frame.ip())), options)
+            }
+        }
+    }
+
+    let location_of = |symbol: &Symbol<'_>, krate: Option<&str>| {
+        let path = symbol.filename.map(|path| {
+            let path = clean_path(path);
+            if options.redact_paths || options.deterministic {
+                Cow::Owned(redact_path(&path).into_owned())
+            } else {
+                path
+            }
+        });
+        match (path, symbol.addr, symbol.lineno) {
+            _ if options.locations == Locations::Hidden => None,
+            _ if options.locations == Locations::Crate => {
+                let krate = krate.unwrap_or(&options.vocabulary.unknown);
+                Some(format!("{}", painter.path(krate)))
+            }
+            (Some(path), _, _) if options.deterministic => {
+                Some(format!("{}", painter.path(portable_path(&path))))
+            }
+            (Some(path), _, Some(line)) => Some(format!(
+                "{}:{}",
+                painter.path(path.to_string_lossy()),
+                painter.line_number(line)
+            )),
+            (Some(path), _, _) => Some(format!("{}", painter.path(path.to_string_lossy()))),
+            (None, Some(addr), _) if !options.deterministic => {
+                Some(format!("{}", painter.path(layout.address(addr))))
+            }
+            (None, _, _) => Some(format!("{}", painter.path(&options.vocabulary.unknown))),
+        }
+    };
 
-        match last_symbol.take() {
-            None => {
-                write!(out, " {}", name).unwrap();
-                last_symbol = Some(name);
+    let shows_call_sites = options.call_sites && options.locations == Locations::Shown;
+    let mut symbols = frame.symbols().enumerate().peekable();
+    let mut last_entry: Option<(String, Option<String>)> = None;
+    while let Some((i, symbol)) = symbols.next() {
+        let name = match symbol.name_lossy() {
+            Some(name) if options.deterministic => {
+                normalize_symbol(&format!("{:#}", demangle(&name))).into_owned()
             }
-            Some(ref sym) if sym != &name => {
-                write!(out, "\n      {}", name).unwrap();
-                last_symbol = Some(name);
+            Some(name) => SymbolName {
+                name: &name,
+                demangling: options.demangling,
             }
+            .to_string(),
+            None => unresolved_name(),
+        };
+        let dependency = app::is_dependency(&name, symbol.filename);
+        let krate = match options.locations {
+            Locations::Crate => crate_name(&name),
+            _ => None,
+        };
+
+        let location = location_of(&symbol, krate.as_deref());
 
-            // FIXME: Make less ugly once "cannot bind by-move into a pattern guard"
-            // is fixed in rustc (post-NLL I believe).
-            old => last_symbol = old,
+        // A symbol repeated with the same location adds nothing, unless it gets a number.
+        let entry = (name, location);
+        if options.numbering == Numbering::Frames && last_entry.as_ref() == Some(&entry) {
+            continue;
+        }
+        let (name, location) = entry;
+        match &last_entry {
+            Some(_) if options.numbering == Numbering::Symbols => {
+                let number = painter.index(layout.number(number + i));
+                write!(out, "\n{}: {}", number, painter.symbol(&name, dependency)).unwrap();
+            }
+            None => write!(out, " {}", painter.symbol(&name, dependency)).unwrap(),
+            Some((last_name, _)) if *last_name != name => {
+                write_indent(out, options, 2);
+                write!(out, "{}", painter.symbol(&name, dependency)).unwrap();
+            }
+            // The same symbol at another location only repeats the name if the style has no line
+            // of its own for the location.
+            Some(_) => {
+                if options.style == Style::Compact {
+                    write_indent(out, options, 2);
+                    write!(out, "{}", painter.symbol(&name, dependency)).unwrap();
+                }
+            }
+        }
+        // The next symbol of the frame is the caller an inlined function was called from.
+        let call_site = match symbols.peek() {
+            Some((_, caller)) if shows_call_sites => location_of(caller, None),
+            _ => None,
+        };
+        match (&location, call_site) {
+            (Some(location), Some(call_site)) => {
+                let inlined = options.vocabulary.inlined(location, &call_site);
+                match options.style {
+                    Style::Full => {
+                        write_indent(out, options, 6);
+                        out.push_str(&inlined);
+                    }
+                    Style::Compact => write!(out, " ({})", inlined).unwrap(),
+                }
+            }
+            (Some(location), None) => write_location(out, location, options),
+            (None, _) => {}
+        }
+        last_entry = Some((name, location));
+        let is_origin = symbol
+            .filename
+            .zip(symbol.lineno)
+            .is_some_and(|(file, line)| options.is_panic_location(file, line));
+        if is_origin {
+            write!(out, " {}", painter.header(&options.vocabulary.panic_origin)).unwrap();
         }
 
-        write!(out, "\n          at ").unwrap();
-        let path = symbol.filename().map(clean_path);
-        match (path, symbol.addr(), symbol.lineno()) {
-            (Some(path), _, Some(line)) => write!(out, "{}:{}", path.display(), line).unwrap(),
-            (Some(path), _, _) => write!(out, "{}", path.display()).unwrap(),
-            (None, Some(addr), _) => write!(out, "address {:p}", addr).unwrap(),
-            (None, None, _) => write!(out, "<unknown>").unwrap(),
+        if options.source_snippets {
+            let line = symbol
+                .filename
+                .zip(symbol.lineno)
+                .and_then(|(path, line)| read_source_line(path, line));
+            if let Some(line) = line {
+                write_indent(out, options, 6);
+                write!(out, "| {}", line.trim()).unwrap();
+            }
         }
     }
 
+    for note in options.notes(index, frame) {
+        write_indent(out, options, 2);
+        write!(out, "{}: {}", options.vocabulary.note, note).unwrap();
+    }
     writeln!(out).unwrap();
 }
 
+/// Writes the location of a symbol in the layout of the style.
+#[cfg(feature = "std")]
+fn write_location(out: &mut String, location: impl fmt::Display, options: &FormatOptions) {
+    options.layout().write_location(out, location).unwrap();
+}
+
+/// Starts a line continuing a frame, indented by `extra` columns beyond the frame numbers.
+#[cfg(feature = "std")]
+fn write_indent(out: &mut String, options: &FormatOptions, extra: usize) {
+    options.layout().write_indent(out, extra).unwrap();
+}
+
 /// "Opportunistic" filtering of frames.
 ///
 /// This will remove frames we're sure are irrelevant. This mostly includes stuff inside the
@@ -93,24 +1171,57 @@ fn format_frame_into(out: &mut String, index: usize, frame: &BacktraceFrame) {
 ///
 /// This is "opportunistic" because it will simply not trim any frames if it isn't sure that the
 /// frames are really irrelevant. Still, if the backtraces act up, try disabling this function.
-fn filter_frames<'a>(frames: &'a [BacktraceFrame]) -> impl Iterator<Item = &'a BacktraceFrame> {
+///
+/// With [relaxed trimming] the frames are also trimmed if the markers are missing, as happens
+/// with partial stacks from unwinders like the one of musl.
+///
+///[relaxed trimming]: struct.FormatOptions.html#method.relaxed_trimming
+#[cfg(feature = "std")]
+fn filter_frames<'a, F: Frame>(
+    frames: &'a [F],
+    options: &FormatOptions,
+) -> impl Iterator<Item = &'a F> {
     // The start of the backtrace (most recent calls) are inside the `backtrace` crate, our panic
     // hook, and `std::panicking`. We search the first 10 frames for `std::panicking::*` symbols and
     // trim just below them.
+    let relaxed = options.relaxed_trimming;
+    let window = options
+        .marker_window
+        .unwrap_or(if relaxed { 20 } else { 10 });
 
     // `Take` cannot implement `DoubleEndedIterator` and so `rposition` doesn't work on it. Get the
     // subslice manually.
-    let fr = if frames.len() > 10 {
-        &frames[..10]
+    let fr = if frames.len() > window {
+        &frames[..window]
     } else {
         frames
     };
-    let start_index = fr.iter().rposition(|frame| {
-        frame_contains_symbol(frame, |sym| {
-            // At some point the `std::panicking` prefix got lost, so we also check for a bare
-            // `panic_fmt` symbol.
-            sym == "panic_fmt" || sym.starts_with("std::panicking")
+    let is_marker = |frame: &F| frame_contains_symbol(frame, is_panic_marker);
+    // The markers of an outer panic follow those of a panic in its hook, which then end at the
+    // first run of markers.
+    let start_index = if options.nested_panics {
+        fr.iter().position(is_marker).map(|first| {
+            let markers = fr[first..].iter().take_while(|frame| is_marker(frame));
+            first + markers.count() - 1
         })
+    } else {
+        fr.iter().rposition(is_marker)
+    };
+    // Without the panic markers at least the frames of the `backtrace` crate are left out.
+    let start_index = start_index.or_else(|| {
+        let capture = fr
+            .iter()
+            .take_while(|frame| {
+                frame_only_contains_symbols(frame, |sym| {
+                    normalize_symbol(sym).starts_with("backtrace::")
+                })
+            })
+            .count();
+        if relaxed && capture > 0 {
+            Some(capture - 1)
+        } else {
+            None
+        }
     });
 
     // The end of the backtrace contains libc startup, Rust runtime startup, possibly the thread
@@ -123,10 +1234,40 @@ fn filter_frames<'a>(frames: &'a [BacktraceFrame]) -> impl Iterator<Item = &'a B
             frame_contains_symbol(frame, |sym| {
                 sym.contains("__rust_begin_short_backtrace") ||
                 // Sometimes the rust marker is not emitted.
-                sym == "__libc_start_main"
+                sym == "__libc_start_main" ||
+                // The startup code of musl.
+                (relaxed && (sym == "libc_start_main_stage2" || sym == "__libc_start1"))
             })
         })
         .map(|(i, _)| i);
+    // The user's `main` ends the interesting part, everything below is the runtime's.
+    let end_index = if options.end_at_main {
+        main_end(frames).into_iter().chain(end_index).min()
+    } else {
+        end_index
+    };
+    // Otherwise the startup code of the platform ends the interesting part. It is searched from
+    // the bottom, as user or FFI functions further up may share the names of its symbols.
+    let end_index = end_index.or_else(|| {
+        let is_startup = |frame: &F| frame_contains_symbol(frame, platform::is_startup_symbol);
+        let last = frames.iter().rposition(is_startup)?;
+        let startup = frames[..last]
+            .iter()
+            .rev()
+            .take_while(|frame| is_startup(frame));
+        Some(last - startup.count())
+    });
+    // Partial stacks end in frames the unwinder couldn't make sense of.
+    let end_index = end_index.or_else(|| {
+        if relaxed {
+            frames
+                .iter()
+                .rposition(|frame| frame.is_resolved())
+                .map(|i| i + 1)
+        } else {
+            None
+        }
+    });
 
     let start_index = start_index.and_then(|s| {
         if end_index.as_ref().map(|e| s >= *e).unwrap_or(false) {
@@ -147,16 +1288,78 @@ fn filter_frames<'a>(frames: &'a [BacktraceFrame]) -> impl Iterator<Item = &'a B
         .map(|(_, frame)| frame)
 }
 
+/// Returns whether `sym` belongs to the panic machinery, whose frames start a panic's backtrace.
+#[cfg(feature = "std")]
+fn is_panic_marker(sym: &str) -> bool {
+    // Newer compilers include crate disambiguators (`std[1a2b3c]::panicking`).
+    let sym = normalize_symbol(sym);
+    // At some point the `std::panicking` prefix got lost, so we also check for a bare
+    // `panic_fmt` symbol. Even newer versions mark the end of the panic machinery with
+    // `__rust_end_short_backtrace`. `catch_unwind` (formerly `try`) lives in
+    // `std::panicking` too, but is part of the runtime startup, not of a panic.
+    sym == "panic_fmt"
+        || (sym.starts_with("std::panicking")
+            && !sym.starts_with("std::panicking::try")
+            && !sym.starts_with("std::panicking::catch_unwind"))
+        || sym.starts_with("std::sys::backtrace::__rust_end_short_backtrace")
+        || sym.ends_with("::rust_begin_unwind")
+        || platform::is_panic_symbol(&sym)
+}
+
+/// Returns the index after the frame of the user's `main`, or the index of the topmost frame of
+/// the runtime startup if it isn't found.
+#[cfg(feature = "std")]
+fn main_end<F: Frame>(frames: &[F]) -> Option<usize> {
+    // `async` mains (e.g. of `tokio::main`) are called by the real `main` further down.
+    let main = frames.iter().rposition(|frame| {
+        frame_contains_symbol(frame, |sym| {
+            let sym = normalize_symbol(sym);
+            sym.ends_with("::main") && sym.matches("::").count() == 1
+        })
+    });
+    main.map(|i| i + 1).or_else(|| {
+        frames.iter().position(|frame| {
+            frame_contains_symbol(frame, |sym| {
+                normalize_symbol(sym).starts_with("std::rt::lang_start")
+                    || sym == "main"
+                    || sym == "__libc_start_main"
+            })
+        })
+    })
+}
+
 /// Returns whether `frame` contains a symbol name for which `pred` returns `true`.
-fn frame_contains_symbol(frame: &BacktraceFrame, mut pred: impl FnMut(&str) -> bool) -> bool {
-    frame.symbols().iter().any(|sym| {
-        sym.name()
-            .and_then(|name| name.as_str())
-            .map(|name| pred(&demangle(name).to_string()))
+#[cfg(feature = "std")]
+fn frame_contains_symbol(frame: &impl Frame, mut pred: impl FnMut(&str) -> bool) -> bool {
+    frame.symbols().any(|sym| {
+        sym.name
+            .map(|name| demangled(name).with_str(&mut pred))
             .unwrap_or(false)
     })
 }
 
+/// Returns whether `frame` has symbols and `pred` returns `true` for all of their names.
+///
+/// Unlike [`frame_contains_symbol`] this doesn't match frames into which the matching code
+/// merely got inlined.
+#[cfg(feature = "std")]
+fn frame_only_contains_symbols(frame: &impl Frame, mut pred: impl FnMut(&str) -> bool) -> bool {
+    frame.symbols().next().is_some()
+        && frame.symbols().all(|sym| {
+            sym.name
+                .map(|name| demangled(name).with_str(&mut pred))
+                .unwrap_or(false)
+        })
+}
+
+/// Returns `name` demangled as the frame filters match it, with hashes.
+#[cfg(feature = "std")]
+fn demangled(name: &str) -> SymbolName<'_> {
+    SymbolName {
+        name,
+        demangling: Demangling::WithHashes,
+    }
+}
 
 /// Opportunistic file path shortening.
 ///
@@ -164,7 +1367,11 @@ fn frame_contains_symbol(frame: &BacktraceFrame, mut pred: impl FnMut(&str) -> b
 /// references to crates.io dependencies use absolute paths, which makes them hard to read
 /// (especially when using futures and tokio in debug builds). This function shortens those paths
 /// to start with the crate's directory instead.
-fn clean_path(p: &Path) -> Cow<Path> {
+#[cfg(feature = "std")]
+fn clean_path(p: &Path) -> Cow<'_, Path> {
+    if let Some(cleaned) = platform::clean_path(p) {
+        return cleaned.into();
+    }
     // Relative paths point to the final crate or the standard library. Absolute paths point to
     // crates.io dependencies. Those are the paths we want to shorten.
     if p.is_absolute() {
@@ -189,26 +1396,81 @@ fn clean_path(p: &Path) -> Cow<Path> {
     }
 }
 
+/// Shortens `p` to the directory containing its last `src` component, or to its file name.
+#[cfg(feature = "std")]
+fn redact_path(p: &Path) -> Cow<'_, Path> {
+    if !p.has_root() {
+        return p.into();
+    }
+
+    let components = p.components().collect::<Vec<_>>();
+    let start = components
+        .iter()
+        .rposition(|component| *component == Component::Normal("src".as_ref()))
+        .map(|src| src.saturating_sub(1))
+        .unwrap_or(components.len() - 1);
+    components[start..]
+        .iter()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect::<PathBuf>()
+        .into()
+}
+
+/// Returns the crate a symbol belongs to, the first segment of its path.
+#[cfg(feature = "std")]
+fn crate_name(symbol: &str) -> Option<String> {
+    let symbol = normalize_symbol(symbol);
+    let krate = &symbol[..symbol.find("::")?];
+    let is_ident = !krate.is_empty() && krate.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if is_ident {
+        Some(krate.to_owned())
+    } else {
+        None
+    }
+}
+
+/// Joins the normal components of `p` with `/`, whatever the separator of the platform is.
+#[cfg(feature = "std")]
+fn portable_path(p: &Path) -> String {
+    let mut out = String::new();
+    for component in p.components() {
+        if let Component::Normal(name) = component {
+            if !out.is_empty() {
+                out.push('/');
+            }
+            out.push_str(&name.to_string_lossy().replace('\\', "/"));
+        }
+    }
+    out
+}
 
-#[cfg(test)]
+/// Reads line `line` (starting at 1) of the source file at `path`.
+#[cfg(feature = "std")]
+fn read_source_line(path: &Path, line: u32) -> Option<String> {
+    let source = fs::read_to_string(path).ok()?;
+    let index = (line as usize).checked_sub(1)?;
+    source.lines().nth(index).map(str::to_owned)
+}
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use lazy_static::lazy_static;
 
     use std::{
         collections::HashMap,
-        panic::{self, PanicInfo, UnwindSafe},
+        panic::{self, PanicHookInfo, UnwindSafe},
         sync::{Mutex, Arc, atomic::{AtomicUsize, Ordering}},
         cell::Cell,
     };
 
-    type PanicHookFn = dyn Fn(&PanicInfo) + Sync + Send + 'static;
+    type PanicHookFn = dyn Fn(&PanicHookInfo) + Sync + Send + 'static;
 
     fn with_panic_hook(hook: Box<PanicHookFn>, func: impl FnOnce() + UnwindSafe) {
         let reset_id = set_panic_hook(hook);
         let _ = panic::catch_unwind(func);
         unset_panic_hook(reset_id);
 
-        ///-----------
+        //-----------
 
         fn set_panic_hook(hook: Box<PanicHookFn>) -> usize {
             let hook_id = set_hook_id();
@@ -224,7 +1486,7 @@ mod tests {
             id
         }
         thread_local! {
-            static HOOK_ID: Cell<usize> = Cell::new(0);
+            static HOOK_ID: Cell<usize> = const { Cell::new(0) };
         }
         static HOOK_ID_GEN: AtomicUsize = AtomicUsize::new(0);
         lazy_static! {
@@ -246,7 +1508,7 @@ mod tests {
     fn backtrace_from_panic_hook(inner: impl FnOnce() + UnwindSafe) -> String {
         let result_cell = Arc::new(Mutex::new(None));
         let result_cell2 = result_cell.clone();
-        let hook = Box::new(move |_panic_info: &PanicInfo| {
+        let hook = Box::new(move |_panic_info: &PanicHookInfo| {
             let out = crate::create_backtrace();
             *result_cell.lock().unwrap() = Some(out);
         });
@@ -260,7 +1522,7 @@ mod tests {
     #[test]
     fn backtrace_in_panic_hook() {
         let bt = backtrace_from_panic_hook(|| panic!("test backtrace from panic hook"));
-        assert!(bt.trim().len() > 0);
+        assert!(!bt.trim().is_empty());
     }
 
     // Note: This tests might brake/start failing with **non braking changes** in rustc and/or std
@@ -294,10 +1556,822 @@ mod tests {
         fuzzy_stacktrace_eq(expected_bt, bt);
     }
 
+    #[test]
+    #[cfg(all(feature = "resolve", feature = "demangle"))]
+    fn backtrace_outside_of_panic_hook_starts_at_caller() {
+        let bt = crate::create_backtrace();
+        let mut lines = bt.trim().lines();
+        assert!(lines.next().unwrap().contains("backtrace_string::create_backtrace"), "{}", bt);
+        let caller = lines.nth(1).unwrap();
+        assert!(caller.contains("backtrace_outside_of_panic_hook_starts_at_caller"), "{}", bt);
+    }
+
+    #[test]
+    #[cfg(all(feature = "resolve", feature = "demangle"))]
+    fn libtest_preset_ends_at_test_function() {
+        let options = crate::FormatOptions::new().filter_preset(crate::Preset::Libtest);
+        let bt = crate::format_backtrace_with(&mut backtrace::Backtrace::new(), &options);
+        let last_symbol = bt.trim().lines().rev().nth(1).unwrap();
+        assert!(last_symbol.contains("libtest_preset_ends_at_test_function"), "{}", bt);
+    }
+
+    #[test]
+    fn compact_style_with_frame_limit() {
+        let options = crate::FormatOptions::new()
+            .style(crate::Style::Compact)
+            .max_frames(Some(1));
+        let bt = crate::format_backtrace_with(&mut backtrace::Backtrace::new(), &options);
+        let lines = bt.trim().lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2, "{}", bt);
+        assert!(lines[0].contains("compact_style_with_frame_limit"));
+        assert!(lines[0].ends_with(")"));
+        assert!(lines[1].ends_with("more frames omitted"));
+    }
+
+    #[test]
+    fn vocabulary_replaces_words() {
+        let options = crate::FormatOptions::new()
+            .max_frames(Some(1))
+            .vocabulary(crate::Vocabulary {
+                at: "bei".into(),
+                frames_omitted: "... {} weitere Frames ausgelassen".into(),
+                ..crate::Vocabulary::default()
+            });
+        let bt = crate::format_backtrace_with(&mut backtrace::Backtrace::new(), &options);
+        assert!(bt.contains("\n          bei "), "{}", bt);
+        assert!(bt.trim_end().ends_with("weitere Frames ausgelassen"), "{}", bt);
+    }
+
+    #[test]
+    fn formatted_frames_match_the_backtrace() {
+        let options = crate::FormatOptions::new();
+        let mut bt = backtrace::Backtrace::new();
+        let whole = crate::format_backtrace_with(&mut bt, &options);
+        let frames = crate::formatted_frames_with(&mut bt, &options).collect::<Vec<_>>();
+        assert!(frames[0].contains("formatted_frames_match_the_backtrace"));
+        assert!(frames.iter().all(|frame| !frame.ends_with('\n')));
+        assert_eq!(format!("\n{}\n", frames.join("\n")), whole);
+    }
+
+    #[test]
+    fn deterministic_frames_are_never_colored() {
+        let options = crate::FormatOptions::new()
+            .color(crate::ColorChoice::Always)
+            .deterministic(true);
+        let mut bt = backtrace::Backtrace::new();
+        let whole = crate::format_backtrace_with(&mut bt, &options);
+        let frames = crate::formatted_frames_with(&mut bt, &options).collect::<Vec<_>>();
+        assert!(!whole.contains('\x1b'), "{:?}", whole);
+        assert!(
+            frames.iter().all(|frame| !frame.contains('\x1b')),
+            "{:?}",
+            frames
+        );
+    }
+
+    #[test]
+    fn annotations_are_shown_below_frames() {
+        let options = crate::FormatOptions::new()
+            .annotate_frame(0, "first")
+            .annotate_symbols(
+                |sym| sym.contains("annotations_are_shown_below_frames"),
+                "test function",
+            );
+        let bt = crate::format_backtrace_with(&mut backtrace::Backtrace::new(), &options);
+        let frame = bt.split("\n   1:").next().unwrap();
+        assert!(frame.contains("annotations_are_shown_below_frames"), "{}", bt);
+        assert!(frame.ends_with("\n      note: first\n      note: test function"), "{}", bt);
+    }
+
+    #[test]
+    fn unknown_symbols() {
+        use crate::{
+            frame::{ResolvedFrame, ResolvedSymbol},
+            FormatOptions, UnknownSymbols,
+        };
+
+        let frames = vec![
+            ResolvedFrame {
+                ip: unknown_symbols as *const () as usize,
+                symbols: vec![],
+            },
+            ResolvedFrame {
+                ip: 0x10,
+                symbols: vec![ResolvedSymbol {
+                    name: Some(String::from("my_app::main")),
+                    ..ResolvedSymbol::default()
+                }],
+            },
+        ];
+        let format = |unknown| {
+            crate::format_frames(&frames, &FormatOptions::new().unknown_symbols(unknown))
+        };
+
+        let address = format!("\n          at address {:#x}\n", frames[0].ip);
+        assert!(format(UnknownSymbols::Show).starts_with(&format!("\n   0: <unknown>{}", address)));
+        assert_eq!(
+            format(UnknownSymbols::Hide),
+            "\n   0: my_app::main\n          at <unknown>\n\
+             \x20     ... 1 frames without symbols hidden\n"
+        );
+        if cfg!(target_os = "linux") {
+            let out = format(UnknownSymbols::ModuleOffset);
+            assert!(out.starts_with("\n   0: backtrace_string-"), "{}", out);
+            assert!(out.contains("+0x"), "{}", out);
+        }
+    }
+
+    #[test]
+    fn stack_overflow_is_collapsed() {
+        use crate::frame::{ResolvedFrame, ResolvedSymbol};
+
+        let frame = |name: &str| ResolvedFrame {
+            ip: 0,
+            symbols: vec![ResolvedSymbol {
+                name: Some(name.to_string()),
+                ..ResolvedSymbol::default()
+            }],
+        };
+        let mut frames = vec![];
+        for _ in 0..100 {
+            frames.push(frame("my_app::parse_expr"));
+            frames.push(frame("my_app::parse_term"));
+        }
+        frames.push(frame("my_app::main"));
+
+        let options = crate::FormatOptions::new()
+            .style(crate::Style::Compact)
+            .detect_stack_overflow(true);
+        assert_eq!(
+            crate::format_frames(&frames, &options),
+            "\nlikely stack overflow: cycle of 2 frames repeated ~100 times\n\
+             \x20  0: my_app::parse_expr (<unknown>)\n\
+             \x20  1: my_app::parse_term (<unknown>)\n\
+             \x20     ... cycle of 2 frames repeated 99 more times\n\
+             \x20200: my_app::main (<unknown>)\n"
+        );
+    }
+
+    #[test]
+    fn capture_depth_limits_frames() {
+        fn recurse(depth: usize) -> backtrace::Backtrace {
+            if depth == 0 {
+                crate::capture_limited(3)
+            } else {
+                std::hint::black_box(recurse(depth - 1))
+            }
+        }
+
+        let mut bt = recurse(20);
+        assert_eq!(bt.frames().len(), 3);
+        bt.resolve();
+        let name = bt.frames()[0].symbols()[0].name().unwrap().to_string();
+        assert!(name.contains("recurse"), "{}", name);
+    }
+
+    #[test]
+    fn minimal_options_do_not_resolve() {
+        let options = crate::FormatOptions::minimal();
+        let mut bt = backtrace::Backtrace::new_unresolved();
+        let out = crate::format_backtrace_with(&mut bt, &options);
+        assert!(bt.frames().iter().all(|frame| frame.symbols().is_empty()));
+        let mut lines = out.lines().skip(1);
+        assert!(lines.next().unwrap().starts_with("   0: "), "{}", out);
+        assert!(lines.next().unwrap().starts_with("          at address 0x"), "{}", out);
+    }
+
+    #[test]
+    #[cfg(all(feature = "resolve", feature = "demangle"))]
+    fn deterministic_output_leaves_out_platform_details() {
+        use {
+            crate::{
+                frame::{ResolvedFrame, ResolvedSymbol},
+                FormatOptions,
+            },
+            std::path::PathBuf,
+        };
+
+        let frames = vec![
+            ResolvedFrame {
+                ip: 0x1234,
+                symbols: vec![ResolvedSymbol {
+                    name: Some(String::from("_ZN6my_app4main17h0123456789abcdefE")),
+                    filename: Some(PathBuf::from("/home/alice/my_app/src/main.rs")),
+                    lineno: Some(3),
+                    addr: Some(0x1200),
+                }],
+            },
+            ResolvedFrame {
+                ip: 0x5678,
+                symbols: vec![ResolvedSymbol {
+                    name: Some(String::from("std[1a2b3c]::rt::lang_start")),
+                    filename: Some(PathBuf::from("/rustc/90b35a62/library/std/src/rt.rs")),
+                    lineno: Some(159),
+                    addr: Some(0x5600),
+                }],
+            },
+            ResolvedFrame {
+                ip: 0x9abc,
+                symbols: vec![],
+            },
+        ];
+        let out = crate::format_frames(&frames, &FormatOptions::new().deterministic(true));
+        assert_eq!(
+            out,
+            "\n   0: my_app::main\n          at my_app/src/main.rs\n\
+             \x20  1: std::rt::lang_start\n          at std/src/rt.rs\n\
+             \x20  2: <unknown>\n          at <unknown>\n"
+        );
+    }
+
+    #[test]
+    fn symbols_can_be_numbered() {
+        use crate::{
+            frame::{ResolvedFrame, ResolvedSymbol},
+            FormatOptions, Numbering, Style,
+        };
+
+        let symbol = |name: &str| ResolvedSymbol {
+            name: Some(name.to_string()),
+            ..ResolvedSymbol::default()
+        };
+        let frames = vec![
+            ResolvedFrame {
+                ip: 0,
+                symbols: vec![symbol("inlined"), symbol("outer")],
+            },
+            ResolvedFrame {
+                ip: 0,
+                symbols: vec![symbol("main")],
+            },
+        ];
+        let format = |numbering| {
+            let options = FormatOptions::new()
+                .style(Style::Compact)
+                .numbering(numbering);
+            crate::format_frames(&frames, &options)
+        };
+        assert_eq!(
+            format(Numbering::Frames),
+            "\n   0: inlined (<unknown>)\n      outer (<unknown>)\n   1: main (<unknown>)\n"
+        );
+        assert_eq!(
+            format(Numbering::Symbols),
+            "\n   0: inlined (<unknown>)\n   1: outer (<unknown>)\n   2: main (<unknown>)\n"
+        );
+    }
+
+    #[test]
+    fn index_base_and_width() {
+        use crate::{
+            frame::{ResolvedFrame, ResolvedSymbol},
+            FormatOptions,
+        };
+
+        let symbol = |name: &str| ResolvedSymbol {
+            name: Some(name.to_string()),
+            ..ResolvedSymbol::default()
+        };
+        let frames = vec![
+            ResolvedFrame {
+                ip: 0,
+                symbols: vec![symbol("inlined"), symbol("outer")],
+            },
+            ResolvedFrame {
+                ip: 0,
+                symbols: vec![symbol("main")],
+            },
+        ];
+        let options = FormatOptions::parse("index_base=1,index_width=6").unwrap();
+        assert_eq!(
+            crate::format_frames(&frames, &options),
+            "\n     1: inlined\n            at <unknown>\n        outer\n            at <unknown>\n\
+             \x20    2: main\n            at <unknown>\n"
+        );
+    }
+
+    #[test]
+    fn locations_can_be_hidden() {
+        use {
+            crate::{
+                frame::{ResolvedFrame, ResolvedSymbol},
+                FormatOptions, Locations,
+            },
+            std::path::PathBuf,
+        };
+
+        let symbol = |name: &str, line| ResolvedSymbol {
+            name: Some(name.to_string()),
+            filename: Some(PathBuf::from("src/main.rs")),
+            lineno: Some(line),
+            addr: None,
+        };
+        let frames = vec![ResolvedFrame {
+            ip: 0,
+            symbols: vec![
+                symbol("my_app::helper", 3),
+                symbol("my_app::helper", 7),
+                symbol("<my_app::Foo as core::fmt::Debug>::fmt", 12),
+            ],
+        }];
+        let format = |locations| {
+            crate::format_frames(&frames, &FormatOptions::new().locations(locations))
+        };
+        assert_eq!(
+            format(Locations::Hidden),
+            "\n   0: my_app::helper\n      <my_app::Foo as core::fmt::Debug>::fmt\n"
+        );
+        assert_eq!(
+            format(Locations::Crate),
+            "\n   0: my_app::helper\n          at my_app\n\
+             \x20     <my_app::Foo as core::fmt::Debug>::fmt\n          at my_app\n"
+        );
+    }
+
+    #[test]
+    fn empty_backtraces_explain_the_likely_causes() {
+        use {
+            crate::{CaptureOutcome, FormatOptions, Vocabulary},
+            backtrace::Backtrace,
+        };
+
+        let mut empty = Backtrace::from(Vec::new());
+        assert_eq!(CaptureOutcome::of(&empty), CaptureOutcome::Empty);
+        let out = crate::format_backtrace_with(&mut empty, &FormatOptions::new());
+        assert_eq!(out, format!("\n{}\n", Vocabulary::default().no_frames));
+        assert!(out.contains("force-frame-pointers"), "{}", out);
+
+        let unresolved = Backtrace::new_unresolved();
+        assert_eq!(CaptureOutcome::of(&unresolved), CaptureOutcome::Unresolved);
+        assert_eq!(CaptureOutcome::of(&Backtrace::new()), CaptureOutcome::Captured);
+    }
+
+    #[cfg(all(target_os = "linux", feature = "resolve", feature = "demangle"))]
+    #[test]
+    fn modules_are_resolved_per_their_symbolication() {
+        use {
+            crate::{FormatOptions, Symbolication},
+            backtrace::Backtrace,
+        };
+
+        let format = |symbolication| {
+            let options = FormatOptions::new().module_symbolication("*", symbolication);
+            crate::format_backtrace_with(&mut Backtrace::new_unresolved(), &options)
+        };
+        let names = format(Symbolication::Names);
+        assert!(names.contains("modules_are_resolved_per"), "{}", names);
+        assert!(!names.contains("src/lib.rs"), "{}", names);
+        let addresses = format(Symbolication::Addresses);
+        assert!(!addresses.contains("modules_are_resolved_per"), "{}", addresses);
+        assert!(addresses.contains("<unknown>"), "{}", addresses);
+    }
+
+    #[test]
+    fn identical_inlined_symbols_are_merged() {
+        use {
+            crate::{
+                frame::{ResolvedFrame, ResolvedSymbol},
+                FormatOptions, Numbering, Style,
+            },
+            std::path::PathBuf,
+        };
+
+        let symbol = |name: &str, line| ResolvedSymbol {
+            name: Some(name.to_string()),
+            filename: Some(PathBuf::from("src/main.rs")),
+            lineno: Some(line),
+            addr: None,
+        };
+        let frames = vec![ResolvedFrame {
+            ip: 0,
+            symbols: vec![
+                symbol("my_app::helper", 3),
+                symbol("my_app::helper", 3),
+                symbol("my_app::helper", 7),
+                symbol("my_app::main", 12),
+            ],
+        }];
+        let format = |options: FormatOptions| crate::format_frames(&frames, &options);
+        assert_eq!(
+            format(FormatOptions::new()),
+            "\n   0: my_app::helper\n          at src/main.rs:3\n          at src/main.rs:7\n\
+             \x20     my_app::main\n          at src/main.rs:12\n"
+        );
+        assert_eq!(
+            format(FormatOptions::new().style(Style::Compact)),
+            "\n   0: my_app::helper (src/main.rs:3)\n      my_app::helper (src/main.rs:7)\n\
+             \x20     my_app::main (src/main.rs:12)\n"
+        );
+        // Numbered symbols are all shown, so the numbers count the symbols.
+        assert_eq!(
+            format(FormatOptions::new().style(Style::Compact).numbering(Numbering::Symbols)),
+            "\n   0: my_app::helper (src/main.rs:3)\n   1: my_app::helper (src/main.rs:3)\n\
+             \x20  2: my_app::helper (src/main.rs:7)\n   3: my_app::main (src/main.rs:12)\n"
+        );
+    }
+
+    #[test]
+    fn inlined_symbols_show_their_call_site() {
+        use {
+            crate::{
+                frame::{ResolvedFrame, ResolvedSymbol},
+                FormatOptions, Style,
+            },
+            std::path::PathBuf,
+        };
+
+        let symbol = |name: &str, file: &str, line| ResolvedSymbol {
+            name: Some(name.to_string()),
+            filename: Some(PathBuf::from(file)),
+            lineno: Some(line),
+            addr: None,
+        };
+        let frames = vec![ResolvedFrame {
+            ip: 0,
+            symbols: vec![
+                symbol("my_app::parser::token", "src/parser.rs", 88),
+                symbol("my_app::lexer::next", "src/lexer.rs", 12),
+            ],
+        }];
+        let format =
+            |options: FormatOptions| crate::format_frames(&frames, &options.call_sites(true));
+        assert_eq!(
+            format(FormatOptions::new()),
+            "\n   0: my_app::parser::token\n          inlined from src/parser.rs:88 at \
+             src/lexer.rs:12\n      my_app::lexer::next\n          at src/lexer.rs:12\n"
+        );
+        assert_eq!(
+            format(FormatOptions::new().style(Style::Compact)),
+            "\n   0: my_app::parser::token (inlined from src/parser.rs:88 at src/lexer.rs:12)\n\
+             \x20     my_app::lexer::next (src/lexer.rs:12)\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        use {
+            crate::{
+                frame::{Frame, Symbol},
+                FormatOptions,
+            },
+            std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path},
+        };
+
+        struct InvalidFrame;
+
+        impl Frame for InvalidFrame {
+            fn ip(&self) -> usize {
+                0
+            }
+
+            fn symbols(&self) -> impl Iterator<Item = Symbol<'_>> + '_ {
+                std::iter::once(Symbol {
+                    raw_name: Some(b"my_app::\xffmain"),
+                    filename: Some(Path::new(OsStr::from_bytes(b"src/\xff.rs"))),
+                    lineno: Some(3),
+                    ..Symbol::default()
+                })
+            }
+        }
+
+        let out = crate::format_frames(&[InvalidFrame], &FormatOptions::new());
+        assert_eq!(out, "\n   0: my_app::\u{fffd}main\n          at src/\u{fffd}.rs:3\n");
+    }
+
+    #[test]
+    fn relaxed_trimming_without_markers() {
+        use crate::{
+            frame::{ResolvedFrame, ResolvedSymbol},
+            FormatOptions,
+        };
+
+        let frame = |name: Option<&str>| ResolvedFrame {
+            ip: 0,
+            symbols: name
+                .map(|name| ResolvedSymbol {
+                    name: Some(name.to_string()),
+                    ..ResolvedSymbol::default()
+                })
+                .into_iter()
+                .collect(),
+        };
+        let frames = vec![
+            frame(Some("backtrace::backtrace::trace")),
+            frame(Some("backtrace::capture::Backtrace::new")),
+            frame(Some("my_app::main")),
+            frame(Some("libc_start_main_stage2")),
+            frame(None),
+        ];
+        let options = FormatOptions::new().locations(crate::Locations::Hidden);
+        let out = crate::format_frame_source(&frames, &options);
+        assert_eq!(out.lines().count(), 6, "{}", out);
+
+        let out = crate::format_frame_source(&frames, &options.relaxed_trimming(true));
+        assert_eq!(out, "\n   0: my_app::main\n");
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn windows_frames_are_trimmed() {
+        use crate::{
+            frame::{ResolvedFrame, ResolvedSymbol},
+            FormatOptions, Locations,
+        };
+
+        let frames = [
+            "backtrace::backtrace::dbghelp::trace",
+            "backtrace_string::create_backtrace",
+            "std::panicking::rust_panic_with_hook",
+            "std::panicking::begin_panic_handler::closure$0",
+            "rust_begin_unwind",
+            "core::panicking::panic_fmt",
+            "my_app::main",
+            "core::ops::function::FnOnce::call_once<void (*)(),tuple$<> >",
+            "invoke_main",
+            "__scrt_common_main_seh",
+            "BaseThreadInitThunk",
+            "RtlUserThreadStart",
+        ]
+        .iter()
+        .map(|name| ResolvedFrame {
+            ip: 0,
+            symbols: vec![ResolvedSymbol {
+                name: Some(name.to_string()),
+                ..ResolvedSymbol::default()
+            }],
+        })
+        .collect::<Vec<_>>();
+        let options = FormatOptions::new().locations(Locations::Hidden);
+        assert_eq!(
+            crate::format_frame_source(&frames, &options),
+            "\n   0: my_app::main\n\
+             \x20  1: core::ops::function::FnOnce::call_once<void (*)(),tuple$<> >\n"
+        );
+    }
+
+    #[test]
+    fn startup_frames_are_trimmed_from_the_bottom() {
+        use crate::{
+            frame::{ResolvedFrame, ResolvedSymbol},
+            platform, FormatOptions, Locations,
+        };
+
+        // Platforms with the markers of the Rust runtime or of their C library have no list.
+        let (first, last) = match platform::STARTUP {
+            [first, .., last] => (*first, *last),
+            _ => return,
+        };
+        // An FFI callback sharing the name of a startup symbol doesn't end the backtrace.
+        let frames = ["my_app::on_event", last, "my_app::main", first, last]
+            .iter()
+            .map(|name| ResolvedFrame {
+                ip: 0,
+                symbols: vec![ResolvedSymbol {
+                    name: Some(name.to_string()),
+                    ..ResolvedSymbol::default()
+                }],
+            })
+            .collect::<Vec<_>>();
+        let options = FormatOptions::new().locations(Locations::Hidden);
+        assert_eq!(
+            crate::format_frame_source(&frames, &options),
+            format!(
+                "\n   0: my_app::on_event\n   1: {}\n   2: my_app::main\n",
+                last
+            )
+        );
+    }
+
+    #[test]
+    fn nested_panics_are_separated() {
+        use crate::{
+            frame::{ResolvedFrame, ResolvedSymbol},
+            FormatOptions, Locations,
+        };
+
+        let machinery = [
+            "std::panicking::rust_panic_with_hook",
+            "std::panicking::begin_panic_handler::{{closure}}",
+            "rust_begin_unwind",
+            "core::panicking::panic_fmt",
+        ];
+        let frames = ["backtrace::backtrace::trace", "my_app::report"]
+            .iter()
+            .chain(&machinery)
+            .chain(&["my_app::hook"])
+            .chain(&machinery)
+            .chain(&[
+                "my_app::main",
+                "std::sys::backtrace::__rust_begin_short_backtrace",
+                "main",
+            ])
+            .map(|name| ResolvedFrame {
+                ip: 0,
+                symbols: vec![ResolvedSymbol {
+                    name: Some(name.to_string()),
+                    ..ResolvedSymbol::default()
+                }],
+            })
+            .collect::<Vec<_>>();
+        let options = FormatOptions::new().locations(Locations::Hidden);
+        // The markers of the outer panic are within the window, hiding the inner one.
+        assert_eq!(
+            crate::format_frame_source(&frames, &options),
+            "\n   0: core::panicking::panic_fmt\n   1: my_app::main\n"
+        );
+        let out = crate::format_frame_source(&frames, &options.clone().marker_window(Some(2)));
+        assert!(out.starts_with("\n   0: backtrace::backtrace::trace\n"), "{}", out);
+        assert_eq!(
+            crate::format_frame_source(&frames, &options.nested_panics(true)),
+            "\n   0: my_app::hook\n\
+             \x20     --- while handling the panic below ---\n\
+             \x20  5: my_app::main\n"
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn shows_the_aslr_slide() {
+        use crate::FormatOptions;
+
+        let frames = Vec::<crate::frame::ResolvedFrame>::new();
+        let options = FormatOptions::new().aslr_slide(true);
+        let out = crate::format_frames(&frames, &options);
+        assert!(
+            out.starts_with("\nASLR slide of the executable: 0x"),
+            "{}",
+            out
+        );
+        assert_eq!(out.lines().count(), 2, "{}", out);
+        let out = crate::format_frames(&frames, &options.deterministic(true));
+        assert_eq!(out, "\n");
+    }
+
+    #[test]
+    fn ends_at_main() {
+        use crate::{
+            frame::{ResolvedFrame, ResolvedSymbol},
+            FormatOptions, Locations,
+        };
+
+        let frames = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| ResolvedFrame {
+                    ip: 0,
+                    symbols: vec![ResolvedSymbol {
+                        name: Some(name.to_string()),
+                        ..ResolvedSymbol::default()
+                    }],
+                })
+                .collect::<Vec<_>>()
+        };
+        let options = FormatOptions::new()
+            .locations(Locations::Hidden)
+            .end_at_main(true);
+        let tokio = frames(&[
+            "my_app::main::{{closure}}",
+            "tokio::runtime::runtime::Runtime::block_on",
+            "my_app::main",
+            "core::ops::function::FnOnce::call_once",
+            "std::rt::lang_start::{{closure}}",
+            "std::rt::lang_start_internal",
+            "main",
+            "__libc_start_call_main",
+        ]);
+        assert_eq!(
+            crate::format_frame_source(&tokio, &options),
+            "\n   0: my_app::main::{{closure}}\n\
+             \x20  1: tokio::runtime::runtime::Runtime::block_on\n\
+             \x20  2: my_app::main\n"
+        );
+
+        let stripped = frames(&["my_app::run", "std::rt::lang_start_internal", "main"]);
+        assert_eq!(
+            crate::format_frame_source(&stripped, &options),
+            "\n   0: my_app::run\n"
+        );
+    }
+
+    #[test]
+    fn format_chain_elides_shared_frames() {
+        use crate::{
+            frame::{ResolvedFrame, ResolvedSymbol},
+            FormatOptions, Locations,
+        };
+
+        let frame = |ip, name: &str| ResolvedFrame {
+            ip,
+            symbols: vec![ResolvedSymbol {
+                name: Some(name.to_owned()),
+                ..ResolvedSymbol::default()
+            }],
+        };
+        let panic = vec![
+            frame(1, "my_app::handle"),
+            frame(2, "my_app::serve"),
+            frame(3, "my_app::main"),
+        ];
+        let error = vec![
+            frame(4, "my_app::config::load"),
+            frame(2, "my_app::serve"),
+            frame(3, "my_app::main"),
+        ];
+        // Called from another place in `my_app::main`.
+        let startup = vec![frame(5, "my_app::config::load"), frame(6, "my_app::main")];
+        let options = FormatOptions::new().locations(Locations::Hidden);
+        assert_eq!(
+            crate::format_chain_frames(
+                &[("panic", &panic), ("error created", &error), ("startup", &startup)],
+                &options
+            ),
+            "panic:\n   0: my_app::handle\n   1: my_app::serve\n   2: my_app::main\n\n\
+             Caused by: error created:\n   0: my_app::config::load\n      \
+             (+ same 2 bottom frames as 'panic')\n\n\
+             Caused by: startup:\n   0: my_app::config::load\n   1: my_app::main\n"
+        );
+    }
+
+    #[test]
+    fn only_my_code() {
+        use crate::{
+            frame::{ResolvedFrame, ResolvedSymbol},
+            FormatOptions, Locations,
+        };
+
+        let frame = |name: &str, file: &str| ResolvedFrame {
+            ip: 0,
+            symbols: vec![ResolvedSymbol {
+                name: Some(name.to_owned()),
+                filename: Some(file.into()),
+                ..ResolvedSymbol::default()
+            }],
+        };
+        let tokio = "/home/alice/.cargo/registry/src/tokio-1.28.0/src/runtime/task.rs";
+        let frames = vec![
+            frame("my_app::handler::{{closure}}", "src/handler.rs"),
+            frame("tokio::runtime::task::harness::Harness<T,S>::poll", tokio),
+            frame("tokio::runtime::task::raw::poll", tokio),
+            frame("tokio::runtime::scheduler::block_on", tokio),
+            frame("my_app::main", "src/main.rs"),
+            frame("std::rt::lang_start_internal", "/rustc/abc/library/std/src/rt.rs"),
+        ];
+        let options = FormatOptions::new()
+            .locations(Locations::Hidden)
+            .only_my_code(true);
+        assert_eq!(
+            crate::format_frame_source(&frames, &options),
+            "\n   0: my_app::handler::{{closure}}\n      \
+             … via tokio::runtime (3 frames) …\n   \
+             4: my_app::main\n      \
+             … via std::rt (1 frames) …\n"
+        );
+
+        // Without any frames of the application all are shown.
+        assert_eq!(
+            crate::format_frame_source(&frames[1..2], &options),
+            "\n   0: tokio::runtime::task::harness::Harness<T,S>::poll\n"
+        );
+    }
+
+    #[test]
+    fn redact_path_keeps_crate_directory() {
+        use std::path::Path;
+
+        let redacted = crate::redact_path(Path::new("/home/alice/my_app/src/bin/main.rs"));
+        assert_eq!(redacted, Path::new("my_app/src/bin/main.rs"));
+        let redacted = crate::redact_path(Path::new("/usr/lib/libc.so.6"));
+        assert_eq!(redacted, Path::new("libc.so.6"));
+        let relative = crate::redact_path(Path::new("src/lib.rs"));
+        assert_eq!(relative, Path::new("src/lib.rs"));
+    }
+
+    #[test]
+    fn dev_profile_shows_source_lines() {
+        use crate::frame::{ResolvedFrame, ResolvedSymbol};
+
+        // The debug info has paths relative to the workspace root, which isn't necessarily the
+        // working directory of the tests.
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/lib.rs");
+        let frames = vec![ResolvedFrame {
+            ip: 0,
+            symbols: vec![ResolvedSymbol {
+                name: Some("backtrace_string::tests::dev_profile_shows_source_lines".to_owned()),
+                filename: Some(path),
+                lineno: Some(line!()),
+                ..ResolvedSymbol::default()
+            }],
+        }];
+        let options = crate::FormatOptions::dev().color(crate::ColorChoice::Never);
+        let bt = crate::format_frame_source(&frames, &options);
+        assert!(bt.contains("| lineno: Some(line!()),"), "{}", bt);
+    }
+
     #[test]
     fn backtrace_outside_of_panic_hook() {
         let bt = crate::create_backtrace();
-        assert!(bt.trim().len() > 0);
+        assert!(!bt.trim().is_empty());
     }
 
     #[test]
@@ -0,0 +1,329 @@
+//! The names of the local variables in scope at the top user frame, read from the debug info,
+//! built with the `local-hints` feature.
+//!
+//! A panic in `parse()` is easier to reproduce knowing that `parse()` had `input` and `config`
+//! as parameters and `line` and `pos` as locals at the time. [`local_hints()`] reads the DWARF
+//! debug info of the module of the top user frame (see [`app`]) and returns the names of the
+//! parameters and locals in scope at its address, not their values:
+//!
+//! ```
+//! use backtrace_string::{locals::local_hints, FormatOptions};
+//!
+//! if let Some(hints) = local_hints(&mut backtrace::Backtrace::new(), &FormatOptions::new()) {
+//!     println!("in scope: {}", hints);
+//! }
+//! ```
+//!
+//! This parses the debug info of the whole module on each call, so it is meant for the final
+//! report of a crash and not for every captured backtrace. The modules are found through
+//! `/proc/self/maps` or the dynamic linker, so there are no hints on platforms other than Linux,
+//! Android, the BSDs, illumos and QNX, nor for modules without DWARF debug info in the binary
+//! itself, e.g. with split or compressed debug info. Locals which are optimized out at the
+//! address are left out where the debug info says so.
+//!
+//![`local_hints()`]: fn.local_hints.html
+//![`app`]: ../app/index.html
+
+use {
+    crate::{
+        app, demangle::SymbolName, filter_frames, frame::Frame, module::Modules, resolve,
+        Demangling, FormatOptions,
+    },
+    backtrace::Backtrace,
+    gimli::{
+        constants::DwAt, AttributeValue, DebuggingInformationEntry, Dwarf, EndianSlice,
+        EntriesTreeNode, RangeIter, RunTimeEndian, Unit,
+    },
+    object::{Object, ObjectSection, ObjectSegment},
+    std::{fmt, fs, path::Path},
+};
+
+type Reader<'a> = EndianSlice<'a, RunTimeEndian>;
+
+/// The names in scope at the top user frame.
+///
+/// It displays like a call with the locals appended, e.g.
+/// `my_app::parse(input, config), locals: line, pos`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LocalHints {
+    /// The name of the function, demangled without hashes.
+    pub function: String,
+    /// The names of the function's parameters, in declaration order.
+    pub parameters: Vec<String>,
+    /// The names of the local variables in scope, in declaration order.
+    pub locals: Vec<String>,
+}
+
+impl fmt::Display for LocalHints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({})", self.function, self.parameters.join(", "))?;
+        if !self.locals.is_empty() {
+            write!(f, ", locals: {}", self.locals.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the names in scope at the top user frame of the frames [`format_backtrace_with()`]
+/// would format, see the [module docs].
+///
+/// Returns `None` if there is no user frame or no debug info covering it.
+///
+///[`format_backtrace_with()`]: ../fn.format_backtrace_with.html
+///[module docs]: index.html
+pub fn local_hints(bt: &mut Backtrace, options: &FormatOptions) -> Option<LocalHints> {
+    resolve(bt, options);
+    let (ip, function) = filter_frames(bt.frames(), options)
+        .filter(|frame| options.is_visible(frame))
+        .find_map(|frame| {
+            let function = Frame::symbols(frame).find_map(|symbol| {
+                let name = demangled(&symbol.name_lossy()?);
+                if app::is_dependency(&name, symbol.filename) {
+                    None
+                } else {
+                    Some(name)
+                }
+            })?;
+            Some((Frame::ip(frame), function))
+        })?;
+    let modules = Modules::default();
+    let (path, offset) = modules.find(ip)?;
+    // The address is the return address after the call, which may belong to the next statement.
+    read_hints(path, (offset as u64).checked_sub(1)?, function)
+}
+
+fn demangled(name: &str) -> String {
+    SymbolName {
+        name,
+        demangling: Demangling::WithoutHashes,
+    }
+    .to_string()
+}
+
+/// Reads the names in scope at the file offset `offset` of the module at `path`.
+fn read_hints(path: &Path, offset: u64, function: String) -> Option<LocalHints> {
+    let data = fs::read(path).ok()?;
+    let file = object::File::parse(&*data).ok()?;
+    let address = file.segments().find_map(|segment| {
+        let (start, size) = segment.file_range();
+        if start <= offset && offset - start < size {
+            Some(segment.address() + (offset - start))
+        } else {
+            None
+        }
+    })?;
+    let endian = if file.is_little_endian() {
+        RunTimeEndian::Little
+    } else {
+        RunTimeEndian::Big
+    };
+    let dwarf = Dwarf::load(|id| -> Result<_, ()> {
+        let data = file
+            .section_by_name(id.name())
+            .and_then(|section| section.data().ok())
+            .unwrap_or(&[]);
+        Ok(EndianSlice::new(data, endian))
+    })
+    .ok()?;
+    let scopes = scopes_at(&dwarf, address).ok()?;
+    // The innermost scope may be a function of a dependency inlined into the user's function.
+    let scope = scopes
+        .iter()
+        .rev()
+        .find(|scope| scope.name.as_deref().map(demangled).as_ref() == Some(&function))
+        .or_else(|| scopes.last())?;
+    Some(LocalHints {
+        function,
+        parameters: scope.parameters.clone(),
+        locals: scope.locals.clone(),
+    })
+}
+
+/// A function, or an inlined call of one, whose code contains the address.
+#[derive(Debug, Default)]
+struct Scope {
+    /// The linkage name if known, the plain name otherwise.
+    name: Option<String>,
+    parameters: Vec<String>,
+    locals: Vec<String>,
+}
+
+/// Returns the scopes containing `address`, the outermost first.
+fn scopes_at(dwarf: &Dwarf<Reader<'_>>, address: u64) -> gimli::Result<Vec<Scope>> {
+    let mut headers = dwarf.units();
+    while let Some(header) = headers.next()? {
+        let unit = dwarf.unit(header)?;
+        if !contains(dwarf.unit_ranges(&unit)?, address)? {
+            continue;
+        }
+        let mut scopes = Vec::new();
+        let mut tree = unit.entries_tree(None)?;
+        walk(dwarf, &unit, tree.root()?, address, None, &mut scopes)?;
+        return Ok(scopes);
+    }
+    Ok(Vec::new())
+}
+
+fn contains(mut ranges: RangeIter<Reader<'_>>, address: u64) -> gimli::Result<bool> {
+    while let Some(range) = ranges.next()? {
+        if range.begin <= address && address < range.end {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Collects the scopes containing `address` below `node`, with the variables declared in them.
+/// `current` is the index of the innermost scope containing `node`.
+fn walk(
+    dwarf: &Dwarf<Reader<'_>>,
+    unit: &Unit<Reader<'_>>,
+    node: EntriesTreeNode<'_, '_, '_, Reader<'_>>,
+    address: u64,
+    mut current: Option<usize>,
+    scopes: &mut Vec<Scope>,
+) -> gimli::Result<()> {
+    let entry = node.entry();
+    match entry.tag() {
+        gimli::DW_TAG_subprogram | gimli::DW_TAG_inlined_subroutine => {
+            if !contains(dwarf.die_ranges(unit, entry)?, address)? {
+                return Ok(());
+            }
+            let names = [
+                gimli::DW_AT_linkage_name,
+                gimli::DW_AT_MIPS_linkage_name,
+                gimli::DW_AT_name,
+            ];
+            scopes.push(Scope {
+                name: name_of(dwarf, unit, entry, &names, 2)?,
+                ..Scope::default()
+            });
+            current = Some(scopes.len() - 1);
+        }
+        gimli::DW_TAG_lexical_block if !contains(dwarf.die_ranges(unit, entry)?, address)? => {
+            return Ok(());
+        }
+        tag @ (gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable) => {
+            let scope = match current {
+                Some(current) => &mut scopes[current],
+                None => return Ok(()),
+            };
+            if entry.attr_value(gimli::DW_AT_artificial)? == Some(AttributeValue::Flag(true))
+                || !is_live(dwarf, unit, entry, address)?
+            {
+                return Ok(());
+            }
+            let name = match name_of(dwarf, unit, entry, &[gimli::DW_AT_name], 2)? {
+                Some(name) => name,
+                None => return Ok(()),
+            };
+            let names = match tag {
+                gimli::DW_TAG_formal_parameter => &mut scope.parameters,
+                _ => &mut scope.locals,
+            };
+            // Shadowing declares a name again.
+            if !names.contains(&name) {
+                names.push(name);
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+    let mut children = node.children();
+    while let Some(child) = children.next()? {
+        walk(dwarf, unit, child, address, current, scopes)?;
+    }
+    Ok(())
+}
+
+/// Returns the first of the attributes `names` of `entry`, or of the entries it refers to as
+/// abstract origin or specification, up to `depth` references away.
+fn name_of(
+    dwarf: &Dwarf<Reader<'_>>,
+    unit: &Unit<Reader<'_>>,
+    entry: &DebuggingInformationEntry<'_, '_, Reader<'_>>,
+    names: &[DwAt],
+    depth: usize,
+) -> gimli::Result<Option<String>> {
+    for &name in names {
+        if let Some(value) = entry.attr_value(name)? {
+            return Ok(Some(
+                dwarf
+                    .attr_string(unit, value)?
+                    .to_string_lossy()
+                    .into_owned(),
+            ));
+        }
+    }
+    for reference in [gimli::DW_AT_abstract_origin, gimli::DW_AT_specification] {
+        if let (Some(AttributeValue::UnitRef(offset)), 1..) = (entry.attr_value(reference)?, depth)
+        {
+            return name_of(dwarf, unit, &unit.entry(offset)?, names, depth - 1);
+        }
+    }
+    Ok(None)
+}
+
+/// Returns whether the variable `entry` has a location at `address`, or doesn't say.
+fn is_live(
+    dwarf: &Dwarf<Reader<'_>>,
+    unit: &Unit<Reader<'_>>,
+    entry: &DebuggingInformationEntry<'_, '_, Reader<'_>>,
+    address: u64,
+) -> gimli::Result<bool> {
+    let value = match entry.attr_value(gimli::DW_AT_location)? {
+        Some(value) => value,
+        None => return Ok(true),
+    };
+    let mut locations = match dwarf.attr_locations(unit, value)? {
+        Some(locations) => locations,
+        // A single location expression is valid in the whole scope.
+        None => return Ok(true),
+    };
+    while let Some(location) = locations.next()? {
+        if location.range.begin <= address && address < location.range.end {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use {
+        super::{local_hints, LocalHints},
+        crate::FormatOptions,
+        backtrace::Backtrace,
+        std::hint::black_box,
+    };
+
+    #[inline(never)]
+    fn parse_record(input: &str) -> Option<LocalHints> {
+        let fields = black_box(input.split(',').count());
+        let hints = local_hints(&mut Backtrace::new(), &FormatOptions::new());
+        black_box(fields);
+        hints
+    }
+
+    #[test]
+    fn names_the_locals_of_the_top_user_frame() {
+        let hints = parse_record("a,b").expect("no hints");
+        assert!(hints.function.ends_with("parse_record"), "{}", hints);
+        assert_eq!(hints.parameters, ["input"]);
+        assert!(
+            hints.locals.iter().any(|local| local == "fields"),
+            "{}",
+            hints
+        );
+        let hints = LocalHints {
+            function: "my_app::parse".to_owned(),
+            parameters: vec!["input".to_owned(), "config".to_owned()],
+            locals: vec!["line".to_owned()],
+        };
+        assert_eq!(
+            hints.to_string(),
+            "my_app::parse(input, config), locals: line"
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! Logical frames recorded by the application, reported together with the physical backtrace.
+//!
+//! Physical backtraces of async code rarely show the chain of `.await`s which lead to a panic,
+//! as each task is polled directly by the executor. With [`trace_frame!`] the application can
+//! record descriptions of what it is doing, these are appended to panic reports created by this
+//! crate:
+//!
+//! ```
+//! # use backtrace_string::trace_frame;
+//! async fn handle_request(id: u32) {
+//!     trace_frame!("handling request {}", id);
+//!     // ...
+//! }
+//!
+//! # async fn run() {
+//! backtrace_string::logical::scope(handle_request(42)).await;
+//! # }
+//! ```
+//!
+//! The frames are kept per thread. Futures holding frames across `.await` points need to be
+//! wrapped with [`scope()`] (which tasks spawned with
+//! [`tokio::spawn_reported()`](../tokio/fn.spawn_reported.html) already are), so that their
+//! frames move with them between threads and don't leak into other tasks.
+//!
+//![`trace_frame!`]: ../macro.trace_frame.html
+//![`scope()`]: fn.scope.html
+
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    future::Future,
+    mem,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+thread_local! {
+    static STACK: RefCell<Vec<Cow<'static, str>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Records a logical frame described by the format arguments until the end of the current block.
+///
+/// See the [`logical`](logical/index.html) module for details.
+#[macro_export]
+macro_rules! trace_frame {
+    ($($arg:tt)+) => {
+        let _logical_frame = $crate::logical::push_frame(format!($($arg)+));
+    };
+}
+
+/// Records a logical frame until the returned guard is dropped.
+pub fn push_frame(description: impl Into<Cow<'static, str>>) -> FrameGuard {
+    STACK.with(|stack| stack.borrow_mut().push(description.into()));
+    FrameGuard { _private: () }
+}
+
+/// Removes the logical frame it was returned for when dropped.
+#[must_use = "the logical frame is removed when the guard is dropped"]
+pub struct FrameGuard {
+    _private: (),
+}
+
+impl Drop for FrameGuard {
+    fn drop(&mut self) {
+        STACK.with(|stack| stack.borrow_mut().pop());
+    }
+}
+
+/// Returns the logical frames of the current thread, the most recent one first.
+pub fn current_frames() -> Vec<String> {
+    STACK.with(|stack| {
+        stack
+            .borrow()
+            .iter()
+            .rev()
+            .map(|frame| frame.to_string())
+            .collect()
+    })
+}
+
+/// Formats the logical frames like the frames of a backtrace, returns `None` if there are none.
+pub(crate) fn format_current_frames() -> Option<String> {
+    let frames = current_frames();
+    if frames.is_empty() {
+        return None;
+    }
+
+    let mut out = String::from("\n");
+    for (index, frame) in frames.iter().enumerate() {
+        out.push_str(&format!("{:4}: {}\n", index, frame));
+    }
+    Some(out)
+}
+
+/// The logical frames of a task while it is not being polled.
+#[derive(Default)]
+pub(crate) struct TaskFrames {
+    frames: Vec<Cow<'static, str>>,
+}
+
+impl TaskFrames {
+    /// Runs `func` with the task's frames on top of the current thread's frames.
+    pub(crate) fn enter<R>(&mut self, func: impl FnOnce() -> R) -> R {
+        struct Leave<'a> {
+            frames: &'a mut Vec<Cow<'static, str>>,
+            base: usize,
+        }
+
+        impl Drop for Leave<'_> {
+            fn drop(&mut self) {
+                STACK.with(|stack| {
+                    let mut stack = stack.borrow_mut();
+                    let base = self.base.min(stack.len());
+                    *self.frames = stack.split_off(base);
+                });
+            }
+        }
+
+        let base = STACK.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            let base = stack.len();
+            stack.append(&mut self.frames);
+            base
+        });
+        let _leave = Leave {
+            frames: &mut self.frames,
+            base,
+        };
+        func()
+    }
+}
+
+/// Wraps `future` so that the logical frames it records stay with it, see [`Scope`].
+///
+///[`Scope`]: struct.Scope.html
+pub fn scope<F: Future>(future: F) -> Scope<F> {
+    Scope {
+        inner: Some(Box::pin(future)),
+        frames: TaskFrames::default(),
+    }
+}
+
+/// A future owning the logical frames recorded while polling it.
+pub struct Scope<F> {
+    inner: Option<Pin<Box<F>>>,
+    frames: TaskFrames,
+}
+
+impl<F: Future> Future for Scope<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let inner = this
+            .inner
+            .as_mut()
+            .expect("`Scope` polled after completion")
+            .as_mut();
+        let poll = this.frames.enter(|| inner.poll(cx));
+        if poll.is_ready() {
+            // Drop the future while its frames are in place, so its guards pop the right ones.
+            let inner = this.inner.take();
+            this.frames.enter(|| mem::drop(inner));
+        }
+        poll
+    }
+}
+
+impl<F> Drop for Scope<F> {
+    fn drop(&mut self) {
+        let inner = self.inner.take();
+        self.frames.enter(|| mem::drop(inner));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{current_frames, scope};
+
+    #[test]
+    fn frames_are_scoped_to_blocks() {
+        {
+            trace_frame!("outer {}", 1);
+            {
+                trace_frame!("inner");
+                assert_eq!(current_frames(), ["inner", "outer 1"]);
+            }
+            assert_eq!(current_frames(), ["outer 1"]);
+        }
+        assert!(current_frames().is_empty());
+    }
+
+    #[test]
+    fn frames_stay_with_their_future() {
+        let runtime = ::tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let (tx, rx) = ::tokio::sync::oneshot::channel::<()>();
+            let task = ::tokio::spawn(scope(async move {
+                trace_frame!("waiting");
+                rx.await.unwrap();
+                current_frames()
+            }));
+            ::tokio::task::yield_now().await;
+            assert!(current_frames().is_empty());
+            tx.send(()).unwrap();
+            assert_eq!(task.await.unwrap(), ["waiting"]);
+        });
+    }
+}
@@ -0,0 +1,279 @@
+//! The modules (the executable and shared libraries) mapped into the process.
+
+use std::{
+    cell::OnceCell,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// A file mapped into the address space of the process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Module {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) file_offset: usize,
+    pub(crate) path: PathBuf,
+}
+
+impl Module {
+    /// Parses a line of `/proc/self/maps`, skipping anonymous and pseudo mappings.
+    #[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+    fn parse_maps_line(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(6, ' ');
+        let range = fields.next()?;
+        let _permissions = fields.next()?;
+        let file_offset = fields.next()?;
+        let _device = fields.next()?;
+        let _inode = fields.next()?;
+        let path = fields.next()?.trim_start();
+        if !path.starts_with('/') {
+            return None;
+        }
+
+        let (start, end) = range.split_once('-')?;
+        Some(Module {
+            start: usize::from_str_radix(start, 16).ok()?,
+            end: usize::from_str_radix(end, 16).ok()?,
+            file_offset: usize::from_str_radix(file_offset, 16).ok()?,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+/// The modules of the process, loaded on first use.
+#[derive(Debug, Default)]
+pub(crate) struct Modules {
+    modules: OnceCell<Vec<Module>>,
+}
+
+impl Modules {
+    /// Returns the module containing `addr` and the offset of `addr` within its file.
+    pub(crate) fn find(&self, addr: usize) -> Option<(&Path, usize)> {
+        self.modules
+            .get_or_init(load)
+            .iter()
+            .find(|module| module.start <= addr && addr < module.end)
+            .map(|module| (&*module.path, addr - module.start + module.file_offset))
+    }
+}
+
+/// Returns the modules of the process.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn load() -> Vec<Module> {
+    let modules: Vec<Module> = std::fs::read_to_string("/proc/self/maps")
+        .map(|maps| maps.lines().filter_map(Module::parse_maps_line).collect())
+        .unwrap_or_default();
+    // Sandboxes and containers may not mount `/proc`.
+    #[cfg(target_os = "linux")]
+    let modules = if modules.is_empty() {
+        loaded_segments()
+    } else {
+        modules
+    };
+    modules
+}
+
+/// Returns the modules of the process.
+#[cfg(any(
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "illumos",
+    target_os = "solaris",
+    target_os = "nto"
+))]
+pub(crate) fn load() -> Vec<Module> {
+    loaded_segments()
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "illumos",
+    target_os = "solaris",
+    target_os = "nto"
+)))]
+pub(crate) fn load() -> Vec<Module> {
+    Vec::new()
+}
+
+/// Returns the load bias of the executable, by which address space layout randomization (ASLR)
+/// moved it from its link-time addresses, looked up once per process.
+pub(crate) fn load_bias() -> Option<usize> {
+    static LOAD_BIAS: OnceLock<Option<usize>> = OnceLock::new();
+    *LOAD_BIAS.get_or_init(executable_load_bias)
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "illumos",
+    target_os = "solaris",
+    target_os = "nto"
+))]
+fn executable_load_bias() -> Option<usize> {
+    unsafe extern "C" fn first_module(
+        info: *mut libc::dl_phdr_info,
+        _size: libc::size_t,
+        bias: *mut libc::c_void,
+    ) -> libc::c_int {
+        // SAFETY: As in `loaded_segments()`, the executable is reported first.
+        unsafe { *(bias as *mut Option<usize>) = Some((*info).dlpi_addr as usize) };
+        1
+    }
+
+    let mut bias = None;
+    // SAFETY: `first_module` only runs during the call, while `bias` is borrowed.
+    unsafe {
+        libc::dl_iterate_phdr(
+            Some(first_module),
+            &mut bias as *mut Option<usize> as *mut _,
+        );
+    }
+    bias
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "illumos",
+    target_os = "solaris",
+    target_os = "nto"
+)))]
+fn executable_load_bias() -> Option<usize> {
+    None
+}
+
+/// Returns the loaded segments of the executable and the shared libraries, as reported by the
+/// dynamic linker.
+///
+/// Unlike `/proc/self/maps` this doesn't need a file system, and the BSDs don't mount `/proc`
+/// by default.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "illumos",
+    target_os = "solaris",
+    target_os = "nto"
+))]
+fn loaded_segments() -> Vec<Module> {
+    use std::{ffi::CStr, os::unix::ffi::OsStrExt, slice};
+
+    const PT_LOAD: u32 = 1;
+
+    unsafe extern "C" fn add_module(
+        info: *mut libc::dl_phdr_info,
+        _size: libc::size_t,
+        modules: *mut libc::c_void,
+    ) -> libc::c_int {
+        // SAFETY: The dynamic linker passes valid infos, `modules` is the vector passed below.
+        let (info, modules) = unsafe { (&*info, &mut *(modules as *mut Vec<Module>)) };
+        // The executable has an empty name.
+        let name = if info.dlpi_name.is_null() {
+            &[][..]
+        } else {
+            unsafe { CStr::from_ptr(info.dlpi_name) }.to_bytes()
+        };
+        let path = match name {
+            [] => match std::env::current_exe() {
+                Ok(path) => path,
+                Err(_) => return 0,
+            },
+            _ => PathBuf::from(std::ffi::OsStr::from_bytes(name)),
+        };
+        let headers = if info.dlpi_phdr.is_null() {
+            &[][..]
+        } else {
+            unsafe { slice::from_raw_parts(info.dlpi_phdr, info.dlpi_phnum as usize) }
+        };
+        for header in headers.iter().filter(|header| header.p_type == PT_LOAD) {
+            let start = (info.dlpi_addr as usize).wrapping_add(header.p_vaddr as usize);
+            modules.push(Module {
+                start,
+                end: start.wrapping_add(header.p_memsz as usize),
+                file_offset: header.p_offset as usize,
+                path: path.clone(),
+            });
+        }
+        0
+    }
+
+    let mut modules = Vec::new();
+    // SAFETY: `add_module` only runs during the call, while `modules` is borrowed.
+    unsafe {
+        libc::dl_iterate_phdr(Some(add_module), &mut modules as *mut Vec<Module> as *mut _);
+    }
+    modules
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::Module, std::path::PathBuf};
+
+    #[test]
+    fn parse_maps() {
+        assert_eq!(
+            Module::parse_maps_line(
+                "7f3c1c600000-7f3c1c628000 r-xp 00028000 fd:01 1835099    /usr/lib/libc.so.6"
+            ),
+            Some(Module {
+                start: 0x7f3c_1c60_0000,
+                end: 0x7f3c_1c62_8000,
+                file_offset: 0x28000,
+                path: PathBuf::from("/usr/lib/libc.so.6"),
+            })
+        );
+        assert_eq!(
+            Module::parse_maps_line("7ffd8a1f2000-7ffd8a213000 rw-p 00000000 00:00 0    [stack]"),
+            None
+        );
+        assert_eq!(
+            Module::parse_maps_line("7f3c1c800000-7f3c1c801000 rw-p 00000000 00:00 0"),
+            None
+        );
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "illumos",
+        target_os = "solaris",
+        target_os = "nto"
+    ))]
+    #[test]
+    fn finds_the_segments_of_the_executable() {
+        let addr = parse_maps as fn() as usize;
+        let modules = super::loaded_segments();
+        let module = modules
+            .iter()
+            .find(|module| module.start <= addr && addr < module.end)
+            .unwrap_or_else(|| panic!("{:#x} not in {:#x?}", addr, modules));
+        assert_eq!(module.path, std::env::current_exe().unwrap());
+        // The offsets within the file agree with those of `/proc/self/maps`.
+        #[cfg(target_os = "linux")]
+        assert_eq!(
+            super::Modules::default().find(addr),
+            Some((&*module.path, addr - module.start + module.file_offset))
+        );
+        // The executable's segments are at their link-time addresses plus the bias.
+        let bias = super::load_bias().unwrap();
+        assert!(bias <= module.start, "{:#x} {:#x}", bias, module.start);
+    }
+}
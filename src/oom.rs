@@ -0,0 +1,157 @@
+//! Reporting failed allocations with a backtrace before the process aborts.
+//!
+//! When an allocation fails the standard library prints the size of the allocation and aborts,
+//! without a panic and without a backtrace. Formatting a backtrace at that point must not
+//! allocate, so [`report()`] uses a [`BacktraceBuffer`] set up at startup with [`set_buffer()`].
+//!
+//! On stable Rust the global allocator is wrapped in [`ReportOnFailure`], which reports before
+//! returning the failure to the caller:
+//!
+//! ```
+//! use {
+//!     backtrace_string::{oom::{self, ReportOnFailure}, BacktraceBuffer},
+//!     std::alloc::System,
+//! };
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: ReportOnFailure<System> = ReportOnFailure(System);
+//!
+//! // At the start of `main`:
+//! oom::set_buffer(BacktraceBuffer::new(64, 16 * 1024));
+//! ```
+//!
+//! As the allocator can't tell whether the caller handles the failure, failures of fallible
+//! allocations like `Vec::try_reserve` are reported as well. On nightly Rust [`report()`] can be
+//! installed as the alloc error hook instead, which is only called for failures that abort:
+//!
+//! ```ignore
+//! #![feature(alloc_error_hook)]
+//!
+//! std::alloc::set_alloc_error_hook(backtrace_string::oom::report);
+//! ```
+//!
+//! The hook replaces the message of the standard library, the report of the wrapper is printed
+//! before it.
+//!
+//![`report()`]: fn.report.html
+//![`BacktraceBuffer`]: ../buffer/struct.BacktraceBuffer.html
+//![`set_buffer()`]: fn.set_buffer.html
+//![`ReportOnFailure`]: struct.ReportOnFailure.html
+
+use {
+    crate::BacktraceBuffer,
+    std::{
+        alloc::{GlobalAlloc, Layout},
+        io::{self, Write},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Mutex,
+        },
+    },
+};
+
+static BUFFER: Mutex<Option<BacktraceBuffer>> = Mutex::new(None);
+
+/// Set while reporting, as the report may fail to allocate itself.
+static REPORTING: AtomicBool = AtomicBool::new(false);
+
+/// Sets the buffer failed allocations are reported with, replacing the previous one.
+///
+/// Without a buffer only the size of the failed allocation is reported.
+pub fn set_buffer(buffer: BacktraceBuffer) {
+    *BUFFER.lock().unwrap_or_else(|err| err.into_inner()) = Some(buffer);
+}
+
+/// Reports the failed allocation of `layout` with a backtrace to stderr, without allocating.
+///
+/// Failures while a report is written, e.g. on other threads, aren't reported.
+pub fn report(layout: Layout) {
+    if REPORTING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let _ = write_report(&mut io::stderr().lock(), layout);
+    REPORTING.store(false, Ordering::SeqCst);
+}
+
+fn write_report(out: &mut dyn Write, layout: Layout) -> io::Result<()> {
+    write!(out, "memory allocation of {} bytes failed", layout.size())?;
+    // The buffer is locked if the failure happened while it was set.
+    match BUFFER.try_lock().as_deref_mut() {
+        Ok(Some(buffer)) => {
+            writeln!(out, ", backtrace:")?;
+            out.write_all(buffer.capture_and_format().as_bytes())
+        }
+        _ => writeln!(out),
+    }
+}
+
+/// A global allocator reporting failed allocations of the allocator it wraps, see the
+/// [module docs].
+///
+///[module docs]: index.html
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReportOnFailure<A>(pub A);
+
+// SAFETY: All calls are forwarded to the wrapped allocator unchanged.
+unsafe impl<A: GlobalAlloc> GlobalAlloc for ReportOnFailure<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.0.alloc(layout);
+        if ptr.is_null() {
+            report(layout);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.0.alloc_zeroed(layout);
+        if ptr.is_null() {
+            report(layout);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.0.realloc(ptr, layout, new_size);
+        if new_ptr.is_null() {
+            // SAFETY: The caller guarantees that the new size is a valid size for the alignment.
+            report(Layout::from_size_align_unchecked(new_size, layout.align()));
+        }
+        new_ptr
+    }
+}
+
+#[cfg(all(test, feature = "resolve", feature = "demangle"))]
+mod tests {
+    use {
+        super::{set_buffer, write_report},
+        crate::{BacktraceBuffer, FormatOptions},
+        std::alloc::Layout,
+    };
+
+    #[inline(never)]
+    fn report_failure() -> String {
+        let mut out = Vec::new();
+        write_report(&mut out, Layout::new::<[u8; 64]>()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn reports_with_backtrace() {
+        set_buffer(BacktraceBuffer::with_options(
+            64,
+            16 * 1024,
+            FormatOptions::new(),
+        ));
+        let out = report_failure();
+        assert!(
+            out.starts_with("memory allocation of 64 bytes failed, backtrace:\n"),
+            "{}",
+            out
+        );
+        assert!(out.contains("oom::tests::report_failure"), "{}", out);
+    }
+}
@@ -0,0 +1,1254 @@
+//! Options controlling how backtraces are formatted.
+
+use {
+    crate::{
+        color::{ColorChoice, Painter, Theme},
+        frame::Frame,
+        frame_contains_symbol, frame_only_contains_symbols,
+        layout::{Layout, Style, NUMBER_WIDTH},
+        preset::{FilterPreset, Preset, UnknownPreset},
+        vocabulary::Vocabulary,
+    },
+    std::{
+        borrow::Cow,
+        cell::RefCell,
+        env,
+        error::Error,
+        fmt,
+        path::{Path, PathBuf},
+        sync::{Arc, RwLock},
+    },
+};
+
+/// The environment variable read by [`FormatOptions::from_env()`].
+///
+///[`FormatOptions::from_env()`]: struct.FormatOptions.html#method.from_env
+pub const FORMAT_ENV_VAR: &str = "BACKTRACE_STRING_FORMAT";
+
+static GLOBAL: RwLock<Option<FormatOptions>> = RwLock::new(None);
+
+thread_local! {
+    static THREAD: RefCell<Option<FormatOptions>> = const { RefCell::new(None) };
+}
+
+/// Options for [`format_backtrace_with()`].
+///
+/// [`format_backtrace()`] and everything else formatting backtraces on the user's behalf uses
+/// the [`current()`](#method.current) options, which are the default options unless configured
+/// otherwise.
+///
+/// Options can also be parsed from a string, see [`parse()`](#method.parse).
+///
+/// ```
+/// use backtrace_string::{FormatOptions, Preset};
+///
+/// let options = FormatOptions::new().filter_preset(Preset::Tokio);
+/// ```
+///
+///[`format_backtrace_with()`]: fn.format_backtrace_with.html
+///[`format_backtrace()`]: fn.format_backtrace.html
+#[derive(Debug, Clone, Default)]
+pub struct FormatOptions {
+    pub(crate) hidden_prefixes: Vec<Cow<'static, str>>,
+    pub(crate) module_symbolication: Vec<(Cow<'static, str>, Symbolication)>,
+    pub(crate) style: Style,
+    pub(crate) unknown_symbols: UnknownSymbols,
+    pub(crate) demangling: Demangling,
+    pub(crate) numbering: Numbering,
+    pub(crate) index_base: usize,
+    pub(crate) index_width: Option<usize>,
+    pub(crate) locations: Locations,
+    pub(crate) color: ColorChoice,
+    pub(crate) theme: Theme,
+    pub(crate) max_frames: Option<usize>,
+    pub(crate) capture_depth: Option<usize>,
+    pub(crate) redact_paths: bool,
+    pub(crate) source_snippets: bool,
+    pub(crate) call_sites: bool,
+    pub(crate) fingerprint: bool,
+    pub(crate) no_resolve: bool,
+    pub(crate) detect_stack_overflow: bool,
+    pub(crate) relaxed_trimming: bool,
+    pub(crate) marker_window: Option<usize>,
+    pub(crate) nested_panics: bool,
+    pub(crate) aslr_slide: bool,
+    pub(crate) end_at_main: bool,
+    pub(crate) core_dump_hint: bool,
+    pub(crate) stack_usage: bool,
+    pub(crate) report_once: bool,
+    pub(crate) only_my_code: bool,
+    pub(crate) atos_fallback: bool,
+    #[cfg(feature = "addr2line-backend")]
+    pub(crate) addr2line_backend: bool,
+    #[cfg(feature = "symbol-table")]
+    pub(crate) symbol_table_fallback: bool,
+    pub(crate) deterministic: bool,
+    pub(crate) box_title: Option<Cow<'static, str>>,
+    pub(crate) annotations: Vec<Annotation>,
+    pub(crate) panic_location: Option<(PathBuf, u32)>,
+    pub(crate) vocabulary: Vocabulary,
+}
+
+/// A note shown below the frames selected by `matcher`.
+#[derive(Clone)]
+pub(crate) struct Annotation {
+    matcher: FrameMatcher,
+    note: Cow<'static, str>,
+}
+
+#[derive(Clone)]
+enum FrameMatcher {
+    Index(usize),
+    Symbol(Arc<dyn Fn(&str) -> bool + Send + Sync>),
+}
+
+impl fmt::Debug for Annotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut f = f.debug_struct("Annotation");
+        match self.matcher {
+            FrameMatcher::Index(index) => f.field("index", &index),
+            FrameMatcher::Symbol(_) => f.field("symbol", &format_args!("..")),
+        };
+        f.field("note", &self.note).finish()
+    }
+}
+
+/// How frames and symbols without symbol name are shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownSymbols {
+    /// Shown with the [`unknown`] placeholder, this is the default.
+    ///
+    ///[`unknown`]: struct.Vocabulary.html#structfield.unknown
+    #[default]
+    Show,
+    /// Frames without any symbol name are left out and counted in a notice at the end.
+    Hide,
+    /// The name is replaced by the module's file name and the offset of the instruction pointer
+    /// in the file, e.g. `my_app+0x1a2b3`, which can be resolved offline. Falls back to the
+    /// placeholder where the modules can't be determined (currently everywhere but Linux,
+    /// Android, the BSDs, illumos and QNX).
+    ModuleOffset,
+}
+
+/// What is shown as the location of a symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locations {
+    /// The file and line, this is the default.
+    #[default]
+    Shown,
+    /// Nothing, the output only lists the symbols. Meant for optimized builds, where the line
+    /// info is often misleading.
+    Hidden,
+    /// The crate the symbol belongs to, taken from the symbol name.
+    Crate,
+}
+
+/// What the numbers in the output count.
+///
+/// A frame has several symbols if functions got inlined into it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Numbering {
+    /// Each frame gets a number, its inlined symbols are listed below it without one. This is
+    /// the default.
+    #[default]
+    Frames,
+    /// Each symbol gets a number of its own, like in the backtraces printed by the standard
+    /// library, so the counts are comparable with other tools.
+    Symbols,
+}
+
+/// How symbol names are demangled.
+///
+/// Legacy mangled symbols end with a hash (`my_app::main::h0123456789abcdef`), v0 mangled ones
+/// carry crate disambiguators (`my_app[1a2b3c4d]::main`). Both are kept or dropped together.
+/// Without the `demangle` feature names are always shown as found in the binary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Demangling {
+    /// Demangled with the hashes and disambiguators, this is the default.
+    #[default]
+    WithHashes,
+    /// Demangled without the hashes and disambiguators, like the alternate format (`{:#}`) of
+    /// `rustc_demangle`.
+    WithoutHashes,
+    /// Not demangled, the names are shown as found in the binary.
+    Off,
+}
+
+/// How much of the frames of a module is resolved, see
+/// [`FormatOptions::module_symbolication()`].
+///
+///[`FormatOptions::module_symbolication()`]: struct.FormatOptions.html#method.module_symbolication
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Symbolication {
+    /// Symbol names and locations, this is the default.
+    #[default]
+    Full,
+    /// Only the symbol names, the locations are left out.
+    Names,
+    /// Nothing, the frames are shown like frames without symbols. This skips resolving them.
+    Addresses,
+}
+
+impl FormatOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the options to use on the current thread.
+    ///
+    /// These are the options set with [`set_thread()`] if any, otherwise the ones set with
+    /// [`set_global()`], otherwise the default options. On musl targets the default options are
+    /// the ones of [`musl()`].
+    ///
+    ///[`set_thread()`]: #method.set_thread
+    ///[`set_global()`]: #method.set_global
+    pub fn current() -> Self {
+        THREAD
+            .with(|options| options.borrow().clone())
+            .or_else(|| GLOBAL.read().unwrap_or_else(|err| err.into_inner()).clone())
+            .unwrap_or_else(Self::target_default)
+    }
+
+    /// Returns the default options for the target the crate is built for.
+    fn target_default() -> Self {
+        if cfg!(target_env = "musl") {
+            Self::musl()
+        } else {
+            Self::default()
+        }
+    }
+
+    /// Sets the options used by all threads without thread specific options.
+    pub fn set_global(options: FormatOptions) {
+        *GLOBAL.write().unwrap_or_else(|err| err.into_inner()) = Some(options);
+    }
+
+    /// Sets (or with `None` removes) options overriding the global ones on the current thread.
+    ///
+    /// Returns the previous thread specific options.
+    pub fn set_thread(options: Option<FormatOptions>) -> Option<FormatOptions> {
+        THREAD.with(|current| current.replace(options))
+    }
+
+    /// Runs `func` with `self` overriding the global options on the current thread.
+    pub fn with_thread_override<R>(self, func: impl FnOnce() -> R) -> R {
+        struct Restore(Option<FormatOptions>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                FormatOptions::set_thread(self.0.take());
+            }
+        }
+
+        let _restore = Restore(Self::set_thread(Some(self)));
+        func()
+    }
+
+    /// Options for developers reading backtraces in their terminal.
+    ///
+    /// All frames are shown in the full style, colored if stderr is a terminal, together with
+    /// the source lines they point to.
+    pub fn dev() -> Self {
+        Self::new()
+            .style(Style::Full)
+            .color(ColorChoice::Auto)
+            .source_snippets(true)
+            .detect_stack_overflow(true)
+    }
+
+    /// Options for backtraces ending up in the logs of deployed services.
+    ///
+    /// The compact style with at most 40 frames keeps log lines short, paths are redacted so
+    /// they don't leak the build machine's directories and a fingerprint footer allows grouping
+    /// identical backtraces.
+    pub fn production() -> Self {
+        Self::new()
+            .style(Style::Compact)
+            .redact_paths(true)
+            .max_frames(Some(40))
+            .fingerprint(true)
+            .detect_stack_overflow(true)
+    }
+
+    /// Options for test runs on CI.
+    ///
+    /// All frames are shown without color and without the test harness frames, together with a
+    /// fingerprint footer to recognize recurring failures.
+    pub fn ci() -> Self {
+        Self::new()
+            .style(Style::Full)
+            .color(ColorChoice::Never)
+            .filter_preset(Preset::Libtest)
+            .fingerprint(true)
+            .detect_stack_overflow(true)
+    }
+
+    /// Options for minimal binaries, e.g. built with `panic = "abort"` and without debug info.
+    ///
+    /// Symbols are never resolved, frames show the module and offset of their instruction
+    /// pointer (see [`UnknownSymbols::ModuleOffset`]) and their address, which can be resolved
+    /// offline with the binary's debug info. The layout stays the same as with the other
+    /// options, so tools parsing the output work for both.
+    ///
+    ///[`UnknownSymbols::ModuleOffset`]: enum.UnknownSymbols.html#variant.ModuleOffset
+    pub fn minimal() -> Self {
+        Self::new()
+            .resolve_symbols(false)
+            .unknown_symbols(UnknownSymbols::ModuleOffset)
+    }
+
+    /// Options for musl targets (e.g. Alpine Linux), the default options of [`current()`] there.
+    ///
+    /// The unwinder of musl often produces partial stacks without the markers used to trim the
+    /// backtrace, so [`relaxed_trimming()`] is enabled. Frames without symbols show their module
+    /// and offset (see [`UnknownSymbols::ModuleOffset`]) to allow resolving them offline.
+    ///
+    ///[`current()`]: #method.current
+    ///[`relaxed_trimming()`]: #method.relaxed_trimming
+    ///[`UnknownSymbols::ModuleOffset`]: enum.UnknownSymbols.html#variant.ModuleOffset
+    pub fn musl() -> Self {
+        Self::new()
+            .relaxed_trimming(true)
+            .unknown_symbols(UnknownSymbols::ModuleOffset)
+    }
+
+    /// Parses options from a comma separated list of `key=value` pairs.
+    ///
+    /// The grammar is:
+    ///
+    /// ```text
+    /// options = [ option *( "," option ) ]
+    /// option  = key "=" value
+    /// ```
+    ///
+    /// Whitespace around keys and values is ignored. The keys are:
+    ///
+    /// | key                | value                                        | see                    |
+    /// |--------------------|----------------------------------------------|------------------------|
+    /// | `style`            | `full` or `compact`                          | [`style()`]            |
+    /// | `unknown`          | `show`, `hide` or `module_offset`            | [`unknown_symbols()`]  |
+    /// | `locations`        | `shown`, `hidden` or `crate`                 | [`locations()`]        |
+    /// | `numbering`        | `frames` or `symbols`                        | [`numbering()`]        |
+    /// | `index_base`       | a number                                     | [`index_base()`]       |
+    /// | `index_width`      | a number                                     | [`index_width()`]      |
+    /// | `demangle`         | `hashes`, `no_hashes` or `off`               | [`demangling()`]       |
+    /// | `color`            | `auto`, `always` or `never`                  | [`color()`]            |
+    /// | `theme`            | `dark`, `light` or `dimmed`                  | [`theme()`]            |
+    /// | `max_frames`       | a number, or `none` for no limit             | [`max_frames()`]       |
+    /// | `capture_depth`    | a number, or `none` for no limit             | [`capture_depth()`]    |
+    /// | `filters`          | preset names separated by `+`                | [`filter_presets()`]   |
+    /// | `redact_paths`     | `true` or `false`                            | [`redact_paths()`]     |
+    /// | `source`           | `true` or `false`                            | [`source_snippets()`]  |
+    /// | `call_sites`       | `true` or `false`                            | [`call_sites()`]       |
+    /// | `fingerprint`      | `true` or `false`                            | [`fingerprint()`]      |
+    /// | `resolve`          | `true` or `false`                            | [`resolve_symbols()`]  |
+    /// | `deterministic`    | `true` or `false`                            | [`deterministic()`]    |
+    /// | `relaxed_trimming` | `true` or `false`                            | [`relaxed_trimming()`] |
+    /// | `marker_window`    | a number, or `default`                       | [`marker_window()`]    |
+    /// | `nested_panics`    | `true` or `false`                            | [`nested_panics()`]    |
+    /// | `aslr_slide`       | `true` or `false`                            | [`aslr_slide()`]       |
+    /// | `atos`             | `true` or `false`                            | [`atos_fallback()`]    |
+    /// | `end_at_main`      | `true` or `false`                            | [`end_at_main()`]      |
+    /// | `core_dump_hint`   | `true` or `false`                            | [`core_dump_hint()`]   |
+    /// | `stack_usage`      | `true` or `false`                            | [`stack_usage()`]      |
+    /// | `report_once`      | `true` or `false`                            | [`report_once()`]      |
+    /// | `only_my_code`     | `true` or `false`                            | [`only_my_code()`]     |
+    /// | `profile`          | `dev`, `production`, `ci`, `minimal`, `musl` | [`dev()`], ...         |
+    ///
+    /// A `profile` replaces all options before it, so it should come first.
+    ///
+    /// Unknown keys, invalid values and unknown preset names are reported as errors.
+    ///
+    /// ```
+    /// use backtrace_string::FormatOptions;
+    ///
+    /// let options =
+    ///     FormatOptions::parse("style=compact,color=auto,max_frames=40,filters=tokio+rayon")
+    ///         .unwrap();
+    /// ```
+    ///
+    ///[`style()`]: #method.style
+    ///[`unknown_symbols()`]: #method.unknown_symbols
+    ///[`locations()`]: #method.locations
+    ///[`numbering()`]: #method.numbering
+    ///[`index_base()`]: #method.index_base
+    ///[`index_width()`]: #method.index_width
+    ///[`demangling()`]: #method.demangling
+    ///[`color()`]: #method.color
+    ///[`theme()`]: #method.theme
+    ///[`max_frames()`]: #method.max_frames
+    ///[`capture_depth()`]: #method.capture_depth
+    ///[`filter_presets()`]: #method.filter_presets
+    ///[`redact_paths()`]: #method.redact_paths
+    ///[`source_snippets()`]: #method.source_snippets
+    ///[`call_sites()`]: #method.call_sites
+    ///[`fingerprint()`]: #method.fingerprint
+    ///[`resolve_symbols()`]: #method.resolve_symbols
+    ///[`deterministic()`]: #method.deterministic
+    ///[`relaxed_trimming()`]: #method.relaxed_trimming
+    ///[`marker_window()`]: #method.marker_window
+    ///[`nested_panics()`]: #method.nested_panics
+    ///[`aslr_slide()`]: #method.aslr_slide
+    ///[`atos_fallback()`]: #method.atos_fallback
+    ///[`end_at_main()`]: #method.end_at_main
+    ///[`core_dump_hint()`]: #method.core_dump_hint
+    ///[`stack_usage()`]: #method.stack_usage
+    ///[`report_once()`]: #method.report_once
+    ///[`only_my_code()`]: #method.only_my_code
+    ///[`dev()`]: #method.dev
+    pub fn parse(input: &str) -> Result<Self, ParseOptionsError> {
+        let mut options = FormatOptions::new();
+        for option in input
+            .split(',')
+            .map(str::trim)
+            .filter(|option| !option.is_empty())
+        {
+            let (key, value) = match option.find('=') {
+                Some(pos) => (option[..pos].trim(), option[pos + 1..].trim()),
+                None => return Err(ParseOptionsError::MissingValue(option.to_owned())),
+            };
+            let invalid = || ParseOptionsError::InvalidValue {
+                key: key.to_owned(),
+                value: value.to_owned(),
+            };
+            let flag = || match value {
+                "true" => Ok(true),
+                "false" => Ok(false),
+                _ => Err(invalid()),
+            };
+
+            options = match key {
+                "style" => options.style(match value {
+                    "full" => Style::Full,
+                    "compact" => Style::Compact,
+                    _ => return Err(invalid()),
+                }),
+                "unknown" => options.unknown_symbols(match value {
+                    "show" => UnknownSymbols::Show,
+                    "hide" => UnknownSymbols::Hide,
+                    "module_offset" => UnknownSymbols::ModuleOffset,
+                    _ => return Err(invalid()),
+                }),
+                "locations" => options.locations(match value {
+                    "shown" => Locations::Shown,
+                    "hidden" => Locations::Hidden,
+                    "crate" => Locations::Crate,
+                    _ => return Err(invalid()),
+                }),
+                "numbering" => options.numbering(match value {
+                    "frames" => Numbering::Frames,
+                    "symbols" => Numbering::Symbols,
+                    _ => return Err(invalid()),
+                }),
+                "index_base" => options.index_base(value.parse().map_err(|_| invalid())?),
+                "index_width" => options.index_width(value.parse().map_err(|_| invalid())?),
+                "demangle" => options.demangling(match value {
+                    "hashes" => Demangling::WithHashes,
+                    "no_hashes" => Demangling::WithoutHashes,
+                    "off" => Demangling::Off,
+                    _ => return Err(invalid()),
+                }),
+                "color" => options.color(value.parse().map_err(|()| invalid())?),
+                "theme" => options.theme(value.parse().map_err(|()| invalid())?),
+                "max_frames" => options.max_frames(match value {
+                    "none" => None,
+                    _ => Some(value.parse().map_err(|_| invalid())?),
+                }),
+                "capture_depth" => options.capture_depth(match value {
+                    "none" => None,
+                    _ => Some(value.parse().map_err(|_| invalid())?),
+                }),
+                "filters" => options
+                    .filter_presets(value)
+                    .map_err(ParseOptionsError::UnknownPreset)?,
+                "redact_paths" => options.redact_paths(flag()?),
+                "source" => options.source_snippets(flag()?),
+                "call_sites" => options.call_sites(flag()?),
+                "fingerprint" => options.fingerprint(flag()?),
+                "resolve" => options.resolve_symbols(flag()?),
+                "deterministic" => options.deterministic(flag()?),
+                "relaxed_trimming" => options.relaxed_trimming(flag()?),
+                "marker_window" => options.marker_window(match value {
+                    "default" => None,
+                    _ => Some(value.parse().map_err(|_| invalid())?),
+                }),
+                "nested_panics" => options.nested_panics(flag()?),
+                "aslr_slide" => options.aslr_slide(flag()?),
+                "atos" => options.atos_fallback(flag()?),
+                "end_at_main" => options.end_at_main(flag()?),
+                "core_dump_hint" => options.core_dump_hint(flag()?),
+                "stack_usage" => options.stack_usage(flag()?),
+                "report_once" => options.report_once(flag()?),
+                "only_my_code" => options.only_my_code(flag()?),
+                "profile" => match value {
+                    "dev" => Self::dev(),
+                    "production" => Self::production(),
+                    "ci" => Self::ci(),
+                    "minimal" => Self::minimal(),
+                    "musl" => Self::musl(),
+                    _ => return Err(invalid()),
+                },
+                _ => return Err(ParseOptionsError::UnknownKey(key.to_owned())),
+            };
+        }
+        Ok(options)
+    }
+
+    /// Parses the options from the [`BACKTRACE_STRING_FORMAT`] environment variable.
+    ///
+    /// Returns the default options if the variable isn't set.
+    ///
+    ///[`BACKTRACE_STRING_FORMAT`]: constant.FORMAT_ENV_VAR.html
+    pub fn from_env() -> Result<Self, ParseOptionsError> {
+        match env::var(FORMAT_ENV_VAR) {
+            Ok(value) => Self::parse(&value),
+            Err(env::VarError::NotPresent) => Ok(Self::default()),
+            Err(env::VarError::NotUnicode(_)) => Err(ParseOptionsError::InvalidValue {
+                key: FORMAT_ENV_VAR.to_owned(),
+                value: String::from("<non-unicode>"),
+            }),
+        }
+    }
+
+    /// Sets the layout of the frames.
+    pub fn style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Sets how frames and symbols without symbol name are shown.
+    ///
+    /// Release builds without debug info have many of those, which drown the few resolved ones.
+    pub fn unknown_symbols(mut self, unknown: UnknownSymbols) -> Self {
+        self.unknown_symbols = unknown;
+        self
+    }
+
+    /// Sets what is shown as the location of a symbol.
+    pub fn locations(mut self, locations: Locations) -> Self {
+        self.locations = locations;
+        self
+    }
+
+    /// Sets what the numbers in the output count.
+    pub fn numbering(mut self, numbering: Numbering) -> Self {
+        self.numbering = numbering;
+        self
+    }
+
+    /// Sets the number of the first frame, 0 by default. With 1 the frames are numbered like by
+    /// gdb.
+    pub fn index_base(mut self, base: usize) -> Self {
+        self.index_base = base;
+        self
+    }
+
+    /// Sets the width the frame numbers are right-aligned to, 4 by default. The lines continuing
+    /// a frame are indented accordingly, so with a width fitting the largest number the columns
+    /// stay aligned in long backtraces.
+    pub fn index_width(mut self, width: usize) -> Self {
+        self.index_width = Some(width);
+        self
+    }
+
+    /// Sets how symbol names are demangled.
+    pub fn demangling(mut self, demangling: Demangling) -> Self {
+        self.demangling = demangling;
+        self
+    }
+
+    /// Sets whether the output is colored.
+    pub fn color(mut self, color: ColorChoice) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Sets the colors used if the output is colored, the default is [`Theme::dark()`].
+    ///
+    ///[`Theme::dark()`]: color/struct.Theme.html#method.dark
+    pub fn theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Limits the number of formatted frames, the remaining frames are summarized in one line.
+    pub fn max_frames(mut self, max_frames: Option<usize>) -> Self {
+        self.max_frames = max_frames;
+        self
+    }
+
+    /// Limits the number of frames captured at all by [`create_backtrace()`] and for panics.
+    ///
+    /// The unwinder stops after that many frames, which bounds the cost of capturing very deep
+    /// stacks. The limit applies to the raw frames, including those of the panic machinery which
+    /// are trimmed before formatting, so it should be well above [`max_frames()`].
+    ///
+    ///[`create_backtrace()`]: fn.create_backtrace.html
+    ///[`max_frames()`]: #method.max_frames
+    pub fn capture_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.capture_depth = max_depth;
+        self
+    }
+
+    /// Hides the frames consisting only of symbols from the given preset.
+    ///
+    /// If the application [registered](preset/struct.FilterPreset.html#method.register) a
+    /// preset with the same name, that one is used instead.
+    pub fn filter_preset(self, preset: Preset) -> Self {
+        let preset = FilterPreset::by_name(preset.name()).unwrap_or_else(|_| preset.into());
+        self.add_filter_preset(preset)
+    }
+
+    /// Hides the frames consisting only of symbols from the given preset.
+    pub fn add_filter_preset(mut self, preset: FilterPreset) -> Self {
+        self.hidden_prefixes
+            .extend(preset.prefixes().iter().cloned());
+        self
+    }
+
+    /// Hides the frames of all presets in the `+` separated list of preset names.
+    ///
+    /// The names are looked up with [`FilterPreset::by_name()`], e.g. `"tokio+rayon"`.
+    ///
+    ///[`FilterPreset::by_name()`]: preset/struct.FilterPreset.html#method.by_name
+    pub fn filter_presets(mut self, names: &str) -> Result<Self, UnknownPreset> {
+        for name in names
+            .split('+')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            self = self.add_filter_preset(FilterPreset::by_name(name)?);
+        }
+        Ok(self)
+    }
+
+    /// Hides the frames consisting only of symbols starting with `prefix`.
+    ///
+    /// A prefix ending with an identifier only matches the whole identifier, e.g. `my_app::run`
+    /// hides `my_app::run` and `my_app::run::{{closure}}` but not `my_app::run_all`. Crate
+    /// disambiguators (like the `[1a2b3c]` in `std[1a2b3c]::panicking`) and leading `<` are
+    /// ignored when matching.
+    pub fn hide_symbols_starting_with(mut self, prefix: impl Into<Cow<'static, str>>) -> Self {
+        self.hidden_prefixes.push(prefix.into());
+        self
+    }
+
+    /// Sets how much of the frames in the modules matching `pattern` is resolved.
+    ///
+    /// The pattern matches the path of the module (the executable or a shared library) if it
+    /// contains a `/`, its file name otherwise. A `*` in it matches any run of characters:
+    ///
+    /// ```
+    /// use backtrace_string::{FormatOptions, Symbolication};
+    ///
+    /// let options = FormatOptions::new()
+    ///     .module_symbolication("libtensorflow*.so*", Symbolication::Addresses)
+    ///     .module_symbolication("/usr/lib/*", Symbolication::Names);
+    /// ```
+    ///
+    /// The first matching pattern applies, frames of other modules are resolved fully. Modules
+    /// are found through `/proc/self/maps` or the dynamic linker, so on other platforms than
+    /// Linux, Android, the BSDs, illumos and QNX all frames are resolved fully. This applies to
+    /// the formatting functions and the resolver of the `backtrace` crate, not to
+    /// [`Backtrace::resolve()`].
+    ///
+    ///[`Backtrace::resolve()`]: https://docs.rs/backtrace/0.3/backtrace/struct.Backtrace.html#method.resolve
+    pub fn module_symbolication(
+        mut self,
+        pattern: impl Into<Cow<'static, str>>,
+        symbolication: Symbolication,
+    ) -> Self {
+        self.module_symbolication
+            .push((pattern.into(), symbolication));
+        self
+    }
+
+    /// Shortens absolute paths to the directory containing `src` and below.
+    ///
+    /// E.g. `/home/alice/my_app/src/main.rs` becomes `my_app/src/main.rs`, so that backtraces
+    /// don't leak the directory layout of the build machine. Paths without `src` component are
+    /// reduced to their file name.
+    pub fn redact_paths(mut self, redact: bool) -> Self {
+        self.redact_paths = redact;
+        self
+    }
+
+    /// Shows the source line below the location of each symbol, if the file can be read.
+    pub fn source_snippets(mut self, show: bool) -> Self {
+        self.source_snippets = show;
+        self
+    }
+
+    /// Shows the call site of inlined functions along with their location, like
+    /// `inlined from parser.rs:88 at lexer.rs:12`.
+    ///
+    /// A frame of inlined functions lists the innermost function first. The location of an
+    /// inlined function is the line in its own body, while the line it was called from is the
+    /// location of the next symbol of the frame, its caller. This puts both on the line of the
+    /// inlined function. It has no effect unless locations are [shown].
+    ///
+    ///[shown]: enum.Locations.html#variant.Shown
+    pub fn call_sites(mut self, show: bool) -> Self {
+        self.call_sites = show;
+        self
+    }
+
+    /// Appends a line with the [`Fingerprint`] of the backtrace.
+    ///
+    ///[`Fingerprint`]: struct.Fingerprint.html
+    pub fn fingerprint(mut self, show: bool) -> Self {
+        self.fingerprint = show;
+        self
+    }
+
+    /// Sets whether symbols are resolved, which is the default.
+    ///
+    /// Without resolving, only the addresses of the frames are known. This avoids the cost of
+    /// reading the debug info, but the frames of the panic machinery can't be trimmed either.
+    /// Backtraces which are already resolved keep their symbols.
+    pub fn resolve_symbols(mut self, resolve: bool) -> Self {
+        self.no_resolve = !resolve;
+        self
+    }
+
+    /// Collapses deep recursion and marks the backtrace as a likely stack overflow.
+    ///
+    /// If a cycle of up to 16 frames repeats at least 32 times, a header like
+    /// `likely stack overflow: cycle of 3 frames repeated ~480 times` is prepended and only the
+    /// first repetition of the cycle is shown. The frames keep their numbers, so the numbers
+    /// after the cycle tell the depth of the recursion.
+    pub fn detect_stack_overflow(mut self, detect: bool) -> Self {
+        self.detect_stack_overflow = detect;
+        self
+    }
+
+    /// Trims the backtrace even if the markers of the panic machinery and the runtime startup are
+    /// missing.
+    ///
+    /// The leading frames of the `backtrace` crate, the startup code of musl and the trailing
+    /// frames without symbols, which partial stacks end with, are left out then.
+    pub fn relaxed_trimming(mut self, relaxed: bool) -> Self {
+        self.relaxed_trimming = relaxed;
+        self
+    }
+
+    /// Sets how many of the most recent frames are searched for the markers of the panic
+    /// machinery, `None` for the default of 10, or 20 with [`relaxed_trimming()`].
+    ///
+    /// The frames down to the deepest marker in the window are trimmed. Hooks which capture
+    /// through many frames of their own need a larger window, while a panic in a panic hook puts
+    /// the markers of the outer panic within reach of a large one, see [`nested_panics()`].
+    ///
+    ///[`relaxed_trimming()`]: #method.relaxed_trimming
+    ///[`nested_panics()`]: #method.nested_panics
+    pub fn marker_window(mut self, frames: Option<usize>) -> Self {
+        self.marker_window = frames;
+        self
+    }
+
+    /// Shows both panics of a panic raised while handling another one, e.g. in a panic hook.
+    ///
+    /// Only the frames above the first markers of the panic machinery are trimmed then, instead
+    /// of those above the deepest ones in the [marker window]. The frames of the machinery of
+    /// further panics are replaced by a separator line, so the frames above it are those of the
+    /// inner panic and the frames below it those of the outer one.
+    ///
+    ///[marker window]: #method.marker_window
+    pub fn nested_panics(mut self, enable: bool) -> Self {
+        self.nested_panics = enable;
+        self
+    }
+
+    /// Starts the backtrace with the ASLR slide of the executable, like
+    /// `ASLR slide of the executable: 0x55d1c0a00000`.
+    ///
+    /// Address space layout randomization loads the executable at a random address, so the
+    /// addresses of unresolved frames differ between runs. Subtracting the slide gives the
+    /// link-time addresses, which tools like `addr2line` and disassemblers show. The slide is
+    /// looked up once per process, currently on Linux, the BSDs, illumos and QNX. It is left out
+    /// with [`deterministic()`].
+    ///
+    ///[`deterministic()`]: #method.deterministic
+    pub fn aslr_slide(mut self, enable: bool) -> Self {
+        self.aslr_slide = enable;
+        self
+    }
+
+    /// Ends the backtrace at the user's `main`, leaving out the runtime startup below it.
+    ///
+    /// `main` is the deepest function named `main` in the root of a crate, so the function
+    /// generated by `#[tokio::main]` is found below the `async` one. Without such a function the
+    /// backtrace ends above the runtime startup (`std::rt::lang_start` and the C `main`). This
+    /// helps on toolchains which don't emit the `__rust_begin_short_backtrace` marker.
+    pub fn end_at_main(mut self, enable: bool) -> Self {
+        self.end_at_main = enable;
+        self
+    }
+
+    /// Appends a note to panic reports telling where a core dump may have been written, or why
+    /// none was, see [`coredump`].
+    ///
+    /// Useful with `panic = "abort"`, where every panic dumps core if enabled.
+    ///
+    ///[`coredump`]: coredump/index.html
+    pub fn core_dump_hint(mut self, enable: bool) -> Self {
+        self.core_dump_hint = enable;
+        self
+    }
+
+    /// Adds a line below the header of panic reports telling how much of its stack the
+    /// panicking thread used, see [`stack`].
+    ///
+    /// A panic using most of the stack hints at a stack overflow in the making, e.g. from
+    /// unbounded recursion, rather than an ordinary bug.
+    ///
+    ///[`stack`]: stack/index.html
+    pub fn stack_usage(mut self, enable: bool) -> Self {
+        self.stack_usage = enable;
+        self
+    }
+
+    /// Keeps the hook of [`install_panic_hook()`] silent for a panic another reporter already
+    /// reported and marked with [`mark_panic_reported()`].
+    ///
+    /// When both an application and its framework install a reporting hook, this keeps the same
+    /// panic from being reported twice, as long as the other reporter calls
+    /// [`mark_panic_reported()`]. Later panics are reported again.
+    ///
+    ///[`install_panic_hook()`]: fn.install_panic_hook.html
+    ///[`mark_panic_reported()`]: fn.mark_panic_reported.html
+    pub fn report_once(mut self, enable: bool) -> Self {
+        self.report_once = enable;
+        self
+    }
+
+    /// Shows only the frames of the application's own code, replacing each run of dependency
+    /// frames between them by a single line like `… via tokio::runtime (9 frames) …`.
+    ///
+    /// The line names the module the run was entered through, its outermost frame. Which code
+    /// is the application's is decided like for the highlighting, by the crates registered with
+    /// [`register_app_crates!`] or else by the source paths. Backtraces without any of the
+    /// application's frames are shown in full.
+    ///
+    ///[`register_app_crates!`]: macro.register_app_crates.html
+    pub fn only_my_code(mut self, enable: bool) -> Self {
+        self.only_my_code = enable;
+        self
+    }
+
+    /// Resolves the frames the `backtrace` crate couldn't resolve with `atos` on macOS.
+    ///
+    /// Without a dSYM bundle, e.g. where a binary is deployed, most frames stay unresolved
+    /// otherwise. `atos` finds at least the names of the symbol table. This spawns a process per
+    /// module, so it is disabled by default. Does nothing on other platforms.
+    pub fn atos_fallback(mut self, enable: bool) -> Self {
+        self.atos_fallback = enable;
+        self
+    }
+
+    /// Resolves the frames with the `addr2line` crate, reading the DWARF debug info of the
+    /// binaries directly, instead of with the `backtrace` crate. Requires the `addr2line-backend`
+    /// feature.
+    ///
+    /// This is more accurate for the inlined functions of release builds on Linux. The debug info
+    /// of a binary is loaded on first use and kept in memory. Frames without debug info, e.g. of
+    /// system libraries, are still resolved with the `backtrace` crate. Only the formatting
+    /// functions use it, not [`Backtrace::resolve()`].
+    ///
+    ///[`Backtrace::resolve()`]: https://docs.rs/backtrace/0.3/backtrace/struct.Backtrace.html#method.resolve
+    #[cfg(feature = "addr2line-backend")]
+    pub fn addr2line_backend(mut self, enable: bool) -> Self {
+        self.addr2line_backend = enable;
+        self
+    }
+
+    /// Names the frames the `backtrace` crate couldn't resolve with the symbol table of their
+    /// binary, like `atos_fallback()` does on macOS. Requires the `symbol-table` feature.
+    ///
+    /// Builds with `strip = "debuginfo"` keep the symbol table, so the frames get the names of
+    /// their functions, without files and lines. The symbol table of a binary is loaded on first
+    /// use and kept in memory. The binaries are found through `/proc/self/maps` or the dynamic
+    /// linker, so this does nothing on other platforms than Linux, Android, the BSDs, illumos and
+    /// QNX.
+    #[cfg(feature = "symbol-table")]
+    pub fn symbol_table_fallback(mut self, enable: bool) -> Self {
+        self.symbol_table_fallback = enable;
+        self
+    }
+
+    /// Leaves out everything which differs between platforms and compiler versions.
+    ///
+    /// Meant for golden files of snapshot tests, which stay the same on all CI runners for the
+    /// same stack of functions: addresses, module offsets, line numbers, symbol hashes, crate
+    /// disambiguators and color are left out, paths are redacted like with
+    /// [`redact_paths()`] and use `/` as separator.
+    ///
+    ///[`redact_paths()`]: #method.redact_paths
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Draws a box with the given title around the output, see [`decor::draw_box()`].
+    ///
+    /// Meant for end users of CLI tools rather than for logs:
+    ///
+    /// ```
+    /// use backtrace_string::FormatOptions;
+    ///
+    /// let options = FormatOptions::new().boxed(format!(
+    ///     "panic in {} v{}",
+    ///     env!("CARGO_PKG_NAME"),
+    ///     env!("CARGO_PKG_VERSION")
+    /// ));
+    /// ```
+    ///
+    ///[`decor::draw_box()`]: decor/fn.draw_box.html
+    pub fn boxed(mut self, title: impl Into<Cow<'static, str>>) -> Self {
+        self.box_title = Some(title.into());
+        self
+    }
+
+    /// Shows `note` below the frame with the given index in the output.
+    ///
+    /// The index counts the shown frames, just like the numbers in front of them, but always
+    /// starts at 0 regardless of [`index_base()`].
+    ///
+    ///[`index_base()`]: #method.index_base
+    pub fn annotate_frame(mut self, index: usize, note: impl Into<Cow<'static, str>>) -> Self {
+        self.annotations.push(Annotation {
+            matcher: FrameMatcher::Index(index),
+            note: note.into(),
+        });
+        self
+    }
+
+    /// Shows `note` below each frame containing a symbol for which `pred` returns `true`.
+    ///
+    /// `pred` gets the demangled symbol name without crate disambiguators and leading `<`.
+    ///
+    /// ```
+    /// use backtrace_string::FormatOptions;
+    ///
+    /// let options = FormatOptions::new().annotate_symbols(
+    ///     |sym| sym.starts_with("my_app::ffi::"),
+    ///     "this is the FFI boundary",
+    /// );
+    /// ```
+    pub fn annotate_symbols(
+        mut self,
+        pred: impl Fn(&str) -> bool + Send + Sync + 'static,
+        note: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        self.annotations.push(Annotation {
+            matcher: FrameMatcher::Symbol(Arc::new(pred)),
+            note: note.into(),
+        });
+        self
+    }
+
+    /// Marks the symbols at the given source location as the origin of the panic.
+    ///
+    /// Inlining often makes the top frames of a panic misleading, the marker shows which one
+    /// panicked. Panic hooks pass the location of the panic:
+    ///
+    /// ```
+    /// use backtrace_string::{create_backtrace, FormatOptions};
+    ///
+    /// std::panic::set_hook(Box::new(|info| {
+    ///     let mut options = FormatOptions::current();
+    ///     if let Some(location) = info.location() {
+    ///         options = options.panic_location(location.file(), location.line());
+    ///     }
+    ///     let bt = options.with_thread_override(create_backtrace);
+    ///     eprintln!("{}{}", info, bt);
+    /// }));
+    /// ```
+    pub fn panic_location(mut self, file: impl Into<PathBuf>, line: u32) -> Self {
+        self.panic_location = Some((file.into(), line));
+        self
+    }
+
+    /// Replaces the words used in the output, see [`Vocabulary`].
+    ///
+    ///[`Vocabulary`]: struct.Vocabulary.html
+    pub fn vocabulary(mut self, vocabulary: Vocabulary) -> Self {
+        self.vocabulary = vocabulary;
+        self
+    }
+
+    /// Returns the width the frame numbers are aligned to.
+    pub(crate) fn number_width(&self) -> usize {
+        self.index_width.unwrap_or(NUMBER_WIDTH)
+    }
+
+    /// Returns the layout of the frames.
+    pub(crate) fn layout(&self) -> Layout<'_> {
+        Layout {
+            style: self.style,
+            width: self.number_width(),
+            vocabulary: &self.vocabulary,
+        }
+    }
+
+    /// Returns the painter coloring the output, which deterministic output never is.
+    pub(crate) fn painter(&self) -> Painter<'_> {
+        let color = if self.deterministic {
+            ColorChoice::Never
+        } else {
+            self.color
+        };
+        Painter::new(color, &self.theme)
+    }
+
+    /// Returns the notes for the frame shown with the given index.
+    pub(crate) fn notes<'a>(
+        &'a self,
+        index: usize,
+        frame: &'a impl Frame,
+    ) -> impl Iterator<Item = &'a str> + 'a {
+        self.annotations
+            .iter()
+            .filter(move |annotation| match &annotation.matcher {
+                FrameMatcher::Index(i) => *i == index,
+                FrameMatcher::Symbol(pred) => {
+                    frame_contains_symbol(frame, |sym| pred(&normalize_symbol(sym)))
+                }
+            })
+            .map(|annotation| &*annotation.note)
+    }
+
+    /// Returns whether a symbol at the given location is where the panic happened.
+    pub(crate) fn is_panic_location(&self, file: &Path, line: u32) -> bool {
+        match &self.panic_location {
+            // The location of the panic is relative for the crate being built and absolute for
+            // dependencies, debug info may have either.
+            Some((panic_file, panic_line)) => {
+                *panic_line == line && (file.ends_with(panic_file) || panic_file.ends_with(file))
+            }
+            None => false,
+        }
+    }
+
+    /// Returns whether the frame isn't hidden by these options.
+    pub(crate) fn is_visible(&self, frame: &impl Frame) -> bool {
+        if self.unknown_symbols == UnknownSymbols::Hide && !frame.is_resolved() {
+            return false;
+        }
+        !frame_only_contains_symbols(frame, |sym| self.hides_symbol(sym))
+    }
+
+    /// Returns how much of the frames of the module at `path` is resolved.
+    #[cfg_attr(not(feature = "resolve"), allow(dead_code))]
+    pub(crate) fn symbolication(&self, path: &Path) -> Symbolication {
+        let file_name = path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy();
+        let path = path.to_string_lossy();
+        self.module_symbolication
+            .iter()
+            .find(|(pattern, _)| {
+                if pattern.contains('/') {
+                    wildcard_match(pattern, &path)
+                } else {
+                    wildcard_match(pattern, &file_name)
+                }
+            })
+            .map_or(Symbolication::Full, |&(_, symbolication)| symbolication)
+    }
+
+    /// Returns whether the frame with the given demangled symbol is hidden.
+    pub(crate) fn hides_symbol(&self, sym: &str) -> bool {
+        if self.hidden_prefixes.is_empty() {
+            return false;
+        }
+        let sym = normalize_symbol(sym);
+        self.hidden_prefixes
+            .iter()
+            .any(|prefix| starts_with_whole(&sym, prefix))
+    }
+}
+
+/// Returns whether `sym` starts with `prefix` without the prefix ending within an identifier of
+/// the symbol, so `clone` matches `clone` and `clone::{{closure}}` but not `clone_repo::main`.
+fn starts_with_whole(sym: &str, prefix: &str) -> bool {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    sym.starts_with(prefix)
+        && (!prefix.ends_with(is_ident) || !sym[prefix.len()..].starts_with(is_ident))
+}
+
+/// The error returned by [`FormatOptions::parse()`].
+///
+///[`FormatOptions::parse()`]: struct.FormatOptions.html#method.parse
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseOptionsError {
+    /// The key isn't known.
+    UnknownKey(String),
+    /// The option has no `=value` part.
+    MissingValue(String),
+    /// The value isn't valid for the key.
+    InvalidValue {
+        /// The key of the option.
+        key: String,
+        /// The invalid value.
+        value: String,
+    },
+    /// The `filters` option names an unknown preset.
+    UnknownPreset(UnknownPreset),
+}
+
+impl fmt::Display for ParseOptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseOptionsError::UnknownKey(key) => write!(f, "unknown option {:?}", key),
+            ParseOptionsError::MissingValue(key) => write!(f, "option {:?} has no value", key),
+            ParseOptionsError::InvalidValue { key, value } => {
+                write!(f, "invalid value {:?} for option {:?}", value, key)
+            }
+            ParseOptionsError::UnknownPreset(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for ParseOptionsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseOptionsError::UnknownPreset(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Removes leading `<` and the crate disambiguators of v0 mangled symbols.
+pub(crate) fn normalize_symbol(sym: &str) -> Cow<'_, str> {
+    let sym = sym.trim_start_matches('<');
+    if !sym.contains('[') {
+        return Cow::Borrowed(sym);
+    }
+
+    let mut out = String::with_capacity(sym.len());
+    let mut rest = sym;
+    while let Some(start) = rest.find('[') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find(']') {
+            Some(end) if end > 0 && after[..end].bytes().all(|b| b.is_ascii_hexdigit()) => {
+                rest = &after[end + 1..];
+            }
+            _ => {
+                out.push('[');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+/// Returns whether `text` matches `pattern`, in which a `*` matches any run of characters.
+#[cfg_attr(not(feature = "resolve"), allow(dead_code))]
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let mut rest = match text.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let mut parts = parts.collect::<Vec<_>>();
+    let last = match parts.pop() {
+        Some(last) => last,
+        None => return rest.is_empty(),
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            normalize_symbol, wildcard_match, FormatOptions, ParseOptionsError, Style,
+            Symbolication, Theme,
+        },
+        std::path::Path,
+    };
+
+    #[test]
+    fn thread_override_replaces_current_options() {
+        assert_eq!(FormatOptions::current().style, Style::Full);
+        FormatOptions::production().with_thread_override(|| {
+            assert_eq!(FormatOptions::current().style, Style::Compact);
+            std::thread::spawn(|| assert_eq!(FormatOptions::current().style, Style::Full))
+                .join()
+                .unwrap();
+        });
+        assert_eq!(FormatOptions::current().style, Style::Full);
+    }
+
+    #[test]
+    fn parse_options() {
+        let options =
+            FormatOptions::parse(" style = compact, max_frames=40,filters=tokio+rayon,").unwrap();
+        assert_eq!(options.style, Style::Compact);
+        assert_eq!(options.max_frames, Some(40));
+        assert!(options.hides_symbol("rayon_core::registry::WorkerThread::wait_until"));
+
+        assert_eq!(
+            FormatOptions::parse("colour=auto").unwrap_err(),
+            ParseOptionsError::UnknownKey(String::from("colour"))
+        );
+        assert_eq!(
+            FormatOptions::parse("max_frames=many")
+                .unwrap_err()
+                .to_string(),
+            "invalid value \"many\" for option \"max_frames\""
+        );
+        assert!(FormatOptions::parse("filters=tokio+rocket").is_err());
+
+        let options = FormatOptions::parse("profile=production,max_frames=none").unwrap();
+        assert_eq!(options.style, Style::Compact);
+        assert_eq!(options.max_frames, None);
+        assert!(options.fingerprint);
+
+        let options = FormatOptions::parse("theme=light").unwrap();
+        assert_eq!(options.theme, Theme::light());
+    }
+
+    #[test]
+    fn normalize_strips_disambiguators() {
+        assert_eq!(
+            normalize_symbol("<std[e28293b1]::panicking::foo<[u8]>>"),
+            "std::panicking::foo<[u8]>>"
+        );
+        assert_eq!(
+            normalize_symbol("tokio::runtime::park"),
+            "tokio::runtime::park"
+        );
+    }
+
+    #[test]
+    fn module_symbolication_matches_patterns() {
+        assert!(wildcard_match("libfoo*.so*", "libfoo-1.2.so.3"));
+        assert!(wildcard_match("*", ""));
+        assert!(!wildcard_match("libfoo*.so", "libfoo.so.3"));
+        assert!(!wildcard_match("lib*a*a", "liba"));
+        let options = FormatOptions::new()
+            .module_symbolication("libtorch*", Symbolication::Addresses)
+            .module_symbolication("/usr/lib/*", Symbolication::Names);
+        let symbolication = |path: &str| options.symbolication(Path::new(path));
+        assert_eq!(
+            symbolication("/opt/torch/libtorch_cpu.so"),
+            Symbolication::Addresses
+        );
+        assert_eq!(symbolication("/usr/lib/libc.so.6"), Symbolication::Names);
+        assert_eq!(symbolication("/home/dev/my_app"), Symbolication::Full);
+    }
+}
@@ -0,0 +1,44 @@
+//! Telling apart why a backtrace shows no useful frames.
+
+use {crate::frame::Frame, backtrace::Backtrace};
+
+/// What the capture of a backtrace yielded, e.g. to fall back to other diagnostics.
+///
+/// ```
+/// use backtrace_string::{CaptureOutcome, FormatOptions};
+///
+/// let mut bt = backtrace::Backtrace::new();
+/// match CaptureOutcome::of(&bt) {
+///     CaptureOutcome::Empty => eprintln!("backtraces are unavailable on this system"),
+///     _ => eprintln!("{}", backtrace_string::format_backtrace_with(&mut bt, &FormatOptions::new())),
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CaptureOutcome {
+    /// Frames were captured and at least one of them has a symbol name.
+    Captured,
+    /// Frames were captured, but none has a symbol name, e.g. because the binary is stripped or
+    /// the backtrace wasn't resolved (yet).
+    Unresolved,
+    /// No frames were captured, e.g. because the binary lacks unwind tables, the target doesn't
+    /// support unwinding or a seccomp policy blocks the unwinder. Formatting the backtrace yields
+    /// the [`Vocabulary::no_frames`] notice explaining this.
+    ///
+    ///[`Vocabulary::no_frames`]: struct.Vocabulary.html#structfield.no_frames
+    Empty,
+}
+
+impl CaptureOutcome {
+    /// Returns the outcome of capturing `bt`.
+    pub fn of(bt: &Backtrace) -> Self {
+        let frames = bt.frames();
+        if frames.is_empty() {
+            CaptureOutcome::Empty
+        } else if frames.iter().any(|frame| frame.is_resolved()) {
+            CaptureOutcome::Captured
+        } else {
+            CaptureOutcome::Unresolved
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Process-global records of the reported panics, for health endpoints and supervisors.
+//!
+//! A service which survives panics in its workers (e.g. with [`spawn_reported()`]) looks healthy
+//! from the outside. Once enabled with [`track()`], the panic reports of this crate are counted
+//! and the last one is kept, so a health endpoint can tell that a process panicked three times
+//! without scraping its logs:
+//!
+//! ```
+//! use backtrace_string::panics;
+//!
+//! panics::track(true);
+//! // ... later, in the health endpoint:
+//! if let Some(fingerprint) = panics::last_fingerprint() {
+//!     println!("panicked {} times, last at {}", panics::count(), fingerprint);
+//! }
+//! ```
+//!
+//![`spawn_reported()`]: ../thread/fn.spawn_reported.html
+//![`track()`]: fn.track.html
+
+use {
+    crate::Fingerprint,
+    std::sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+static TRACKING: AtomicBool = AtomicBool::new(false);
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+static LAST: Mutex<Option<(Fingerprint, String)>> = Mutex::new(None);
+
+/// Enables or disables the tracking of reported panics, which is disabled by default.
+///
+/// Disabling it keeps the count and the last panic recorded so far.
+pub fn track(enable: bool) {
+    TRACKING.store(enable, Ordering::Relaxed);
+}
+
+/// Returns the number of panics reported while tracking was enabled.
+pub fn count() -> usize {
+    COUNT.load(Ordering::Relaxed)
+}
+
+/// Returns the fingerprint of the backtrace of the last reported panic.
+pub fn last_fingerprint() -> Option<Fingerprint> {
+    LAST.lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .as_ref()
+        .map(|(fingerprint, _)| *fingerprint)
+}
+
+/// Returns the report of the last reported panic, as it was written.
+pub fn last_report() -> Option<String> {
+    LAST.lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .as_ref()
+        .map(|(_, report)| report.clone())
+}
+
+pub(crate) fn is_tracking() -> bool {
+    TRACKING.load(Ordering::Relaxed)
+}
+
+/// Records a reported panic.
+pub(crate) fn record(fingerprint: Fingerprint, report: &str) {
+    COUNT.fetch_add(1, Ordering::Relaxed);
+    *LAST.lock().unwrap_or_else(|err| err.into_inner()) = Some((fingerprint, report.to_owned()));
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{count, last_fingerprint, last_report, track},
+        crate::{hook::catch_captured, FormatOptions},
+    };
+
+    #[test]
+    fn counts_reported_panics() {
+        track(true);
+        let before = count();
+        let (_, captured) = catch_captured(|| panic!("tracked")).unwrap_err();
+        let report = captured.unwrap().into_report("test", &FormatOptions::new());
+        // Other tests may report panics at the same time.
+        assert!(count() > before);
+        assert!(last_fingerprint().is_some());
+        assert!(last_report().is_some());
+        assert!(report.contains("panicked"), "{}", report);
+    }
+}
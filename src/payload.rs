@@ -0,0 +1,91 @@
+//! Describing panic payloads which aren't strings.
+//!
+//! Panics usually carry a `&str` or `String` message, but `std::panic::panic_any()` and
+//! `resume_unwind()` accept any type, which the reports of this crate can't show unless the type
+//! is registered here:
+//!
+//! ```
+//! use backtrace_string::payload;
+//!
+//! struct BailError {
+//!     code: i32,
+//! }
+//!
+//! payload::register_formatter(|error: &BailError| format!("bailed with code {}", error.code));
+//!
+//! let payload: Box<dyn std::any::Any + Send> = Box::new(BailError { code: 3 });
+//! assert_eq!(payload::describe(&*payload), "bailed with code 3");
+//! ```
+
+use std::{
+    any::{self, Any},
+    sync::RwLock,
+};
+
+type Formatter = Box<dyn Fn(&(dyn Any + Send)) -> Option<String> + Send + Sync>;
+
+static FORMATTERS: RwLock<Vec<Formatter>> = RwLock::new(Vec::new());
+
+/// Registers `format` to describe panic payloads of type `T`.
+///
+/// Formatters registered later take precedence.
+pub fn register_formatter<T: Any>(format: impl Fn(&T) -> String + Send + Sync + 'static) {
+    FORMATTERS
+        .write()
+        .unwrap_or_else(|err| err.into_inner())
+        .push(Box::new(move |payload| {
+            payload.downcast_ref::<T>().map(&format)
+        }));
+}
+
+/// Registers the type `T` to be named in the description of panic payloads.
+///
+/// The payload is described as `payload: <type name> (non-string panic payload)`.
+pub fn register_type<T: Any>() {
+    register_formatter(|_: &T| {
+        format!(
+            "payload: {} (non-string panic payload)",
+            any::type_name::<T>()
+        )
+    });
+}
+
+/// Returns the message of a `&str` or `String` payload, or the description of a registered
+/// payload type, or `Box<dyn Any>`.
+pub fn describe(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        return msg.to_string();
+    }
+    if let Some(msg) = payload.downcast_ref::<String>() {
+        return msg.clone();
+    }
+    FORMATTERS
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .iter()
+        .rev()
+        .find_map(|format| format(payload))
+        .unwrap_or_else(|| String::from("Box<dyn Any>"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{describe, register_type};
+
+    #[test]
+    fn registered_types_are_named() {
+        struct Unregistered;
+        struct Registered;
+
+        register_type::<Registered>();
+        assert_eq!(describe(&Unregistered), "Box<dyn Any>");
+        assert_eq!(
+            describe(&Registered),
+            format!(
+                "payload: {} (non-string panic payload)",
+                std::any::type_name::<Registered>()
+            )
+        );
+        assert_eq!(describe(&"message"), "message");
+    }
+}
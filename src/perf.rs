@@ -0,0 +1,198 @@
+//! Parsing the stacks printed by `perf script` and `bpftrace`, to render them like backtraces.
+//!
+//! Kernel-side profilers print the raw symbol names of the binary, which are mangled for Rust.
+//! [`parse()`] turns their text into [`PerfStack`]s of [`ResolvedFrame`]s, which are formatted
+//! with the demangling, trimming and path cleaning of this crate:
+//!
+//! ```
+//! use backtrace_string::{perf, FormatOptions};
+//!
+//! let text = "\
+//! my_app 4242 [003] 12345.678901:     250000 cpu-clock:u:
+//! \t    55d1c0a1b2c3 _ZN6my_app5parse17h0123456789abcdefE+0x13 (/usr/bin/my_app)
+//! \t    55d1c0a1b000 _ZN6my_app4main17h0123456789abcdefE+0x40 (/usr/bin/my_app)
+//! ";
+//! let stacks = perf::parse(text);
+//! assert_eq!(stacks[0].header, "my_app 4242 [003] 12345.678901:     250000 cpu-clock:u:");
+//! print!("{}", stacks[0].format(&FormatOptions::new()));
+//! ```
+//!
+//! Both the output of `perf script` (with call graphs, optionally with `-F +srcline`) and the
+//! `ustack`/`kstack` maps printed by `bpftrace` are understood.
+//!
+//![`parse()`]: fn.parse.html
+//![`PerfStack`]: struct.PerfStack.html
+//![`ResolvedFrame`]: ../frame/struct.ResolvedFrame.html
+
+use crate::{
+    format_frame_source,
+    frame::{ResolvedFrame, ResolvedSymbol},
+    FormatOptions,
+};
+
+/// A stack parsed from profiler output.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PerfStack {
+    /// The line introducing the stack, e.g. the command, process id and event of `perf script`
+    /// or the map key of `bpftrace`.
+    pub header: String,
+    /// How often the stack was sampled, 1 for the events of `perf script`.
+    pub count: u64,
+    /// The frames, the most recent call first.
+    pub frames: Vec<ResolvedFrame>,
+}
+
+impl PerfStack {
+    /// Formats the frames like [`format_frame_source()`].
+    ///
+    ///[`format_frame_source()`]: ../fn.format_frame_source.html
+    pub fn format(&self, options: &FormatOptions) -> String {
+        format_frame_source(&self.frames, options)
+    }
+}
+
+/// Parses the stacks of `perf script` or `bpftrace` output, skipping lines it doesn't understand.
+pub fn parse(text: &str) -> Vec<PerfStack> {
+    let mut stacks = Vec::new();
+    let mut current: Option<PerfStack> = None;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            stacks.extend(current.take());
+        } else if let Some(count) = trimmed.strip_prefix("]:") {
+            // The end of a `bpftrace` map entry, with the count of the stack.
+            if let Some(mut stack) = current.take() {
+                stack.count = count.trim().parse().unwrap_or(1);
+                stacks.push(stack);
+            }
+        } else if !line.starts_with(char::is_whitespace) {
+            stacks.extend(current.take());
+            current = Some(PerfStack {
+                header: trimmed.trim_end_matches('[').to_owned(),
+                count: 1,
+                frames: Vec::new(),
+            });
+        } else if let Some(stack) = &mut current {
+            match parse_source_line(trimmed) {
+                Some((file, line)) if !stack.frames.is_empty() => {
+                    // The line of `-F +srcline` belongs to the frame before it.
+                    let frame = stack.frames.last_mut().unwrap();
+                    if let Some(symbol) = frame.symbols.first_mut() {
+                        symbol.filename = Some(file.into());
+                        symbol.lineno = Some(line);
+                    }
+                }
+                _ => stack.frames.push(parse_frame(trimmed)),
+            }
+        }
+    }
+    stacks.extend(current);
+    // Other lines, like the `Attaching 1 probe...` of `bpftrace`, look like stacks without frames.
+    stacks.retain(|stack| !stack.frames.is_empty());
+    stacks
+}
+
+/// Parses a frame like `55d1c0a1b2c3 my_app::main+0x13 (/usr/bin/my_app)`, where the address
+/// and the module are optional.
+fn parse_frame(line: &str) -> ResolvedFrame {
+    let (ip, rest) = match line.split_once(char::is_whitespace) {
+        Some((address, rest)) => match usize::from_str_radix(address, 16) {
+            Ok(ip) => (ip, rest.trim_start()),
+            Err(_) => (0, line),
+        },
+        None => (0, line),
+    };
+    let symbol = match rest.rfind(" (") {
+        Some(index) if rest.ends_with(')') => &rest[..index],
+        _ => rest,
+    };
+    // The offset within the symbol, e.g. `+0x13` or `+19`.
+    let symbol = match symbol.rsplit_once('+') {
+        Some((name, offset)) if is_offset(offset) => name,
+        _ => symbol,
+    };
+    let name = match symbol {
+        "" | "[unknown]" => None,
+        name => Some(name.to_owned()),
+    };
+    ResolvedFrame {
+        ip,
+        symbols: name
+            .map(|name| ResolvedSymbol {
+                name: Some(name),
+                ..ResolvedSymbol::default()
+            })
+            .into_iter()
+            .collect(),
+    }
+}
+
+fn is_offset(offset: &str) -> bool {
+    match offset.strip_prefix("0x") {
+        Some(hex) => !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => !offset.is_empty() && offset.chars().all(|c| c.is_ascii_digit()),
+    }
+}
+
+/// Parses a source location of `-F +srcline` like `src/main.rs:3`.
+fn parse_source_line(line: &str) -> Option<(&str, u32)> {
+    let (file, line) = line.rsplit_once(':')?;
+    if file.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((file, line.parse().ok()?))
+}
+
+#[cfg(all(test, feature = "resolve", feature = "demangle"))]
+mod tests {
+    use {
+        super::parse,
+        crate::{Demangling, FormatOptions, Locations},
+    };
+
+    #[test]
+    fn parse_perf_script() {
+        let stacks = parse(
+            "my_app 4242 [003] 12345.678901:     250000 cpu-clock:u: \n\
+             \t    55d1c0a1b2c3 _ZN6my_app5parse17h0123456789abcdefE+0x13 (/usr/bin/my_app)\n\
+             \t  /build/my_app/src/parse.rs:12\n\
+             \t    55d1c0a1b000 _ZN6my_app4main17h0123456789abcdefE+0x40 (/usr/bin/my_app)\n\
+             \t    7f0000001234 [unknown] (/usr/lib/libc.so.6)\n\
+             \n\
+             my_app 4242 [003] 12345.679001:     250000 cpu-clock:u: \n\
+             \t    55d1c0a1b000 _ZN6my_app4main17h0123456789abcdefE+0x40 (/usr/bin/my_app)\n",
+        );
+        assert_eq!(stacks.len(), 2);
+        assert_eq!(stacks[0].frames.len(), 3);
+        assert_eq!(stacks[0].frames[0].ip, 0x55d1_c0a1_b2c3);
+        assert_eq!(
+            stacks[0].format(&FormatOptions::new().demangling(Demangling::WithoutHashes)),
+            "\n   0: my_app::parse\n          at /build/my_app/src/parse.rs:12\n   \
+             1: my_app::main\n          at <unknown>\n   \
+             2: <unknown>\n          at address 0x7f0000001234\n"
+        );
+        assert_eq!(stacks[1].frames.len(), 1);
+    }
+
+    #[test]
+    fn parse_bpftrace() {
+        let stacks = parse(
+            "Attaching 1 probe...\n\
+             \n\
+             @[\n    \
+             _ZN6my_app5parse17h0123456789abcdefE+19\n    \
+             _ZN6my_app4main17h0123456789abcdefE+64\n\
+             ]: 12\n",
+        );
+        assert_eq!(stacks.len(), 1);
+        let stack = &stacks[0];
+        assert_eq!((&*stack.header, stack.count), ("@", 12));
+        let options = FormatOptions::new()
+            .demangling(Demangling::WithoutHashes)
+            .locations(Locations::Hidden);
+        assert_eq!(
+            stack.format(&options),
+            "\n   0: my_app::parse\n   1: my_app::main\n"
+        );
+    }
+}
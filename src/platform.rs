@@ -0,0 +1,178 @@
+//! Knowledge about the frames and paths of specific platforms.
+
+use std::path::{Path, PathBuf};
+
+/// The symbols of the thread startup of macOS, below the frames of the Rust runtime, and the
+/// entry point of dyld.
+#[cfg(target_vendor = "apple")]
+pub(crate) const STARTUP: &[&str] = &["_pthread_start", "__pthread_start", "thread_start", "start"];
+
+/// The symbols of the process and thread startup of Windows and the MSVC C runtime.
+#[cfg(windows)]
+pub(crate) const STARTUP: &[&str] = &[
+    "BaseThreadInitThunk",
+    "RtlUserThreadStart",
+    "__scrt_common_main_seh",
+    "invoke_main",
+    "mainCRTStartup",
+];
+
+/// The C runtime of FreeBSD and DragonFly, and the threads of their `libthr`.
+#[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
+pub(crate) const STARTUP: &[&str] = &["_start", "__libc_start1", "thread_start"];
+
+/// The C runtime of NetBSD and its `libpthread`.
+#[cfg(target_os = "netbsd")]
+pub(crate) const STARTUP: &[&str] = &["_start", "___start", "pthread__create_tramp"];
+
+/// The C runtime of OpenBSD and its `librthread`.
+#[cfg(target_os = "openbsd")]
+pub(crate) const STARTUP: &[&str] = &["_start", "___start", "_rthread_start"];
+
+/// The C runtime and the threads of illumos and Solaris.
+#[cfg(any(target_os = "illumos", target_os = "solaris"))]
+pub(crate) const STARTUP: &[&str] = &["_start", "_start_crt", "_thrp_setup", "_lwp_start"];
+
+/// The C runtime of QNX.
+#[cfg(target_os = "nto")]
+pub(crate) const STARTUP: &[&str] = &["_start", "_CMain"];
+
+/// Other platforms, like Linux, have the markers of the Rust runtime or of their C library.
+#[cfg(not(any(
+    target_vendor = "apple",
+    windows,
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "illumos",
+    target_os = "solaris",
+    target_os = "nto"
+)))]
+pub(crate) const STARTUP: &[&str] = &[];
+
+/// Returns whether `sym` belongs to the panic machinery in the naming of a platform.
+///
+/// The symbols of PDBs, as used with MSVC, lack the prefixes of the symbols of other platforms
+/// (e.g. `rust_begin_unwind` instead of `__rustc::rust_begin_unwind`).
+pub(crate) fn is_panic_symbol(sym: &str) -> bool {
+    sym == "rust_begin_unwind" || sym == "core::panicking::panic_fmt"
+}
+
+/// Returns whether `sym` belongs to the process or thread startup code of the target platform.
+///
+/// These are used to trim the end of the backtrace if the markers of the Rust runtime are
+/// missing. The names are generic enough to be those of user or FFI functions on other
+/// platforms, so only the ones of the target are matched.
+pub(crate) fn is_startup_symbol(sym: &str) -> bool {
+    STARTUP.contains(&sym)
+}
+
+/// Shortens paths which only differ between machines because of the platform's file system
+/// layout, returns `None` for other paths.
+///
+/// - Sources of the standard library shipped with a toolchain (by rustup or Homebrew's
+///   `Cellar`) start at `library/`, like the ones of the official builds without the
+///   `/rustc/<commit>` prefix.
+/// - So do those of toolchains built by FreeBSD ports or pkgsrc (NetBSD, illumos), which keep
+///   their build directory like `/wrkdirs/usr/ports/lang/rust/work/rustc-1.75.0-src`.
+/// - `/private/tmp` and `/private/var`, to which `/tmp` and `/var` link on macOS, become `/tmp`
+///   and `/var`.
+pub(crate) fn clean_path(p: &Path) -> Option<PathBuf> {
+    let mut rest = p.iter();
+    loop {
+        let mut after = rest.clone();
+        if ["lib", "rustlib", "src", "rust"]
+            .iter()
+            .all(|name| after.next() == Some(name.as_ref()))
+        {
+            return Some(after.collect());
+        }
+        let mut after = rest.clone();
+        let dir = after.next().and_then(|name| name.to_str()).unwrap_or("");
+        if dir.starts_with("rustc-")
+            && dir.ends_with("-src")
+            && after.clone().next() == Some("library".as_ref())
+        {
+            return Some(after.collect());
+        }
+        if rest.next().is_none() {
+            break;
+        }
+    }
+    ["/private/tmp", "/private/var"]
+        .iter()
+        .find(|prefix| p.starts_with(prefix))
+        .map(|_| Path::new("/").join(p.strip_prefix("/private").unwrap_or(p)))
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{clean_path, is_startup_symbol},
+        std::path::{Path, PathBuf},
+    };
+
+    #[test]
+    fn clean_macos_paths() {
+        let clean = |path: &str| clean_path(Path::new(path));
+        assert_eq!(
+            clean("/opt/homebrew/Cellar/rust/1.75.0/lib/rustlib/src/rust/library/std/src/rt.rs"),
+            Some(PathBuf::from("library/std/src/rt.rs"))
+        );
+        assert_eq!(
+            clean("/private/tmp/my_app/src/main.rs"),
+            Some(PathBuf::from("/tmp/my_app/src/main.rs"))
+        );
+        assert_eq!(clean("/private/etc/hosts"), None);
+        assert_eq!(clean("/home/alice/my_app/src/main.rs"), None);
+    }
+
+    #[test]
+    fn clean_distribution_paths() {
+        let clean = |path: &str| clean_path(Path::new(path));
+        assert_eq!(
+            clean("/wrkdirs/usr/ports/lang/rust/work/rustc-1.75.0-src/library/std/src/rt.rs"),
+            Some(PathBuf::from("library/std/src/rt.rs"))
+        );
+        assert_eq!(
+            clean("/usr/pkgsrc/lang/rust/work/rustc-1.74.1-src/library/core/src/panicking.rs"),
+            Some(PathBuf::from("library/core/src/panicking.rs"))
+        );
+        assert_eq!(clean("/home/alice/rustc-fork-src/src/main.rs"), None);
+    }
+
+    #[test]
+    fn windows_startup_symbols() {
+        assert_eq!(is_startup_symbol("invoke_main"), cfg!(windows));
+        assert_eq!(is_startup_symbol("RtlUserThreadStart"), cfg!(windows));
+    }
+
+    #[test]
+    fn unix_startup_symbols() {
+        assert_eq!(
+            is_startup_symbol("thread_start"),
+            cfg!(any(
+                target_vendor = "apple",
+                target_os = "freebsd",
+                target_os = "dragonfly"
+            ))
+        );
+        assert_eq!(
+            is_startup_symbol("_thrp_setup"),
+            cfg!(any(target_os = "illumos", target_os = "solaris"))
+        );
+        assert_eq!(
+            is_startup_symbol("_start"),
+            cfg!(any(
+                target_os = "freebsd",
+                target_os = "dragonfly",
+                target_os = "netbsd",
+                target_os = "openbsd",
+                target_os = "illumos",
+                target_os = "solaris",
+                target_os = "nto"
+            ))
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! Export of captured stacks as `pprof` profiles.
+//!
+//! A [`Profile`] collects stacks as samples with a count of 1 each and is written as gzipped
+//! `profile.proto`, which `go tool pprof`, Grafana Pyroscope and similar tools load next to their
+//! CPU profiles:
+//!
+//! ```
+//! use {
+//!     backtrace::Backtrace,
+//!     backtrace_string::{pprof::Profile, FormatOptions},
+//! };
+//!
+//! let mut profile = Profile::new(FormatOptions::new());
+//! profile.add_backtrace(&mut Backtrace::new_unresolved());
+//! let mut file = Vec::new();
+//! profile.write_gzip(&mut file).unwrap();
+//! ```
+//!
+//! The frames are trimmed and hidden and their symbols demangled according to the options, like
+//! in the formatted backtraces.
+//!
+//![`Profile`]: struct.Profile.html
+
+use {
+    crate::{
+        demangle::SymbolName,
+        filter_frames,
+        frame::{Frame, FrameSource},
+        resolve, FormatOptions,
+    },
+    backtrace::Backtrace,
+    flate2::{write::GzEncoder, Compression},
+    std::{collections::HashMap, io},
+};
+
+/// Stacks collected for a `pprof` profile.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    options: FormatOptions,
+    strings: Vec<String>,
+    string_ids: HashMap<String, u64>,
+    /// The name and file of each function, by id - 1.
+    functions: Vec<(u64, u64)>,
+    function_ids: HashMap<(u64, u64), u64>,
+    /// The address and the function and line of each symbol of each location, by id - 1.
+    locations: Vec<(u64, Vec<(u64, u32)>)>,
+    location_ids: HashMap<(u64, Vec<(u64, u32)>), u64>,
+    /// The location ids of each sample, the most recent call first.
+    samples: Vec<Vec<u64>>,
+}
+
+impl Profile {
+    /// Creates an empty profile, trimming, hiding and naming the frames according to the
+    /// `options`.
+    pub fn new(options: FormatOptions) -> Self {
+        let mut profile = Profile {
+            options,
+            strings: Vec::new(),
+            string_ids: HashMap::new(),
+            functions: Vec::new(),
+            function_ids: HashMap::new(),
+            locations: Vec::new(),
+            location_ids: HashMap::new(),
+            samples: Vec::new(),
+        };
+        // The string table starts with the empty string, followed by those of the sample type.
+        for string in &["", "samples", "count"] {
+            profile.string(string);
+        }
+        profile
+    }
+
+    /// Resolves `bt` and adds it as a sample.
+    pub fn add_backtrace(&mut self, bt: &mut Backtrace) {
+        resolve(bt, &self.options);
+        self.add_stack(&*bt);
+    }
+
+    /// Adds the frames of `source` as a sample.
+    pub fn add_stack<S: FrameSource + ?Sized>(&mut self, source: &S) {
+        let options = self.options.clone();
+        let frames = filter_frames(source.frames(), &options)
+            .filter(|frame| options.is_visible(frame))
+            .map(|frame| self.location(frame))
+            .collect();
+        self.samples.push(frames);
+    }
+
+    /// Returns the number of samples.
+    pub fn samples(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Encodes the profile as uncompressed `profile.proto`.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        // sample_type: the samples are counted.
+        message(&mut out, 1, |out| {
+            uint(out, 1, 1);
+            uint(out, 2, 2);
+        });
+        for sample in &self.samples {
+            message(&mut out, 2, |out| {
+                packed(out, 1, sample.iter().copied());
+                packed(out, 2, Some(1));
+            });
+        }
+        for (id, (address, lines)) in (1..).zip(&self.locations) {
+            message(&mut out, 4, |out| {
+                uint(out, 1, id);
+                uint(out, 3, *address);
+                // The innermost inlined function comes first, like in the frames.
+                for &(function, line) in lines {
+                    message(out, 4, |out| {
+                        uint(out, 1, function);
+                        uint(out, 2, u64::from(line));
+                    });
+                }
+            });
+        }
+        for (id, &(name, file)) in (1..).zip(&self.functions) {
+            message(&mut out, 5, |out| {
+                uint(out, 1, id);
+                uint(out, 2, name);
+                uint(out, 3, name);
+                uint(out, 4, file);
+            });
+        }
+        for string in &self.strings {
+            bytes(&mut out, 6, string.as_bytes());
+        }
+        out
+    }
+
+    /// Writes the profile as gzipped `profile.proto`, the format `pprof` reads.
+    pub fn write_gzip(&self, out: impl io::Write) -> io::Result<()> {
+        let mut encoder = GzEncoder::new(out, Compression::default());
+        io::Write::write_all(&mut encoder, &self.encode())?;
+        encoder.finish().map(drop)
+    }
+
+    fn location(&mut self, frame: &impl Frame) -> u64 {
+        let mut lines = Vec::new();
+        for symbol in frame.symbols() {
+            let name = match symbol.name_lossy() {
+                Some(name) => SymbolName {
+                    name: &name,
+                    demangling: self.options.demangling,
+                }
+                .to_string(),
+                None => self.options.vocabulary.unknown.to_string(),
+            };
+            let file = symbol
+                .filename
+                .map(|file| file.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let function = (self.string(&name), self.string(&file));
+            let function = match self.function_ids.get(&function) {
+                Some(&id) => id,
+                None => {
+                    self.functions.push(function);
+                    let id = self.functions.len() as u64;
+                    self.function_ids.insert(function, id);
+                    id
+                }
+            };
+            lines.push((function, symbol.lineno.unwrap_or(0)));
+        }
+        let location = (frame.ip() as u64, lines);
+        match self.location_ids.get(&location) {
+            Some(&id) => id,
+            None => {
+                self.locations.push(location.clone());
+                let id = self.locations.len() as u64;
+                self.location_ids.insert(location, id);
+                id
+            }
+        }
+    }
+
+    fn string(&mut self, s: &str) -> u64 {
+        match self.string_ids.get(s) {
+            Some(&id) => id,
+            None => {
+                let id = self.strings.len() as u64;
+                self.strings.push(s.to_owned());
+                self.string_ids.insert(s.to_owned(), id);
+                id
+            }
+        }
+    }
+}
+
+fn varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn uint(out: &mut Vec<u8>, field: u64, value: u64) {
+    varint(out, field << 3);
+    varint(out, value);
+}
+
+fn bytes(out: &mut Vec<u8>, field: u64, value: &[u8]) {
+    varint(out, field << 3 | 2);
+    varint(out, value.len() as u64);
+    out.extend_from_slice(value);
+}
+
+fn packed(out: &mut Vec<u8>, field: u64, values: impl IntoIterator<Item = u64>) {
+    let mut buf = Vec::new();
+    for value in values {
+        varint(&mut buf, value);
+    }
+    bytes(out, field, &buf);
+}
+
+fn message(out: &mut Vec<u8>, field: u64, encode: impl FnOnce(&mut Vec<u8>)) {
+    let mut buf = Vec::new();
+    encode(&mut buf);
+    bytes(out, field, &buf);
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{varint, Profile},
+        crate::{
+            frame::{ResolvedFrame, ResolvedSymbol},
+            FormatOptions,
+        },
+        flate2::read::GzDecoder,
+        std::io::Read,
+    };
+
+    #[test]
+    fn varints() {
+        let mut out = Vec::new();
+        varint(&mut out, 1);
+        varint(&mut out, 300);
+        assert_eq!(out, [0x01, 0xac, 0x02]);
+    }
+
+    #[test]
+    fn encodes_profile() {
+        let frame = |ip, name: &str| ResolvedFrame {
+            ip,
+            symbols: vec![ResolvedSymbol {
+                name: Some(name.to_owned()),
+                filename: Some("src/main.rs".into()),
+                lineno: Some(3),
+                addr: None,
+            }],
+        };
+        let mut profile = Profile::new(FormatOptions::new());
+        profile.add_stack(&vec![
+            frame(0x10, "my_app::parse"),
+            frame(0x20, "my_app::main"),
+        ]);
+        profile.add_stack(&vec![frame(0x20, "my_app::main")]);
+        assert_eq!(profile.samples(), 2);
+        assert_eq!(profile.locations.len(), 2);
+        assert_eq!(profile.samples, [vec![1, 2], vec![2]]);
+
+        let encoded = profile.encode();
+        // sample_type { type: "samples", unit: "count" }, with the strings 1 and 2.
+        assert_eq!(encoded[..6], [0x0a, 0x04, 0x08, 0x01, 0x10, 0x02]);
+        // The first sample, with the locations 1 and 2 and the value 1.
+        assert_eq!(
+            encoded[6..16],
+            [0x12, 0x07, 0x0a, 0x02, 0x01, 0x02, 0x12, 0x01, 0x01, 0x12]
+        );
+
+        let mut gzipped = Vec::new();
+        profile.write_gzip(&mut gzipped).unwrap();
+        let mut decoded = Vec::new();
+        GzDecoder::new(&gzipped[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, encoded);
+    }
+}
@@ -0,0 +1,420 @@
+//! Curated sets of symbols which are noise in most backtraces.
+//!
+//! Runtimes, executors and thread pools add many frames between the code which panicked and the
+//! code which started it. The presets list the symbol prefixes of such frames so they can be
+//! hidden with [`FormatOptions::filter_preset()`]. Like with
+//! [`FormatOptions::hide_symbols_starting_with()`], a prefix ending with an identifier only
+//! matches the whole identifier, so `clone` (of `libc`) doesn't hide `clone_repo::main`.
+//!
+//! The lists follow the internals of the respective crates, so they need to be updated when
+//! those get renamed. Prefixes for old names are kept as long as those versions are in use.
+//!
+//! Applications can [register](struct.FilterPreset.html#method.register) their own presets at
+//! startup, or replace the shipped ones, and select them by name, e.g. from configuration:
+//!
+//! ```
+//! use backtrace_string::{preset::FilterPreset, FormatOptions};
+//!
+//! FilterPreset::new("my-framework", vec!["my_framework::dispatch::"]).register();
+//!
+//! let options = FormatOptions::new().filter_presets("tokio+my-framework").unwrap();
+//! ```
+//!
+//![`FormatOptions::filter_preset()`]: ../struct.FormatOptions.html#method.filter_preset
+//![`FormatOptions::hide_symbols_starting_with()`]: ../struct.FormatOptions.html#method.hide_symbols_starting_with
+
+use std::{borrow::Cow, error::Error, fmt, str::FromStr, sync::RwLock};
+
+/// A named set of symbol prefixes to hide.
+///
+/// Presets can also be selected by their [name](#method.name) using `str::parse`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Preset {
+    /// The `tokio` runtime, its task harness and the `Future` plumbing of `core`.
+    Tokio,
+    /// The `async-std` runtime and the `async-global-executor` it is built on.
+    AsyncStd,
+    /// The `smol` runtime and its building blocks (`async-executor`, `async-io`, ...).
+    Smol,
+    /// The executors of the `futures` crate (`block_on`, `LocalPool`, `ThreadPool`).
+    Futures,
+    /// The `rayon` thread pool, its parallel iterator plumbing and the thread startup of `std`.
+    Rayon,
+    /// The harness of `#[test]` functions (`libtest`), so backtraces end at the test function.
+    Libtest,
+    /// The request dispatch of `actix-web` and the `actix` crates below it.
+    ActixWeb,
+    /// The protocol machinery of `hyper` (and `hyper-util` and `h2`).
+    Hyper,
+    /// The middleware layers of `tower` and `tower-http`.
+    Tower,
+}
+
+impl Preset {
+    /// All presets shipped with this crate.
+    pub const ALL: &'static [Preset] = &[
+        Preset::Tokio,
+        Preset::AsyncStd,
+        Preset::Smol,
+        Preset::Futures,
+        Preset::Rayon,
+        Preset::Libtest,
+        Preset::ActixWeb,
+        Preset::Hyper,
+        Preset::Tower,
+    ];
+
+    /// The name used to select the preset, e.g. in configuration.
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Tokio => "tokio",
+            Preset::AsyncStd => "async-std",
+            Preset::Smol => "smol",
+            Preset::Futures => "futures",
+            Preset::Rayon => "rayon",
+            Preset::Libtest => "libtest",
+            Preset::ActixWeb => "actix-web",
+            Preset::Hyper => "hyper",
+            Preset::Tower => "tower",
+        }
+    }
+
+    /// The symbol prefixes of the preset.
+    pub fn prefixes(self) -> impl Iterator<Item = &'static str> {
+        let (specific, shared) = match self {
+            Preset::Tokio => (TOKIO, FUTURE_PLUMBING),
+            Preset::AsyncStd => (ASYNC_STD, FUTURE_PLUMBING),
+            Preset::Smol => (SMOL, FUTURE_PLUMBING),
+            Preset::Futures => (FUTURES, FUTURE_PLUMBING),
+            Preset::Rayon => (RAYON, THREAD_START),
+            Preset::Libtest => (LIBTEST, THREAD_START),
+            Preset::ActixWeb => (ACTIX_WEB, FUTURE_PLUMBING),
+            Preset::Hyper => (HYPER, FUTURE_PLUMBING),
+            Preset::Tower => (TOWER, FUTURE_PLUMBING),
+        };
+        specific.iter().chain(shared).copied()
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Preset {
+    type Err = UnknownPreset;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Preset::ALL
+            .iter()
+            .copied()
+            .find(|preset| preset.name() == name)
+            .ok_or_else(|| UnknownPreset(name.to_owned()))
+    }
+}
+
+/// A named set of symbol prefixes, either shipped as a [`Preset`] or defined by the application.
+///
+///[`Preset`]: enum.Preset.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterPreset {
+    name: Cow<'static, str>,
+    prefixes: Vec<Cow<'static, str>>,
+}
+
+static REGISTRY: RwLock<Vec<FilterPreset>> = RwLock::new(Vec::new());
+
+impl FilterPreset {
+    /// Creates a preset named `name` hiding frames with symbols starting with `prefixes`.
+    pub fn new<P>(name: impl Into<Cow<'static, str>>, prefixes: impl IntoIterator<Item = P>) -> Self
+    where
+        P: Into<Cow<'static, str>>,
+    {
+        FilterPreset {
+            name: name.into(),
+            prefixes: prefixes.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Looks up the preset with the given name.
+    ///
+    /// Registered presets take precedence over the ones shipped with this crate.
+    pub fn by_name(name: &str) -> Result<Self, UnknownPreset> {
+        let registry = REGISTRY.read().unwrap_or_else(|err| err.into_inner());
+        match registry.iter().find(|preset| preset.name == name) {
+            Some(preset) => Ok(preset.clone()),
+            None => name.parse::<Preset>().map(FilterPreset::from),
+        }
+    }
+
+    /// Registers the preset for lookups by name, replacing a preset of the same name.
+    ///
+    /// Replacing a shipped preset also affects the places using the [`Preset`] directly, like
+    /// [`FormatOptions::filter_preset()`].
+    ///
+    ///[`Preset`]: enum.Preset.html
+    ///[`FormatOptions::filter_preset()`]: ../struct.FormatOptions.html#method.filter_preset
+    pub fn register(self) {
+        let mut registry = REGISTRY.write().unwrap_or_else(|err| err.into_inner());
+        registry.retain(|preset| preset.name != self.name);
+        registry.push(self);
+    }
+
+    /// The name of the preset.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The symbol prefixes of the preset.
+    pub fn prefixes(&self) -> &[Cow<'static, str>] {
+        &self.prefixes
+    }
+}
+
+impl From<Preset> for FilterPreset {
+    fn from(preset: Preset) -> Self {
+        FilterPreset::new(preset.name(), preset.prefixes())
+    }
+}
+
+/// The error returned when parsing an unknown preset name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPreset(String);
+
+impl fmt::Display for UnknownPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown filter preset {:?}", self.0)
+    }
+}
+
+impl Error for UnknownPreset {}
+
+/// Frames of `core` and `std` through which every executor polls its futures.
+const FUTURE_PLUMBING: &[&str] = &[
+    "core::future::",
+    "core::pin::Pin<P> as core::future::future::Future>::poll",
+    "core::panic::unwind_safe::AssertUnwindSafe<F> as core::ops::function::FnOnce<()>>::call_once",
+    "core::panic::unwind_safe::AssertUnwindSafe<F> as core::future::future::Future>::poll",
+    "std::panicking::try",
+    "std::panicking::catch_unwind",
+    "std::panic::catch_unwind",
+    "std::thread::local::LocalKey<T>::",
+    "__rust_try",
+];
+
+const TOKIO: &[&str] = &[
+    "tokio::runtime::",
+    "tokio::task::",
+    "tokio::loom::",
+    "tokio::util::",
+    "tokio::park::",
+    "tokio::coop::",
+];
+
+const ASYNC_STD: &[&str] = &[
+    "async_std::task::",
+    "async_std::rt::",
+    "async_std::utils::",
+    "async_global_executor::",
+    "async_executor::",
+    "async_task::",
+    "async_io::driver::",
+    "futures_lite::future::",
+    "kv_log_macro::",
+];
+
+const SMOL: &[&str] = &[
+    "smol::",
+    "async_executor::",
+    "async_task::",
+    "async_io::driver::",
+    "async_io::reactor::",
+    "blocking::",
+    "futures_lite::future::",
+];
+
+const FUTURES: &[&str] = &[
+    "futures_executor::local_pool::",
+    "futures_executor::thread_pool::",
+    "futures_executor::enter::",
+    "futures_task::",
+    "futures_util::future::future::catch_unwind::",
+    "futures_util::future::future::Map",
+    "futures_util::future::future::map::",
+    "futures_util::future::future::FutureExt::poll_unpin",
+    "futures_util::task::",
+];
+
+/// Frames of `std` starting a thread and calling its closure.
+const THREAD_START: &[&str] = &[
+    "std::sys::unix::thread::Thread::new::thread_start",
+    "std::sys::pal::unix::thread::Thread::new::thread_start",
+    "std::sys::thread::unix::Thread::new::thread_start",
+    "std::thread::Builder::spawn_unchecked",
+    "std::thread::Builder::spawn_unchecked_",
+    "std::thread::lifecycle::spawn_unchecked",
+    "alloc::boxed::Box<F,A> as core::ops::function::FnOnce<Args>>::call_once",
+    "alloc::boxed::Box<F> as core::ops::function::FnOnce<A>>::call_once",
+    "core::ops::function::FnOnce::call_once{{vtable.shim}}",
+    "core::panic::unwind_safe::AssertUnwindSafe<F> as core::ops::function::FnOnce<()>>::call_once",
+    "std::panicking::try",
+    "std::panicking::catch_unwind",
+    "std::panic::catch_unwind",
+    "__rust_try",
+    "start_thread",
+    "clone",
+    "__clone",
+    "__clone3",
+];
+
+const RAYON: &[&str] = &[
+    "rayon_core::registry::",
+    "rayon_core::job::",
+    "rayon_core::join::",
+    "rayon_core::scope::",
+    "rayon_core::latch::",
+    "rayon_core::sleep::",
+    "rayon_core::unwind::",
+    "rayon_core::thread_pool::",
+    "rayon_core::ThreadPoolBuilder",
+    "rayon::iter::plumbing::",
+    "rayon::iter::for_each::",
+    "rayon::iter::map::",
+    "rayon::iter::filter::",
+    "rayon::iter::collect::",
+    "rayon::iter::extend::",
+    "rayon::iter::reduce::",
+    "rayon::iter::ParallelIterator::",
+    "rayon::iter::IndexedParallelIterator::",
+    "rayon::range::",
+    "rayon::slice::",
+    "rayon::vec::",
+];
+
+const LIBTEST: &[&str] = &[
+    "test::__rust_begin_short_backtrace",
+    "test::run_test",
+    "test::run_test_in_process",
+    "test::run_tests",
+    "test::types::",
+    "test::console::",
+    "test::test_main",
+    "test::test_main_static",
+    "test::test_main_with_exit_callback",
+    "test::assert_test_result",
+    "core::panic::unwind_safe::AssertUnwindSafe<test::",
+    "fn() -> core::result::Result<(), alloc::string::String> as core::ops::function::FnOnce<()>>::call_once",
+    "fn() as core::ops::function::FnOnce<()>>::call_once",
+    "core::ops::function::FnOnce::call_once",
+    "__rust_maybe_catch_panic",
+];
+
+const ACTIX_WEB: &[&str] = &[
+    "actix_web::app_service::",
+    "actix_web::handler::",
+    "actix_web::service::",
+    "actix_web::route::",
+    "actix_web::resource::",
+    "actix_web::scope::",
+    "actix_web::middleware::",
+    "actix_web::server::",
+    "actix_http::h1::",
+    "actix_http::h2::",
+    "actix_http::service::",
+    "actix_service::",
+    "actix_server::",
+    "actix_rt::",
+    "actix_router::",
+    "actix_utils::future::",
+    "actix_codec::",
+];
+
+const HYPER: &[&str] = &[
+    "hyper::proto::",
+    "hyper::server::",
+    "hyper::client::",
+    "hyper::service::",
+    "hyper::common::",
+    "hyper_util::server::",
+    "hyper_util::client::",
+    "hyper_util::service::",
+    "hyper_util::common::",
+    "h2::",
+];
+
+const TOWER: &[&str] = &[
+    "tower::util::",
+    "tower::buffer::",
+    "tower::limit::",
+    "tower::timeout::",
+    "tower::load_shed::",
+    "tower::retry::",
+    "tower::balance::",
+    "tower::make::",
+    "tower_http::",
+    "tower_layer::",
+    "tower_service::",
+];
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{FilterPreset, Preset},
+        crate::FormatOptions,
+    };
+
+    #[test]
+    fn presets_parse_from_their_names() {
+        for preset in Preset::ALL {
+            assert_eq!(preset.name().parse::<Preset>(), Ok(*preset));
+        }
+        assert!("rocket".parse::<Preset>().is_err());
+    }
+
+    #[test]
+    fn registered_presets_are_found_by_name() {
+        assert!(FilterPreset::by_name("test-registered").is_err());
+        FilterPreset::new("test-registered", vec!["my_app::"]).register();
+        FilterPreset::new("test-registered", vec!["my_app::dispatch::"]).register();
+
+        let preset = FilterPreset::by_name("test-registered").unwrap();
+        assert_eq!(preset.prefixes(), ["my_app::dispatch::"]);
+        assert_eq!(FilterPreset::by_name("tokio"), Ok(Preset::Tokio.into()));
+    }
+
+    #[test]
+    fn prefixes_only_match_whole_identifiers() {
+        for preset in [Preset::Rayon, Preset::Libtest] {
+            let options = FormatOptions::new().filter_preset(preset);
+            for sym in [
+                "clone",
+                "__clone3",
+                "start_thread",
+                "std::panicking::try::do_call",
+            ] {
+                assert!(options.hides_symbol(sym), "{} should hide {}", preset, sym);
+            }
+            for sym in [
+                "clone_repo::main",
+                "cloner::run",
+                "start_threads",
+                "__clone_all",
+            ] {
+                assert!(
+                    !options.hides_symbol(sym),
+                    "{} shouldn't hide {}",
+                    preset,
+                    sym
+                );
+            }
+        }
+
+        let options = FormatOptions::new().filter_preset(Preset::Libtest);
+        assert!(options.hides_symbol("core::ops::function::FnOnce::call_once{{vtable.shim}}"));
+        assert!(options.hides_symbol("test::run_test::run_test_inner::{{closure}}"));
+        assert!(!options.hides_symbol("core::ops::function::FnOnce::call_once_with_my_app"));
+        assert!(!options.hides_symbol("test::run_test_suite_of_my_app"));
+    }
+}
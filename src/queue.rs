@@ -0,0 +1,211 @@
+//! A persisted queue of crash reports, delivered on the next start of the application.
+//!
+//! Sending a report from the panic hook is unreliable, the process is in an unknown state and may
+//! exit any moment. The hook rather [enqueues] the [`CrashPayload`] in a [`CrashQueue`], which
+//! writes it to a temporary file and renames it, so a partly written report is never delivered.
+//! On the next start the application sends the [pending reports] and acknowledges the ones which
+//! were delivered, deleting them:
+//!
+//! ```no_run
+//! use backtrace_string::{
+//!     queue::CrashQueue,
+//!     report::CrashReport,
+//!     upload::CrashPayload,
+//!     FormatOptions,
+//! };
+//!
+//! let queue = CrashQueue::new("pending-crashes");
+//! let transport = |content_type: &str, body: &[u8]| {
+//!     // e.g. POST the body to the crash collector with the HTTP client of the application.
+//!     Ok(())
+//! };
+//! for report in queue.pending_reports().unwrap() {
+//!     if report.send(&transport).is_ok() {
+//!         report.acknowledge().unwrap();
+//!     }
+//! }
+//!
+//! std::panic::set_hook(Box::new(move |info| {
+//!     let payload = CrashPayload::new(CrashReport::from_panic(info), FormatOptions::new());
+//!     let _ = queue.enqueue(&payload);
+//! }));
+//! ```
+//!
+//![enqueues]: struct.CrashQueue.html#method.enqueue
+//![`CrashPayload`]: ../upload/struct.CrashPayload.html
+//![`CrashQueue`]: struct.CrashQueue.html
+//![pending reports]: struct.CrashQueue.html#method.pending_reports
+
+use {
+    crate::{
+        crashfile::format_timestamp,
+        upload::{CrashPayload, Transport},
+    },
+    std::{
+        fs,
+        io::{self, Write},
+        path::{Path, PathBuf},
+        process,
+        sync::atomic::{AtomicU64, Ordering},
+        time::SystemTime,
+    },
+};
+
+/// Distinguishes the reports enqueued by this process within the same second.
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// A directory of crash reports waiting to be sent, see the [module docs].
+///
+///[module docs]: index.html
+#[derive(Debug, Clone)]
+pub struct CrashQueue {
+    dir: PathBuf,
+}
+
+/// A crash report of a [`CrashQueue`] which wasn't acknowledged yet.
+///
+///[`CrashQueue`]: struct.CrashQueue.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PendingReport {
+    /// The path of the file holding the report.
+    pub path: PathBuf,
+    /// The JSON of the [`CrashPayload`].
+    ///
+    ///[`CrashPayload`]: ../upload/struct.CrashPayload.html
+    pub body: Vec<u8>,
+}
+
+impl CrashQueue {
+    /// Creates a queue kept in `dir`, which is created when the first report is enqueued.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        CrashQueue { dir: dir.into() }
+    }
+
+    /// Returns the path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Writes the JSON of `payload` to the queue. Returns the path of the file.
+    ///
+    /// The file only appears under its final name once it was written completely.
+    pub fn enqueue(&self, payload: &CrashPayload) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+        let name = format!(
+            "{}-{}-{}-{}.json",
+            format_timestamp(SystemTime::now()),
+            process::id(),
+            SEQUENCE.fetch_add(1, Ordering::Relaxed),
+            payload.fingerprint()
+        );
+        let path = self.dir.join(&name);
+        let temp_path = self.dir.join(format!(".{}.tmp", name));
+
+        let mut file = fs::File::create(&temp_path)?;
+        let written = file
+            .write_all(payload.to_json().as_bytes())
+            .and_then(|()| file.sync_all());
+        drop(file);
+        if let Err(err) = written.and_then(|()| fs::rename(&temp_path, &path)) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(err);
+        }
+        Ok(path)
+    }
+
+    /// Reads the reports which weren't acknowledged yet, the oldest first. A missing directory has
+    /// no pending reports.
+    pub fn pending_reports(&self) -> io::Result<Vec<PendingReport>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut paths = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            let name = path.file_name().and_then(|name| name.to_str());
+            // Temporary files of reports which were never completed start with a dot.
+            if name.is_some_and(|name| !name.starts_with('.') && name.ends_with(".json")) {
+                paths.push(path);
+            }
+        }
+        // The names start with the fixed width time, so they sort by it.
+        paths.sort();
+
+        let mut reports = Vec::with_capacity(paths.len());
+        for path in paths {
+            match fs::read(&path) {
+                Ok(body) => reports.push(PendingReport { path, body }),
+                // Acknowledged by another instance of the application meanwhile.
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(reports)
+    }
+}
+
+impl PendingReport {
+    /// Sends the report through `transport`.
+    pub fn send(&self, transport: &impl Transport) -> io::Result<()> {
+        transport.send("application/json", &self.body)
+    }
+
+    /// Removes the report from the queue, once it was delivered.
+    pub fn acknowledge(self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::CrashQueue,
+        crate::{report::CrashReport, upload::CrashPayload, FormatOptions},
+        std::{fs, io, process},
+    };
+
+    #[test]
+    fn delivers_until_acknowledged() {
+        let dir = std::env::temp_dir().join(format!("backtrace-string-queue-{}", process::id()));
+        let queue = CrashQueue::new(&dir);
+        assert_eq!(queue.pending_reports().unwrap(), []);
+
+        let payloads: Vec<_> = ["first", "second"]
+            .iter()
+            .map(|version| {
+                CrashPayload::new(CrashReport::default(), FormatOptions::new())
+                    .metadata("version", *version)
+            })
+            .collect();
+        for payload in &payloads {
+            queue.enqueue(payload).unwrap();
+        }
+        fs::write(dir.join(".unfinished.json.tmp"), "{").unwrap();
+
+        let pending = queue.pending_reports().unwrap();
+        let bodies: Vec<_> = pending.iter().map(|report| report.body.clone()).collect();
+        let failing = |_: &str, _: &[u8]| Err(io::Error::other("offline"));
+        assert!(pending[0].send(&failing).is_err());
+        let delivering = |content_type: &str, _: &[u8]| {
+            assert_eq!(content_type, "application/json");
+            Ok(())
+        };
+        pending[0].send(&delivering).unwrap();
+        pending[0].clone().acknowledge().unwrap();
+        let remaining = queue.pending_reports().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let expected: Vec<_> = payloads
+            .iter()
+            .map(|payload| payload.to_json().into_bytes())
+            .collect();
+        assert_eq!(bodies, expected);
+        assert_eq!(remaining, pending[1..]);
+    }
+}
@@ -0,0 +1,869 @@
+//! A structured crash report, captured once and rendered in several formats.
+//!
+//! A [`CrashReport`] holds the process, the panic, the resolved stacks of the threads and the
+//! modules of the process, separate from how they are shown. It renders as text like the panic
+//! reports of this crate, as JSON for log pipelines and as HTML for bug trackers, or in a format
+//! of its own by implementing [`Render`]:
+//!
+//! ```
+//! use backtrace_string::{report::CrashReport, FormatOptions};
+//!
+//! std::panic::set_hook(Box::new(|info| {
+//!     let report = CrashReport::from_panic(info);
+//!     eprintln!("{}", report.to_text(&FormatOptions::current()));
+//! }));
+//! ```
+//!
+//![`CrashReport`]: struct.CrashReport.html
+//![`Render`]: trait.Render.html
+
+use {
+    crate::{
+        capture_below,
+        demangle::{demangle, SymbolName},
+        filter_frames, format_elided, format_frame_source,
+        frame::{Frame, ResolvedFrame},
+        module,
+        options::normalize_symbol,
+        payload, resolve, shared_suffixes,
+        sink::Sink,
+        ColorChoice, FormatOptions,
+    },
+    std::{
+        borrow::Cow,
+        fmt::{self, Write},
+        panic::PanicHookInfo,
+        path::PathBuf,
+        thread,
+    },
+};
+
+/// The process, panic, thread stacks and modules of a crash.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct CrashReport {
+    /// The crashed process.
+    pub process: ProcessInfo,
+    /// The panic, if the report is about one.
+    pub panic: Option<PanicInfo>,
+    /// The stacks of the threads, the crashed thread first.
+    pub threads: Vec<ThreadTrace>,
+    /// The modules mapped into the process, currently only known on Linux.
+    pub modules: Vec<ModuleInfo>,
+}
+
+/// The crashed process.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct ProcessInfo {
+    /// The process id.
+    pub id: u32,
+    /// The path of the executable.
+    pub executable: Option<PathBuf>,
+}
+
+/// A panic.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct PanicInfo {
+    /// The panic message.
+    pub message: String,
+    /// The file, line and column where the panic occurred.
+    pub location: Option<(String, u32, u32)>,
+}
+
+/// The stack of a thread.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct ThreadTrace {
+    /// The name of the thread.
+    pub name: Option<String>,
+    /// The id of the thread given by the operating system, if known.
+    pub id: Option<u32>,
+    /// Whether this thread crashed, e.g. panicked.
+    pub crashed: bool,
+    /// The frames, the most recent call first.
+    pub frames: Vec<ResolvedFrame>,
+}
+
+/// A file mapped into the address space of the process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ModuleInfo {
+    /// The first address of the mapping.
+    pub start: usize,
+    /// The address after the mapping.
+    pub end: usize,
+    /// The offset of the mapping in the file.
+    pub file_offset: usize,
+    /// The path of the file.
+    pub path: PathBuf,
+}
+
+impl CrashReport {
+    /// Captures a report of the calling thread, without panic.
+    ///
+    /// The stack is resolved according to the [current options].
+    ///
+    ///[current options]: ../struct.FormatOptions.html#method.current
+    #[inline(never)]
+    pub fn capture() -> Self {
+        let thread = capture_thread(Self::capture as *const () as usize);
+        Self::with_crashed_thread(thread, None)
+    }
+
+    /// Captures the report of a panic from inside the panic hook.
+    ///
+    /// The stack is resolved according to the [current options].
+    ///
+    ///[current options]: ../struct.FormatOptions.html#method.current
+    #[inline(never)]
+    pub fn from_panic(info: &PanicHookInfo) -> Self {
+        let thread = capture_thread(Self::from_panic as *const () as usize);
+        let panic = PanicInfo {
+            message: payload::describe(info.payload()),
+            location: info
+                .location()
+                .map(|loc| (loc.file().to_owned(), loc.line(), loc.column())),
+        };
+        Self::with_crashed_thread(thread, Some(panic))
+    }
+
+    /// Adds the stacks of the other threads of the process, see [`watchdog`].
+    ///
+    /// Threads which don't handle the signal within `timeout` are left out.
+    ///
+    ///[`watchdog`]: ../watchdog/index.html
+    #[cfg(target_os = "linux")]
+    pub fn capture_other_threads(&mut self, timeout: std::time::Duration) -> std::io::Result<()> {
+        use crate::watchdog;
+
+        let options = FormatOptions::current();
+        let current = watchdog::current_thread_id();
+        for id in watchdog::thread_ids()? {
+            if id == current {
+                continue;
+            }
+            if let Ok(frames) = watchdog::capture_frames(id, timeout, &options) {
+                let name = std::fs::read_to_string(format!("/proc/self/task/{}/comm", id)).ok();
+                self.threads.push(ThreadTrace {
+                    name: name.map(|name| name.trim_end().to_owned()),
+                    id: Some(id),
+                    crashed: false,
+                    frames,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn with_crashed_thread(thread: ThreadTrace, panic: Option<PanicInfo>) -> Self {
+        CrashReport {
+            process: ProcessInfo {
+                id: std::process::id(),
+                executable: std::env::current_exe().ok(),
+            },
+            panic,
+            threads: vec![thread],
+            modules: module::load()
+                .into_iter()
+                .map(|module| ModuleInfo {
+                    start: module.start,
+                    end: module.end,
+                    file_offset: module.file_offset,
+                    path: module.path,
+                })
+                .collect(),
+        }
+    }
+
+    /// Renders the report with the given renderer.
+    pub fn render(&self, renderer: &impl Render) -> String {
+        let mut out = String::new();
+        // Writing into a `String` doesn't fail.
+        let _ = renderer.render(self, &mut out);
+        out
+    }
+
+    /// Renders the report with the [`TextRenderer`].
+    ///
+    ///[`TextRenderer`]: struct.TextRenderer.html
+    pub fn to_text(&self, options: &FormatOptions) -> String {
+        self.render(&TextRenderer::new(options.clone()))
+    }
+
+    /// Renders the report with the [`JsonRenderer`].
+    ///
+    ///[`JsonRenderer`]: struct.JsonRenderer.html
+    pub fn to_json(&self, options: &FormatOptions) -> String {
+        self.render(&JsonRenderer::new(options.clone()))
+    }
+
+    /// Renders the report with the [`HtmlRenderer`].
+    ///
+    ///[`HtmlRenderer`]: struct.HtmlRenderer.html
+    pub fn to_html(&self, options: &FormatOptions) -> String {
+        self.render(&HtmlRenderer::new(options.clone()))
+    }
+
+    /// Returns the header line of a thread in the text, e.g. `thread 'main' panicked at ...`.
+    pub fn thread_header(&self, thread: &ThreadTrace, options: &FormatOptions) -> String {
+        let vocabulary = &options.vocabulary;
+        let mut header = format!("thread '{}'", thread.name.as_deref().unwrap_or("<unnamed>"));
+        if let (Some(panic), true) = (&self.panic, thread.crashed) {
+            write!(header, " {}", vocabulary.panicked).unwrap();
+            if let Some((file, line, column)) = &panic.location {
+                write!(header, " {} {}:{}:{}", vocabulary.at, file, line, column).unwrap();
+            }
+            write!(header, ": {}", panic.message).unwrap();
+        }
+        header
+    }
+
+    /// Formats the backtrace of a thread like [`format_frame_source()`], marking the origin of
+    /// the panic in the crashed thread.
+    ///
+    ///[`format_frame_source()`]: ../fn.format_frame_source.html
+    pub fn thread_backtrace(&self, thread: &ThreadTrace, options: &FormatOptions) -> String {
+        format_frame_source(&thread.frames, &self.thread_options(thread, options))
+    }
+
+    /// Formats the backtraces of the threads like [`thread_backtrace()`], showing the trailing
+    /// frames a thread shares with one before it, like the runtime startup, only once.
+    ///
+    ///[`thread_backtrace()`]: #method.thread_backtrace
+    fn thread_backtraces(&self, options: &FormatOptions) -> Vec<String> {
+        let frames = self
+            .threads
+            .iter()
+            .map(|thread| filter_frames(&thread.frames, options).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let visible = self
+            .threads
+            .iter()
+            .map(|thread| thread.shown_frames(options).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let shared = shared_suffixes(visible.iter().map(|frames| &frames[..]));
+        self.threads
+            .iter()
+            .zip(frames.iter().zip(shared))
+            .map(|(thread, (frames, shared))| {
+                let shared = shared.map(|(count, other)| {
+                    let name = self.threads[other].name.as_deref();
+                    (count, format!("thread '{}'", name.unwrap_or("<unnamed>")))
+                });
+                let shared = shared.as_ref().map(|(count, other)| (*count, &other[..]));
+                format_elided(frames, shared, &self.thread_options(thread, options))
+            })
+            .collect()
+    }
+
+    /// Returns the `options` for the backtrace of `thread`, marking the origin of the panic in
+    /// the crashed thread.
+    fn thread_options<'a>(
+        &self,
+        thread: &ThreadTrace,
+        options: &'a FormatOptions,
+    ) -> Cow<'a, FormatOptions> {
+        let location = self
+            .panic
+            .as_ref()
+            .and_then(|panic| panic.location.as_ref());
+        match location {
+            Some((file, line, _)) if thread.crashed => {
+                Cow::Owned(options.clone().panic_location(file, *line))
+            }
+            _ => Cow::Borrowed(options),
+        }
+    }
+}
+
+impl ThreadTrace {
+    /// Returns the frames shown with the `options`, trimmed and without the hidden ones.
+    pub fn shown_frames<'a>(
+        &'a self,
+        options: &'a FormatOptions,
+    ) -> impl Iterator<Item = &'a ResolvedFrame> + 'a {
+        filter_frames(&self.frames, options).filter(move |frame| options.is_visible(frame))
+    }
+
+    /// Returns the most recent frame for which `predicate` returns `true`.
+    pub fn find_frame(
+        &self,
+        mut predicate: impl FnMut(&ResolvedFrame) -> bool,
+    ) -> Option<&ResolvedFrame> {
+        self.frames.iter().find(|frame| predicate(frame))
+    }
+
+    /// Returns the index of the most recent frame with a symbol in the function or module
+    /// `symbol`, e.g. `my_app::handler` matches `my_app::handler` and its closures, but not
+    /// `my_app::handler_two`.
+    ///
+    /// The names are compared demangled and without the hashes rustc appends.
+    pub fn position_of_symbol(&self, symbol: &str) -> Option<usize> {
+        self.frames.iter().position(|frame| {
+            frame.symbols().any(|sym| match sym.name_lossy() {
+                Some(name) => {
+                    let name = format!("{:#}", demangle(&name));
+                    let name = normalize_symbol(&name);
+                    match name.strip_prefix(symbol) {
+                        Some(rest) => rest.is_empty() || rest.starts_with("::"),
+                        None => false,
+                    }
+                }
+                None => false,
+            })
+        })
+    }
+
+    /// Removes the frames above the [frame of `symbol`], the more recent calls, so it becomes the
+    /// first frame. Returns whether the symbol was found, nothing is removed otherwise.
+    ///
+    ///[frame of `symbol`]: #method.position_of_symbol
+    pub fn trim_above(&mut self, symbol: &str) -> bool {
+        match self.position_of_symbol(symbol) {
+            Some(index) => {
+                self.frames.drain(..index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the frames below the [frame of `symbol`], its callers, so it becomes the last
+    /// frame. Returns whether the symbol was found, nothing is removed otherwise.
+    ///
+    ///[frame of `symbol`]: #method.position_of_symbol
+    pub fn trim_below(&mut self, symbol: &str) -> bool {
+        match self.position_of_symbol(symbol) {
+            Some(index) => {
+                self.frames.truncate(index + 1);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Returns the name of a symbol as shown with the `options`, demangled according to them.
+pub fn symbol_name(name: &str, options: &FormatOptions) -> String {
+    SymbolName {
+        name,
+        demangling: options.demangling,
+    }
+    .to_string()
+}
+
+/// A format of crash reports.
+///
+/// Custom formats build on the helpers of [`CrashReport`] and [`ThreadTrace`], which trim,
+/// hide, demangle and format the frames like the built-in renderers:
+///
+/// ```
+/// use {
+///     backtrace_string::{
+///         report::{symbol_name, CrashReport, Render},
+///         FormatOptions,
+///     },
+///     std::fmt::{self, Write},
+/// };
+///
+/// /// One line per frame, the innermost symbol only.
+/// struct Oneline(FormatOptions);
+///
+/// impl Render for Oneline {
+///     fn render(&self, report: &CrashReport, out: &mut dyn Write) -> fmt::Result {
+///         for thread in &report.threads {
+///             write!(out, "{}:", report.thread_header(thread, &self.0))?;
+///             for frame in thread.shown_frames(&self.0) {
+///                 let name = frame.symbols.first().and_then(|symbol| symbol.name.as_deref());
+///                 write!(out, " {}", symbol_name(name.unwrap_or("?"), &self.0))?;
+///             }
+///             writeln!(out)?;
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// let report = CrashReport::capture();
+/// println!("{}", report.render(&Oneline(FormatOptions::new())));
+/// ```
+///
+///[`CrashReport`]: struct.CrashReport.html
+///[`ThreadTrace`]: struct.ThreadTrace.html
+pub trait Render {
+    /// Writes the rendered `report` to `out`.
+    fn render(&self, report: &CrashReport, out: &mut dyn Write) -> fmt::Result;
+}
+
+/// Renders reports as text, like the panic reports of this crate.
+///
+/// The trailing frames a thread shares with one before it, e.g. the runtime startup, are
+/// replaced by a line like `(+ same 7 bottom frames as thread 'main')`. The modules aren't
+/// included.
+#[derive(Debug, Clone, Default)]
+pub struct TextRenderer {
+    options: FormatOptions,
+}
+
+impl TextRenderer {
+    /// Creates a renderer formatting according to the `options`.
+    pub fn new(options: FormatOptions) -> Self {
+        TextRenderer { options }
+    }
+}
+
+impl Render for TextRenderer {
+    fn render(&self, report: &CrashReport, out: &mut dyn Write) -> fmt::Result {
+        let options = &self.options;
+        let painter = options.painter();
+        let backtraces = report.thread_backtraces(options);
+        for (thread, backtrace) in report.threads.iter().zip(backtraces) {
+            writeln!(
+                out,
+                "{}",
+                painter.header(report.thread_header(thread, options))
+            )?;
+            write!(out, "{}:", options.vocabulary.backtrace)?;
+            out.write_str(&backtrace)?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders reports as a JSON object.
+///
+/// The frames are trimmed and hidden and their symbols demangled like in the text. Addresses
+/// are strings of hexadecimal numbers, as they may exceed the numbers JSON parsers support.
+#[derive(Debug, Clone, Default)]
+pub struct JsonRenderer {
+    options: FormatOptions,
+}
+
+impl JsonRenderer {
+    /// Creates a renderer trimming, hiding and demangling the frames according to the `options`.
+    pub fn new(options: FormatOptions) -> Self {
+        JsonRenderer { options }
+    }
+}
+
+impl Render for JsonRenderer {
+    fn render(&self, report: &CrashReport, out: &mut dyn Write) -> fmt::Result {
+        let options = &self.options;
+        write!(
+            out,
+            "{{\"process\":{{\"id\":{},\"executable\":",
+            report.process.id
+        )?;
+        let executable = report.process.executable.as_ref();
+        json_option(out, executable.map(|path| path.to_string_lossy()))?;
+        out.write_str("},\"panic\":")?;
+        match &report.panic {
+            Some(panic) => {
+                out.write_str("{\"message\":")?;
+                json_string(out, &panic.message)?;
+                out.write_str(",\"location\":")?;
+                match &panic.location {
+                    Some((file, line, column)) => {
+                        out.write_str("{\"file\":")?;
+                        json_string(out, file)?;
+                        write!(out, ",\"line\":{},\"column\":{}}}", line, column)?;
+                    }
+                    None => out.write_str("null")?,
+                }
+                out.write_char('}')?;
+            }
+            None => out.write_str("null")?,
+        }
+
+        out.write_str(",\"threads\":[")?;
+        for (i, thread) in report.threads.iter().enumerate() {
+            if i > 0 {
+                out.write_char(',')?;
+            }
+            out.write_str("{\"name\":")?;
+            json_option(out, thread.name.as_deref())?;
+            out.write_str(",\"id\":")?;
+            match thread.id {
+                Some(id) => write!(out, "{}", id)?,
+                None => out.write_str("null")?,
+            }
+            write!(out, ",\"crashed\":{},\"frames\":[", thread.crashed)?;
+            for (i, frame) in thread.shown_frames(options).enumerate() {
+                if i > 0 {
+                    out.write_char(',')?;
+                }
+                write!(out, "{{\"ip\":\"{:#x}\",\"symbols\":[", frame.ip)?;
+                for (i, symbol) in frame.symbols().enumerate() {
+                    if i > 0 {
+                        out.write_char(',')?;
+                    }
+                    out.write_str("{\"name\":")?;
+                    let name = symbol.name_lossy();
+                    json_option(out, name.map(|name| symbol_name(&name, options)))?;
+                    out.write_str(",\"file\":")?;
+                    json_option(out, symbol.filename.map(|path| path.to_string_lossy()))?;
+                    out.write_str(",\"line\":")?;
+                    match symbol.lineno {
+                        Some(line) => write!(out, "{}", line)?,
+                        None => out.write_str("null")?,
+                    }
+                    out.write_char('}')?;
+                }
+                out.write_str("]}")?;
+            }
+            out.write_str("]}")?;
+        }
+
+        out.write_str("],\"modules\":[")?;
+        for (i, module) in report.modules.iter().enumerate() {
+            if i > 0 {
+                out.write_char(',')?;
+            }
+            out.write_str("{\"path\":")?;
+            json_string(out, &module.path.to_string_lossy())?;
+            write!(
+                out,
+                ",\"start\":\"{:#x}\",\"end\":\"{:#x}\",\"file_offset\":\"{:#x}\"}}",
+                module.start, module.end, module.file_offset
+            )?;
+        }
+        out.write_str("]}")
+    }
+}
+
+/// Renders reports as an HTML fragment, a `<section>` to embed into a page.
+///
+/// Each thread is a heading followed by the text of its backtrace, without colors, in a `<pre>`
+/// element.
+#[derive(Debug, Clone, Default)]
+pub struct HtmlRenderer {
+    options: FormatOptions,
+}
+
+impl HtmlRenderer {
+    /// Creates a renderer formatting the backtraces according to the `options`.
+    pub fn new(options: FormatOptions) -> Self {
+        HtmlRenderer {
+            options: options.color(ColorChoice::Never),
+        }
+    }
+}
+
+impl Render for HtmlRenderer {
+    fn render(&self, report: &CrashReport, out: &mut dyn Write) -> fmt::Result {
+        let options = &self.options;
+        out.write_str("<section class=\"crash-report\">\n")?;
+        if let Some(panic) = &report.panic {
+            out.write_str("<h1>")?;
+            html_escape(out, &panic.message)?;
+            out.write_str("</h1>\n")?;
+        }
+        let backtraces = report.thread_backtraces(options);
+        for (thread, backtrace) in report.threads.iter().zip(backtraces) {
+            let class = if thread.crashed {
+                " class=\"crashed\""
+            } else {
+                ""
+            };
+            write!(out, "<h2{}>", class)?;
+            html_escape(out, &report.thread_header(thread, options))?;
+            write!(out, "</h2>\n<pre>{}:", options.vocabulary.backtrace)?;
+            html_escape(out, &backtrace)?;
+            out.write_str("</pre>\n")?;
+        }
+        out.write_str("</section>\n")
+    }
+}
+
+/// Writes reports rendered in several formats, each to a sink of its own.
+///
+/// The report is captured and resolved once, only the rendering is done per output, e.g. text
+/// for stderr and JSON for a file collected by a log pipeline. Closures are sinks too, so an
+/// output can also go to a logging framework:
+///
+/// ```no_run
+/// use backtrace_string::{
+///     report::{CrashReport, FanOut, JsonRenderer, TextRenderer},
+///     sink::{FileSink, StderrSink},
+///     FormatOptions,
+/// };
+///
+/// let fan_out = FanOut::new()
+///     .output(TextRenderer::new(FormatOptions::current()), StderrSink)
+///     .output(JsonRenderer::new(FormatOptions::new()), FileSink::new("crashes.jsonl"));
+/// std::panic::set_hook(Box::new(move |info| fan_out.write(&CrashReport::from_panic(info))));
+/// ```
+#[derive(Default)]
+pub struct FanOut {
+    outputs: Vec<(Box<dyn Render + Send + Sync>, Box<dyn Sink>)>,
+}
+
+impl FanOut {
+    /// Creates a fan-out without outputs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an output writing reports rendered with `renderer` to `sink`.
+    pub fn output(
+        mut self,
+        renderer: impl Render + Send + Sync + 'static,
+        sink: impl Sink + 'static,
+    ) -> Self {
+        self.outputs.push((Box::new(renderer), Box::new(sink)));
+        self
+    }
+
+    /// Renders `report` for each output and writes it to the output's sink, in the order the
+    /// outputs were added.
+    pub fn write(&self, report: &CrashReport) {
+        let mut out = String::new();
+        for (renderer, sink) in &self.outputs {
+            out.clear();
+            // Writing into a `String` doesn't fail.
+            let _ = renderer.render(report, &mut out);
+            sink.write_report(&out);
+        }
+    }
+}
+
+impl fmt::Debug for FanOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FanOut")
+            .field("outputs", &self.outputs.len())
+            .finish()
+    }
+}
+
+/// Captures the stack of the calling thread below the function at `ip`.
+fn capture_thread(ip: usize) -> ThreadTrace {
+    let mut bt = capture_below(ip);
+    resolve(&mut bt, &FormatOptions::current());
+    let current = thread::current();
+    ThreadTrace {
+        name: current.name().map(str::to_owned),
+        id: current_thread_id(),
+        crashed: true,
+        frames: bt.frames().iter().map(ResolvedFrame::from).collect(),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn current_thread_id() -> Option<u32> {
+    Some(crate::watchdog::current_thread_id())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn current_thread_id() -> Option<u32> {
+    None
+}
+
+fn json_option(out: &mut dyn Write, value: Option<impl AsRef<str>>) -> fmt::Result {
+    match value {
+        Some(value) => json_string(out, value.as_ref()),
+        None => out.write_str("null"),
+    }
+}
+
+pub(crate) fn json_string(out: &mut dyn Write, s: &str) -> fmt::Result {
+    out.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            c if c < ' ' => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
+
+fn html_escape(out: &mut dyn Write, s: &str) -> fmt::Result {
+    for c in s.chars() {
+        match c {
+            '<' => out.write_str("&lt;")?,
+            '>' => out.write_str("&gt;")?,
+            '&' => out.write_str("&amp;")?,
+            '"' => out.write_str("&quot;")?,
+            c => out.write_char(c)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            CrashReport, FanOut, JsonRenderer, PanicInfo, ProcessInfo, TextRenderer, ThreadTrace,
+        },
+        crate::{
+            frame::{ResolvedFrame, ResolvedSymbol},
+            FormatOptions,
+        },
+    };
+
+    fn report() -> CrashReport {
+        let frame = |name: &str, line| ResolvedFrame {
+            ip: 0x1000,
+            symbols: vec![ResolvedSymbol {
+                name: Some(name.to_owned()),
+                filename: Some("src/main.rs".into()),
+                lineno: Some(line),
+                addr: None,
+            }],
+        };
+        CrashReport {
+            process: ProcessInfo {
+                id: 42,
+                executable: Some("/usr/bin/my_app".into()),
+            },
+            panic: Some(PanicInfo {
+                message: String::from("bad <input>"),
+                location: Some((String::from("src/main.rs"), 3, 5)),
+            }),
+            threads: vec![
+                ThreadTrace {
+                    name: Some(String::from("main")),
+                    id: Some(7),
+                    crashed: true,
+                    frames: vec![frame("my_app::parse", 3), frame("my_app::main", 9)],
+                },
+                ThreadTrace {
+                    name: None,
+                    id: None,
+                    crashed: false,
+                    frames: vec![frame("my_app::worker", 20)],
+                },
+            ],
+            modules: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn renders_text() {
+        assert_eq!(
+            report().to_text(&FormatOptions::new()),
+            "thread 'main' panicked at src/main.rs:3:5: bad <input>\n\
+             Backtrace:\n   \
+             0: my_app::parse\n          at src/main.rs:3 <-- panic origin\n   \
+             1: my_app::main\n          at src/main.rs:9\n\
+             thread '<unnamed>'\n\
+             Backtrace:\n   \
+             0: my_app::worker\n          at src/main.rs:20\n"
+        );
+    }
+
+    #[test]
+    fn shared_bottom_frames_are_shown_once() {
+        let mut report = report();
+        let main = report.threads[0].frames[1].clone();
+        report.threads[1].frames.push(main);
+        let text = report.to_text(&FormatOptions::new());
+        assert!(
+            text.ends_with(
+                "thread '<unnamed>'\n\
+                 Backtrace:\n   \
+                 0: my_app::worker\n          at src/main.rs:20\n      \
+                 (+ same 1 bottom frames as thread 'main')\n"
+            ),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn renders_json() {
+        let mut report = report();
+        report.threads.truncate(1);
+        report.threads[0].frames.truncate(1);
+        assert_eq!(
+            report.to_json(&FormatOptions::new()),
+            "{\"process\":{\"id\":42,\"executable\":\"/usr/bin/my_app\"},\
+             \"panic\":{\"message\":\"bad <input>\",\
+             \"location\":{\"file\":\"src/main.rs\",\"line\":3,\"column\":5}},\
+             \"threads\":[{\"name\":\"main\",\"id\":7,\"crashed\":true,\"frames\":[\
+             {\"ip\":\"0x1000\",\"symbols\":[\
+             {\"name\":\"my_app::parse\",\"file\":\"src/main.rs\",\"line\":3}]}]}],\
+             \"modules\":[]}"
+        );
+    }
+
+    #[test]
+    fn renders_html() {
+        let html = report().to_html(&FormatOptions::new());
+        assert!(html.starts_with("<section class=\"crash-report\">\n<h1>bad &lt;input&gt;</h1>\n"));
+        assert!(html.contains(
+            "<h2 class=\"crashed\">thread 'main' panicked at src/main.rs:3:5: bad &lt;input&gt;</h2>\n\
+             <pre>Backtrace:\n   0: my_app::parse\n"
+        ));
+        assert!(
+            html.contains("<h2>thread '&lt;unnamed&gt;'</h2>\n"),
+            "{}",
+            html
+        );
+    }
+
+    #[test]
+    fn search_and_trim_frames() {
+        let mut thread = report().threads.remove(0);
+        thread.frames.push(thread.frames[0].clone());
+        thread.frames[2].symbols[0].name = Some(String::from("my_app::parse_all::{{closure}}"));
+        assert_eq!(thread.position_of_symbol("my_app::parse"), Some(0));
+        assert_eq!(thread.position_of_symbol("my_app::parse_all"), Some(2));
+        assert_eq!(thread.position_of_symbol("my_app::pars"), None);
+        assert_eq!(
+            thread
+                .find_frame(|frame| frame.symbols[0].lineno == Some(9))
+                .map(|frame| frame.ip),
+            Some(0x1000)
+        );
+
+        let mut trimmed = thread.clone();
+        assert!(trimmed.trim_above("my_app::main"));
+        assert!(trimmed.trim_below("my_app::main"));
+        assert_eq!(trimmed.frames, thread.frames[1..2]);
+        assert!(!trimmed.trim_above("my_app::worker"));
+        assert_eq!(trimmed.frames.len(), 1);
+    }
+
+    #[test]
+    #[cfg(all(feature = "resolve", feature = "demangle"))]
+    fn captures_current_thread() {
+        let report = CrashReport::capture();
+        assert!(report.panic.is_none());
+        assert_eq!(report.process.id, std::process::id());
+        let text = report.to_text(&FormatOptions::new());
+        assert!(
+            text.contains("   0: backtrace_string::report::tests::captures_current_thread"),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn fans_out_to_each_format() {
+        use std::sync::{Arc, Mutex};
+
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let (text, json) = (written.clone(), written.clone());
+        let options = FormatOptions::new();
+        FanOut::new()
+            .output(TextRenderer::new(options.clone()), move |report: &str| {
+                text.lock().unwrap().push(report.to_owned())
+            })
+            .output(JsonRenderer::new(options.clone()), move |report: &str| {
+                json.lock().unwrap().push(report.to_owned())
+            })
+            .write(&report());
+        let written = written.lock().unwrap();
+        assert_eq!(
+            *written,
+            [report().to_text(&options), report().to_json(&options)]
+        );
+    }
+}
@@ -0,0 +1,240 @@
+//! Diagnostics of resolving the frames of a backtrace.
+//!
+//! A backtrace full of `<unknown>` frames may come from a binary built without debug info, a
+//! system library without symbols, or a bug in the resolver. [`format_with_report()`] formats
+//! a backtrace like [`format_backtrace_with()`] and tells them apart with a
+//! [`ResolutionReport`], counting the resolved frames and timing the resolution per module:
+//!
+//! ```
+//! use backtrace_string::{resolution::format_with_report, FormatOptions};
+//!
+//! let mut bt = backtrace::Backtrace::new_unresolved();
+//! let (formatted, report) = format_with_report(&mut bt, &FormatOptions::new());
+//! if report.resolved < report.frames {
+//!     eprintln!("{}", report);
+//! }
+//! ```
+//!
+//! The modules are found through `/proc/self/maps` or the dynamic linker, on platforms other
+//! than Linux, Android, the BSDs, illumos and QNX all frames are counted for a single unknown
+//! module.
+//!
+//![`format_with_report()`]: fn.format_with_report.html
+//![`format_backtrace_with()`]: ../fn.format_backtrace_with.html
+//![`ResolutionReport`]: struct.ResolutionReport.html
+
+use {
+    crate::{
+        format_frame_source,
+        frame::{Frame, ResolvedFrame},
+        module::Modules,
+        FormatOptions,
+    },
+    backtrace::Backtrace,
+    std::{
+        fmt,
+        path::{Path, PathBuf},
+        time::{Duration, Instant},
+    },
+};
+
+/// How the frames of a backtrace were resolved, see the [module docs].
+///
+/// It displays as a summary like
+/// `resolved 14 of 20 frames, without debug info: libc.so.6 (4 frames), my_app (2 frames)`.
+///
+///[module docs]: index.html
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ResolutionReport {
+    /// The number of captured frames, before any filtering.
+    pub frames: usize,
+    /// The number of frames with a symbol name.
+    pub resolved: usize,
+    /// The modules of the frames, in the order of their first frame.
+    pub modules: Vec<ModuleResolution>,
+    /// The time spent resolving all frames.
+    pub resolve_time: Duration,
+}
+
+/// How the frames of a single module were resolved.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ModuleResolution {
+    /// The path of the executable or shared library, `None` for frames outside of any known
+    /// module.
+    pub path: Option<PathBuf>,
+    /// The number of frames in the module.
+    pub frames: usize,
+    /// The number of frames with a symbol name.
+    pub resolved: usize,
+    /// Whether any symbol of the module has a source location, which needs debug info.
+    pub has_debug_info: bool,
+    /// The time spent resolving the frames of the module.
+    pub resolve_time: Duration,
+}
+
+impl ResolutionReport {
+    /// Returns the modules none of whose symbols has a source location.
+    pub fn modules_without_debug_info(&self) -> impl Iterator<Item = &ModuleResolution> {
+        self.modules.iter().filter(|module| !module.has_debug_info)
+    }
+}
+
+impl fmt::Display for ResolutionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "resolved {} of {} frames", self.resolved, self.frames)?;
+        for (i, module) in self.modules_without_debug_info().enumerate() {
+            let name = module.path.as_deref().map(|path| {
+                path.file_name()
+                    .unwrap_or(path.as_os_str())
+                    .to_string_lossy()
+            });
+            f.write_str(match i {
+                0 => ", without debug info: ",
+                _ => ", ",
+            })?;
+            match name {
+                Some(name) => write!(f, "{} ({} frames)", name, module.frames)?,
+                None => write!(f, "<unknown module> ({} frames)", module.frames)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats `bt` like [`format_backtrace_with()`], returning how its frames were resolved along
+/// with it.
+///
+/// Frames which are already resolved are counted but not timed. Unless disabled by the options
+/// or the `resolve` feature, the others are resolved one by one with the `backtrace` crate,
+/// which leaves `bt` itself unresolved.
+///
+///[`format_backtrace_with()`]: ../fn.format_backtrace_with.html
+pub fn format_with_report(
+    bt: &mut Backtrace,
+    options: &FormatOptions,
+) -> (String, ResolutionReport) {
+    if bt.frames().is_empty() {
+        return (
+            format!("\n{}\n", options.vocabulary.no_frames),
+            ResolutionReport::default(),
+        );
+    }
+    let modules = Modules::default();
+    let mut report = ResolutionReport {
+        frames: bt.frames().len(),
+        ..ResolutionReport::default()
+    };
+    let start = Instant::now();
+    let frames = bt
+        .frames()
+        .iter()
+        .map(|frame| {
+            let path = modules.find(Frame::ip(frame)).map(|(path, _)| path);
+            let start = Instant::now();
+            let frame = resolve_frame(frame, options);
+            let module = module_entry(&mut report.modules, path);
+            module.resolve_time += start.elapsed();
+            module.frames += 1;
+            if frame.is_resolved() {
+                module.resolved += 1;
+                report.resolved += 1;
+            }
+            module.has_debug_info |= frame.symbols.iter().any(|symbol| symbol.filename.is_some());
+            frame
+        })
+        .collect::<Vec<_>>();
+    report.resolve_time = start.elapsed();
+    (format_frame_source(&frames, options), report)
+}
+
+/// Returns the symbols of `frame`, resolving it if needed.
+#[cfg(feature = "resolve")]
+fn resolve_frame(frame: &backtrace::BacktraceFrame, options: &FormatOptions) -> ResolvedFrame {
+    let mut resolved = ResolvedFrame::from(frame);
+    if !resolved.is_resolved() && !options.no_resolve {
+        resolved.symbols = crate::frame::resolve_symbols(resolved.ip);
+    }
+    resolved
+}
+
+#[cfg(not(feature = "resolve"))]
+fn resolve_frame(frame: &backtrace::BacktraceFrame, _options: &FormatOptions) -> ResolvedFrame {
+    ResolvedFrame::from(frame)
+}
+
+/// Returns the entry of the module at `path`, adding it if it is new.
+fn module_entry<'a>(
+    modules: &'a mut Vec<ModuleResolution>,
+    path: Option<&Path>,
+) -> &'a mut ModuleResolution {
+    let index = match modules
+        .iter()
+        .position(|module| module.path.as_deref() == path)
+    {
+        Some(index) => index,
+        None => {
+            modules.push(ModuleResolution {
+                path: path.map(Path::to_owned),
+                ..ModuleResolution::default()
+            });
+            modules.len() - 1
+        }
+    };
+    &mut modules[index]
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{ModuleResolution, ResolutionReport},
+        std::path::PathBuf,
+    };
+
+    #[test]
+    #[cfg(all(feature = "resolve", feature = "demangle"))]
+    fn reports_resolved_frames() {
+        use {super::format_with_report, crate::FormatOptions, backtrace::Backtrace};
+
+        let mut bt = Backtrace::new_unresolved();
+        let (formatted, report) = format_with_report(&mut bt, &FormatOptions::new());
+        assert!(
+            formatted.contains("reports_resolved_frames"),
+            "{}",
+            formatted
+        );
+        assert_eq!(report.frames, bt.frames().len());
+        assert!(report.resolved > 0, "{}", report);
+        let frames = report
+            .modules
+            .iter()
+            .map(|module| module.frames)
+            .sum::<usize>();
+        assert_eq!(frames, report.frames);
+    }
+
+    #[test]
+    fn names_modules_without_debug_info() {
+        let module = |path: &str, frames, has_debug_info| ModuleResolution {
+            path: Some(PathBuf::from(path)),
+            frames,
+            resolved: frames,
+            has_debug_info,
+            ..ModuleResolution::default()
+        };
+        let report = ResolutionReport {
+            frames: 20,
+            resolved: 14,
+            modules: vec![
+                module("/usr/lib/libc.so.6", 4, false),
+                module("/opt/my_app", 16, true),
+            ],
+            ..ResolutionReport::default()
+        };
+        assert_eq!(
+            report.to_string(),
+            "resolved 14 of 20 frames, without debug info: libc.so.6 (4 frames)"
+        );
+    }
+}
@@ -0,0 +1,306 @@
+//! A sampling profiler built on the formatter, for finding out where threads spend their time.
+//!
+//! A [`Sampler`] captures stacks, trims and hides their frames like the formatted backtraces and
+//! counts identical stacks by their [fingerprint]. The counts are reported as folded stacks, the
+//! input of flame graph tools, or as the formatted backtraces of the most frequent stacks:
+//!
+//! ```
+//! use backtrace_string::{sampler::Sampler, FormatOptions};
+//!
+//! let mut sampler = Sampler::new(FormatOptions::new());
+//! for _ in 0..3 {
+//!     sampler.sample_current();
+//! }
+//! assert_eq!(sampler.samples(), 3);
+//! println!("{}", sampler.folded());
+//! ```
+//!
+//! On Linux the sampler can also run on a thread of its own with [`start()`], sampling other
+//! threads through the [watchdog](../watchdog/index.html) capture.
+//!
+//![`Sampler`]: struct.Sampler.html
+//![fingerprint]: ../struct.Fingerprint.html
+//![`start()`]: struct.Sampler.html#method.start
+
+#[cfg(target_os = "linux")]
+use {
+    crate::watchdog,
+    std::{
+        io,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread::{self, JoinHandle},
+        time::Duration,
+    },
+};
+use {
+    crate::{
+        capture_below,
+        demangle::SymbolName,
+        filter_frames, format_frames,
+        frame::{Frame, ResolvedFrame},
+        resolve, Fingerprint, FormatOptions,
+    },
+    std::{collections::HashMap, fmt::Write},
+};
+
+/// How long a single capture of another thread may take.
+#[cfg(target_os = "linux")]
+const CAPTURE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Stacks counted by their fingerprint.
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    options: FormatOptions,
+    stacks: HashMap<Fingerprint, SampledStack>,
+    samples: usize,
+}
+
+/// The frames of a stack and how often it was sampled.
+#[derive(Debug, Clone)]
+struct SampledStack {
+    frames: Vec<ResolvedFrame>,
+    count: usize,
+}
+
+impl Sampler {
+    /// Creates a sampler trimming, hiding and naming the frames according to the `options`.
+    pub fn new(options: FormatOptions) -> Self {
+        Sampler {
+            options,
+            stacks: HashMap::new(),
+            samples: 0,
+        }
+    }
+
+    /// Samples the stack below the caller.
+    #[inline(never)]
+    pub fn sample_current(&mut self) {
+        let mut bt = capture_below(Self::sample_current as *const () as usize);
+        resolve(&mut bt, &self.options);
+        let frames = bt.frames().iter().map(ResolvedFrame::from).collect();
+        self.record(frames);
+    }
+
+    /// Samples the stack of the thread `id` of this process, see [`watchdog`].
+    ///
+    ///[`watchdog`]: ../watchdog/index.html
+    #[cfg(target_os = "linux")]
+    pub fn sample_thread(&mut self, id: u32) -> io::Result<()> {
+        let frames = watchdog::capture_frames(id, CAPTURE_TIMEOUT, &self.options)?;
+        self.record(frames);
+        Ok(())
+    }
+
+    /// Samples the stacks of all threads of this process but the calling one.
+    ///
+    /// Threads which exit or can't be captured meanwhile are left out.
+    #[cfg(target_os = "linux")]
+    pub fn sample_all(&mut self) -> io::Result<()> {
+        let current = watchdog::current_thread_id();
+        for id in watchdog::thread_ids()? {
+            if id != current {
+                let _ = self.sample_thread(id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Samples the threads every `interval` on a thread of its own until it is stopped.
+    ///
+    /// [`Threads::Current`] samples the calling thread.
+    ///
+    ///[`Threads::Current`]: enum.Threads.html#variant.Current
+    #[cfg(target_os = "linux")]
+    pub fn start(mut self, interval: Duration, threads: Threads) -> io::Result<RunningSampler> {
+        let target = match threads {
+            Threads::Current => Some(watchdog::current_thread_id()),
+            Threads::All => None,
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = Arc::clone(&stop);
+            thread::Builder::new()
+                .name(String::from("backtrace-sampler"))
+                .spawn(move || {
+                    while !stop.load(Ordering::Acquire) {
+                        let _ = match target {
+                            Some(id) => self.sample_thread(id),
+                            None => self.sample_all(),
+                        };
+                        thread::sleep(interval);
+                    }
+                    self
+                })?
+        };
+        Ok(RunningSampler { stop, thread })
+    }
+
+    /// Returns the number of samples taken.
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    /// Formats the stacks in the folded format of flame graph tools.
+    ///
+    /// Each line holds the symbol names of a stack, the outermost function first and separated
+    /// by `;`, followed by the number of its samples. The lines are sorted.
+    pub fn folded(&self) -> String {
+        let mut lines = self
+            .stacks
+            .values()
+            .map(|stack| {
+                let mut line = String::new();
+                for symbol in stack
+                    .frames
+                    .iter()
+                    .rev()
+                    .flat_map(|frame| frame.symbols().collect::<Vec<_>>().into_iter().rev())
+                {
+                    if !line.is_empty() {
+                        line.push(';');
+                    }
+                    match symbol.name_lossy() {
+                        Some(name) => {
+                            let name = SymbolName {
+                                name: &name,
+                                demangling: self.options.demangling,
+                            };
+                            write!(line, "{}", name).unwrap();
+                        }
+                        None => line.push_str(&self.options.vocabulary.unknown),
+                    }
+                }
+                write!(line, " {}", stack.count).unwrap();
+                line
+            })
+            .collect::<Vec<_>>();
+        lines.sort();
+        lines.into_iter().map(|line| line + "\n").collect()
+    }
+
+    /// Formats the `n` most frequent stacks with their share of the samples.
+    pub fn top(&self, n: usize) -> String {
+        let mut stacks = self.stacks.iter().collect::<Vec<_>>();
+        stacks.sort_by(|(a_print, a), (b_print, b)| (b.count, a_print).cmp(&(a.count, b_print)));
+        let mut out = String::new();
+        for (fingerprint, stack) in stacks.into_iter().take(n) {
+            let share = stack.count as f64 * 100.0 / self.samples as f64;
+            write!(
+                out,
+                "{}: {}, {} samples ({:.1}%):",
+                self.options.vocabulary.fingerprint, fingerprint, stack.count, share
+            )
+            .unwrap();
+            out.push_str(&format_frames(&stack.frames, &self.options));
+        }
+        out
+    }
+
+    fn record(&mut self, frames: Vec<ResolvedFrame>) {
+        let options = &self.options;
+        let frames = filter_frames(&frames, options)
+            .filter(|frame| options.is_visible(frame))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.stacks
+            .entry(Fingerprint::from_frames(&frames))
+            .or_insert_with(|| SampledStack { frames, count: 0 })
+            .count += 1;
+        self.samples += 1;
+    }
+}
+
+/// The threads sampled by a [running sampler](struct.Sampler.html#method.start).
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Threads {
+    /// The thread starting the sampler.
+    Current,
+    /// All threads of the process but the sampler's own.
+    All,
+}
+
+/// A sampler running on a thread of its own.
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+pub struct RunningSampler {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<Sampler>,
+}
+
+#[cfg(target_os = "linux")]
+impl RunningSampler {
+    /// Stops sampling and returns the sampler with the samples taken.
+    pub fn stop(self) -> Sampler {
+        self.stop.store(true, Ordering::Release);
+        self.thread
+            .join()
+            .unwrap_or_else(|payload| std::panic::resume_unwind(payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::Sampler, crate::FormatOptions};
+
+    #[inline(never)]
+    fn sampled_function(sampler: &mut Sampler) {
+        sampler.sample_current();
+    }
+
+    #[test]
+    fn counts_identical_stacks() {
+        let mut sampler =
+            Sampler::new(FormatOptions::new().demangling(crate::Demangling::WithoutHashes));
+        for _ in 0..3 {
+            sampled_function(&mut sampler);
+        }
+        sampler.sample_current();
+        assert_eq!(sampler.samples(), 4);
+
+        let folded = sampler.folded();
+        let line = folded
+            .lines()
+            .find(|line| line.contains("sampled_function"))
+            .unwrap();
+        assert!(
+            line.ends_with(";backtrace_string::sampler::tests::sampled_function 3"),
+            "{}",
+            folded
+        );
+        assert_eq!(folded.lines().count(), 2);
+
+        let top = sampler.top(1);
+        assert!(top.starts_with("Fingerprint: "), "{}", top);
+        assert!(top.contains(", 3 samples (75.0%):\n"), "{}", top);
+        assert!(top.contains("   0: backtrace_string::sampler::tests::sampled_function"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn samples_running_thread() {
+        use {
+            super::Threads,
+            std::time::{Duration, Instant},
+        };
+
+        #[inline(never)]
+        fn busy() {
+            let start = Instant::now();
+            while start.elapsed() < Duration::from_millis(200) {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+
+        let running = Sampler::new(FormatOptions::new())
+            .start(Duration::from_millis(5), Threads::Current)
+            .unwrap();
+        busy();
+        let sampler = running.stop();
+        assert!(sampler.samples() > 0);
+        assert!(sampler.folded().contains("samples_running_thread::busy"));
+    }
+}
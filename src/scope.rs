@@ -0,0 +1,116 @@
+//! Collecting the panic reports of the threads and tasks a piece of code spawns.
+//!
+//! Orchestration code running workers wants to attach the reports of their panics to its own
+//! errors, not just find them in the logs. Threads spawned with [`thread::spawn_reported()`] and
+//! tasks spawned with [`tokio::spawn_reported()`] within [`ReportScope::run()`], also
+//! indirectly, add their reports to the scope, which returns them when it ends:
+//!
+//! ```
+//! use backtrace_string::{scope::ReportScope, thread::spawn_reported};
+//!
+//! let (result, reports) = ReportScope::run(|| {
+//!     spawn_reported("worker", || panic!("worker failed")).unwrap().join()
+//! });
+//! assert!(result.is_err());
+//! assert_eq!(reports.len(), 1);
+//! ```
+//!
+//! The reports are still written to the [sink] too. Reports of threads and tasks which finish
+//! after the scope ended are not returned, so they should be joined within it.
+//!
+//![`thread::spawn_reported()`]: ../thread/fn.spawn_reported.html
+//![`tokio::spawn_reported()`]: ../tokio/fn.spawn_reported.html
+//![`ReportScope::run()`]: struct.ReportScope.html#method.run
+//![sink]: ../sink/index.html
+
+use {
+    crate::sink,
+    std::{
+        cell::RefCell,
+        mem,
+        sync::{Arc, Mutex},
+    },
+};
+
+thread_local! {
+    static CURRENT: RefCell<Option<ReportScope>> = const { RefCell::new(None) };
+}
+
+/// A scope collecting the panic reports of the threads and tasks spawned within it, see the
+/// [module docs].
+///
+///[module docs]: index.html
+#[derive(Debug, Clone, Default)]
+pub struct ReportScope {
+    reports: Arc<Mutex<Vec<String>>>,
+}
+
+impl ReportScope {
+    /// Runs `func` in a new scope, returning its result and the reports collected meanwhile.
+    ///
+    /// Scopes nest, the reports go to the innermost one.
+    pub fn run<T>(func: impl FnOnce() -> T) -> (T, Vec<String>) {
+        let scope = ReportScope::default();
+        let result = scope.enter(func);
+        let mut reports = scope.reports.lock().unwrap_or_else(|err| err.into_inner());
+        (result, mem::take(&mut *reports))
+    }
+
+    /// Returns the scope of the current thread, to be entered by the threads and tasks it spawns.
+    pub(crate) fn current() -> Option<ReportScope> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Runs `func` with `self` as the scope of the current thread.
+    pub(crate) fn enter<T>(&self, func: impl FnOnce() -> T) -> T {
+        struct Restore(Option<ReportScope>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT.with(|current| *current.borrow_mut() = self.0.take());
+            }
+        }
+
+        let previous = CURRENT.with(|current| current.borrow_mut().replace(self.clone()));
+        let _restore = Restore(previous);
+        func()
+    }
+}
+
+/// Writes the panic report of a thread or task spawned in `scope` to the sink, and adds it to
+/// the scope.
+pub(crate) fn write_report(scope: Option<&ReportScope>, report: &str) {
+    if let Some(scope) = scope {
+        let mut reports = scope.reports.lock().unwrap_or_else(|err| err.into_inner());
+        reports.push(report.to_owned());
+    }
+    sink::write_report(report);
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::ReportScope,
+        crate::{sink::collect_reports, thread::spawn_reported},
+    };
+
+    #[test]
+    fn collects_the_reports_of_nested_threads() {
+        let reports = collect_reports(|| {
+            let ((), reports) = ReportScope::run(|| {
+                let outer = spawn_reported("outer", || {
+                    let inner = spawn_reported("inner", || panic!("inner failed")).unwrap();
+                    assert!(inner.join().is_err());
+                })
+                .unwrap();
+                assert!(outer.join().is_ok());
+            });
+            assert_eq!(reports.len(), 1);
+            assert!(reports[0].starts_with("thread 'inner' panicked at "));
+            assert!(reports[0].contains(": inner failed\n"), "{}", reports[0]);
+
+            let ((), reports) = ReportScope::run(|| ());
+            assert!(reports.is_empty());
+        });
+        assert_eq!(reports.len(), 1);
+    }
+}
@@ -0,0 +1,448 @@
+//! Destinations for backtrace reports produced by this crate.
+//!
+//! Reports which are created on behalf of the user (e.g. for panics in threads spawned with
+//! [`spawn_reported()`]) are written to the sink configured with [`set_sink()`], which defaults
+//! to stderr. [`StreamSink`] selects other standard streams or a file descriptor passed by a
+//! supervisor, also from configuration:
+//!
+//! ```
+//! use backtrace_string::sink::{set_sink, StreamSink};
+//!
+//! let sink = "stderr-flush".parse::<StreamSink>().unwrap();
+//! assert_eq!(sink, StreamSink::StderrFlushed);
+//! set_sink(sink);
+//! ```
+//!
+//! [`FileSink`] appends the reports to a log file instead, rotating it once it grows too large:
+//!
+//! ```no_run
+//! use backtrace_string::sink::{set_sink, FileSink};
+//!
+//! set_sink(FileSink::new("crashes.log").max_size(1 << 20).max_files(3));
+//! ```
+//!
+//![`spawn_reported()`]: ../thread/fn.spawn_reported.html
+//![`set_sink()`]: fn.set_sink.html
+//![`StreamSink`]: enum.StreamSink.html
+//![`FileSink`]: struct.FileSink.html
+
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    sync::{Mutex, RwLock},
+};
+#[cfg(unix)]
+use std::{fs::File, mem::ManuallyDrop, os::unix::io::FromRawFd};
+
+/// A destination for formatted reports.
+///
+/// This is implemented for all `Fn(&str)` closures which are `Send + Sync`.
+pub trait Sink: Send + Sync {
+    /// Writes a single, complete report.
+    fn write_report(&self, report: &str);
+}
+
+impl<F> Sink for F
+where
+    F: Fn(&str) + Send + Sync,
+{
+    fn write_report(&self, report: &str) {
+        self(report)
+    }
+}
+
+/// Writes reports to stderr, this is the default sink.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StderrSink;
+
+impl Sink for StderrSink {
+    fn write_report(&self, report: &str) {
+        // There is nowhere left to report a failure to.
+        let _ = write_line(&mut io::stderr().lock(), report);
+    }
+}
+
+/// Writes reports to the standard streams or a file descriptor.
+///
+/// It is parsed from `stderr`, `stderr-flush`, `stdout`, `both` and `fd:<number>` (e.g. `fd:3`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StreamSink {
+    /// Writes to stderr like [`StderrSink`].
+    ///
+    ///[`StderrSink`]: struct.StderrSink.html
+    Stderr,
+    /// Writes to stderr, then flushes stdout and stderr, so output the application buffered
+    /// isn't lost if the process exits right after the report.
+    StderrFlushed,
+    /// Writes to stdout and flushes it.
+    Stdout,
+    /// Writes to stdout and then to stderr.
+    Both,
+    /// Writes to an open file descriptor, e.g. one a supervisor passed for crash reports. The
+    /// descriptor isn't closed.
+    #[cfg(unix)]
+    Fd(i32),
+}
+
+impl Sink for StreamSink {
+    fn write_report(&self, report: &str) {
+        // There is nowhere left to report a failure to.
+        match *self {
+            StreamSink::Stderr => StderrSink.write_report(report),
+            StreamSink::StderrFlushed => {
+                StderrSink.write_report(report);
+                let _ = io::stdout().flush();
+                let _ = io::stderr().flush();
+            }
+            StreamSink::Stdout => {
+                let _ = write_line(&mut io::stdout().lock(), report);
+            }
+            StreamSink::Both => {
+                let _ = write_line(&mut io::stdout().lock(), report);
+                StderrSink.write_report(report);
+            }
+            #[cfg(unix)]
+            StreamSink::Fd(fd) => {
+                // SAFETY: The descriptor is owned by the application and never closed here.
+                let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+                let _ = write_line(&mut *file, report);
+            }
+        }
+    }
+}
+
+/// The buffer `write_line()` assembles reports in, kept to not allocate again on every panic.
+static LINE: Mutex<String> = Mutex::new(String::new());
+
+/// Writes `report` and a line break with a single write where possible, and flushes.
+///
+/// The reports of concurrently panicking threads are written one after another, so their lines
+/// don't interleave even with writers not taking the stream's lock, like the default panic hook.
+///
+/// The stream isn't locked with `flock` against other processes: the lock is advisory, so it
+/// would only keep out writers taking it too, which neither the default panic hook nor other
+/// programs sharing the terminal do. A single write to a pipe of up to `PIPE_BUF` bytes, or to a
+/// file opened for appending, isn't interleaved with the writes of other processes anyway.
+fn write_line(out: &mut dyn Write, report: &str) -> io::Result<()> {
+    let mut line = LINE.lock().unwrap_or_else(|err| err.into_inner());
+    line.clear();
+    line.push_str(report);
+    line.push('\n');
+    out.write_all(line.as_bytes())?;
+    out.flush()
+}
+
+impl fmt::Display for StreamSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamSink::Stderr => f.write_str("stderr"),
+            StreamSink::StderrFlushed => f.write_str("stderr-flush"),
+            StreamSink::Stdout => f.write_str("stdout"),
+            StreamSink::Both => f.write_str("both"),
+            #[cfg(unix)]
+            StreamSink::Fd(fd) => write!(f, "fd:{}", fd),
+        }
+    }
+}
+
+impl FromStr for StreamSink {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "stderr" => Ok(StreamSink::Stderr),
+            "stderr-flush" => Ok(StreamSink::StderrFlushed),
+            "stdout" => Ok(StreamSink::Stdout),
+            "both" => Ok(StreamSink::Both),
+            #[cfg(unix)]
+            _ => match value.strip_prefix("fd:").map(str::parse) {
+                Some(Ok(fd)) if fd >= 0 => Ok(StreamSink::Fd(fd)),
+                _ => Err(()),
+            },
+            #[cfg(not(unix))]
+            _ => Err(()),
+        }
+    }
+}
+
+/// Appends reports to a file, rotating it when it would grow beyond a maximum size.
+///
+/// Every report is preceded by a header line with its index (counting the reports of this sink,
+/// starting at 1) and the id of the process, e.g. `==== report 2 (pid 4242) ====`. Reports of
+/// concurrently panicking threads are serialized, and the file is opened in append mode and each
+/// report is written with a single write, so reports of several processes sharing the file
+/// aren't interleaved either. Rotation isn't coordinated between processes though.
+#[derive(Debug)]
+pub struct FileSink {
+    path: PathBuf,
+    max_size: Option<u64>,
+    max_files: usize,
+    written: Mutex<u64>,
+}
+
+impl FileSink {
+    /// Creates a sink appending to the file at `path`, which is created when the first report is
+    /// written. By default the file isn't rotated.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileSink {
+            path: path.into(),
+            max_size: None,
+            max_files: 1,
+            written: Mutex::new(0),
+        }
+    }
+
+    /// Sets the size in bytes the file may not grow beyond by appending a report. A larger file is
+    /// rotated before the report is written; a single report larger than this is still written.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Sets how many rotated files are kept next to the current one, 1 by default. They are named
+    /// after the file with `.1` (the most recent) to `.<n>` appended, the oldest one is deleted.
+    /// With 0 the full file is deleted instead of being rotated.
+    pub fn max_files(mut self, n: usize) -> Self {
+        self.max_files = n;
+        self
+    }
+
+    /// Returns the path of the file the reports are appended to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&self) -> io::Result<()> {
+        if self.max_files == 0 {
+            return fs::remove_file(&self.path);
+        }
+        // The oldest file is replaced by the rename, unless it is missing.
+        for n in (1..self.max_files).rev() {
+            match fs::rename(self.rotated_path(n), self.rotated_path(n + 1)) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))
+    }
+
+    fn append(&self, report: &str) -> io::Result<()> {
+        let mut written = self.written.lock().unwrap_or_else(|err| err.into_inner());
+        *written += 1;
+        let entry = format!(
+            "==== report {} (pid {}) ====\n{}\n",
+            *written,
+            process::id(),
+            report
+        );
+
+        if let Some(max_size) = self.max_size {
+            let len = match fs::metadata(&self.path) {
+                Ok(metadata) => metadata.len(),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+                Err(err) => return Err(err),
+            };
+            if len > 0 && len + entry.len() as u64 > max_size {
+                self.rotate()?;
+            }
+        }
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(entry.as_bytes())?;
+        file.flush()
+    }
+}
+
+impl Sink for FileSink {
+    fn write_report(&self, report: &str) {
+        // Fall back to stderr rather than losing the report.
+        if self.append(report).is_err() {
+            StderrSink.write_report(report);
+        }
+    }
+}
+
+static SINK: RwLock<Option<Box<dyn Sink>>> = RwLock::new(None);
+
+/// Sets the sink reports are written to, replacing the previous one.
+pub fn set_sink(sink: impl Sink + 'static) {
+    *SINK.write().unwrap_or_else(|err| err.into_inner()) = Some(Box::new(sink));
+}
+
+/// Writes `report` to the configured sink.
+pub(crate) fn write_report(report: &str) {
+    // Reports are often written while panicking, so a poisoned lock must not stop us.
+    let sink = SINK.read().unwrap_or_else(|err| err.into_inner());
+    match sink.as_ref() {
+        Some(sink) => sink.write_report(report),
+        None => StderrSink.write_report(report),
+    }
+}
+
+/// Runs `func` with a sink collecting the reports, which are returned afterwards.
+///
+/// Tests using this are serialized, as the sink is global.
+#[cfg(test)]
+pub(crate) fn collect_reports(func: impl FnOnce()) -> Vec<String> {
+    use std::sync::{Arc, Mutex};
+
+    static SERIALIZE: Mutex<()> = Mutex::new(());
+    let _guard = SERIALIZE.lock().unwrap_or_else(|err| err.into_inner());
+
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let reports2 = reports.clone();
+    set_sink(move |report: &str| reports2.lock().unwrap().push(report.to_owned()));
+    func();
+    *SINK.write().unwrap_or_else(|err| err.into_inner()) = None;
+
+    let mut reports = reports.lock().unwrap();
+    std::mem::take(&mut *reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{write_line, StreamSink},
+        std::{
+            io::{self, Write},
+            sync::{Arc, Mutex},
+            thread,
+        },
+    };
+
+    /// Writes at most a few bytes at a time to a shared output without serializing the callers,
+    /// like a stream written to without taking its lock.
+    #[derive(Clone, Default)]
+    struct Chunked(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Chunked {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let len = buf.len().min(7);
+            self.0.lock().unwrap().extend_from_slice(&buf[..len]);
+            // Lets the other threads write in between.
+            thread::yield_now();
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn concurrent_reports_are_not_interleaved() {
+        let report = |i: usize| format!("thread {} panicked\n{}", i, "frame\n".repeat(50));
+        let out = Chunked::default();
+        let threads = (0..8)
+            .map(|i| {
+                let mut out = out.clone();
+                thread::spawn(move || write_line(&mut out, &report(i)).unwrap())
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let out = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        let mut rest = &out[..];
+        let mut written = Vec::new();
+        while !rest.is_empty() {
+            let i = (0..8)
+                .find(|&i| rest.starts_with(&format!("{}\n", report(i))))
+                .unwrap_or_else(|| panic!("interleaved reports:\n{}", out));
+            rest = &rest[report(i).len() + 1..];
+            written.push(i);
+        }
+        written.sort_unstable();
+        assert_eq!(written, (0..8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn stream_sinks_parse_and_display() {
+        for name in ["stderr", "stderr-flush", "stdout", "both"] {
+            let sink = name.parse::<StreamSink>().unwrap();
+            assert_eq!(sink.to_string(), name);
+        }
+        assert!("stdin".parse::<StreamSink>().is_err());
+        assert!("fd:-1".parse::<StreamSink>().is_err());
+    }
+
+    #[test]
+    fn file_sink_rotates() {
+        use {
+            super::{FileSink, Sink},
+            std::{fs, process},
+        };
+
+        let dir =
+            std::env::temp_dir().join(format!("backtrace-string-file-sink-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let sink = FileSink::new(dir.join("crashes.log"))
+            .max_size(64)
+            .max_files(2);
+        for report in [
+            "first report",
+            "second report",
+            "third report",
+            "fourth report",
+        ] {
+            sink.write_report(report);
+        }
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        let (current, rotated, oldest) = (
+            read("crashes.log"),
+            read("crashes.log.1"),
+            read("crashes.log.2"),
+        );
+        assert!(!dir.join("crashes.log.3").exists());
+        fs::remove_dir_all(&dir).unwrap();
+
+        let pid = process::id();
+        assert_eq!(
+            current,
+            format!("==== report 4 (pid {}) ====\nfourth report\n", pid)
+        );
+        assert_eq!(
+            rotated,
+            format!("==== report 3 (pid {}) ====\nthird report\n", pid)
+        );
+        assert_eq!(
+            oldest,
+            format!("==== report 2 (pid {}) ====\nsecond report\n", pid)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_to_file_descriptor() {
+        use {
+            super::Sink,
+            std::{fs, os::unix::io::AsRawFd, process},
+        };
+
+        let path = std::env::temp_dir().join(format!("backtrace-string-sink-{}", process::id()));
+        let file = fs::File::create(&path).unwrap();
+        let sink = format!("fd:{}", file.as_raw_fd())
+            .parse::<StreamSink>()
+            .unwrap();
+        sink.write_report("report");
+        sink.write_report("another report");
+        drop(file);
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(written, "report\nanother report\n");
+    }
+}
@@ -0,0 +1,192 @@
+//! Measuring the stack of the current thread, to tell stack overflows from ordinary panics and
+//! faults.
+//!
+//! A panic deep in a recursion, or a crash right after it, may be a stack overflow in disguise.
+//! [`usage()`] measures the distance of the current stack pointer from the base of the thread's
+//! stack and compares it to the stack's size:
+//!
+//! ```
+//! if let Some(usage) = backtrace_string::stack::usage() {
+//!     eprintln!("{}", usage);
+//! }
+//! ```
+//!
+//! Reports of [`FormatOptions::stack_usage()`] include it below their header, measured in the
+//! panic hook.
+//!
+//! A fault in the guard below the stack is a stack overflow, which crash reports easily present
+//! as memory corruption. [`bounds()`] returns the ranges of the stack and of its guard for
+//! classifying fault addresses, e.g. in a `SIGSEGV` handler.
+//!
+//![`usage()`]: fn.usage.html
+//![`bounds()`]: fn.bounds.html
+//![`FormatOptions::stack_usage()`]: ../struct.FormatOptions.html#method.stack_usage
+
+use std::fmt;
+
+/// How much of its stack a thread uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct StackUsage {
+    /// The approximate number of bytes between the stack pointer and the base of the stack.
+    pub used: usize,
+    /// The size of the stack in bytes, for the main thread its size limit (`ulimit -s`).
+    pub size: usize,
+}
+
+impl fmt::Display for StackUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "stack usage: ~{} of {} bytes", self.used, self.size)
+    }
+}
+
+/// The address range of a thread's stack and of the guard pages below it.
+///
+/// Signal handlers can't look these up, as that may allocate or read `/proc/self/maps`. A
+/// handler for `SIGSEGV` takes them from before, e.g. stored per thread at its start, and uses
+/// [`is_overflow_fault()`] to tell a stack overflow from other invalid accesses.
+///
+///[`is_overflow_fault()`]: #method.is_overflow_fault
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct StackBounds {
+    /// The lowest address of the stack, which grows down towards it.
+    pub start: usize,
+    /// The address past the base of the stack.
+    pub end: usize,
+    /// The size of the guard below `start` in bytes, at least a page.
+    pub guard: usize,
+}
+
+impl StackBounds {
+    /// Returns whether a fault at `address` hit the guard below the stack, i.e. whether the
+    /// thread overflowed its stack.
+    ///
+    /// Users tend to read such faults as memory corruption, while they usually come from
+    /// unbounded recursion or large values on the stack.
+    pub fn is_overflow_fault(&self, address: usize) -> bool {
+        self.start.saturating_sub(self.guard) <= address && address < self.start
+    }
+}
+
+/// Returns the stack of the current thread, `None` where that isn't known.
+///
+/// Currently only Linux is supported. This is not async-signal-safe, see [`StackBounds`].
+///
+///[`StackBounds`]: struct.StackBounds.html
+#[cfg(target_os = "linux")]
+pub fn bounds() -> Option<StackBounds> {
+    // SAFETY: `pthread_getattr_np` initializes `attr`, which is destroyed after reading the
+    // stack from it.
+    let (start, size, guard) = unsafe {
+        let mut attr = std::mem::zeroed::<libc::pthread_attr_t>();
+        if libc::pthread_getattr_np(libc::pthread_self(), &mut attr) != 0 {
+            return None;
+        }
+        let mut start = std::ptr::null_mut();
+        let mut size = 0;
+        let mut guard = 0;
+        let result = libc::pthread_attr_getstack(&attr, &mut start, &mut size);
+        // The main thread has no guard of its own, the kernel keeps a gap below its stack.
+        libc::pthread_attr_getguardsize(&attr, &mut guard);
+        libc::pthread_attr_destroy(&mut attr);
+        if result != 0 {
+            return None;
+        }
+        (start as usize, size, guard)
+    };
+    // SAFETY: `sysconf` has no preconditions.
+    let page = match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        page if page > 0 => page as usize,
+        _ => 4096,
+    };
+    Some(StackBounds {
+        start,
+        end: start.checked_add(size)?,
+        guard: guard.max(page),
+    })
+}
+
+/// Returns the stack of the current thread, `None` where that isn't known.
+///
+/// Currently only Linux is supported.
+#[cfg(not(target_os = "linux"))]
+pub fn bounds() -> Option<StackBounds> {
+    None
+}
+
+/// Measures how much of its stack the current thread uses, `None` where that isn't known.
+///
+/// Currently only Linux is measured. On an alternate signal stack, as used by the stack
+/// overflow handler of the standard library, the stack pointer is outside of the thread's stack
+/// and the usage isn't known either.
+#[inline(never)]
+pub fn usage() -> Option<StackUsage> {
+    let marker = 0u8;
+    let sp = std::hint::black_box(&marker) as *const u8 as usize;
+    let bounds = bounds()?;
+    if bounds.start <= sp && sp < bounds.end {
+        Some(StackUsage {
+            used: bounds.end - sp,
+            size: bounds.end - bounds.start,
+        })
+    } else {
+        None
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use {
+        super::{bounds, usage},
+        crate::{hook::catch_captured, FormatOptions},
+        std::thread,
+    };
+
+    #[inline(never)]
+    fn usage_at_depth(depth: usize) -> usize {
+        let padding = std::hint::black_box([0u8; 1024]);
+        match depth {
+            0 => usage().expect("no stack usage").used,
+            _ => usage_at_depth(depth - 1) + usize::from(padding[0]),
+        }
+    }
+
+    #[test]
+    fn measures_the_stack_of_the_thread() {
+        let (shallow, deep, size) = thread::Builder::new()
+            .stack_size(1 << 20)
+            .spawn(|| (usage_at_depth(0), usage_at_depth(64), usage().unwrap().size))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert!(size >= 1 << 20, "{}", size);
+        assert!(0 < shallow && shallow < deep, "{} {}", shallow, deep);
+        assert!(deep - shallow >= 64 * 1024, "{} {}", shallow, deep);
+        assert!(deep < size, "{} {}", deep, size);
+    }
+
+    #[test]
+    fn panic_reports_show_the_stack_usage() {
+        let options = FormatOptions::new().stack_usage(true);
+        let (_, captured) = options
+            .clone()
+            .with_thread_override(|| catch_captured(|| panic!("too deep")).unwrap_err());
+        let report = captured.unwrap().into_report("test", &options);
+        let line = report.lines().nth(1).unwrap();
+        assert!(line.starts_with("stack usage: ~"), "{}", report);
+        assert!(line.ends_with(" bytes"), "{}", report);
+    }
+
+    #[test]
+    fn classifies_faults_in_the_guard_as_overflows() {
+        let bounds = bounds().unwrap();
+        assert!(bounds.is_overflow_fault(bounds.start - 1));
+        assert!(bounds.is_overflow_fault(bounds.start - bounds.guard));
+        assert!(!bounds.is_overflow_fault(bounds.start - bounds.guard - 1));
+        assert!(!bounds.is_overflow_fault(bounds.start));
+        let heap = Box::new(0u8);
+        assert!(!bounds.is_overflow_fault(&*heap as *const u8 as usize));
+        assert!(!bounds.is_overflow_fault(0));
+    }
+}
@@ -0,0 +1,133 @@
+//! Instrumentation of the cost of creating backtraces.
+//!
+//! ```
+//! backtrace_string::stats::on_stats(|stats| {
+//!     eprintln!("resolving {} frames took {:?}", stats.frames, stats.resolve_time);
+//! });
+//! ```
+
+use std::{
+    cell::Cell,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+/// Measurements of a single formatted backtrace.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CaptureStats {
+    /// The number of captured frames, before any filtering.
+    pub frames: usize,
+    /// The time spent capturing the backtrace, if it was captured by this crate.
+    pub capture_time: Option<Duration>,
+    /// The time spent resolving the symbols.
+    pub resolve_time: Duration,
+    /// The time spent formatting the resolved frames.
+    pub format_time: Duration,
+    /// The length of the formatted backtrace in bytes.
+    pub bytes: usize,
+    /// The number of symbol names demangled while formatting.
+    pub demangle_calls: usize,
+}
+
+type StatsCallback = Box<dyn Fn(&CaptureStats) + Send + Sync>;
+
+thread_local! {
+    static DEMANGLE_CALLS: Cell<usize> = const { Cell::new(0) };
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static CALLBACK: RwLock<Option<StatsCallback>> = RwLock::new(None);
+
+/// Sets the callback invoked with the [`CaptureStats`] of every formatted backtrace.
+///
+///[`CaptureStats`]: struct.CaptureStats.html
+pub fn on_stats(callback: impl Fn(&CaptureStats) + Send + Sync + 'static) {
+    *CALLBACK.write().unwrap_or_else(|err| err.into_inner()) = Some(Box::new(callback));
+    ENABLED.store(true, Ordering::Release);
+}
+
+/// Removes the callback set with [`on_stats()`](fn.on_stats.html).
+pub fn clear_on_stats() {
+    ENABLED.store(false, Ordering::Release);
+    *CALLBACK.write().unwrap_or_else(|err| err.into_inner()) = None;
+}
+
+/// Returns the number of symbol names demangled on the current thread so far.
+///
+/// Meant for benchmarks and regression tests, see `BENCH.md` in the repository.
+pub fn demangle_calls() -> usize {
+    DEMANGLE_CALLS.with(Cell::get)
+}
+
+/// Counts a demangled symbol name.
+pub(crate) fn count_demangle_call() {
+    DEMANGLE_CALLS.with(|calls| calls.set(calls.get() + 1));
+}
+
+/// Measures the durations of the steps if a callback is set.
+pub(crate) struct Timer {
+    last: Option<Instant>,
+}
+
+impl Timer {
+    pub(crate) fn start() -> Self {
+        Timer {
+            last: if ENABLED.load(Ordering::Acquire) {
+                Some(Instant::now())
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Returns the time since the start or the previous lap.
+    pub(crate) fn lap(&mut self) -> Duration {
+        match &mut self.last {
+            Some(last) => {
+                let now = Instant::now();
+                let elapsed = now - *last;
+                *last = now;
+                elapsed
+            }
+            None => Duration::default(),
+        }
+    }
+
+    pub(crate) fn is_running(&self) -> bool {
+        self.last.is_some()
+    }
+}
+
+/// Invokes the callback, if any.
+pub(crate) fn report(stats: &CaptureStats) {
+    if let Some(callback) = &*CALLBACK.read().unwrap_or_else(|err| err.into_inner()) {
+        callback(stats);
+    }
+}
+
+#[cfg(all(test, feature = "resolve", feature = "demangle"))]
+mod tests {
+    use {
+        super::{clear_on_stats, on_stats},
+        std::sync::{Arc, Mutex},
+    };
+
+    #[test]
+    fn stats_are_reported() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let reported2 = reported.clone();
+        on_stats(move |stats| reported2.lock().unwrap().push(*stats));
+
+        let bt = crate::create_backtrace();
+        clear_on_stats();
+
+        let reported = reported.lock().unwrap();
+        assert!(reported.iter().any(|stats| stats.capture_time.is_some()
+            && stats.bytes == bt.len()
+            && stats.demangle_calls > 0));
+    }
+}
@@ -0,0 +1,497 @@
+//! Reformatting the panics printed by the standard library, e.g. by child processes.
+//!
+//! Rust programs not using this crate print panics with the default hook of the standard library,
+//! so orchestrators running several of them get backtraces formatted in different ways.
+//! [`run_reformatted()`] runs a child process and rewrites the backtraces in its stderr with the
+//! trimming, hiding and path cleaning of this crate:
+//!
+//! ```no_run
+//! use {
+//!     backtrace_string::{std_trace, FormatOptions},
+//!     std::process::Command,
+//! };
+//!
+//! let output = std_trace::run_reformatted(&mut Command::new("my_worker"), &FormatOptions::new())?;
+//! eprint!("{}", String::from_utf8_lossy(&output.stderr));
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Text captured otherwise is rewritten with [`reformat()`], [`parse()`] returns the panics found
+//! in it, which convert to [`CrashReport`]s. Both the short backtraces of `RUST_BACKTRACE=1` and
+//! the full ones of `RUST_BACKTRACE=full` are understood, as well as the panic messages of Rust
+//! versions before 1.73 (`thread 'main' panicked at 'message', src/main.rs:2:5`). The hashes of
+//! full backtraces are left out, so both parse to the same frames apart from the addresses.
+//!
+//! Backtraces without a panic, like the ones of `std::backtrace::Backtrace` in logged errors, are
+//! parsed by [`parse_backtrace()`].
+//!
+//![`run_reformatted()`]: fn.run_reformatted.html
+//![`reformat()`]: fn.reformat.html
+//![`parse()`]: fn.parse.html
+//![`CrashReport`]: ../report/struct.CrashReport.html
+//![`parse_backtrace()`]: fn.parse_backtrace.html
+
+use {
+    crate::{
+        format_frame_source,
+        frame::{ResolvedFrame, ResolvedSymbol},
+        report::{CrashReport, PanicInfo, ThreadTrace},
+        FormatOptions,
+    },
+    std::{
+        io,
+        iter::Peekable,
+        ops::Range,
+        process::{Command, Output},
+    },
+};
+
+/// A file, line and column.
+type Location = (String, u32, u32);
+
+/// A panic printed by the standard library's panic hook.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct StdPanic {
+    /// The name of the panicking thread, `None` for unnamed threads.
+    pub thread: Option<String>,
+    /// The panic message.
+    pub message: String,
+    /// The file, line and column the panic originated from.
+    pub location: Option<(String, u32, u32)>,
+    /// The frames of the backtrace, the most recent call first, empty if none was printed.
+    pub frames: Vec<ResolvedFrame>,
+}
+
+impl StdPanic {
+    /// Converts the panic to a crash report with the panicking thread as the only one.
+    pub fn to_report(&self) -> CrashReport {
+        CrashReport {
+            panic: Some(PanicInfo {
+                message: self.message.clone(),
+                location: self.location.clone(),
+            }),
+            threads: vec![ThreadTrace {
+                name: self.thread.clone(),
+                crashed: true,
+                frames: self.frames.clone(),
+                ..ThreadTrace::default()
+            }],
+            ..CrashReport::default()
+        }
+    }
+}
+
+/// Runs `command`, waiting for it to exit, and returns its output with the backtraces in stderr
+/// reformatted.
+///
+/// Unless the command sets `RUST_BACKTRACE` itself, it is set to `1` so panics print a backtrace.
+pub fn run_reformatted(command: &mut Command, options: &FormatOptions) -> io::Result<Output> {
+    if !command.get_envs().any(|(key, _)| key == "RUST_BACKTRACE") {
+        command.env("RUST_BACKTRACE", "1");
+    }
+    let mut output = command.output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    output.stderr = reformat(&stderr, options).into_bytes();
+    Ok(output)
+}
+
+/// Returns `text` with the backtraces of panics formatted by this crate, the other lines are left
+/// as they are.
+pub fn reformat(text: &str, options: &FormatOptions) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    for (panic, backtrace) in scan(text) {
+        let backtrace = match backtrace {
+            Some(backtrace) => backtrace,
+            None => continue,
+        };
+        out.push_str(&text[copied..backtrace.start]);
+        out.push_str(&format_frame_source(&panic.frames, options));
+        copied = backtrace.end;
+    }
+    out.push_str(&text[copied..]);
+    out
+}
+
+/// Returns the panics in `text`, skipping the lines around them.
+pub fn parse(text: &str) -> Vec<StdPanic> {
+    scan(text).into_iter().map(|(panic, _)| panic).collect()
+}
+
+/// Returns the frames of the first backtrace in `text`, like the display format of
+/// `std::backtrace::Backtrace`, skipping the lines before it.
+pub fn parse_backtrace(text: &str) -> Vec<ResolvedFrame> {
+    let mut lines = lines_with_offsets(text).peekable();
+    while let Some((_, line)) = lines.peek() {
+        if parse_frame_line(line.trim_start()).is_some() {
+            break;
+        }
+        lines.next();
+    }
+    let mut frames = Vec::new();
+    parse_frames(&mut lines, &mut frames);
+    frames
+}
+
+/// Finds the panics and the byte ranges of their backtraces, from the line break ending the
+/// `stack backtrace:` line to the end of the last frame.
+fn scan(text: &str) -> Vec<(StdPanic, Option<Range<usize>>)> {
+    let mut panics = Vec::new();
+    let mut lines = lines_with_offsets(text).peekable();
+    while let Some((_, line)) = lines.next() {
+        let (mut panic, mut in_quotes) = match parse_panic_line(line) {
+            Some(panic) => panic,
+            None => continue,
+        };
+        // The quoted message of older Rust versions goes on until the line ending with the
+        // location.
+        while in_quotes {
+            let line = match lines.next() {
+                Some((_, line)) => line,
+                None => break,
+            };
+            panic.message.push('\n');
+            match split_quoted_message(line) {
+                Some((message, location)) => {
+                    panic.message.push_str(message);
+                    panic.location = location;
+                    in_quotes = false;
+                }
+                None => panic.message.push_str(line),
+            }
+        }
+        let mut backtrace = None;
+        while let Some(&(_, line)) = lines.peek() {
+            if parse_panic_line(line).is_some() || line.starts_with("note: ") {
+                break;
+            }
+            lines.next();
+            if line == "stack backtrace:" {
+                backtrace = parse_frames(&mut lines, &mut panic.frames);
+                break;
+            }
+            if !panic.message.is_empty() {
+                panic.message.push('\n');
+            }
+            panic.message.push_str(line);
+        }
+        // The line break of the `stack backtrace:` line stays, the rendered frames start with one.
+        let backtrace = backtrace.map(|range: Range<usize>| range.start - 1..range.end);
+        panics.push((panic, backtrace));
+    }
+    panics
+}
+
+/// Returns the lines of `text` without line breaks, with their byte ranges including them.
+fn lines_with_offsets(text: &str) -> impl Iterator<Item = (Range<usize>, &str)> {
+    text.split_inclusive('\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len();
+        Some((start..*offset, line.trim_end_matches(['\n', '\r'])))
+    })
+}
+
+/// Parses a line like `thread 'main' panicked at src/main.rs:2:5:`, or like
+/// `thread 'main' panicked at 'boom', src/main.rs:2:5` before Rust 1.73. Returns whether the
+/// quoted message of the latter goes on in the next lines.
+fn parse_panic_line(line: &str) -> Option<(StdPanic, bool)> {
+    let rest = line.strip_prefix("thread '")?;
+    let (thread, rest) = rest.split_once("' panicked at ")?;
+    let mut panic = StdPanic {
+        thread: match thread {
+            "<unnamed>" => None,
+            thread => Some(thread.to_owned()),
+        },
+        ..StdPanic::default()
+    };
+    if let Some(quoted) = rest.strip_prefix('\'') {
+        return Some(match split_quoted_message(quoted) {
+            Some((message, location)) => {
+                panic.message = message.to_owned();
+                panic.location = location;
+                (panic, false)
+            }
+            None => {
+                panic.message = quoted.to_owned();
+                (panic, true)
+            }
+        });
+    }
+    panic.location = panic_location(rest.strip_suffix(':')?);
+    Some((panic, false))
+}
+
+/// Splits the end of a quoted message like `boom', src/main.rs:2:5` from the location.
+fn split_quoted_message(text: &str) -> Option<(&str, Option<Location>)> {
+    let (message, location) = text.rsplit_once("', ")?;
+    parse_location(location)?;
+    Some((message, panic_location(location)))
+}
+
+fn panic_location(location: &str) -> Option<Location> {
+    match parse_location(location)? {
+        (file, line, Some(column)) => Some((file, line, column)),
+        // Rust versions before 1.42 left out the column.
+        (file, line, None) => Some((file, line, 0)),
+    }
+}
+
+/// Parses the frames after `stack backtrace:` into `frames`, returning the byte range of their
+/// lines.
+fn parse_frames<'a>(
+    lines: &mut Peekable<impl Iterator<Item = (Range<usize>, &'a str)>>,
+    frames: &mut Vec<ResolvedFrame>,
+) -> Option<Range<usize>> {
+    let mut range: Option<Range<usize>> = None;
+    while let Some((line_range, line)) = lines.peek().cloned() {
+        if !parse_backtrace_line(line, frames) {
+            break;
+        }
+        range = Some(range.map_or(line_range.clone(), |range| range.start..line_range.end));
+        lines.next();
+    }
+    range
+}
+
+/// Adds a line of a backtrace, printed by the standard library or this crate, to `frames`.
+/// Returns `false` if the line isn't part of the backtrace.
+pub(crate) fn parse_backtrace_line(line: &str, frames: &mut Vec<ResolvedFrame>) -> bool {
+    let trimmed = line.trim_start();
+    if let Some(location) = trimmed.strip_prefix("at ") {
+        // The location of the symbol before it.
+        let symbol = match frames.last_mut().and_then(|frame| frame.symbols.last_mut()) {
+            Some(symbol) if line.starts_with(' ') => symbol,
+            _ => return false,
+        };
+        if let Some((file, line, _)) = parse_location(location) {
+            symbol.filename = Some(file.into());
+            symbol.lineno = Some(line);
+        } else if let Some(address) = location.strip_prefix("address 0x") {
+            // The address of an unresolved frame formatted by this crate.
+            if let Ok(ip) = usize::from_str_radix(address, 16) {
+                frames.last_mut().unwrap().ip = ip;
+            }
+        }
+    } else if let Some(frame) = parse_frame_line(trimmed) {
+        frames.push(frame);
+    } else if trimmed.starts_with("[... omitted ") {
+        // Newer Rust versions mark the frames left out of short backtraces.
+    } else if line.starts_with("      ") && !trimmed.is_empty() && !frames.is_empty() {
+        // A function inlined into the one of the frame, printed without an index, in full
+        // backtraces after the padding of the address.
+        let name = trimmed.strip_prefix("- ").unwrap_or(trimmed);
+        frames.last_mut().unwrap().symbols.push(symbol(name));
+    } else {
+        return false;
+    }
+    true
+}
+
+/// Parses a frame like `2: my_app::main` or, in full backtraces,
+/// `2:     0x55d1c0a1b2c3 - my_app::main::h0123456789abcdef`.
+fn parse_frame_line(line: &str) -> Option<ResolvedFrame> {
+    let (index, rest) = line.split_once(": ")?;
+    if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let rest = rest.trim_start();
+    let address = rest
+        .split_once(" - ")
+        .and_then(|(address, name)| Some((address.strip_prefix("0x")?, name)))
+        .and_then(|(address, name)| Some((usize::from_str_radix(address, 16).ok()?, name)));
+    let (ip, name) = address.unwrap_or((0, rest));
+    Some(ResolvedFrame {
+        ip,
+        symbols: vec![symbol(name)],
+    })
+}
+
+fn symbol(name: &str) -> ResolvedSymbol {
+    ResolvedSymbol {
+        name: match name {
+            "<unknown>" => None,
+            name => Some(without_hash(name).to_owned()),
+        },
+        ..ResolvedSymbol::default()
+    }
+}
+
+/// Removes the hash rustc appends to legacy mangled names, e.g. `::h0123456789abcdef`.
+fn without_hash(name: &str) -> &str {
+    match name.rsplit_once("::") {
+        Some((path, hash))
+            if hash.len() == 17
+                && hash.starts_with('h')
+                && hash[1..].bytes().all(|b| b.is_ascii_hexdigit()) =>
+        {
+            path
+        }
+        _ => name,
+    }
+}
+
+/// Parses a location like `src/main.rs:2:5`, where the column is left out by older Rust versions.
+fn parse_location(location: &str) -> Option<(String, u32, Option<u32>)> {
+    let (rest, last) = location.rsplit_once(':')?;
+    let last = last.parse().ok()?;
+    match rest.rsplit_once(':') {
+        Some((file, line)) => match line.parse() {
+            Ok(line) => Some((file.to_owned(), line, Some(last))),
+            Err(_) => Some((rest.to_owned(), last, None)),
+        },
+        None => Some((rest.to_owned(), last, None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{parse, parse_backtrace, reformat},
+        crate::{FormatOptions, Locations},
+    };
+
+    const OUTPUT: &str = "\
+starting worker
+thread 'worker' panicked at src/worker.rs:10:9:
+index out of bounds
+stack backtrace:
+   0: rust_begin_unwind
+             at /rustc/0123456789abcdef/library/std/src/panicking.rs:652:5
+   1: core::panicking::panic_fmt
+             at /rustc/0123456789abcdef/library/core/src/panicking.rs:72:14
+   2: my_worker::process
+             at ./src/worker.rs:10:9
+      my_worker::run
+             at ./src/worker.rs:4:5
+   3: my_worker::main
+             at ./src/main.rs:3:5
+note: Some details are omitted, run with `RUST_BACKTRACE=full` for a verbose backtrace.
+shutting down
+";
+
+    #[test]
+    fn parse_std_panics() {
+        let panics = parse(OUTPUT);
+        assert_eq!(panics.len(), 1);
+        let panic = &panics[0];
+        assert_eq!(panic.thread.as_deref(), Some("worker"));
+        assert_eq!(panic.message, "index out of bounds");
+        assert_eq!(panic.location, Some((String::from("src/worker.rs"), 10, 9)));
+        assert_eq!(panic.frames.len(), 4);
+        let inlined = &panic.frames[2].symbols;
+        assert_eq!(inlined[1].name.as_deref(), Some("my_worker::run"));
+        assert_eq!(inlined[1].lineno, Some(4));
+
+        let panics = parse(
+            "thread '<unnamed>' panicked at src/lib.rs:1:1:\nfirst line\nsecond line\n\
+             note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace\n",
+        );
+        assert_eq!(panics[0].thread, None);
+        assert_eq!(panics[0].message, "first line\nsecond line");
+        assert!(panics[0].frames.is_empty());
+    }
+
+    #[test]
+    fn parse_full_and_old_formats() {
+        let full = parse(
+            "thread 'main' panicked at src/main.rs:2:5:\n\
+             boom\n\
+             stack backtrace:\n   \
+             0:     0x55d1c0a1b2c3 - std::backtrace_rs::backtrace::libunwind::trace::h0123456789abcdef\n                               \
+             at /rustc/0123456789abcdef/library/std/src/../../backtrace/src/backtrace/libunwind.rs:116:5\n  \
+             12:     0x55d1c0a1b000 - my_app::parse::h0123456789abcdef\n                               \
+             at ./src/main.rs:2:5\n                           \
+             - my_app::main\n                               \
+             at ./src/main.rs:7:5\n  \
+             13:     0x7f0000001234 - <unknown>\n",
+        );
+        let frames = &full[0].frames;
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[1].ip, 0x55d1_c0a1_b000);
+        let names = frames[1]
+            .symbols
+            .iter()
+            .map(|symbol| symbol.name.as_deref());
+        assert_eq!(
+            names.collect::<Vec<_>>(),
+            [Some("my_app::parse"), Some("my_app::main")]
+        );
+        assert_eq!(frames[1].symbols[1].lineno, Some(7));
+        assert_eq!(frames[2].symbols[0].name, None);
+
+        let old = parse(
+            "thread 'main' panicked at 'first line\n\
+             second line', src/main.rs:2:5\n\
+             stack backtrace:\n   \
+             0: my_app::main\n             \
+             at src/main.rs:2\n\
+             thread '<unnamed>' panicked at 'boom', src/lib.rs:3:1\n",
+        );
+        assert_eq!(old.len(), 2);
+        assert_eq!(old[0].message, "first line\nsecond line");
+        assert_eq!(old[0].location, Some((String::from("src/main.rs"), 2, 5)));
+        assert_eq!(old[0].frames[0].symbols[0].lineno, Some(2));
+        assert_eq!(old[1].message, "boom");
+
+        let report = old[0].to_report();
+        assert_eq!(report.panic.unwrap().message, "first line\nsecond line");
+        assert!(report.threads[0].crashed);
+        assert_eq!(report.threads[0].name.as_deref(), Some("main"));
+    }
+
+    #[test]
+    fn parse_bare_backtraces() {
+        let frames = parse_backtrace(
+            "Error: config missing\n\n\
+             Stack backtrace:\n   \
+             0: my_app::load\n             \
+             at ./src/config.rs:4:9\n      \
+             [... omitted 2 frames ...]\n   \
+             1: my_app::main\n",
+        );
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].symbols[0].name.as_deref(), Some("my_app::main"));
+    }
+
+    #[test]
+    fn reformat_std_panics() {
+        let options = FormatOptions::new().locations(Locations::Hidden);
+        assert_eq!(
+            reformat(OUTPUT, &options),
+            "starting worker\n\
+             thread 'worker' panicked at src/worker.rs:10:9:\n\
+             index out of bounds\n\
+             stack backtrace:\n   \
+             0: my_worker::process\n      \
+             my_worker::run\n   \
+             1: my_worker::main\n\
+             note: Some details are omitted, run with `RUST_BACKTRACE=full` for a verbose backtrace.\n\
+             shutting down\n"
+        );
+        let text = "no panic here\n";
+        assert_eq!(reformat(text, &options), text);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_reformatted() {
+        use std::process::Command;
+
+        let script = "printf '%s' \"$RUST_BACKTRACE\"; printf '%s' \"$OUTPUT\" >&2";
+        let output = super::run_reformatted(
+            Command::new("sh")
+                .args(["-c", script])
+                .env("OUTPUT", OUTPUT),
+            &FormatOptions::new().locations(Locations::Hidden),
+        )
+        .unwrap();
+        assert_eq!(output.stdout, b"1");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.contains("stack backtrace:\n   0: my_worker::process\n"),
+            "{}",
+            stderr
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! Rewriting the backtraces embedded in log streams.
+//!
+//! A [`StreamRewriter`] is fed a text stream line by line, e.g. in a log pipeline sidecar. Lines
+//! which aren't part of a backtrace pass through untouched, backtraces printed by the standard
+//! library or this crate are formatted with the given options once their last frame was read:
+//!
+//! ```no_run
+//! use {
+//!     backtrace_string::{stream::StreamRewriter, FormatOptions},
+//!     std::io,
+//! };
+//!
+//! let mut rewriter = StreamRewriter::new(FormatOptions::production());
+//! rewriter.rewrite(io::stdin().lock(), io::stdout().lock())?;
+//! # Ok::<(), io::Error>(())
+//! ```
+//!
+//! A backtrace starts with an indented frame 0 like `   0: my_app::main`, lines with prefixes
+//! like timestamps aren't recognized.
+//!
+//![`StreamRewriter`]: struct.StreamRewriter.html
+
+use {
+    crate::{
+        format_frame_source, frame::ResolvedFrame, std_trace::parse_backtrace_line, FormatOptions,
+    },
+    std::io::{self, BufRead, Write},
+};
+
+/// Rewrites the backtraces in a stream of lines, see the [module docs].
+///
+///[module docs]: index.html
+#[derive(Debug, Clone)]
+pub struct StreamRewriter {
+    options: FormatOptions,
+    /// The frames of the backtrace being read, empty between backtraces.
+    frames: Vec<ResolvedFrame>,
+}
+
+impl StreamRewriter {
+    /// Creates a rewriter formatting backtraces with the `options`.
+    pub fn new(options: FormatOptions) -> Self {
+        StreamRewriter {
+            options,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Feeds a line without its line break, appending the lines which are complete to `out`.
+    ///
+    /// The lines of a backtrace are held back until the first line after it.
+    pub fn push_line(&mut self, line: &str, out: &mut String) {
+        if !self.frames.is_empty() && parse_backtrace_line(line, &mut self.frames) {
+            return;
+        }
+        self.finish(out);
+        let trimmed = line.trim_start();
+        let starts_backtrace = trimmed.len() < line.len() && trimmed.starts_with("0: ");
+        if !(starts_backtrace && parse_backtrace_line(line, &mut self.frames)) {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+
+    /// Appends the backtrace held back, if any, to `out`, e.g. at the end of the stream.
+    pub fn finish(&mut self, out: &mut String) {
+        if self.frames.is_empty() {
+            return;
+        }
+        let formatted = format_frame_source(&self.frames, &self.options);
+        // The formatted frames start with a line break.
+        out.push_str(formatted.strip_prefix('\n').unwrap_or(&formatted));
+        self.frames.clear();
+    }
+
+    /// Rewrites the lines of `input` to `output` until the end of the input.
+    ///
+    /// The output is flushed whenever no backtrace is held back, so lines aren't delayed.
+    pub fn rewrite(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        let mut out = String::new();
+        for line in input.lines() {
+            let line = line?;
+            self.push_line(line.trim_end_matches('\r'), &mut out);
+            output.write_all(out.as_bytes())?;
+            out.clear();
+            if self.frames.is_empty() {
+                output.flush()?;
+            }
+        }
+        self.finish(&mut out);
+        output.write_all(out.as_bytes())?;
+        output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::StreamRewriter,
+        crate::{Demangling, FormatOptions, Locations},
+    };
+
+    #[test]
+    fn rewrites_embedded_backtraces() {
+        let options = FormatOptions::new()
+            .demangling(Demangling::WithoutHashes)
+            .locations(Locations::Hidden);
+        let input = "\
+INFO starting
+thread 'main' panicked at src/main.rs:2:5:
+boom
+stack backtrace:
+   0:     0x55d1c0a1b2c3 - my_app::parse::h0123456789abcdef
+                               at ./src/main.rs:2:5
+   1:     0x55d1c0a1b000 - my_app::main::h0123456789abcdef
+                               at ./src/main.rs:7:5
+note: Some details are omitted
+INFO 1: not a backtrace
+";
+        let mut out = Vec::new();
+        StreamRewriter::new(options)
+            .rewrite(input.as_bytes(), &mut out)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "INFO starting\n\
+             thread 'main' panicked at src/main.rs:2:5:\n\
+             boom\n\
+             stack backtrace:\n   \
+             0: my_app::parse\n   \
+             1: my_app::main\n\
+             note: Some details are omitted\n\
+             INFO 1: not a backtrace\n"
+        );
+    }
+
+    #[test]
+    fn backtraces_are_held_back_until_complete() {
+        let mut rewriter = StreamRewriter::new(FormatOptions::new().locations(Locations::Hidden));
+        let mut out = String::new();
+        rewriter.push_line("   0: my_app::main", &mut out);
+        assert_eq!(out, "");
+        rewriter.finish(&mut out);
+        assert_eq!(out, "   0: my_app::main\n");
+    }
+}
@@ -0,0 +1,146 @@
+//! The short fields of a backtrace monitoring systems want, e.g. for alert titles.
+
+use {
+    crate::{
+        app, demangle::SymbolName, filter_frames, frame::Frame, resolve, Demangling, Fingerprint,
+        FormatOptions,
+    },
+    backtrace::Backtrace,
+    std::{fmt, path::PathBuf},
+};
+
+/// The most relevant fields of a backtrace, without formatting all of it.
+///
+/// It displays as the top user symbol with the file name and line of its location, e.g.
+/// `my_app::orders::submit (orders.rs:412)`, or as `<unknown>` if there is no user symbol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TraceSummary {
+    /// The name of the most recent symbol of the application's code (see [`app`]), demangled
+    /// without hashes.
+    ///
+    ///[`app`]: app/index.html
+    pub top_user_symbol: Option<String>,
+    /// The file and line of the top user symbol, if known.
+    pub top_user_location: Option<(PathBuf, u32)>,
+    /// The number of shown frames.
+    pub total_frames: usize,
+    /// The fingerprint of the shown frames.
+    pub fingerprint: Fingerprint,
+}
+
+impl TraceSummary {
+    fn from_frames<'a, F: Frame + 'a>(frames: &[&'a F]) -> Self {
+        let mut summary = TraceSummary {
+            top_user_symbol: None,
+            top_user_location: None,
+            total_frames: frames.len(),
+            fingerprint: Fingerprint::from_frames(frames.iter().copied()),
+        };
+        let user_symbol = frames
+            .iter()
+            .flat_map(|frame| frame.symbols())
+            .find_map(|symbol| {
+                let name = symbol.name_lossy()?;
+                let name = SymbolName {
+                    name: &name,
+                    demangling: Demangling::WithoutHashes,
+                }
+                .to_string();
+                if app::is_dependency(&name, symbol.filename) {
+                    None
+                } else {
+                    Some((name, symbol))
+                }
+            });
+        if let Some((name, symbol)) = user_symbol {
+            summary.top_user_symbol = Some(name);
+            summary.top_user_location = symbol
+                .filename
+                .zip(symbol.lineno)
+                .map(|(file, line)| (file.to_owned(), line));
+        }
+        summary
+    }
+}
+
+impl fmt::Display for TraceSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match &self.top_user_symbol {
+            Some(symbol) => symbol,
+            None => return f.write_str("<unknown>"),
+        };
+        f.write_str(symbol)?;
+        if let Some((file, line)) = &self.top_user_location {
+            let name = file.file_name().unwrap_or(file.as_os_str());
+            write!(f, " ({}:{})", name.to_string_lossy(), line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Summarizes the frames [`format_backtrace_with()`] would format.
+///
+/// ```
+/// use backtrace_string::{summary, FormatOptions};
+///
+/// let summary = summary(&mut backtrace::Backtrace::new(), &FormatOptions::new());
+/// println!("panic in {}", summary);
+/// ```
+///
+///[`format_backtrace_with()`]: fn.format_backtrace_with.html
+pub fn summary(bt: &mut Backtrace, options: &FormatOptions) -> TraceSummary {
+    resolve(bt, options);
+    let frames = filter_frames(bt.frames(), options)
+        .filter(|frame| options.is_visible(frame))
+        .collect::<Vec<_>>();
+    TraceSummary::from_frames(&frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::TraceSummary,
+        crate::{
+            frame::{ResolvedFrame, ResolvedSymbol},
+            Fingerprint,
+        },
+        std::path::PathBuf,
+    };
+
+    #[test]
+    fn finds_top_user_frame() {
+        let frame = |name: &str, file: &str, line| ResolvedFrame {
+            ip: 0,
+            symbols: vec![ResolvedSymbol {
+                name: Some(name.to_owned()),
+                filename: Some(PathBuf::from(file)),
+                lineno: Some(line),
+                addr: None,
+            }],
+        };
+        let frames = [
+            frame(
+                "core::panicking::panic",
+                "/rustc/0123/library/core/src/panicking.rs",
+                72,
+            ),
+            frame(
+                "my_app::orders::submit",
+                "/home/dev/my_app/src/orders.rs",
+                412,
+            ),
+            frame("my_app::main", "/home/dev/my_app/src/main.rs", 3),
+        ];
+        let frames = frames.iter().collect::<Vec<_>>();
+        let summary = TraceSummary::from_frames(&frames);
+        assert_eq!(summary.total_frames, 3);
+        assert_eq!(summary.fingerprint, Fingerprint::from_frames(frames));
+        assert_eq!(
+            summary.to_string(),
+            "my_app::orders::submit (orders.rs:412)"
+        );
+        let empty = TraceSummary::from_frames::<ResolvedFrame>(&[]);
+        assert_eq!(empty.to_string(), "<unknown>");
+    }
+}
@@ -0,0 +1,171 @@
+//! Naming frames with the symbol table of their binary, for builds without debug info.
+//!
+//! Release builds with `strip = "debuginfo"` keep the symbol table, which names the functions
+//! but has no files or lines. Where the `backtrace` crate finds no symbol for a frame, the
+//! symbol table of the binary mapped at the frame's address still names the function starting
+//! before it, or the dynamic symbol table and the exports of binaries without one.
+
+use {
+    crate::{
+        frame::{ResolvedFrame, ResolvedSymbol},
+        module::Modules,
+    },
+    object::{Object, ObjectSegment},
+    std::{
+        fs,
+        path::{Path, PathBuf},
+        sync::Mutex,
+    },
+};
+
+/// The loaded symbol tables, `None` for unreadable binaries.
+static TABLES: Mutex<Vec<(PathBuf, Option<SymbolTable>)>> = Mutex::new(Vec::new());
+
+/// The symbols and segments of a binary.
+struct SymbolTable {
+    /// The address and name of each symbol, sorted by address.
+    symbols: Vec<(u64, String)>,
+    /// The file range and the address of each segment.
+    segments: Vec<(u64, u64, u64)>,
+}
+
+impl SymbolTable {
+    fn open(path: &Path) -> Option<Self> {
+        let data = fs::read(path).ok()?;
+        let file = object::File::parse(&*data).ok()?;
+        let mut symbols = file
+            .symbol_map()
+            .symbols()
+            .iter()
+            .map(|symbol| (symbol.address(), symbol.name().to_owned()))
+            .collect::<Vec<_>>();
+        if symbols.is_empty() {
+            // PE images only name their exports.
+            symbols = file
+                .exports()
+                .unwrap_or_default()
+                .iter()
+                .map(|export| {
+                    let name = String::from_utf8_lossy(export.name()).into_owned();
+                    (export.address(), name)
+                })
+                .collect();
+            symbols.sort();
+        }
+        let segments = file
+            .segments()
+            .filter_map(|segment| {
+                let (offset, size) = segment.file_range();
+                Some((offset, size, segment.address())).filter(|_| size > 0)
+            })
+            .collect();
+        Some(SymbolTable { symbols, segments })
+    }
+
+    /// Returns the symbol table of the binary at `path` from the loaded `tables`, loading it on
+    /// first use.
+    fn get<'a>(tables: &'a mut Vec<(PathBuf, Option<Self>)>, path: &Path) -> Option<&'a Self> {
+        let index = match tables.iter().position(|(known, _)| known == path) {
+            Some(index) => index,
+            None => {
+                tables.push((path.to_owned(), SymbolTable::open(path)));
+                tables.len() - 1
+            }
+        };
+        tables[index].1.as_ref()
+    }
+
+    /// Returns the name of the symbol containing the file offset `offset`, the last one starting
+    /// before it, and the distance of `offset` from its start.
+    fn symbol(&self, offset: u64) -> Option<(&str, u64)> {
+        let address = self
+            .segments
+            .iter()
+            .find(|(start, size, _)| *start <= offset && offset - start < *size)
+            .map(|(start, _, address)| address + (offset - start))?;
+        let index = match self
+            .symbols
+            .binary_search_by_key(&address, |(start, _)| *start)
+        {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        let (start, name) = &self.symbols[index];
+        Some((name, address - start))
+    }
+}
+
+/// Names the frames without symbols with the symbol tables of their binaries, leaving them as
+/// they are if there is none.
+pub(crate) fn resolve_unresolved(frames: &mut [ResolvedFrame]) {
+    let modules = Modules::default();
+    let mut tables = TABLES.lock().unwrap_or_else(|err| err.into_inner());
+    for frame in frames {
+        if frame.symbols.iter().any(|symbol| symbol.name.is_some()) {
+            continue;
+        }
+        // The address is the return address after the call, which may be past the function.
+        let ip = frame.ip.saturating_sub(1);
+        let (path, offset) = match modules.find(ip) {
+            Some(found) => found,
+            None => continue,
+        };
+        let table = match SymbolTable::get(&mut tables, path) {
+            Some(table) => table,
+            None => continue,
+        };
+        if let Some((name, distance)) = table.symbol(offset as u64) {
+            frame.symbols = vec![ResolvedSymbol {
+                name: Some(name.to_owned()),
+                addr: Some(ip.wrapping_sub(distance as usize)),
+                ..ResolvedSymbol::default()
+            }];
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use {
+        super::resolve_unresolved,
+        crate::{
+            frame::{Frame, ResolvedFrame},
+            report::symbol_name,
+            Demangling, FormatOptions,
+        },
+        backtrace::Backtrace,
+    };
+
+    #[inline(never)]
+    fn capture() -> Backtrace {
+        Backtrace::new_unresolved()
+    }
+
+    #[test]
+    fn names_frames_from_the_symbol_table() {
+        let mut frames = capture()
+            .frames()
+            .iter()
+            .map(|frame| ResolvedFrame {
+                ip: Frame::ip(frame),
+                symbols: Vec::new(),
+            })
+            .collect::<Vec<_>>();
+        resolve_unresolved(&mut frames);
+        let options = FormatOptions::new().demangling(Demangling::WithoutHashes);
+        let names = frames
+            .iter()
+            .flat_map(|frame| &frame.symbols)
+            .filter_map(|symbol| symbol.name.as_deref())
+            .map(|name| symbol_name(name, &options))
+            .collect::<Vec<_>>();
+        assert!(
+            names
+                .iter()
+                .any(|name| name.ends_with("symtab::tests::capture")),
+            "{:?}",
+            names
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! Failure sections of test reports carrying panic backtraces.
+//!
+//! Custom test harnesses, e.g. built on `libtest-mimic`, write their results as JUnit XML or TAP.
+//! [`TestFailure::catch()`] runs a test, capturing a panic with its backtrace, and the failure
+//! renders as a JUnit `<failure>` element or as TAP diagnostic lines, escaped as needed:
+//!
+//! ```
+//! use backtrace_string::test_report::TestFailure;
+//!
+//! let failure = TestFailure::catch(|| assert_eq!(1 + 1, 3)).unwrap_err();
+//! assert!(failure.to_junit().starts_with("<failure message=\"assertion `left == right` failed"));
+//! assert!(failure.to_tap().starts_with("# test panicked"));
+//! ```
+//!
+//! [`junit_failure()`] and [`tap_diagnostics()`] wrap other texts, like reports formatted by the
+//! application itself.
+//!
+//![`TestFailure::catch()`]: struct.TestFailure.html#method.catch
+//![`junit_failure()`]: fn.junit_failure.html
+//![`tap_diagnostics()`]: fn.tap_diagnostics.html
+
+use {
+    crate::{hook::catch_captured, payload, ColorChoice, FormatOptions},
+    std::fmt::Write,
+};
+
+/// A panicked test, see the [module docs].
+///
+///[module docs]: index.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TestFailure {
+    /// The panic message.
+    pub message: String,
+    /// The panic with its location and backtrace, formatted like the reports of this crate.
+    pub report: String,
+}
+
+impl TestFailure {
+    /// Runs the test `func`, returning its panic as failure.
+    ///
+    /// The backtrace is formatted with the [current options], without colors. If the panic hook
+    /// was replaced after the first call, the report only holds the message.
+    ///
+    ///[current options]: ../struct.FormatOptions.html#method.current
+    pub fn catch<T>(func: impl FnOnce() -> T) -> Result<T, TestFailure> {
+        catch_captured(func).map_err(|(payload, captured)| {
+            let message = payload::describe(&*payload);
+            let report = match captured {
+                Some(captured) => {
+                    let options = FormatOptions::current().color(ColorChoice::Never);
+                    captured.into_report("test", &options)
+                }
+                None => message.clone(),
+            };
+            TestFailure { message, report }
+        })
+    }
+
+    /// Renders the failure as JUnit `<failure>` element, see [`junit_failure()`].
+    ///
+    ///[`junit_failure()`]: fn.junit_failure.html
+    pub fn to_junit(&self) -> String {
+        junit_failure(&self.message, &self.report)
+    }
+
+    /// Renders the report as TAP diagnostic lines, see [`tap_diagnostics()`].
+    ///
+    ///[`tap_diagnostics()`]: fn.tap_diagnostics.html
+    pub fn to_tap(&self) -> String {
+        tap_diagnostics(&self.report)
+    }
+}
+
+/// Returns a JUnit `<failure>` element of type `panic` with `message` as attribute and `details`
+/// in a CDATA section.
+///
+/// Characters XML doesn't allow, like the escape character of ANSI colors, are replaced with
+/// `U+FFFD`, and a `]]>` in `details` is split across two CDATA sections.
+pub fn junit_failure(message: &str, details: &str) -> String {
+    let mut out = String::from("<failure message=\"");
+    for c in message.chars().map(xml_char) {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            '\n' | '\r' | '\t' => write!(out, "&#{};", u32::from(c)).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push_str("\" type=\"panic\"><![CDATA[");
+    let details: String = details.chars().map(xml_char).collect();
+    out.push_str(&details.replace("]]>", "]]]]><![CDATA[>"));
+    out.push_str("]]></failure>");
+    out
+}
+
+/// Returns `text` as TAP diagnostic lines, each starting with `# ` and ending with a line break.
+pub fn tap_diagnostics(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for line in text.trim_end_matches('\n').lines() {
+        out.push('#');
+        if !line.is_empty() {
+            out.push(' ');
+            out.push_str(line);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Replaces the characters which aren't allowed in XML 1.0 documents.
+fn xml_char(c: char) -> char {
+    match c {
+        '\t' | '\n' | '\r' => c,
+        '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => '\u{fffd}',
+        c => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{junit_failure, tap_diagnostics};
+
+    #[test]
+    fn escapes_junit_failures() {
+        assert_eq!(
+            junit_failure("a < \"b\"\nc", "x ]]> y\u{1b}[31m"),
+            "<failure message=\"a &lt; &quot;b&quot;&#10;c\" type=\"panic\">\
+             <![CDATA[x ]]]]><![CDATA[> y\u{fffd}[31m]]></failure>"
+        );
+    }
+
+    #[test]
+    fn prefixes_tap_diagnostics() {
+        assert_eq!(
+            tap_diagnostics("panicked\n\n   0: main\n"),
+            "# panicked\n#\n#    0: main\n"
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "resolve", feature = "demangle"))]
+    fn catches_failing_tests() {
+        use super::TestFailure;
+
+        assert_eq!(TestFailure::catch(|| 42), Ok(42));
+        let failure = TestFailure::catch(|| panic!("boom")).unwrap_err();
+        assert_eq!(failure.message, "boom");
+        assert!(
+            failure.report.starts_with("test panicked at "),
+            "{}",
+            failure.report
+        );
+        assert!(
+            failure.report.contains("catches_failing_tests"),
+            "{}",
+            failure.report
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! Spawning of threads which report their panics.
+//!
+//! A panicking worker thread usually dies silently, as nobody joins it or nobody looks at the
+//! error returned by `join`. Threads spawned through this module format the panic including its
+//! backtrace and write it to the configured [sink](../sink/index.html) before the thread dies.
+//! The panic still propagates to `join` as usual. Within a [`ReportScope`] the report is also
+//! collected by the scope.
+//!
+//![`ReportScope`]: ../scope/struct.ReportScope.html
+
+use {
+    crate::{
+        hook::catch_captured,
+        scope::{self, ReportScope},
+        FormatOptions,
+    },
+    std::{
+        io, panic,
+        thread::{self, JoinHandle, Scope, ScopedJoinHandle},
+    },
+};
+
+/// Spawns a new thread named `name` which reports a panic of `func` before it dies.
+pub fn spawn_reported<F, T>(name: impl Into<String>, func: F) -> io::Result<JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let scope = ReportScope::current();
+    thread::Builder::new()
+        .name(name.into())
+        .spawn(move || run_reported(scope, func))
+}
+
+/// Extension of [`std::thread::Scope`] to spawn scoped threads which report their panics.
+///
+///[`std::thread::Scope`]: https://doc.rust-lang.org/std/thread/struct.Scope.html
+pub trait ScopeExt<'scope> {
+    /// Scoped version of [`spawn_reported()`].
+    ///
+    ///[`spawn_reported()`]: fn.spawn_reported.html
+    fn spawn_reported<F, T>(
+        &'scope self,
+        name: impl Into<String>,
+        func: F,
+    ) -> io::Result<ScopedJoinHandle<'scope, T>>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope;
+}
+
+impl<'scope, 'env> ScopeExt<'scope> for Scope<'scope, 'env> {
+    fn spawn_reported<F, T>(
+        &'scope self,
+        name: impl Into<String>,
+        func: F,
+    ) -> io::Result<ScopedJoinHandle<'scope, T>>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        let scope = ReportScope::current();
+        thread::Builder::new()
+            .name(name.into())
+            .spawn_scoped(self, move || run_reported(scope, func))
+    }
+}
+
+fn run_reported<T>(scope: Option<ReportScope>, func: impl FnOnce() -> T) -> T {
+    let result = match &scope {
+        Some(scope) => catch_captured(|| scope.enter(func)),
+        None => catch_captured(func),
+    };
+    match result {
+        Ok(value) => value,
+        Err((payload, captured)) => {
+            if let Some(captured) = captured {
+                let current = thread::current();
+                let subject = format!("thread '{}'", current.name().unwrap_or("<unnamed>"));
+                let report = captured.into_report(&subject, &FormatOptions::current());
+                scope::write_report(scope.as_ref(), &report);
+            }
+            panic::resume_unwind(payload)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "resolve", feature = "demangle"))]
+mod tests {
+    use {
+        super::{spawn_reported, ScopeExt},
+        crate::sink::collect_reports,
+        std::thread,
+    };
+
+    #[test]
+    fn reports_panic_with_thread_name() {
+        let reports = collect_reports(|| {
+            let result = spawn_reported("reported-worker", || panic!("worker failed"))
+                .unwrap()
+                .join();
+            assert!(result.is_err());
+
+            thread::scope(|scope| {
+                let handle = scope.spawn_reported("scoped-worker", || 42).unwrap();
+                assert_eq!(handle.join().unwrap(), 42);
+            });
+        });
+
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].starts_with("thread 'reported-worker' panicked at src/thread.rs:"));
+        assert!(reports[0].contains(": worker failed\nBacktrace:\n"));
+        let origin = reports[0]
+            .lines()
+            .find(|line| line.ends_with(" <-- panic origin"))
+            .unwrap();
+        assert!(origin.contains("src/thread.rs:99"), "{}", reports[0]);
+    }
+}
@@ -0,0 +1,123 @@
+//! Reporting of panics in [`tokio`](https://crates.io/crates/tokio) tasks.
+//!
+//! When a task panics, tokio only hands out the panic payload through the `JoinError`, the stack
+//! of the panic is lost. Futures wrapped in [`ReportPanics`] format the panic including its
+//! backtrace (hiding the runtime frames with [`Preset::Tokio`]) and write it to the configured
+//! [sink](../sink/index.html). The panic then continues to propagate to the `JoinHandle`.
+//!
+//! ```no_run
+//! # async fn handle_request() {}
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let handle = backtrace_string::tokio::spawn_reported(async {
+//!     handle_request().await;
+//! });
+//! # }
+//! ```
+//!
+//![`ReportPanics`]: struct.ReportPanics.html
+//![`Preset::Tokio`]: ../preset/enum.Preset.html#variant.Tokio
+
+use {
+    crate::{
+        hook::catch_captured,
+        scope::{self, ReportScope},
+        FormatOptions, Preset,
+    },
+    ::tokio::task::{self, JoinHandle},
+    std::{
+        future::Future,
+        panic,
+        pin::Pin,
+        task::{Context, Poll},
+    },
+};
+
+/// Spawns `future` wrapped in [`ReportPanics`] on the current runtime.
+///
+///[`ReportPanics`]: struct.ReportPanics.html
+pub fn spawn_reported<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    task::spawn(ReportPanics::new(future))
+}
+
+/// A future reporting panics of the wrapped future before propagating them.
+///
+/// With the `logical-frames` feature it keeps the logical frames recorded by the future like
+/// `logical::scope()`, which are included in the report.
+pub struct ReportPanics<F> {
+    #[cfg(feature = "logical-frames")]
+    inner: crate::logical::Scope<F>,
+    #[cfg(not(feature = "logical-frames"))]
+    inner: Pin<Box<F>>,
+    /// The [`ReportScope`] the future was created in.
+    ///
+    ///[`ReportScope`]: ../scope/struct.ReportScope.html
+    scope: Option<ReportScope>,
+}
+
+impl<F: Future> ReportPanics<F> {
+    /// Wraps `future`.
+    pub fn new(future: F) -> Self {
+        ReportPanics {
+            #[cfg(feature = "logical-frames")]
+            inner: crate::logical::scope(future),
+            #[cfg(not(feature = "logical-frames"))]
+            inner: Box::pin(future),
+            scope: ReportScope::current(),
+        }
+    }
+}
+
+impl<F: Future> Future for ReportPanics<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let inner = Pin::new(&mut this.inner);
+        let scope = this.scope.as_ref();
+        match catch_captured(move || match scope {
+            Some(scope) => scope.enter(|| inner.poll(cx)),
+            None => inner.poll(cx),
+        }) {
+            Ok(poll) => poll,
+            Err((payload, captured)) => {
+                if let Some(captured) = captured {
+                    let subject = match task::try_id() {
+                        Some(id) => format!("task {}", id),
+                        None => String::from("task"),
+                    };
+                    let options = FormatOptions::current().filter_preset(Preset::Tokio);
+                    let report = captured.into_report(&subject, &options);
+                    scope::write_report(this.scope.as_ref(), &report);
+                }
+                panic::resume_unwind(payload)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::spawn_reported, crate::sink::collect_reports};
+
+    #[test]
+    fn reports_task_panic() {
+        let reports = collect_reports(|| {
+            let runtime = ::tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap();
+            let result =
+                runtime.block_on(async { spawn_reported(async { panic!("task failed") }).await });
+            assert!(result.unwrap_err().is_panic());
+        });
+
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].starts_with("task "));
+        assert!(reports[0].contains(": task failed\nBacktrace:\n"));
+        assert!(!reports[0].contains("tokio::runtime::"));
+    }
+}
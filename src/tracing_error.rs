@@ -0,0 +1,193 @@
+//! Integration with the [`tracing-error` crate](https://crates.io/crates/tracing-error).
+//!
+//! The physical frames of async code end in the executor polling it, they don't tell which
+//! request or task the code was working on. The spans of `tracing` do. With a subscriber
+//! including `tracing_error::ErrorLayer`, [`format_with_spans()`] formats a backtrace together
+//! with a `SpanTrace`: the frames of functions instrumented with `#[instrument]` are annotated
+//! with their span, and all spans follow the frames below a `Span trace:` heading:
+//!
+//! ```
+//! use {
+//!     backtrace_string::{tracing_error::format_with_spans, FormatOptions},
+//!     tracing_error::SpanTrace,
+//! };
+//!
+//! let mut bt = backtrace::Backtrace::new_unresolved();
+//! let spans = SpanTrace::capture();
+//! println!(
+//!     "Backtrace:{}",
+//!     format_with_spans(&mut bt, &spans, &FormatOptions::new())
+//! );
+//! ```
+//!
+//! The panic reports of this crate, e.g. of [`spawn_reported()`], include the spans of the
+//! panicking thread the same way.
+//!
+//![`format_with_spans()`]: fn.format_with_spans.html
+//![`spawn_reported()`]: ../thread/fn.spawn_reported.html
+
+use {
+    crate::{clean_path, format_backtrace_with, write_location, FormatOptions, Locations},
+    ::tracing_error::SpanTrace,
+    backtrace::Backtrace,
+    std::{fmt::Write, path::Path},
+};
+
+/// A span of a span trace.
+pub(crate) struct Span {
+    /// The module the span was created in, with `#[instrument]` that of the function the span is
+    /// named after.
+    module: Option<&'static str>,
+    name: &'static str,
+    fields: String,
+    location: Option<String>,
+}
+
+impl Span {
+    /// Returns the name and the fields of the span, like `process{id=3}`.
+    fn label(&self) -> String {
+        match self.fields.as_str() {
+            "" => self.name.to_owned(),
+            fields => format!("{}{{{}}}", self.name, fields),
+        }
+    }
+}
+
+/// Formats `bt` like [`format_backtrace_with()`] and appends the spans of `spans`, annotating
+/// the frames of instrumented functions with their span.
+///
+/// Nothing is appended if `spans` is empty, e.g. because the subscriber lacks the `ErrorLayer`.
+///
+///[`format_backtrace_with()`]: ../fn.format_backtrace_with.html
+pub fn format_with_spans(bt: &mut Backtrace, spans: &SpanTrace, options: &FormatOptions) -> String {
+    let spans = collect_spans(spans);
+    let mut out = format_backtrace_with(bt, &annotate_spans(options.clone(), &spans));
+    write_spans(&mut out, &spans, options);
+    out
+}
+
+/// Returns the spans of `trace`, the innermost first.
+pub(crate) fn collect_spans(trace: &SpanTrace) -> Vec<Span> {
+    let mut spans = Vec::new();
+    trace.with_spans(|metadata, fields| {
+        let location = metadata.file().map(|file| {
+            let file = clean_path(Path::new(file));
+            match metadata.line() {
+                Some(line) => format!("{}:{}", file.display(), line),
+                None => file.display().to_string(),
+            }
+        });
+        spans.push(Span {
+            module: metadata.module_path(),
+            name: metadata.name(),
+            fields: fields.to_owned(),
+            location,
+        });
+        true
+    });
+    spans
+}
+
+/// Adds the notes naming the span of each frame of an instrumented function to `options`.
+pub(crate) fn annotate_spans(mut options: FormatOptions, spans: &[Span]) -> FormatOptions {
+    for span in spans {
+        if let Some(module) = span.module {
+            let path = format!("{}::{}", module, span.name);
+            let note = options.vocabulary.in_span(&span.label());
+            options = options.annotate_symbols(
+                move |sym| {
+                    sym.strip_prefix(path.as_str())
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+                },
+                note,
+            );
+        }
+    }
+    options
+}
+
+/// Appends the spans below the `Span trace:` heading, if there are any.
+pub(crate) fn write_spans(out: &mut String, spans: &[Span], options: &FormatOptions) {
+    if spans.is_empty() {
+        return;
+    }
+    writeln!(out, "{}:", options.vocabulary.span_trace).unwrap();
+    for (index, span) in spans.iter().enumerate() {
+        let number = index + options.index_base;
+        write!(out, "{:1$}: ", number, options.number_width()).unwrap();
+        match span.module {
+            Some(module) => write!(out, "{}::{}", module, span.label()).unwrap(),
+            None => out.push_str(&span.label()),
+        }
+        match &span.location {
+            Some(location) if options.locations == Locations::Shown => {
+                write_location(out, location, options)
+            }
+            _ => {}
+        }
+        writeln!(out).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::format_with_spans,
+        crate::{hook::catch_captured, FormatOptions, Locations},
+        backtrace::Backtrace,
+        tracing_error::{ErrorLayer, SpanTrace},
+        tracing_subscriber::{layer::SubscriberExt, Registry},
+    };
+
+    #[tracing::instrument]
+    #[inline(never)]
+    fn process(id: u32) -> String {
+        let mut bt = Backtrace::new_unresolved();
+        let options = FormatOptions::new().locations(Locations::Hidden);
+        format_with_spans(&mut bt, &SpanTrace::capture(), &options)
+    }
+
+    #[test]
+    fn annotates_and_appends_spans() {
+        let subscriber = Registry::default().with(ErrorLayer::default());
+        let out = tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("request", path = "/").in_scope(|| process(3))
+        });
+        assert!(
+            out.contains("\n      note: in span process{id=3}\n"),
+            "{}",
+            out
+        );
+        assert!(
+            out.ends_with(
+                "Span trace:\n   \
+                 0: backtrace_string::tracing_error::tests::process{id=3}\n   \
+                 1: backtrace_string::tracing_error::tests::request{path=\"/\"}\n"
+            ),
+            "{}",
+            out
+        );
+
+        // Without the layer there are no spans.
+        let out = process(3);
+        assert!(!out.contains("Span trace:"), "{}", out);
+    }
+
+    #[test]
+    fn panic_reports_show_the_spans() {
+        let subscriber = Registry::default().with(ErrorLayer::default());
+        let report = tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("task", id = 7);
+            let (_, captured) = span
+                .in_scope(|| catch_captured(|| panic!("in a span")))
+                .unwrap_err();
+            captured.unwrap().into_report("test", &FormatOptions::new())
+        });
+        assert!(report.contains("\nSpan trace:\n"), "{}", report);
+        assert!(
+            report.contains("tests::task{id=7}\n          at src/tracing_error.rs:"),
+            "{}",
+            report
+        );
+    }
+}
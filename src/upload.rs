@@ -0,0 +1,250 @@
+//! Crash reports prepared for sending to a crash collection service.
+//!
+//! A [`CrashPayload`] is the JSON body of a [`CrashReport`] with its fingerprint and metadata
+//! like the version or environment, capped in size so collectors don't reject it. It is sent
+//! through a [`Transport`], which is implemented with the HTTP client of the application:
+//!
+//! ```
+//! use backtrace_string::{
+//!     report::CrashReport,
+//!     upload::{CrashPayload, Transport},
+//!     FormatOptions,
+//! };
+//!
+//! let transport = |content_type: &str, body: &[u8]| {
+//!     // e.g. POST the body to the crash collector with the HTTP client of the application.
+//!     assert_eq!(content_type, "application/json");
+//!     assert!(!body.is_empty());
+//!     Ok(())
+//! };
+//! let payload = CrashPayload::new(CrashReport::capture(), FormatOptions::new())
+//!     .metadata("version", env!("CARGO_PKG_VERSION"));
+//! payload.send(&transport).unwrap();
+//! ```
+//!
+//![`CrashPayload`]: struct.CrashPayload.html
+//![`CrashReport`]: ../report/struct.CrashReport.html
+//![`Transport`]: trait.Transport.html
+
+use {
+    crate::{
+        report::{json_string, CrashReport},
+        Fingerprint, FormatOptions,
+    },
+    std::{fmt::Write, io},
+};
+
+/// A sender of crash payloads, e.g. by an HTTP `POST` to a crash collection service.
+///
+/// This is implemented for all `Fn(&str, &[u8]) -> io::Result<()>` closures.
+pub trait Transport {
+    /// Sends `body`, which has the MIME type `content_type`.
+    fn send(&self, content_type: &str, body: &[u8]) -> io::Result<()>;
+}
+
+impl<F> Transport for F
+where
+    F: Fn(&str, &[u8]) -> io::Result<()>,
+{
+    fn send(&self, content_type: &str, body: &[u8]) -> io::Result<()> {
+        self(content_type, body)
+    }
+}
+
+/// A crash report with its fingerprint and metadata, serialized as size-capped JSON.
+///
+/// The JSON object has the fields `fingerprint`, the [fingerprint] of the crashed thread's
+/// frames as hexadecimal string, `metadata`, an object of the metadata strings, `truncated`,
+/// whether parts of the report were left out to stay within the size limit, and `report`, the
+/// report as rendered by the [`JsonRenderer`].
+///
+///[fingerprint]: ../struct.Fingerprint.html
+///[`JsonRenderer`]: ../report/struct.JsonRenderer.html
+#[derive(Debug, Clone)]
+pub struct CrashPayload {
+    report: CrashReport,
+    options: FormatOptions,
+    metadata: Vec<(String, String)>,
+    max_bytes: usize,
+}
+
+impl CrashPayload {
+    /// Creates a payload of `report`, with the frames trimmed, hidden and demangled according to
+    /// the `options`.
+    pub fn new(report: CrashReport, options: FormatOptions) -> Self {
+        CrashPayload {
+            report,
+            options,
+            metadata: Vec::new(),
+            max_bytes: 64 * 1024,
+        }
+    }
+
+    /// Adds a metadata entry, e.g. the version of the application.
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.push((key.into(), value.into()));
+        self
+    }
+
+    /// Sets the size limit of the JSON in bytes, 64 KiB by default.
+    ///
+    /// Over the limit, the modules are left out first, then the threads which didn't crash and
+    /// finally the outermost frames of the crashed thread. The fingerprint and metadata are
+    /// always included.
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Returns the fingerprint of the crashed thread's shown frames.
+    pub fn fingerprint(&self) -> Fingerprint {
+        let options = &self.options;
+        let crashed = self.report.threads.iter().find(|thread| thread.crashed);
+        Fingerprint::from_frames(
+            crashed
+                .into_iter()
+                .flat_map(|thread| thread.shown_frames(options)),
+        )
+    }
+
+    /// Serializes the payload as JSON.
+    pub fn to_json(&self) -> String {
+        let options = &self.options;
+        let mut report = self.report.clone();
+        // The frames are trimmed and hidden up front, so truncating them leaves the shown ones.
+        for thread in &mut report.threads {
+            thread.frames = thread.shown_frames(options).cloned().collect();
+        }
+        let mut json = self.with_report(&report, false);
+        if json.len() <= self.max_bytes {
+            return json;
+        }
+        report.modules.clear();
+        json = self.with_report(&report, true);
+        if json.len() > self.max_bytes {
+            report.threads.retain(|thread| thread.crashed);
+            json = self.with_report(&report, true);
+        }
+        while json.len() > self.max_bytes {
+            let thread = report
+                .threads
+                .iter_mut()
+                .filter(|thread| !thread.frames.is_empty())
+                .last();
+            match thread {
+                Some(thread) => thread.frames.pop(),
+                // Only the metadata and the panic are left.
+                None => break,
+            };
+            json = self.with_report(&report, true);
+        }
+        json
+    }
+
+    /// Sends the JSON through `transport`.
+    pub fn send(&self, transport: &impl Transport) -> io::Result<()> {
+        transport.send("application/json", self.to_json().as_bytes())
+    }
+
+    fn with_report(&self, report: &CrashReport, truncated: bool) -> String {
+        let mut out = String::new();
+        write!(
+            out,
+            "{{\"fingerprint\":\"{}\",\"metadata\":{{",
+            self.fingerprint()
+        )
+        .unwrap();
+        for (i, (key, value)) in self.metadata.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            json_string(&mut out, key).unwrap();
+            out.push(':');
+            json_string(&mut out, value).unwrap();
+        }
+        write!(out, "}},\"truncated\":{},\"report\":", truncated).unwrap();
+        out.push_str(&report.to_json(&self.options));
+        out.push('}');
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::CrashPayload,
+        crate::{
+            frame::{ResolvedFrame, ResolvedSymbol},
+            report::{CrashReport, ThreadTrace},
+            FormatOptions,
+        },
+        std::{cell::RefCell, io},
+    };
+
+    fn report() -> CrashReport {
+        let frame = |name: &str| ResolvedFrame {
+            ip: 0x1000,
+            symbols: vec![ResolvedSymbol {
+                name: Some(name.to_owned()),
+                ..ResolvedSymbol::default()
+            }],
+        };
+        let mut report = CrashReport::default();
+        report.threads.push(ThreadTrace {
+            crashed: true,
+            frames: vec![frame("my_app::parse"), frame("my_app::main")],
+            ..ThreadTrace::default()
+        });
+        report.threads.push(ThreadTrace {
+            frames: vec![frame("my_app::worker")],
+            ..ThreadTrace::default()
+        });
+        report
+    }
+
+    #[test]
+    fn serializes_payload() {
+        let payload = CrashPayload::new(report(), FormatOptions::new()).metadata("env", "prod");
+        let json = payload.to_json();
+        assert!(
+            json.starts_with(&format!(
+                "{{\"fingerprint\":\"{}\",\"metadata\":{{\"env\":\"prod\"}},\"truncated\":false,\
+                 \"report\":{{\"process\":",
+                payload.fingerprint()
+            )),
+            "{}",
+            json
+        );
+        assert!(json.contains("my_app::worker"));
+
+        let sent = RefCell::new(Vec::new());
+        let transport = |content_type: &str, body: &[u8]| -> io::Result<()> {
+            assert_eq!(content_type, "application/json");
+            sent.borrow_mut().extend_from_slice(body);
+            Ok(())
+        };
+        payload.send(&transport).unwrap();
+        assert_eq!(sent.into_inner(), json.into_bytes());
+    }
+
+    #[test]
+    fn truncates_to_the_limit() {
+        let options = FormatOptions::new();
+        let full = CrashPayload::new(report(), options.clone()).to_json();
+        let json = CrashPayload::new(report(), options.clone())
+            .max_bytes(full.len() - 2)
+            .to_json();
+        assert!(json.contains("\"truncated\":true"));
+        assert!(!json.contains("my_app::worker") && json.contains("my_app::main"));
+
+        let mut small = report();
+        small.threads.truncate(1);
+        small.threads[0].frames.truncate(1);
+        let small = CrashPayload::new(small, options.clone()).to_json();
+        let json = CrashPayload::new(report(), options)
+            .max_bytes(small.len())
+            .to_json();
+        assert!(json.len() <= small.len(), "{}", json);
+        assert!(json.contains("my_app::parse") && !json.contains("my_app::main"));
+    }
+}
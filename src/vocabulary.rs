@@ -0,0 +1,214 @@
+//! The words used in the formatted output.
+
+use {
+    alloc::{borrow::Cow, format, string::String},
+    core::fmt::{self, Display, Write},
+};
+
+/// The literal strings of the formatted output, English by default.
+///
+/// Replace them to match the language of a product or the style guide of a log format:
+///
+/// ```
+/// use backtrace_string::{FormatOptions, Vocabulary};
+///
+/// let options = FormatOptions::new().vocabulary(Vocabulary {
+///     at: "bei".into(),
+///     frames_omitted: "... {} weitere Frames ausgelassen".into(),
+///     ..Vocabulary::default()
+/// });
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vocabulary {
+    /// Introduces the location of a symbol, `at`.
+    pub at: Cow<'static, str>,
+    /// Introduces the address of a symbol without location, `address`.
+    pub address: Cow<'static, str>,
+    /// Stands in for a missing symbol name or location, `<unknown>`.
+    pub unknown: Cow<'static, str>,
+    /// The notice for frames left out because of the frame limit, `{}` is replaced by their
+    /// number. `... {} more frames omitted` by default.
+    pub frames_omitted: Cow<'static, str>,
+    /// The notice for frames without symbol names left out because of
+    /// [`UnknownSymbols::Hide`], `{}` is replaced by their number.
+    /// `... {} frames without symbols hidden` by default.
+    ///
+    ///[`UnknownSymbols::Hide`]: enum.UnknownSymbols.html#variant.Hide
+    pub unresolved_hidden: Cow<'static, str>,
+    /// The header of backtraces with deep recursion, the `{}` are replaced by the number of
+    /// frames in the cycle and by the number of repetitions.
+    /// `likely stack overflow: cycle of {} frames repeated ~{} times` by default.
+    pub stack_overflow: Cow<'static, str>,
+    /// The notice for the left out repetitions of a recursion cycle, the `{}` are replaced by the
+    /// number of frames in the cycle and by the number of left out repetitions.
+    /// `... cycle of {} frames repeated {} more times` by default.
+    pub cycle_repeated: Cow<'static, str>,
+    /// The line standing in for the dependency frames left out by
+    /// [`FormatOptions::only_my_code()`], the `{}` are replaced by the module the frames were
+    /// entered through and by their number. `… via {} ({} frames) …` by default.
+    ///
+    ///[`FormatOptions::only_my_code()`]: struct.FormatOptions.html#method.only_my_code
+    pub external_frames: Cow<'static, str>,
+    /// The line standing in for the trailing frames a backtrace shares with one shown before
+    /// it, the `{}` are replaced by their number and the name of the other backtrace.
+    /// `(+ same {} bottom frames as {})` by default.
+    pub same_frames: Cow<'static, str>,
+    /// The label of the fingerprint footer, `Fingerprint`.
+    pub fingerprint: Cow<'static, str>,
+    /// The heading of the backtrace in reports, `Backtrace`.
+    pub backtrace: Cow<'static, str>,
+    /// The heading of the logical frames in panic reports, `Logical frames`.
+    pub logical_frames: Cow<'static, str>,
+    /// The heading of the spans of `tracing` in reports, `Span trace`.
+    pub span_trace: Cow<'static, str>,
+    /// The note on the frames of functions instrumented with a span, `{}` is replaced by the
+    /// name and the fields of the span. `in span {}` by default.
+    pub in_span: Cow<'static, str>,
+    /// The verb in the header of panic reports, `panicked`.
+    pub panicked: Cow<'static, str>,
+    /// The heading of the sources of an error, `Caused by`.
+    pub caused_by: Cow<'static, str>,
+    /// The heading of the location an error report was created at, `Location`.
+    pub location: Cow<'static, str>,
+    /// Introduces the notes attached to frames, `note`.
+    pub note: Cow<'static, str>,
+    /// The line below the header of panic reports with [`FormatOptions::stack_usage()`], the
+    /// `{}` are replaced by the used bytes of the stack and its size.
+    /// `stack usage: ~{} of {} bytes` by default.
+    ///
+    ///[`FormatOptions::stack_usage()`]: struct.FormatOptions.html#method.stack_usage
+    pub stack_usage: Cow<'static, str>,
+    /// The location of an inlined function with the call site in its caller, the `{}` are
+    /// replaced by the two locations. `inlined from {} at {}` by default.
+    pub inlined: Cow<'static, str>,
+    /// The line separating the frames of a panic from those of the panic it was raised while
+    /// handling, with [`FormatOptions::nested_panics()`].
+    /// `--- while handling the panic below ---` by default.
+    ///
+    ///[`FormatOptions::nested_panics()`]: struct.FormatOptions.html#method.nested_panics
+    pub nested_panic: Cow<'static, str>,
+    /// The header of backtraces with [`FormatOptions::aslr_slide()`], `{}` is replaced by the
+    /// slide in hex. `ASLR slide of the executable: {}` by default.
+    ///
+    ///[`FormatOptions::aslr_slide()`]: struct.FormatOptions.html#method.aslr_slide
+    pub aslr_slide: Cow<'static, str>,
+    /// Marks the location a panic happened at, `<-- panic origin`.
+    pub panic_origin: Cow<'static, str>,
+    /// Stands in for a backtrace without any frames, explaining the likely causes, see
+    /// [`CaptureOutcome::Empty`].
+    ///
+    ///[`CaptureOutcome::Empty`]: enum.CaptureOutcome.html#variant.Empty
+    pub no_frames: Cow<'static, str>,
+}
+
+impl Vocabulary {
+    /// Returns the notice for `count` hidden frames without symbol names.
+    pub(crate) fn unresolved_hidden(&self, count: usize) -> String {
+        fill(&self.unresolved_hidden, &[&count])
+    }
+
+    /// Returns the header for a recursion cycle of `period` frames repeated `repeats` times.
+    pub(crate) fn stack_overflow(&self, period: usize, repeats: usize) -> String {
+        fill(&self.stack_overflow, &[&period, &repeats])
+    }
+
+    /// Returns the notice for `repeats` left out repetitions of a cycle of `period` frames.
+    pub(crate) fn cycle_repeated(&self, period: usize, repeats: usize) -> String {
+        fill(&self.cycle_repeated, &[&period, &repeats])
+    }
+
+    /// Returns the line for `count` dependency frames entered through `module`.
+    pub(crate) fn external_frames(&self, module: &str, count: usize) -> String {
+        fill(&self.external_frames, &[&module, &count])
+    }
+
+    /// Returns the location of an inlined function called at `call_site`.
+    pub(crate) fn inlined(&self, location: &str, call_site: &str) -> String {
+        fill(&self.inlined, &[&location, &call_site])
+    }
+
+    /// Returns the line for a stack of `size` bytes of which `used` bytes are used.
+    pub(crate) fn stack_usage(&self, used: usize, size: usize) -> String {
+        fill(&self.stack_usage, &[&used, &size])
+    }
+
+    /// Returns the header for the ASLR slide `slide` of the executable.
+    pub(crate) fn aslr_slide(&self, slide: usize) -> String {
+        fill(&self.aslr_slide, &[&format!("{:#x}", slide)])
+    }
+
+    /// Returns the note on the frames of a function instrumented with the span `span`.
+    #[cfg_attr(not(feature = "tracing-error"), allow(dead_code))]
+    pub(crate) fn in_span(&self, span: &str) -> String {
+        fill(&self.in_span, &[&span])
+    }
+
+    /// Returns the line for `count` trailing frames shared with the backtrace named `other`.
+    pub(crate) fn same_frames(&self, count: usize, other: &str) -> String {
+        fill(&self.same_frames, &[&count, &other])
+    }
+}
+
+impl Default for Vocabulary {
+    fn default() -> Self {
+        Vocabulary {
+            at: Cow::Borrowed("at"),
+            address: Cow::Borrowed("address"),
+            unknown: Cow::Borrowed("<unknown>"),
+            frames_omitted: Cow::Borrowed("... {} more frames omitted"),
+            unresolved_hidden: Cow::Borrowed("... {} frames without symbols hidden"),
+            stack_overflow: Cow::Borrowed(
+                "likely stack overflow: cycle of {} frames repeated ~{} times",
+            ),
+            cycle_repeated: Cow::Borrowed("... cycle of {} frames repeated {} more times"),
+            external_frames: Cow::Borrowed("… via {} ({} frames) …"),
+            same_frames: Cow::Borrowed("(+ same {} bottom frames as {})"),
+            fingerprint: Cow::Borrowed("Fingerprint"),
+            backtrace: Cow::Borrowed("Backtrace"),
+            logical_frames: Cow::Borrowed("Logical frames"),
+            span_trace: Cow::Borrowed("Span trace"),
+            in_span: Cow::Borrowed("in span {}"),
+            panicked: Cow::Borrowed("panicked"),
+            caused_by: Cow::Borrowed("Caused by"),
+            location: Cow::Borrowed("Location"),
+            note: Cow::Borrowed("note"),
+            stack_usage: Cow::Borrowed("stack usage: ~{} of {} bytes"),
+            inlined: Cow::Borrowed("inlined from {} at {}"),
+            nested_panic: Cow::Borrowed("--- while handling the panic below ---"),
+            aslr_slide: Cow::Borrowed("ASLR slide of the executable: {}"),
+            panic_origin: Cow::Borrowed("<-- panic origin"),
+            no_frames: Cow::Borrowed(
+                "<no frames captured: the binary may lack unwind tables (build with \
+                 `-C force-unwind-tables=yes` or `-C force-frame-pointers=yes`), the target may \
+                 not support unwinding, or a seccomp policy or sandbox may block the unwinder>",
+            ),
+        }
+    }
+}
+
+/// Replaces the `{}` in `template` by the `values` in order.
+fn fill(template: &str, values: &[&dyn Display]) -> String {
+    let mut out = String::with_capacity(template.len());
+    write_filled(&mut out, template, values).unwrap();
+    out
+}
+
+/// Like [`fill()`] but writes to `out` instead of allocating.
+///
+///[`fill()`]: fn.fill.html
+pub(crate) fn write_filled(
+    out: &mut impl Write,
+    template: &str,
+    values: &[&dyn Display],
+) -> fmt::Result {
+    let mut parts = template.split("{}");
+    out.write_str(parts.next().unwrap_or(""))?;
+    for (i, part) in parts.enumerate() {
+        match values.get(i) {
+            Some(value) => write!(out, "{}", value)?,
+            None => out.write_str("{}")?,
+        }
+        out.write_str(part)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,497 @@
+//! Capturing the stack of another thread, for reports of hung threads.
+//!
+//! A thread which hangs doesn't panic, so no hook ever formats its backtrace. A watchdog or
+//! supervisor thread can capture it instead with [`format_thread()`], given the id of the hung
+//! thread as returned by [`current_thread_id()`] on that thread (or listed in `/proc/self/task`):
+//!
+//! ```no_run
+//! use {
+//!     backtrace_string::{watchdog, FormatOptions},
+//!     std::{sync::mpsc, thread, time::Duration},
+//! };
+//!
+//! let (send, recv) = mpsc::channel();
+//! thread::spawn(move || {
+//!     send.send(watchdog::current_thread_id()).unwrap();
+//!     loop {
+//!         thread::park();
+//!     }
+//! });
+//! let id = recv.recv().unwrap();
+//! let out = watchdog::format_thread(id, Duration::from_secs(1), &FormatOptions::new()).unwrap();
+//! eprintln!("thread {} is stuck:{}", id, out);
+//! ```
+//!
+//! Panic hooks of processes which exit on panics can arm [`arm_after_panic()`], which prints the
+//! stacks of all threads and aborts if the process is still alive a while after the panic, e.g.
+//! because unwinding hangs in a destructor or a thread joined during teardown never finishes:
+//!
+//! ```no_run
+//! use {
+//!     backtrace_string::{watchdog, FormatOptions},
+//!     std::{panic, time::Duration},
+//! };
+//!
+//! let old_hook = panic::take_hook();
+//! panic::set_hook(Box::new(move |info| {
+//!     old_hook(info);
+//!     let _ = watchdog::arm_after_panic(Duration::from_secs(10), &FormatOptions::new());
+//! }));
+//! ```
+//!
+//! The capture sends the first real-time signal (`SIGRTMIN`) to the thread, whose handler walks
+//! the stack into storage shared with the watchdog. The handler is installed on the first capture,
+//! replacing any other handler of the signal. A thread which blocks the signal can't be captured.
+//! The unwinder isn't async-signal-safe, so a capture can deadlock the thread, see
+//! [`format_thread()`].
+//!
+//![`format_thread()`]: fn.format_thread.html
+//![`current_thread_id()`]: fn.current_thread_id.html
+//![`arm_after_panic()`]: fn.arm_after_panic.html
+
+use {
+    crate::{
+        format_frame_source,
+        frame::{resolve_symbols, ResolvedFrame},
+        FormatOptions,
+    },
+    std::{
+        fmt::Write as _,
+        fs,
+        io::{self, Write as _},
+        mem, process, ptr,
+        sync::{
+            atomic::{AtomicU8, AtomicUsize, Ordering},
+            Condvar, Mutex, Once,
+        },
+        thread,
+        time::{Duration, Instant},
+    },
+};
+
+/// The maximum number of frames captured.
+const MAX_FRAMES: usize = 256;
+
+const IDLE: u8 = 0;
+const REQUESTED: u8 = 1;
+const CAPTURING: u8 = 2;
+const DONE: u8 = 3;
+
+/// The storage the signal handler captures into, which can't allocate or lock.
+static STATE: AtomicU8 = AtomicU8::new(IDLE);
+static LEN: AtomicUsize = AtomicUsize::new(0);
+static FRAMES: [AtomicUsize; MAX_FRAMES] = [const { AtomicUsize::new(0) }; MAX_FRAMES];
+
+/// Serializes the captures, as there is only one storage.
+static CAPTURE: Mutex<()> = Mutex::new(());
+
+/// How long the capture of a thread may take when the watchdog armed after a panic fires.
+const FIRE_CAPTURE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The watchdog armed after a panic: when it fires, the options and the panicked thread.
+type Armed = Option<(Instant, FormatOptions, u32)>;
+
+static ARMED: Mutex<Armed> = Mutex::new(None);
+static ARMED_CHANGED: Condvar = Condvar::new();
+
+/// Returns the id of the calling thread, which other threads pass to [`format_thread()`].
+///
+///[`format_thread()`]: fn.format_thread.html
+pub fn current_thread_id() -> u32 {
+    // SAFETY: `gettid` has no preconditions; the syscall is used as older C libraries lack it.
+    unsafe { libc::syscall(libc::SYS_gettid) as u32 }
+}
+
+/// Captures the current stack of the thread `id` of this process and formats it like
+/// [`format_frame_source()`].
+///
+/// Fails with `ErrorKind::TimedOut` if the thread doesn't handle the signal within `timeout`,
+/// e.g. because it blocks the signal, and with `ErrorKind::NotFound` if there is no such thread.
+///
+/// The signal handler walks the stack with the unwinder of the platform, which isn't
+/// async-signal-safe: with glibc it takes the lock of the dynamic loader in `dl_iterate_phdr`,
+/// and libgcc may allocate when it first unwinds through a module. If the thread was interrupted
+/// while holding one of these locks, e.g. while loading a library or within `malloc`, the
+/// handler deadlocks and the thread stays stuck in it. The capture then fails with
+/// `ErrorKind::TimedOut`, and so do all further captures, as the handler may still write to the
+/// storage they share.
+///
+///[`format_frame_source()`]: ../fn.format_frame_source.html
+pub fn format_thread(id: u32, timeout: Duration, options: &FormatOptions) -> io::Result<String> {
+    let frames = capture_frames(id, timeout, options)?;
+    Ok(format_frame_source(&frames, options))
+}
+
+/// Arms a watchdog which, if the process is still alive `timeout` after the call, prints the
+/// stacks of all its threads to stderr, formatted with `options`, and aborts the process.
+///
+/// It is meant to be called from the panic hook of a process which exits on panics, see the
+/// [module docs]. Processes recovering from a panic call [`disarm_after_panic()`] once they did.
+/// While the watchdog is armed further calls don't move its deadline. It runs on a thread of its
+/// own, which is spawned by the first call. Threads whose capture fails, e.g. because it
+/// deadlocked as described for [`format_thread()`], are listed with the error instead of their
+/// stack, and the process is aborted all the same.
+///
+///[module docs]: index.html
+///[`disarm_after_panic()`]: fn.disarm_after_panic.html
+///[`format_thread()`]: fn.format_thread.html
+pub fn arm_after_panic(timeout: Duration, options: &FormatOptions) -> io::Result<()> {
+    static SPAWN: Once = Once::new();
+    let mut spawned = Ok(());
+    SPAWN.call_once(|| {
+        spawned = thread::Builder::new()
+            .name(String::from("backtrace-string-watchdog"))
+            .spawn(run_armed_watchdog)
+            .map(drop);
+    });
+    spawned?;
+    let mut armed = ARMED.lock().unwrap_or_else(|err| err.into_inner());
+    if armed.is_none() {
+        *armed = Some((
+            Instant::now() + timeout,
+            options.clone(),
+            current_thread_id(),
+        ));
+        ARMED_CHANGED.notify_all();
+    }
+    Ok(())
+}
+
+/// Disarms the watchdog armed by [`arm_after_panic()`], if it didn't fire yet.
+///
+///[`arm_after_panic()`]: fn.arm_after_panic.html
+pub fn disarm_after_panic() {
+    *ARMED.lock().unwrap_or_else(|err| err.into_inner()) = None;
+    ARMED_CHANGED.notify_all();
+}
+
+fn run_armed_watchdog() {
+    let mut armed = ARMED.lock().unwrap_or_else(|err| err.into_inner());
+    let (options, panicked) = loop {
+        let deadline = match &*armed {
+            Some((deadline, _, _)) => *deadline,
+            None => {
+                armed = ARMED_CHANGED
+                    .wait(armed)
+                    .unwrap_or_else(|err| err.into_inner());
+                continue;
+            }
+        };
+        let now = Instant::now();
+        if now >= deadline {
+            let (_, options, panicked) = armed.take().unwrap();
+            break (options, panicked);
+        }
+        armed = ARMED_CHANGED
+            .wait_timeout(armed, deadline - now)
+            .unwrap_or_else(|err| err.into_inner())
+            .0;
+    };
+    drop(armed);
+    let out = format_all_threads(panicked, &options);
+    let _ = io::stderr().write_all(out.as_bytes());
+    process::abort();
+}
+
+/// Formats the stacks of all threads but the calling one, the panicked thread first.
+fn format_all_threads(panicked: u32, options: &FormatOptions) -> String {
+    let mut out = String::from("the process is still running after a panic, aborting\n");
+    let mut ids = thread_ids().unwrap_or_default();
+    let current = current_thread_id();
+    ids.retain(|id| *id != current);
+    ids.sort_by_key(|id| (*id != panicked, *id));
+    for id in ids {
+        let name = fs::read_to_string(format!("/proc/self/task/{}/comm", id)).unwrap_or_default();
+        write!(out, "thread {} '{}'", id, name.trim_end()).unwrap();
+        if id == panicked {
+            out.push_str(" (panicked)");
+        }
+        match format_thread(id, FIRE_CAPTURE_TIMEOUT, options) {
+            Ok(frames) => {
+                out.push(':');
+                out.push_str(&frames);
+            }
+            Err(err) => writeln!(out, ": {}", err).unwrap(),
+        }
+    }
+    out
+}
+
+/// Captures the frames of the thread `id`, resolved unless the `options` disable it.
+pub(crate) fn capture_frames(
+    id: u32,
+    timeout: Duration,
+    options: &FormatOptions,
+) -> io::Result<Vec<ResolvedFrame>> {
+    let ips = capture_thread(id, timeout)?;
+    let resolve = cfg!(feature = "resolve") && !options.no_resolve;
+    Ok(ips
+        .iter()
+        .enumerate()
+        .map(|(i, &ip)| ResolvedFrame {
+            ip,
+            symbols: if resolve {
+                // Except for the interrupted one the frames are at the instruction after the call.
+                resolve_symbols(if i == 0 { ip } else { ip.saturating_sub(1) })
+            } else {
+                Vec::new()
+            },
+        })
+        .collect())
+}
+
+/// Returns the ids of the threads of this process.
+pub(crate) fn thread_ids() -> io::Result<Vec<u32>> {
+    let mut ids = Vec::new();
+    for entry in std::fs::read_dir("/proc/self/task")? {
+        if let Some(id) = entry?.file_name().to_str().and_then(|id| id.parse().ok()) {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
+
+/// Captures the instruction pointers of the stack of the thread `id`.
+pub(crate) fn capture_thread(id: u32, timeout: Duration) -> io::Result<Vec<usize>> {
+    install_handler()?;
+    let _guard = CAPTURE.lock().unwrap_or_else(|err| err.into_inner());
+
+    if STATE.load(Ordering::SeqCst) == CAPTURING {
+        return Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "a previous capture never finished",
+        ));
+    }
+    STATE.store(REQUESTED, Ordering::SeqCst);
+    // SAFETY: `tgkill` only sends the signal, whose handler is installed.
+    let sent = unsafe {
+        libc::syscall(
+            libc::SYS_tgkill,
+            libc::getpid(),
+            id as libc::pid_t,
+            libc::SIGRTMIN(),
+        )
+    };
+    if sent != 0 {
+        STATE.store(IDLE, Ordering::SeqCst);
+        let err = io::Error::last_os_error();
+        return Err(match err.raw_os_error() {
+            Some(libc::ESRCH) => io::Error::new(io::ErrorKind::NotFound, "no such thread"),
+            _ => err,
+        });
+    }
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        match STATE.load(Ordering::Acquire) {
+            DONE => break,
+            REQUESTED if Instant::now() >= deadline => {
+                if STATE
+                    .compare_exchange(REQUESTED, IDLE, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+                {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "the thread didn't handle the signal",
+                    ));
+                }
+            }
+            // The stack is finite, so a capture which takes this long deadlocked in the unwinder.
+            // The state stays `CAPTURING`, which keeps later captures off the storage.
+            CAPTURING if Instant::now() >= deadline => {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "the thread didn't finish the capture",
+                ));
+            }
+            _ => thread::sleep(Duration::from_millis(1)),
+        }
+    }
+
+    let len = LEN.load(Ordering::Acquire);
+    let ips = FRAMES[..len]
+        .iter()
+        .map(|ip| ip.load(Ordering::Relaxed))
+        .collect();
+    STATE.store(IDLE, Ordering::SeqCst);
+    Ok(ips)
+}
+
+fn install_handler() -> io::Result<()> {
+    static INSTALL: Once = Once::new();
+    let mut result = Ok(());
+    INSTALL.call_once(|| {
+        // SAFETY: The action is fully initialized and the handler only uses atomics and the
+        // unwinder.
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = handle_signal as *const () as usize;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART | libc::SA_ONSTACK;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(libc::SIGRTMIN(), &action, ptr::null_mut()) != 0 {
+                result = Err(io::Error::last_os_error());
+            }
+        }
+    });
+    result
+}
+
+extern "C" fn handle_signal(_: libc::c_int, _: *mut libc::siginfo_t, _: *mut libc::c_void) {
+    // Only a pending request is served, a stray or late signal is ignored.
+    if STATE
+        .compare_exchange(REQUESTED, CAPTURING, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        return;
+    }
+    let mut len = 0;
+    let mut handler = false;
+    let mut trampoline = false;
+    // SAFETY: `trace` would take the lock of the `backtrace` crate, which the interrupted thread
+    // may hold, so the unsynchronized variant is used while the capture lock serializes the
+    // requests. The unwinder isn't async-signal-safe either: glibc's `dl_iterate_phdr` takes the
+    // loader lock and libgcc may `malloc` when it registers unwind info, which deadlocks if the
+    // thread was interrupted holding those locks. That hangs the thread but doesn't corrupt
+    // memory, and `capture_thread()` times out, see `format_thread()`.
+    unsafe {
+        backtrace::trace_unsynchronized(|frame| {
+            // The frames up to the handler and the trampoline returning from it are dropped.
+            if !handler {
+                handler = ptr::eq(frame.symbol_address(), handle_signal as *mut libc::c_void);
+                return true;
+            }
+            if !trampoline {
+                trampoline = true;
+                return true;
+            }
+            FRAMES[len].store(frame.ip() as usize, Ordering::Relaxed);
+            len += 1;
+            len < MAX_FRAMES
+        });
+    }
+    LEN.store(len, Ordering::Release);
+    STATE.store(DONE, Ordering::Release);
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "resolve", feature = "demangle"))]
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        thread,
+    };
+    use {
+        super::format_thread,
+        crate::FormatOptions,
+        std::{io, time::Duration},
+    };
+
+    #[cfg(all(feature = "resolve", feature = "demangle"))]
+    #[inline(never)]
+    fn hang(stop: &AtomicBool) {
+        while !stop.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "resolve", feature = "demangle"))]
+    fn captures_hung_thread() {
+        use {
+            super::current_thread_id,
+            std::sync::{mpsc, Arc},
+        };
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let (send, recv) = mpsc::channel();
+        let hung = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                send.send(current_thread_id()).unwrap();
+                hang(&stop);
+            })
+        };
+        let id = recv.recv().unwrap();
+
+        // The thread may still be sending its id.
+        let mut out = String::new();
+        for _ in 0..100 {
+            out = format_thread(id, Duration::from_secs(5), &FormatOptions::new()).unwrap();
+            if out.contains("::hang") {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        stop.store(true, Ordering::Relaxed);
+        hung.join().unwrap();
+        assert!(
+            out.contains("backtrace_string::watchdog::tests::hang"),
+            "{}",
+            out
+        );
+        assert!(!out.contains("handle_signal"), "{}", out);
+    }
+
+    #[cfg(all(feature = "test-harness", feature = "resolve", feature = "demangle"))]
+    #[test]
+    fn aborts_when_hung_after_panic() {
+        use {super::arm_after_panic, crate::harness::run_in_subprocess, std::panic};
+
+        struct HangOnDrop;
+
+        impl Drop for HangOnDrop {
+            fn drop(&mut self) {
+                hang(&AtomicBool::new(false));
+            }
+        }
+
+        let output = run_in_subprocess(|| {
+            panic::set_hook(Box::new(|_| {
+                arm_after_panic(Duration::from_millis(100), &FormatOptions::new()).unwrap();
+            }));
+            let _hang = HangOnDrop;
+            panic!("boom");
+        });
+        assert!(
+            !output.status.success() && !output.panicked(),
+            "{:?}",
+            output
+        );
+        assert!(
+            output
+                .stderr
+                .starts_with("the process is still running after a panic, aborting\n"),
+            "{}",
+            output.stderr
+        );
+        assert!(output.stderr.contains("(panicked):"), "{}", output.stderr);
+        assert!(
+            output.stderr.contains("watchdog::tests::hang"),
+            "{}",
+            output.stderr
+        );
+    }
+
+    #[cfg(feature = "test-harness")]
+    #[test]
+    fn disarmed_after_recovery() {
+        use {
+            super::{arm_after_panic, disarm_after_panic},
+            crate::harness::run_in_subprocess,
+            std::thread,
+        };
+
+        let output = run_in_subprocess(|| {
+            arm_after_panic(Duration::from_millis(50), &FormatOptions::new()).unwrap();
+            disarm_after_panic();
+            thread::sleep(Duration::from_millis(200));
+        });
+        assert!(output.status.success(), "{:?}", output);
+    }
+
+    #[test]
+    fn unknown_thread_is_not_found() {
+        let err = format_thread(u32::MAX >> 1, Duration::from_secs(1), &FormatOptions::new());
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,65 @@
+//! `#[derive(Backtraced)]` on structs and enums.
+
+#![cfg(feature = "derive")]
+
+use {
+    backtrace_string::{lazy::LazyBacktrace, Backtraced},
+    std::{io, num::ParseIntError},
+};
+
+#[derive(Debug, Backtraced)]
+struct ReadError {
+    #[backtraced(from)]
+    source: io::Error,
+    backtrace: LazyBacktrace,
+}
+
+#[derive(Debug, Backtraced)]
+enum AppError {
+    Io(#[backtraced(from)] io::Error, LazyBacktrace),
+    Parse {
+        #[backtraced(from)]
+        source: ParseIntError,
+        #[backtraced(trace)]
+        trace: LazyBacktrace,
+    },
+    Unsupported,
+}
+
+#[inline(never)]
+fn read() -> Result<(), ReadError> {
+    Err(io::Error::new(io::ErrorKind::NotFound, "config.toml"))?;
+    Ok(())
+}
+
+#[inline(never)]
+fn parse(text: &str) -> Result<u16, AppError> {
+    Ok(text.parse()?)
+}
+
+#[test]
+fn structs_capture_on_conversion() {
+    let error = read().unwrap_err();
+    assert_eq!(error.source.kind(), io::ErrorKind::NotFound);
+    let backtrace = error.backtrace().to_string();
+    assert!(backtrace.contains("derive::read"), "{}", backtrace);
+}
+
+#[test]
+fn enums_capture_on_conversion() {
+    let error = parse("port").unwrap_err();
+    match &error {
+        AppError::Parse { source, .. } => assert_eq!(source, &"port".parse::<u16>().unwrap_err()),
+        _ => panic!("{:?}", error),
+    }
+    let backtrace = error.backtrace().unwrap().to_string();
+    assert!(backtrace.contains("derive::parse"), "{}", backtrace);
+
+    let error = AppError::from(io::Error::from(io::ErrorKind::Other));
+    match &error {
+        AppError::Io(source, _) => assert_eq!(source.kind(), io::ErrorKind::Other),
+        _ => panic!("{:?}", error),
+    }
+    assert!(error.backtrace().is_some());
+    assert!(AppError::Unsupported.backtrace().is_none());
+}
@@ -0,0 +1,146 @@
+//! Property tests feeding synthetic frames through the formatter.
+//!
+//! The formatter must never panic and its output must stay proportional to its input, whatever
+//! the symbols and paths look like.
+
+#![cfg(feature = "std")]
+
+use {
+    backtrace_string::{
+        frame::{ResolvedFrame, ResolvedSymbol},
+        ColorChoice, Demangling, FormatOptions, Locations, Numbering, Style, UnknownSymbols,
+    },
+    proptest::{collection::vec, option, prelude::*},
+    std::path::PathBuf,
+};
+
+fn symbol_name() -> impl Strategy<Value = String> {
+    prop_oneof![
+        any::<String>(),
+        "[_a-zA-Z0-9:<>\\[\\]{} ]{0,80}",
+        "_ZN([0-9]{1,2}[a-z_]{0,12}){0,6}(17h[0-9a-f]{16})?E",
+        "_R[0-9A-Za-z_]{0,60}",
+    ]
+}
+
+fn path() -> impl Strategy<Value = PathBuf> {
+    prop_oneof![
+        any::<String>(),
+        "(/[a-z.\\-]{0,10}){0,40}",
+        "[a-zA-Z]:(\\\\[a-z. ]{0,10}){0,10}",
+        "/rustc/[0-9a-f]{40}/library/(std|core)/src/[a-z]{1,10}\\.rs",
+    ]
+    .prop_map(PathBuf::from)
+}
+
+fn symbol() -> impl Strategy<Value = ResolvedSymbol> {
+    (
+        option::of(symbol_name()),
+        option::of(path()),
+        option::of(any::<u32>()),
+        option::of(any::<usize>()),
+    )
+        .prop_map(|(name, filename, lineno, addr)| ResolvedSymbol {
+            name,
+            filename,
+            lineno,
+            addr,
+        })
+}
+
+fn frames() -> impl Strategy<Value = Vec<ResolvedFrame>> {
+    vec(
+        (any::<usize>(), vec(symbol(), 0..4))
+            .prop_map(|(ip, symbols)| ResolvedFrame { ip, symbols }),
+        0..40,
+    )
+}
+
+fn options() -> impl Strategy<Value = FormatOptions> {
+    (
+        prop_oneof![
+            Just(FormatOptions::new()),
+            Just(FormatOptions::production()),
+            Just(FormatOptions::ci()),
+            Just(FormatOptions::minimal()),
+        ],
+        prop_oneof![Just(Style::Full), Just(Style::Compact)],
+        prop_oneof![
+            Just(UnknownSymbols::Show),
+            Just(UnknownSymbols::Hide),
+            Just(UnknownSymbols::ModuleOffset),
+        ],
+        prop_oneof![Just(Numbering::Frames), Just(Numbering::Symbols)],
+        prop_oneof![
+            Just(Locations::Shown),
+            Just(Locations::Hidden),
+            Just(Locations::Crate),
+        ],
+        prop_oneof![
+            Just(Demangling::WithHashes),
+            Just(Demangling::WithoutHashes),
+            Just(Demangling::Off),
+        ],
+        any::<bool>(),
+        option::of(0..50usize),
+    )
+        .prop_map(
+            |(options, style, unknown, numbering, locations, demangling, deterministic, max)| {
+                options
+                    .color(ColorChoice::Never)
+                    .style(style)
+                    .unknown_symbols(unknown)
+                    .numbering(numbering)
+                    .locations(locations)
+                    .demangling(demangling)
+                    .deterministic(deterministic)
+                    .max_frames(max)
+                    .detect_stack_overflow(true)
+            },
+        )
+}
+
+/// An upper bound of the output for `frames`, generous but linear in their size.
+fn output_bound(frames: &[ResolvedFrame]) -> usize {
+    let symbols = frames.iter().flat_map(|frame| &frame.symbols);
+    let text = symbols
+        .map(|symbol| {
+            let name = symbol.name.as_ref().map_or(0, String::len);
+            let path = symbol
+                .filename
+                .as_ref()
+                .map_or(0, |path| path.as_os_str().len());
+            // Demangling and lossy conversions may grow the text a bit.
+            4 * (name + path) + 128
+        })
+        .sum::<usize>();
+    512 + 128 * frames.len() + text
+}
+
+proptest! {
+    #[test]
+    fn formatting_never_panics(frames in frames(), options in options()) {
+        let out = backtrace_string::format_frames(&frames, &options);
+        prop_assert!(out.len() <= output_bound(&frames), "{} bytes: {}", out.len(), out);
+
+        let out = backtrace_string::format_frame_source(&frames, &options);
+        prop_assert!(out.len() <= output_bound(&frames), "{} bytes: {}", out.len(), out);
+    }
+
+    #[test]
+    fn recursion_is_collapsed(
+        cycle in vec(symbol(), 1..4),
+        repeats in 32..200usize,
+    ) {
+        let frames = (0..repeats)
+            .flat_map(|_| &cycle)
+            .map(|symbol| ResolvedFrame {
+                ip: 0,
+                symbols: vec![symbol.clone()],
+            })
+            .collect::<Vec<_>>();
+        let options = FormatOptions::new().detect_stack_overflow(true);
+        let out = backtrace_string::format_frames(&frames, &options);
+        prop_assert!(out.len() <= output_bound(&frames[..cycle.len() * 2]));
+    }
+}
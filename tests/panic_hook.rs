@@ -0,0 +1,93 @@
+//! Installing and uninstalling the reporting panic hook, in a process of its own as the panic
+//! hook is global.
+
+#![cfg(feature = "std")]
+
+use {
+    backtrace_string::{
+        install_panic_hook, mark_panic_reported, panic_reported, sink::set_sink,
+        uninstall_panic_hook, FormatOptions,
+    },
+    std::{
+        panic,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+    },
+};
+
+static REPORTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+static APP_HOOK_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn panic_quietly(message: &'static str) {
+    let _ = panic::catch_unwind(|| panic!("{}", message));
+}
+
+#[test]
+fn chains_with_and_restores_the_previous_hook() {
+    set_sink(|report: &str| REPORTS.lock().unwrap().push(report.to_owned()));
+    panic::set_hook(Box::new(|_| {
+        APP_HOOK_CALLS.fetch_add(1, Ordering::SeqCst);
+    }));
+
+    assert!(install_panic_hook());
+    assert!(!install_panic_hook());
+    panic_quietly("reported");
+    assert_eq!(APP_HOOK_CALLS.load(Ordering::SeqCst), 1);
+    {
+        let reports = REPORTS.lock().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert!(
+            reports[0].contains("panicked at tests/panic_hook.rs:"),
+            "{}",
+            reports[0]
+        );
+        assert!(
+            reports[0].contains(": reported\nBacktrace:\n"),
+            "{}",
+            reports[0]
+        );
+    }
+
+    assert!(uninstall_panic_hook());
+    assert!(!uninstall_panic_hook());
+    panic_quietly("not reported");
+    assert_eq!(APP_HOOK_CALLS.load(Ordering::SeqCst), 2);
+    assert_eq!(REPORTS.lock().unwrap().len(), 1);
+
+    // With `report_once` the hook stays silent for a panic the app's hook marked as reported,
+    // but still reports the next one.
+    let app_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        app_hook(info);
+        if info.payload().downcast_ref::<String>().unwrap() == "reported before" {
+            mark_panic_reported();
+        }
+    }));
+    FormatOptions::new()
+        .report_once(true)
+        .with_thread_override(|| {
+            assert!(install_panic_hook());
+            panic_quietly("reported before");
+            assert_eq!(REPORTS.lock().unwrap().len(), 1);
+            panic_quietly("reported after");
+            assert!(uninstall_panic_hook());
+        });
+    assert!(!panic_reported());
+    assert_eq!(APP_HOOK_CALLS.load(Ordering::SeqCst), 4);
+    {
+        let reports = REPORTS.lock().unwrap();
+        assert_eq!(reports.len(), 2);
+        assert!(reports[1].contains(": reported after\n"), "{}", reports[1]);
+    }
+
+    // A hook installed on top stays, only the reporting stops.
+    assert!(install_panic_hook());
+    let ours = panic::take_hook();
+    panic::set_hook(Box::new(move |info| ours(info)));
+    assert!(!uninstall_panic_hook());
+    panic_quietly("still not reported");
+    assert_eq!(APP_HOOK_CALLS.load(Ordering::SeqCst), 5);
+    assert_eq!(REPORTS.lock().unwrap().len(), 2);
+}
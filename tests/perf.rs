@@ -0,0 +1,126 @@
+//! Checks the allocation and demangling budgets listed in `BENCH.md`.
+//!
+//! Unlike timings, these counts are the same on every machine, so exceeding a budget reliably
+//! points to a regression. Lower the budget in `BENCH.md` when an optimization beats it.
+
+#![cfg(feature = "std")]
+
+use {
+    backtrace_string::{
+        frame::{ResolvedFrame, ResolvedSymbol},
+        stats, BacktraceBuffer, FormatOptions,
+    },
+    std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+        path::PathBuf,
+    },
+};
+
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+/// The counts of a single run of a scenario.
+#[derive(Debug)]
+struct Counts {
+    allocations: usize,
+    demangle_calls: usize,
+}
+
+fn measure(func: impl FnOnce()) -> Counts {
+    let allocations = ALLOCATIONS.with(Cell::get);
+    let demangle_calls = stats::demangle_calls();
+    func();
+    Counts {
+        allocations: ALLOCATIONS.with(Cell::get) - allocations,
+        demangle_calls: stats::demangle_calls() - demangle_calls,
+    }
+}
+
+/// Returns the budget of `scenario` from the table in `BENCH.md`.
+fn budget(scenario: &str) -> Counts {
+    let row = include_str!("../BENCH.md")
+        .lines()
+        .find(|line| line.starts_with(&format!("| `{}`", scenario)))
+        .unwrap_or_else(|| panic!("no budget for `{}` in BENCH.md", scenario));
+    let cells = row.split('|').map(str::trim).collect::<Vec<_>>();
+    Counts {
+        allocations: cells[2].parse().unwrap(),
+        demangle_calls: cells[3].parse().unwrap(),
+    }
+}
+
+fn check(scenario: &str, func: impl FnOnce()) {
+    let counts = measure(func);
+    let budget = budget(scenario);
+    eprintln!("{}: {:?} (budget {:?})", scenario, counts, budget);
+    assert!(
+        counts.allocations <= budget.allocations && counts.demangle_calls <= budget.demangle_calls,
+        "`{}` exceeds its budget: {:?} > {:?}",
+        scenario,
+        counts,
+        budget
+    );
+}
+
+/// The same frames as in the benchmarks.
+fn synthetic_frames(count: usize) -> Vec<ResolvedFrame> {
+    (0..count)
+        .map(|i| ResolvedFrame {
+            ip: 0x5555_0000 + i * 0x40,
+            symbols: vec![ResolvedSymbol {
+                name: Some(format!("_ZN6my_app6module8function{}17h0123456789abcdefE", i)),
+                filename: Some(PathBuf::from(format!(
+                    "/home/alice/.cargo/registry/src/index.crates.io-6f17d22bba15001f/dep-1.0.0/src/lib{}.rs",
+                    i
+                ))),
+                lineno: Some(10 + i as u32),
+                addr: Some(0x5555_0000 + i * 0x40),
+            }],
+        })
+        .collect()
+}
+
+#[test]
+fn format_frames() {
+    let frames = synthetic_frames(32);
+    let options = FormatOptions::new();
+    check("format_frames/32", || {
+        backtrace_string::format_frames(&frames, &options);
+    });
+    let production = FormatOptions::production();
+    check("format_frames/32/production", || {
+        backtrace_string::format_frames(&frames, &production);
+    });
+}
+
+#[test]
+fn buffer() {
+    let options = FormatOptions::new().resolve_symbols(false);
+    let mut buffer = BacktraceBuffer::with_options(64, 16 * 1024, options);
+    check("buffer/capture_and_format/unresolved", || {
+        buffer.capture_and_format();
+    });
+}